@@ -0,0 +1,32 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+use kompression::huffman::{AdaptiveHuffmanCompressor, AdaptiveHuffmanDecompressor};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    data: Vec<u8>,
+    read_sizes: Vec<u8>,
+}
+
+fuzz_target!(|input: Input| {
+    let mut compressed = Vec::new();
+    let mut c = AdaptiveHuffmanCompressor::new(&mut compressed);
+    c.write(&input.data).unwrap();
+    c.finalize().unwrap();
+
+    let mut uncompressed = Vec::new();
+    let mut d = AdaptiveHuffmanDecompressor::new(&mut uncompressed);
+    let mut rest = &compressed[..];
+    for &size in &input.read_sizes {
+        let (chunk, tail) = rest.split_at(usize::min(size as usize, rest.len()));
+        d.read(chunk).unwrap();
+        rest = tail;
+    }
+    d.read(rest).unwrap();
+    d.finalize().unwrap();
+
+    assert_eq!(input.data, uncompressed);
+});
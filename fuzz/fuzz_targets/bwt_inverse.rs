@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use kompression::bwt;
+
+fuzz_target!(|data: &[u8]| {
+    if let Some((&origin, last)) = data.split_first() {
+        let _ = bwt::inverse(last, origin as usize);
+    }
+});
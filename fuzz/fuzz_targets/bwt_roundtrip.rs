@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use kompression::bwt;
+
+fuzz_target!(|data: &[u8]| {
+    let (last, origin) = bwt::forward(data);
+    assert_eq!(bwt::inverse(&last, origin).unwrap(), data);
+});
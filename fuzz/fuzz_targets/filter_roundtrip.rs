@@ -0,0 +1,41 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+use kompression::transform::{BitShuffle, Delta, Shuffle, Stage, Transform, Xor};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    width: u8,
+    data: Vec<u8>,
+    chunk_size: u8,
+}
+
+fn round_trip<T: Transform>(transform: T, input: &Input) {
+    let size = input.chunk_size.max(1) as usize;
+
+    let mut forward = Vec::new();
+    let mut e = transform.encoder();
+    for chunk in input.data.chunks(size) {
+        e.push(chunk, &mut forward).unwrap();
+    }
+    e.finish(&mut forward).unwrap();
+    assert_eq!(forward, transform.forward(&input.data).unwrap());
+
+    let mut inverse = Vec::new();
+    let mut d = transform.decoder();
+    for chunk in forward.chunks(size) {
+        d.push(chunk, &mut inverse).unwrap();
+    }
+    d.finish(&mut inverse).unwrap();
+    assert_eq!(input.data, inverse);
+}
+
+fuzz_target!(|input: Input| {
+    let width = input.width as usize;
+    round_trip(Delta::new(width), &input);
+    round_trip(Xor::new(width), &input);
+    round_trip(Shuffle::new(width), &input);
+    round_trip(BitShuffle::new(width), &input);
+});
@@ -0,0 +1,34 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use kompression::{
+    lz78::{BlockCompressor, BlockDecompressor, Mode},
+    DecompressOptions,
+};
+
+fuzz_target!(|data: &[u8]| {
+    // Whatever the input, decompressing it mustn't panic
+    let options = DecompressOptions::new()
+        .max_output_bytes(1 << 20)
+        .max_dictionary_bytes(1 << 20);
+    let mut d = BlockDecompressor::with_options(Vec::new(), options);
+    if d.read(data).is_ok() {
+        let _ = d.finalize();
+    }
+
+    // And compressing it in small blocks has to come back the same
+    let block_size = data.first().map_or(1, |&b| b as usize + 1);
+    for mode in [Mode::Raw, Mode::Entropy] {
+        let mut compressed = Vec::new();
+        let mut c = BlockCompressor::with_block_size(&mut compressed, mode, block_size);
+        c.write(data).unwrap();
+        c.finalize().unwrap();
+
+        let mut uncompressed = Vec::new();
+        let mut d = BlockDecompressor::new(&mut uncompressed);
+        d.read(&compressed).unwrap();
+        d.finalize().unwrap();
+        assert_eq!(uncompressed, data);
+    }
+});
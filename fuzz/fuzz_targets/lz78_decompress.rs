@@ -0,0 +1,20 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use kompression::{lz78, DecompressOptions, LZ78Decompressor};
+
+fuzz_target!(|data: &[u8]| {
+    let options = DecompressOptions::new()
+        .max_output_bytes(1 << 20)
+        .max_dictionary_bytes(1 << 20);
+    let mut uncompressed = Vec::new();
+    let mut d = LZ78Decompressor::with_options(&mut uncompressed, options);
+    if d.read(data).is_ok() {
+        let _ = d.finalize();
+    }
+
+    let mut out = vec![0; 1 << 16];
+    let mut workspace = Box::new(lz78::Workspace::new());
+    let _ = lz78::decompress_into_with(&mut workspace, data, &mut out);
+});
@@ -0,0 +1,47 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use kompression::{
+    lz78::{Dictionary, Mode},
+    DecompressOptions, LZ78Compressor, LZ78Decompressor,
+};
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte says how much of the rest is the dictionary's corpus
+    let (&n, rest) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let (corpus, data) = rest.split_at((n as usize).min(rest.len()));
+    let dictionary = Dictionary::from_corpus(corpus);
+    assert_eq!(
+        Dictionary::from_bytes(&dictionary.to_bytes()).unwrap(),
+        dictionary
+    );
+    // Whatever a saved dictionary says, reading it mustn't panic
+    let _ = Dictionary::from_bytes(data);
+
+    let options = DecompressOptions::new()
+        .max_output_bytes(1 << 20)
+        .max_dictionary_bytes(1 << 20);
+    for mode in [Mode::Raw, Mode::Entropy] {
+        let mut compressed = Vec::new();
+        let mut c = LZ78Compressor::with_dictionary(&mut compressed, mode, &dictionary).unwrap();
+        c.write(data).unwrap();
+        c.finalize().unwrap();
+
+        let mut uncompressed = Vec::new();
+        let mut d =
+            LZ78Decompressor::with_dictionary(&mut uncompressed, mode, &dictionary, options);
+        d.read(&compressed).unwrap();
+        d.finalize().unwrap();
+        assert_eq!(uncompressed, data);
+
+        // Nor decompressing anything at all with it
+        let mut d = LZ78Decompressor::with_dictionary(Vec::new(), mode, &dictionary, options);
+        if d.read(data).is_ok() {
+            let _ = d.finalize();
+        }
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use kompression::{lz78::Mode, DecompressOptions, LZ78Decompressor};
+
+fuzz_target!(|data: &[u8]| {
+    let options = DecompressOptions::new()
+        .max_output_bytes(1 << 20)
+        .max_dictionary_bytes(1 << 20);
+    let mut uncompressed = Vec::new();
+    let mut d = LZ78Decompressor::with_mode(&mut uncompressed, Mode::Entropy, options);
+    if d.read(data).is_ok() {
+        let _ = d.finalize();
+    }
+});
@@ -0,0 +1,32 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use kompression::{lz78, lz78::Mode, DecompressOptions, LZ78Decompressor};
+
+fuzz_target!(|data: &[u8]| {
+    // Both held to the same limits, so neither can blow up
+    let options = DecompressOptions::new()
+        .max_output_bytes(1 << 20)
+        .max_dictionary_bytes(1 << 20);
+    for mode in [Mode::Raw, Mode::Entropy] {
+        let mut uncompressed = Vec::new();
+        let mut d = LZ78Decompressor::with_mode(&mut uncompressed, mode, options);
+        let decompressed = d.read(data).and_then(|_| d.finalize()).is_ok();
+
+        // The listing has to agree with the decompressor
+        match lz78::inspect(data, mode, options) {
+            Ok(tokens) => {
+                assert!(decompressed);
+                let mut phrases = Vec::new();
+                for token in &tokens {
+                    phrases.extend_from_slice(&token.phrase);
+                    phrases.extend(token.literal);
+                }
+                assert_eq!(phrases, uncompressed);
+                let _ = lz78::to_json(&tokens);
+            }
+            Err(_) => assert!(!decompressed),
+        }
+    }
+});
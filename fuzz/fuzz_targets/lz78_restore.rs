@@ -0,0 +1,25 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use kompression::{DecompressOptions, LZ78Compressor, LZ78Decompressor};
+
+fuzz_target!(|data: &[u8]| {
+    // Whatever a snapshot says, restoring it and carrying on mustn't panic
+    if let Ok(mut c) = LZ78Compressor::restore(Vec::new(), data) {
+        let snapshot = c.snapshot();
+        assert_eq!(snapshot, data);
+        if c.write(b"abcabcabc").is_ok() {
+            let _ = c.finalize();
+        }
+    }
+
+    let options = DecompressOptions::new()
+        .max_output_bytes(1 << 20)
+        .max_dictionary_bytes(1 << 20);
+    if let Ok(mut d) = LZ78Decompressor::restore(Vec::new(), data, options) {
+        if d.read(&[0x5a; 64]).is_ok() {
+            let _ = d.finalize();
+        }
+    }
+});
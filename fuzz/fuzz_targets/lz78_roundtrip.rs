@@ -0,0 +1,55 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+use kompression::{lz78, LZ78Compressor, LZ78Decompressor};
+
+/// Everything the compressor can be configured with, plus how the data gets
+/// split up between calls to `write` and `read`.
+#[derive(Debug, Arbitrary)]
+struct Input {
+    data: Vec<u8>,
+    write_sizes: Vec<u8>,
+    read_sizes: Vec<u8>,
+}
+
+/// Split `data` into chunks of the given sizes, the remainder goes in one
+/// final chunk. Zero sized chunks are kept, they're valid calls too.
+fn chunks<'a>(mut data: &'a [u8], sizes: &[u8]) -> Vec<&'a [u8]> {
+    let mut chunks = Vec::new();
+    for &size in sizes {
+        let size = usize::min(size as usize, data.len());
+        let (chunk, rest) = data.split_at(size);
+        chunks.push(chunk);
+        data = rest;
+    }
+    chunks.push(data);
+    chunks
+}
+
+fuzz_target!(|input: Input| {
+    let mut compressed = Vec::new();
+    let mut c = LZ78Compressor::new(&mut compressed);
+    for chunk in chunks(&input.data, &input.write_sizes) {
+        c.write(chunk).unwrap();
+    }
+    c.finalize().unwrap();
+
+    let mut uncompressed = Vec::new();
+    let mut d = LZ78Decompressor::new(&mut uncompressed);
+    for chunk in chunks(&compressed, &input.read_sizes) {
+        d.read(chunk).unwrap();
+    }
+    d.finalize().unwrap();
+
+    assert_eq!(input.data, uncompressed);
+
+    let mut out = vec![0; lz78::compress_bound(input.data.len())];
+    let mut workspace = Box::new(lz78::Workspace::new());
+    let n = lz78::compress_into_with(&mut workspace, &input.data, &mut out).unwrap();
+    assert_eq!(out[..n], compressed[..]);
+    let mut uncompressed = vec![0; input.data.len()];
+    let m = lz78::decompress_into_with(&mut workspace, &out[..n], &mut uncompressed).unwrap();
+    assert_eq!(input.data, uncompressed[..m]);
+});
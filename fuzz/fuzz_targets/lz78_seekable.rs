@@ -0,0 +1,38 @@
+#![no_main]
+
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use libfuzzer_sys::fuzz_target;
+
+use kompression::{
+    lz78::{BlockCompressor, Mode, SeekableReader},
+    DecompressOptions,
+};
+
+fuzz_target!(|data: &[u8]| {
+    // Whatever the input, opening it and reading anywhere mustn't panic
+    let options = DecompressOptions::new()
+        .max_output_bytes(1 << 20)
+        .max_dictionary_bytes(1 << 20);
+    if let Ok(mut r) = SeekableReader::with_options(Cursor::new(data), options) {
+        let len = r.len();
+        for position in [0, len / 2, len.saturating_sub(1)] {
+            if r.seek(SeekFrom::Start(position)).is_ok() {
+                let _ = r.read(&mut [0; 64]);
+            }
+        }
+    }
+
+    // And any range of it compressed has to read back the same
+    let block_size = data.first().map_or(1, |&b| b as usize + 1);
+    let mut compressed = Vec::new();
+    let mut c = BlockCompressor::seekable(&mut compressed, Mode::Raw, block_size);
+    c.write(data).unwrap();
+    c.finalize().unwrap();
+    let mut r = SeekableReader::new(Cursor::new(compressed)).unwrap();
+    let start = data.len() / 3;
+    r.seek(SeekFrom::Start(start as u64)).unwrap();
+    let mut rest = Vec::new();
+    r.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, data[start..]);
+});
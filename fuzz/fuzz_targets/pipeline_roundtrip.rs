@@ -0,0 +1,61 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+use kompression::{
+    lz78::Mode,
+    pipeline::{Pipeline, PipelineCompressor, PipelineDecompressor, Step},
+    transform::{BitShuffle, Delta, Shuffle, Xor},
+};
+
+const STEPS: [Step; 10] = [
+    Step::Rle1,
+    Step::Mtf,
+    Step::ZeroRun,
+    Step::Bwt,
+    Step::Lz78(Mode::Raw),
+    Step::Lz78(Mode::Entropy),
+    Step::Huffman,
+    Step::AdaptiveHuffman,
+    Step::RangeCoder,
+    Step::Bzip2,
+];
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    steps: Vec<u8>,
+    data: Vec<u8>,
+    read_sizes: Vec<u8>,
+}
+
+fuzz_target!(|input: Input| {
+    let pipeline = input.steps.iter().take(4).fold(Pipeline::new(), |p, &s| {
+        // The filters take their width from the top bits
+        let width = (s >> 4) as usize + 1;
+        p.then(match s % 14 {
+            10 => Step::Delta(Delta::new(width)),
+            11 => Step::Xor(Xor::new(width)),
+            12 => Step::Shuffle(Shuffle::new(width)),
+            13 => Step::BitShuffle(BitShuffle::new(width)),
+            s => STEPS[s as usize],
+        })
+    });
+    let mut compressed = Vec::new();
+    let mut c = PipelineCompressor::new(&mut compressed, &pipeline);
+    c.write(&input.data).unwrap();
+    c.finalize().unwrap();
+
+    let mut uncompressed = Vec::new();
+    let mut d = PipelineDecompressor::new(&mut uncompressed);
+    let mut rest = &compressed[..];
+    for &size in &input.read_sizes {
+        let (chunk, tail) = rest.split_at(usize::min(size as usize, rest.len()));
+        d.read(chunk).unwrap();
+        rest = tail;
+    }
+    d.read(rest).unwrap();
+    d.finalize().unwrap();
+
+    assert_eq!(input.data, uncompressed);
+});
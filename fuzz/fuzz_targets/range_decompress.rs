@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use kompression::{range_coder::RangeDecompressor, DecompressOptions};
+
+fuzz_target!(|data: &[u8]| {
+    let options = DecompressOptions::new().max_output_bytes(1 << 20);
+    let mut uncompressed = Vec::new();
+    let mut d = RangeDecompressor::with_options(&mut uncompressed, options);
+    if d.read(data).is_ok() {
+        let _ = d.finalize();
+    }
+});
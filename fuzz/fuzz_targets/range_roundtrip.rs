@@ -0,0 +1,56 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+use kompression::{
+    range_coder::{
+        default_model, BitTreeModel, ContextModel, Model, RangeCompressor, RangeDecompressor,
+    },
+    DecompressOptions,
+};
+
+#[derive(Debug, Arbitrary)]
+enum Order {
+    Zero,
+    Context(u8),
+    BitTree,
+}
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    order: Order,
+    data: Vec<u8>,
+    read_sizes: Vec<u8>,
+}
+
+fn roundtrip<M: Model + Clone>(model: M, input: &Input) {
+    let mut compressed = Vec::new();
+    let mut c = RangeCompressor::with_model(&mut compressed, model.clone());
+    c.write(&input.data).unwrap();
+    c.finalize().unwrap();
+
+    let mut uncompressed = Vec::new();
+    let mut d = RangeDecompressor::with_model(&mut uncompressed, model, DecompressOptions::new());
+    let mut rest = &compressed[..];
+    for &size in &input.read_sizes {
+        let (chunk, tail) = rest.split_at(usize::min(size as usize, rest.len()));
+        d.read(chunk).unwrap();
+        rest = tail;
+    }
+    d.read(rest).unwrap();
+    d.finalize().unwrap();
+
+    assert_eq!(input.data, uncompressed);
+}
+
+fuzz_target!(|input: Input| {
+    match input.order {
+        Order::Zero => roundtrip(default_model(), &input),
+        Order::Context(order) => {
+            let model = ContextModel::new(default_model(), order as u32 % 4, 9);
+            roundtrip(model, &input)
+        }
+        Order::BitTree => roundtrip(BitTreeModel::new(9), &input),
+    }
+});
@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use kompression::{
+    ans::{rans_compress, rans_decompress},
+    DecompressOptions,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let mut compressed = Vec::new();
+    rans_compress(data, &mut compressed).unwrap();
+
+    let mut uncompressed = Vec::new();
+    rans_decompress(&compressed, &mut uncompressed, DecompressOptions::new()).unwrap();
+
+    assert_eq!(data, &uncompressed[..]);
+});
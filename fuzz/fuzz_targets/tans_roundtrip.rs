@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use kompression::{
+    ans::{tans_compress, tans_decompress},
+    DecompressOptions,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let mut compressed = Vec::new();
+    tans_compress(data, &mut compressed).unwrap();
+
+    let mut uncompressed = Vec::new();
+    tans_decompress(&compressed, &mut uncompressed, DecompressOptions::new()).unwrap();
+
+    assert_eq!(data, &uncompressed[..]);
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use kompression::transform::{Mtf, Rle1, Transform, ZeroRun};
+
+fuzz_target!(|data: &[u8]| {
+    // Each zero-run digit can double the output, keep it bounded
+    if data.len() > 1024 {
+        return;
+    }
+    let _ = Mtf.inverse(data);
+    let _ = Rle1.inverse(data);
+    let _ = ZeroRun.inverse(data);
+    let _ = Rle1.then(Mtf).then(ZeroRun).inverse(data);
+});
@@ -0,0 +1,33 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+use kompression::transform::{Mtf, Rle1, Stage, Transform, ZeroRun};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    data: Vec<u8>,
+    chunk_size: u8,
+}
+
+fuzz_target!(|input: Input| {
+    let transform = Rle1.then(Mtf).then(ZeroRun);
+    let size = input.chunk_size.max(1) as usize;
+
+    let mut forward = Vec::new();
+    let mut e = transform.encoder();
+    for chunk in input.data.chunks(size) {
+        e.push(chunk, &mut forward).unwrap();
+    }
+    e.finish(&mut forward).unwrap();
+    assert_eq!(forward, transform.forward(&input.data).unwrap());
+
+    let mut inverse = Vec::new();
+    let mut d = transform.decoder();
+    for chunk in forward.chunks(size) {
+        d.push(chunk, &mut inverse).unwrap();
+    }
+    d.finish(&mut inverse).unwrap();
+    assert_eq!(input.data, inverse);
+});
@@ -0,0 +1,264 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    ans::SYMBOLS,
+    bits::{BitReader, BitWriter},
+    error::{Error, ErrorKind},
+    io::Sink,
+};
+
+pub const MIN_TABLE_LOG: u32 = 5;
+pub const MAX_TABLE_LOG: u32 = 15;
+pub const DEFAULT_TABLE_LOG: u32 = 12;
+
+/// Byte frequencies scaled to sum to `1 << table_log`, with every byte that
+/// occurs keeping a count of at least one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizedCounts {
+    table_log: u32,
+    counts: Vec<u32>,
+}
+
+impl NormalizedCounts {
+    /// Count the bytes in `data`, which can't be empty.
+    pub fn from_data(data: &[u8], table_log: u32) -> Self {
+        let mut frequencies = vec![0; SYMBOLS];
+        for &b in data {
+            frequencies[b as usize] += 1;
+        }
+        Self::from_frequencies(&frequencies, table_log)
+    }
+
+    /// Scale `frequencies` for up to 256 symbols, at least one of them
+    /// nonzero. `table_log` is clamped to [`MIN_TABLE_LOG`] and
+    /// [`MAX_TABLE_LOG`], and raised if need be so every nonzero symbol
+    /// gets a count of one at least.
+    pub fn from_frequencies(frequencies: &[u64], table_log: u32) -> Self {
+        assert!(frequencies.len() <= SYMBOLS);
+        let total = frequencies.iter().sum::<u64>();
+        assert!(total > 0, "no symbols to count");
+        let distinct = frequencies.iter().filter(|&&f| f > 0).count() as u32;
+        let table_log = table_log
+            .clamp(MIN_TABLE_LOG, MAX_TABLE_LOG)
+            .max(u32::BITS - (distinct - 1).leading_zeros());
+        let target = 1_u64 << table_log;
+
+        let mut counts = vec![0_u64; SYMBOLS];
+        for (c, &f) in counts.iter_mut().zip(frequencies) {
+            if f > 0 {
+                *c = ((f as u128 * target as u128 + total as u128 / 2) / total as u128).max(1)
+                    as u64;
+            }
+        }
+
+        // Rounding leaves the sum a little off, the largest count can
+        // usually take up the difference on its own
+        let mut sum = counts.iter().sum::<u64>();
+        while sum != target {
+            let (i, largest) = counts
+                .iter()
+                .copied()
+                .enumerate()
+                .max_by_key(|&(i, c)| (c, core::cmp::Reverse(i)))
+                .unwrap();
+            if sum < target {
+                counts[i] += target - sum;
+                sum = target;
+            } else {
+                let d = (sum - target).min(largest - 1);
+                counts[i] -= d;
+                sum -= d;
+            }
+        }
+
+        Self {
+            table_log,
+            counts: counts.into_iter().map(|c| c as u32).collect(),
+        }
+    }
+
+    pub fn table_log(&self) -> u32 {
+        self.table_log
+    }
+
+    /// Count for every byte value.
+    pub fn counts(&self) -> &[u32] {
+        &self.counts
+    }
+
+    /// Where each byte's counts start, with the total at the end.
+    pub(crate) fn cumulative(&self) -> Vec<u32> {
+        let mut starts = Vec::with_capacity(SYMBOLS + 1);
+        let mut sum = 0;
+        starts.push(0);
+        for &c in &self.counts {
+            sum += c;
+            starts.push(sum);
+        }
+        starts
+    }
+
+    /// Write the table log in 4 bits, then each count in as many bits as
+    /// the counts still to come could add up to, until they reach the total.
+    /// A zero count is followed by the number of zeros after it, 2 bits at a
+    /// time with 3 meaning there are more.
+    pub fn write<W: Sink>(&self, writer: &mut BitWriter<W>) -> Result<usize, Error> {
+        let mut written = writer.write_bits(self.table_log - MIN_TABLE_LOG, 4)?;
+        let mut remaining = 1 << self.table_log;
+        let mut s = 0;
+        while remaining > 0 {
+            let c = self.counts[s];
+            written += writer.write_bits(c, 32 - u32::leading_zeros(remaining))?;
+            remaining -= c;
+            s += 1;
+            if c == 0 {
+                let mut run = self.counts[s..].iter().take_while(|&&c| c == 0).count();
+                s += run;
+                while run >= 3 {
+                    written += writer.write_bits(3, 2)?;
+                    run -= 3;
+                }
+                written += writer.write_bits(run as u32, 2)?;
+            }
+        }
+        Ok(written)
+    }
+
+    pub fn read(reader: &mut BitReader) -> Result<Self, ErrorKind> {
+        let read = |reader: &mut BitReader, bits| {
+            reader
+                .read_bits(bits)
+                .ok_or(ErrorKind::Truncated(reader.remaining()))
+        };
+        let table_log = read(reader, 4)? + MIN_TABLE_LOG;
+        if table_log > MAX_TABLE_LOG {
+            return Err(ErrorKind::BadTable);
+        }
+        let mut counts = vec![0; SYMBOLS];
+        let mut remaining = 1 << table_log;
+        let mut s = 0;
+        while remaining > 0 {
+            if s >= SYMBOLS {
+                return Err(ErrorKind::BadTable);
+            }
+            let c = read(reader, 32 - u32::leading_zeros(remaining))?;
+            if c > remaining {
+                return Err(ErrorKind::BadTable);
+            }
+            counts[s] = c;
+            remaining -= c;
+            s += 1;
+            if c == 0 {
+                loop {
+                    let run = read(reader, 2)?;
+                    s += run as usize;
+                    if run < 3 {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(Self { table_log, counts })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize() {
+        let counts = NormalizedCounts::from_data(b"aaab", 5);
+        assert_eq!(counts.counts()[b'a' as usize], 24);
+        assert_eq!(counts.counts()[b'b' as usize], 8);
+        assert_eq!(counts.counts().iter().sum::<u32>(), 32);
+    }
+
+    #[test]
+    fn rare_symbols_keep_a_count() {
+        let mut frequencies = vec![1; SYMBOLS];
+        frequencies[0] = 1_000_000;
+        let counts = NormalizedCounts::from_frequencies(&frequencies, 8);
+        // 256 symbols need at least a table log of 8
+        assert!(counts.counts().iter().all(|&c| c == 1));
+
+        let counts = NormalizedCounts::from_frequencies(&frequencies, 12);
+        assert!(counts.counts().iter().all(|&c| c >= 1));
+        assert_eq!(counts.counts().iter().sum::<u32>(), 1 << 12);
+        assert_eq!(counts.counts()[0], (1 << 12) - 255);
+    }
+
+    #[test]
+    fn clamps_table_log() {
+        assert_eq!(
+            NormalizedCounts::from_data(b"a", 0).table_log(),
+            MIN_TABLE_LOG
+        );
+        assert_eq!(
+            NormalizedCounts::from_data(b"a", 20).table_log(),
+            MAX_TABLE_LOG
+        );
+    }
+
+    #[test]
+    fn raises_table_log() {
+        // 256 symbols don't fit in a table of 32
+        let counts = NormalizedCounts::from_frequencies(&[1; SYMBOLS], MIN_TABLE_LOG);
+        assert_eq!(counts.table_log(), 8);
+        assert!(counts.counts().iter().all(|&c| c == 1));
+
+        let mut frequencies = vec![1; 33];
+        frequencies[0] = 1000;
+        let counts = NormalizedCounts::from_frequencies(&frequencies, MIN_TABLE_LOG);
+        assert_eq!(counts.table_log(), 6);
+        assert_eq!(counts.counts().iter().sum::<u32>(), 64);
+        assert_eq!(counts.counts()[0], 32);
+    }
+
+    #[test]
+    fn header() {
+        let counts = NormalizedCounts::from_data(b"aaab", 5);
+        let mut out = Vec::new();
+        let mut w = BitWriter::new(&mut out);
+        counts.write(&mut w).unwrap();
+        w.flush().unwrap();
+        // Table log 5, a zero for byte 0 followed by 96 more zeros, then 24
+        // for 'a' in 6 bits and 8 for 'b' in 4 bits
+        let mut expected = Vec::new();
+        let mut w = BitWriter::new(&mut expected);
+        w.write_bits(0, 4).unwrap();
+        w.write_bits(0, 6).unwrap();
+        for _ in 0..32 {
+            w.write_bits(3, 2).unwrap();
+        }
+        w.write_bits(0, 2).unwrap();
+        w.write_bits(24, 6).unwrap();
+        w.write_bits(8, 4).unwrap();
+        w.flush().unwrap();
+        assert_eq!(out, expected);
+        // Much smaller than a full table
+        assert_eq!(out.len(), 11);
+
+        let mut r = BitReader::new(&out);
+        assert_eq!(NormalizedCounts::read(&mut r), Ok(counts));
+    }
+
+    #[test]
+    fn bad_header() {
+        // A count larger than what's left
+        let mut out = Vec::new();
+        let mut w = BitWriter::new(&mut out);
+        w.write_bits(0, 4).unwrap();
+        w.write_bits(33, 6).unwrap();
+        w.flush().unwrap();
+        let mut r = BitReader::new(&out);
+        assert_eq!(NormalizedCounts::read(&mut r), Err(ErrorKind::BadTable));
+
+        // Counts that never add up
+        let mut r = BitReader::new(&[0x00, 0x10]);
+        assert!(matches!(
+            NormalizedCounts::read(&mut r),
+            Err(ErrorKind::Truncated(_))
+        ));
+    }
+}
@@ -0,0 +1,59 @@
+//! Asymmetric numeral systems: [`rans_compress`] is an interleaved rANS
+//! coder and [`tans_compress`] a table driven tANS (FSE) coder. Both work on
+//! whole buffers with static frequencies, stored up front as
+//! [`NormalizedCounts`].
+
+mod counts;
+mod rans;
+mod tans;
+
+pub use self::{
+    counts::{NormalizedCounts, DEFAULT_TABLE_LOG, MAX_TABLE_LOG, MIN_TABLE_LOG},
+    rans::{rans_compress, rans_compress_bound, rans_decompress},
+    tans::{tans_compress, tans_compress_bound, tans_decompress},
+};
+
+use crate::{
+    bits::{BitReader, BitWriter},
+    error::{Error, ErrorKind},
+    io::Sink,
+};
+
+/// Byte alphabet.
+const SYMBOLS: usize = 256;
+
+/// Decoded bytes are passed on to the sink in chunks of this size.
+const CHUNK: usize = 4096;
+
+/// Write the number of symbols as a 6 bit width followed by the value.
+fn write_length<W: Sink>(writer: &mut BitWriter<W>, len: u64) -> Result<usize, Error> {
+    let bits = 64 - len.leading_zeros();
+    let mut written = writer.write_bits(bits, 6)?;
+    written += writer.write_bits((len >> 32) as u32, bits.saturating_sub(32))?;
+    written += writer.write_bits(len as u32, bits.min(32))?;
+    Ok(written)
+}
+
+/// Most bits [`write_length`] and [`NormalizedCounts::write`] take for
+/// `len` bytes. The table log is always [`DEFAULT_TABLE_LOG`], so no count
+/// takes more than 13 bits, or 15 with the run of zeros after it.
+const fn header_bound(len: usize) -> u64 {
+    let length = 6 + (u64::BITS - (len as u64).leading_zeros()) as u64;
+    if len == 0 {
+        return length;
+    }
+    let count = u64::BITS - (1_u64 << DEFAULT_TABLE_LOG).leading_zeros();
+    length + 4 + SYMBOLS as u64 * (count as u64 + 2)
+}
+
+fn read_length(reader: &mut BitReader) -> Result<u64, ErrorKind> {
+    let truncated = |r: &BitReader| ErrorKind::Truncated(r.remaining());
+    let bits = reader.read_bits(6).ok_or_else(|| truncated(reader))?;
+    let high = reader
+        .read_bits(bits.saturating_sub(32))
+        .ok_or_else(|| truncated(reader))?;
+    let low = reader
+        .read_bits(bits.min(32))
+        .ok_or_else(|| truncated(reader))?;
+    Ok((high as u64) << 32 | low as u64)
+}
@@ -0,0 +1,246 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    ans::{header_bound, read_length, write_length, NormalizedCounts, CHUNK, DEFAULT_TABLE_LOG},
+    bits::{BitReader, BitWriter},
+    error::{Error, ErrorKind},
+    io::Sink,
+    options::DecompressOptions,
+};
+
+/// Name reported in errors from the rANS codec.
+const CODEC: &str = "rans";
+
+/// Lower bound of the normalized state. States stay in `[L, L << 8)` and
+/// move a byte at a time.
+const L: u32 = 1 << 23;
+/// Symbols take turns between this many states, so decoding one doesn't
+/// have to wait on the one before.
+const STATES: usize = 4;
+
+/// Compress `data` with an interleaved rANS coder. Writes the length, the
+/// [`NormalizedCounts`], the final encoder states and then the coded bytes.
+pub fn rans_compress<W: Sink>(data: &[u8], writer: W) -> Result<usize, Error> {
+    let mut writer = BitWriter::new(writer);
+    let mut written = write_length(&mut writer, data.len() as u64)?;
+    if data.is_empty() {
+        return Ok(written + writer.flush()?);
+    }
+
+    let counts = NormalizedCounts::from_data(data, DEFAULT_TABLE_LOG);
+    let table_log = counts.table_log();
+    let starts = counts.cumulative();
+    written += counts.write(&mut writer)?;
+
+    // The decoder reads in the opposite order, so encode backwards and
+    // reverse the bytes
+    let mut states = [L; STATES];
+    let mut stack = Vec::new();
+    for (i, &b) in data.iter().enumerate().rev() {
+        let x = &mut states[i % STATES];
+        let f = counts.counts()[b as usize];
+        let x_max = ((L >> table_log) << 8) * f;
+        while *x >= x_max {
+            stack.push(*x as u8);
+            *x >>= 8;
+        }
+        *x = ((*x / f) << table_log) + *x % f + starts[b as usize];
+    }
+
+    for x in states {
+        written += writer.write_bits(x, 32)?;
+    }
+    for &b in stack.iter().rev() {
+        written += writer.write_bits(b as u32, 8)?;
+    }
+    Ok(written + writer.flush()?)
+}
+
+/// Worst case size of `len` bytes compressed by [`rans_compress`]. Each
+/// symbol grows a state by at most `table_log` bits, plus under 1/1024 of
+/// a bit from rounding, and states shed a byte for every 8 bits they grow.
+pub const fn rans_compress_bound(len: usize) -> usize {
+    let mut bits = header_bound(len);
+    if len > 0 {
+        let n = len as u64;
+        let growth = DEFAULT_TABLE_LOG as u64 * n + n.div_ceil(1024);
+        bits += STATES as u64 * 32 + growth / 8 * 8;
+    }
+    bits.div_ceil(8) as usize
+}
+
+/// Decompress a buffer from [`rans_compress`], returning how many bytes went
+/// out to `writer`.
+pub fn rans_decompress<W: Sink>(
+    data: &[u8],
+    mut writer: W,
+    options: DecompressOptions,
+) -> Result<usize, Error> {
+    let mut reader = BitReader::new(data);
+    let mut produced = 0;
+    let error = |kind, reader: &BitReader, produced: usize| {
+        Error::stream(CODEC, kind, reader.position() as u64, produced as u64)
+    };
+
+    let len = read_length(&mut reader).map_err(|k| error(k, &reader, 0))?;
+    options
+        .check_output(data.len() as u64, 0, len)
+        .map_err(|l| error(ErrorKind::LimitExceeded(l), &reader, 0))?;
+    if len == 0 {
+        return Ok(0);
+    }
+
+    let counts = NormalizedCounts::read(&mut reader).map_err(|k| error(k, &reader, 0))?;
+    let table_log = counts.table_log();
+    let mask = (1 << table_log) - 1;
+    let starts = counts.cumulative();
+    let mut slots = vec![0_u8; 1 << table_log];
+    for s in 0..counts.counts().len() {
+        slots[starts[s] as usize..starts[s + 1] as usize].fill(s as u8);
+    }
+
+    let mut states = [0; STATES];
+    for x in &mut states {
+        *x = reader
+            .read_bits(32)
+            .ok_or_else(|| error(ErrorKind::Truncated(reader.remaining()), &reader, 0))?;
+    }
+
+    let mut output = Vec::with_capacity(CHUNK);
+    for i in 0..len {
+        let x = &mut states[i as usize % STATES];
+        let slot = *x & mask;
+        let s = slots[slot as usize] as usize;
+        *x = counts.counts()[s]
+            .wrapping_mul(*x >> table_log)
+            .wrapping_add(slot - starts[s]);
+        while *x < L {
+            let b = reader.read_bits(8).ok_or_else(|| {
+                error(ErrorKind::Truncated(reader.remaining()), &reader, produced)
+            })?;
+            *x = *x << 8 | b;
+        }
+        output.push(s as u8);
+        if output.len() == CHUNK {
+            writer.write_all(&output)?;
+            produced += output.len();
+            output.clear();
+        }
+    }
+    writer.write_all(&output)?;
+    produced += output.len();
+
+    // Decoding ends where encoding started
+    if let Some(&x) = states.iter().find(|&&x| x != L) {
+        return Err(error(ErrorKind::BadCode(x), &reader, produced));
+    }
+    Ok(produced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{io::SliceSink, options::Limit, utils::noise};
+
+    #[test]
+    fn bound() {
+        assert_eq!(rans_compress_bound(0), 1);
+        // Every byte but one once, which leaves them a count of one each
+        let mut rare = (0..=255).collect::<Vec<u8>>();
+        rare.resize(100_000, 0);
+        for data in [noise(100_000), noise(10), vec![9], rare] {
+            let mut out = vec![0; rans_compress_bound(data.len())];
+            rans_compress(&data, SliceSink::new(&mut out)).unwrap();
+        }
+    }
+
+    fn roundtrip(data: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        let written = rans_compress(data, &mut compressed).unwrap();
+        assert_eq!(written, compressed.len());
+        let mut uncompressed = Vec::new();
+        let produced =
+            rans_decompress(&compressed, &mut uncompressed, DecompressOptions::new()).unwrap();
+        assert_eq!(produced, data.len());
+        assert_eq!(uncompressed, data);
+        compressed
+    }
+
+    #[test]
+    fn empty() {
+        // A width of zero and nothing else
+        assert_eq!(roundtrip(b""), [0]);
+    }
+
+    #[test]
+    fn one_symbol() {
+        // The only symbol takes the whole table and costs nothing
+        let compressed = roundtrip(&[7; 1000]);
+        assert_eq!(compressed.len(), 23);
+    }
+
+    #[test]
+    fn skewed() {
+        let data = (0..10_000)
+            .map(|i| if i % 10 == 0 { b'b' } else { b'a' })
+            .collect::<Vec<_>>();
+        let compressed = roundtrip(&data);
+        // About 0.47 bits a byte
+        assert!(compressed.len() < 650, "{}", compressed.len());
+    }
+
+    #[test]
+    fn all_bytes() {
+        let data = (0..=255).cycle().take(5000).collect::<Vec<u8>>();
+        roundtrip(&data);
+        roundtrip(b"abc");
+    }
+
+    #[test]
+    fn truncated() {
+        let data = b"abracadabra".repeat(20);
+        let mut compressed = Vec::new();
+        rans_compress(&data, &mut compressed).unwrap();
+        let mut uncompressed = Vec::new();
+        let e = rans_decompress(
+            &compressed[..compressed.len() - 10],
+            &mut uncompressed,
+            DecompressOptions::new(),
+        )
+        .unwrap_err();
+        assert!(matches!(e.kind(), Some(ErrorKind::Truncated(_))));
+        assert_eq!(e.codec(), Some("rans"));
+    }
+
+    #[test]
+    fn corrupt() {
+        let data = b"abracadabra".repeat(20);
+        let mut compressed = Vec::new();
+        rans_compress(&data, &mut compressed).unwrap();
+        let middle = compressed.len() / 2;
+        compressed[middle] ^= 0x10;
+        let mut uncompressed = Vec::new();
+        let e =
+            rans_decompress(&compressed, &mut uncompressed, DecompressOptions::new()).unwrap_err();
+        // Decoding goes off the rails, either running out of bytes or
+        // ending in the wrong state
+        assert!(matches!(
+            e.kind(),
+            Some(ErrorKind::BadCode(_) | ErrorKind::Truncated(_))
+        ));
+    }
+
+    #[test]
+    fn max_output_bytes() {
+        let mut compressed = Vec::new();
+        rans_compress(b"aaa", &mut compressed).unwrap();
+        let mut uncompressed = Vec::new();
+        let options = DecompressOptions::new().max_output_bytes(2);
+        let e = rans_decompress(&compressed, &mut uncompressed, options).unwrap_err();
+        assert_eq!(
+            e.kind(),
+            Some(ErrorKind::LimitExceeded(Limit::OutputBytes(2)))
+        );
+        assert!(uncompressed.is_empty());
+    }
+}
@@ -0,0 +1,295 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    ans::{header_bound, read_length, write_length, NormalizedCounts, CHUNK, DEFAULT_TABLE_LOG},
+    bits::{BitReader, BitWriter},
+    error::{Error, ErrorKind},
+    io::Sink,
+    options::DecompressOptions,
+};
+
+/// Name reported in errors from the tANS codec.
+const CODEC: &str = "tans";
+
+/// Spread the symbols over the table, each one `count` times, stepping by
+/// a stride that visits every slot.
+fn spread(counts: &NormalizedCounts) -> Vec<u8> {
+    let size = 1 << counts.table_log();
+    let step = (size >> 1) + (size >> 3) + 3;
+    let mut table = vec![0; size];
+    let mut position = 0;
+    for (s, &c) in counts.counts().iter().enumerate() {
+        for _ in 0..c {
+            table[position] = s as u8;
+            position = (position + step) & (size - 1);
+        }
+    }
+    table
+}
+
+/// One decoding step, for a state in `[size, 2 * size)`.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    symbol: u8,
+    bits: u8,
+    /// The next state, before adding the bits read.
+    base: u32,
+}
+
+/// Build the decoding table. Each symbol's slots are numbered from its
+/// count up, and a slot numbered `x` leads to `x` shifted back up to the
+/// table size.
+fn decode_table(counts: &NormalizedCounts) -> Vec<Entry> {
+    let table_log = counts.table_log();
+    let mut next = counts.counts().to_vec();
+    spread(counts)
+        .into_iter()
+        .map(|symbol| {
+            let x = next[symbol as usize];
+            next[symbol as usize] += 1;
+            let bits = table_log - (31 - x.leading_zeros());
+            Entry {
+                symbol,
+                bits: bits as u8,
+                base: x << bits,
+            }
+        })
+        .collect()
+}
+
+/// Build the encoding table, the inverse of [`decode_table`]: for each
+/// symbol and each slot number `x` from its count up, the state that
+/// decodes to it.
+fn encode_table(counts: &NormalizedCounts) -> Vec<u32> {
+    let size = 1 << counts.table_log();
+    let starts = counts.cumulative();
+    let mut next = starts.clone();
+    let mut table = vec![0; size];
+    for (u, symbol) in spread(counts).into_iter().enumerate() {
+        let s = symbol as usize;
+        table[next[s] as usize] = (size + u) as u32;
+        next[s] += 1;
+    }
+    table
+}
+
+/// Compress `data` with a tANS (FSE) coder. Writes the length, the
+/// [`NormalizedCounts`], the final encoder state and then the bits.
+pub fn tans_compress<W: Sink>(data: &[u8], writer: W) -> Result<usize, Error> {
+    let mut writer = BitWriter::new(writer);
+    let mut written = write_length(&mut writer, data.len() as u64)?;
+    if data.is_empty() {
+        return Ok(written + writer.flush()?);
+    }
+
+    let counts = NormalizedCounts::from_data(data, DEFAULT_TABLE_LOG);
+    let table_log = counts.table_log();
+    let starts = counts.cumulative();
+    let table = encode_table(&counts);
+    written += counts.write(&mut writer)?;
+
+    // The decoder reads in the opposite order, so encode backwards and
+    // reverse the bits
+    let size = 1 << table_log;
+    let mut x = size;
+    let mut stack = Vec::new();
+    for &b in data.iter().rev() {
+        let c = counts.counts()[b as usize];
+        // Shift the state down into `[c, 2 * c)`
+        let mut bits = table_log - (31 - c.leading_zeros());
+        if x >> bits < c {
+            bits -= 1;
+        }
+        stack.push((x & ((1 << bits) - 1), bits));
+        x = table[(starts[b as usize] + (x >> bits) - c) as usize];
+    }
+
+    written += writer.write_bits(x - size, table_log)?;
+    for &(v, bits) in stack.iter().rev() {
+        written += writer.write_bits(v, bits)?;
+    }
+    Ok(written + writer.flush()?)
+}
+
+/// Worst case size of `len` bytes compressed by [`tans_compress`]. No
+/// symbol takes more than `table_log` bits, and neither does the final
+/// state.
+pub const fn tans_compress_bound(len: usize) -> usize {
+    let mut bits = header_bound(len);
+    if len > 0 {
+        bits += DEFAULT_TABLE_LOG as u64 * (len as u64 + 1);
+    }
+    bits.div_ceil(8) as usize
+}
+
+/// Decompress a buffer from [`tans_compress`], returning how many bytes went
+/// out to `writer`.
+pub fn tans_decompress<W: Sink>(
+    data: &[u8],
+    mut writer: W,
+    options: DecompressOptions,
+) -> Result<usize, Error> {
+    let mut reader = BitReader::new(data);
+    let mut produced = 0;
+    let error = |kind, reader: &BitReader, produced: usize| {
+        Error::stream(CODEC, kind, reader.position() as u64, produced as u64)
+    };
+
+    let len = read_length(&mut reader).map_err(|k| error(k, &reader, 0))?;
+    options
+        .check_output(data.len() as u64, 0, len)
+        .map_err(|l| error(ErrorKind::LimitExceeded(l), &reader, 0))?;
+    if len == 0 {
+        return Ok(0);
+    }
+
+    let counts = NormalizedCounts::read(&mut reader).map_err(|k| error(k, &reader, 0))?;
+    let table_log = counts.table_log();
+    let size = 1 << table_log;
+    let table = decode_table(&counts);
+
+    let mut x = reader
+        .read_bits(table_log)
+        .ok_or_else(|| error(ErrorKind::Truncated(reader.remaining()), &reader, 0))?
+        + size;
+    let mut output = Vec::with_capacity(CHUNK);
+    for _ in 0..len {
+        let entry = table[(x - size) as usize];
+        let v = reader
+            .read_bits(entry.bits as u32)
+            .ok_or_else(|| error(ErrorKind::Truncated(reader.remaining()), &reader, produced))?;
+        x = entry.base + v;
+        output.push(entry.symbol);
+        if output.len() == CHUNK {
+            writer.write_all(&output)?;
+            produced += output.len();
+            output.clear();
+        }
+    }
+    writer.write_all(&output)?;
+    produced += output.len();
+
+    // Decoding ends where encoding started
+    if x != size {
+        return Err(error(ErrorKind::BadCode(x), &reader, produced));
+    }
+    Ok(produced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{io::SliceSink, options::Limit, utils::noise};
+
+    #[test]
+    fn bound() {
+        assert_eq!(tans_compress_bound(0), 1);
+        // Every byte but one once, which leaves them a count of one each
+        let mut rare = (0..=255).collect::<Vec<u8>>();
+        rare.resize(100_000, 0);
+        for data in [noise(100_000), noise(10), vec![9], rare] {
+            let mut out = vec![0; tans_compress_bound(data.len())];
+            tans_compress(&data, SliceSink::new(&mut out)).unwrap();
+        }
+    }
+
+    fn roundtrip(data: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        let written = tans_compress(data, &mut compressed).unwrap();
+        assert_eq!(written, compressed.len());
+        let mut uncompressed = Vec::new();
+        let produced =
+            tans_decompress(&compressed, &mut uncompressed, DecompressOptions::new()).unwrap();
+        assert_eq!(produced, data.len());
+        assert_eq!(uncompressed, data);
+        compressed
+    }
+
+    #[test]
+    fn tables() {
+        let counts = NormalizedCounts::from_data(b"aaab", 5);
+        let decode = decode_table(&counts);
+        let encode = encode_table(&counts);
+        // Every state decodes to the symbol and slot it was encoded from
+        for (s, first) in [(b'a', 24), (b'b', 8)] {
+            let start = counts.cumulative()[s as usize];
+            for x in first..2 * first {
+                let state = encode[(start + x - first) as usize];
+                let entry = decode[state as usize - 32];
+                assert_eq!(entry.symbol, s);
+                assert_eq!(entry.base >> entry.bits, x);
+                assert!((32..64).contains(&entry.base));
+            }
+        }
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(roundtrip(b""), [0]);
+    }
+
+    #[test]
+    fn one_symbol() {
+        let compressed = roundtrip(&[7; 1000]);
+        assert_eq!(compressed.len(), 8);
+    }
+
+    #[test]
+    fn skewed() {
+        let data = (0..10_000)
+            .map(|i| if i % 10 == 0 { b'b' } else { b'a' })
+            .collect::<Vec<_>>();
+        let compressed = roundtrip(&data);
+        assert!(compressed.len() < 650, "{}", compressed.len());
+    }
+
+    #[test]
+    fn all_bytes() {
+        let data = (0..=255).cycle().take(5000).collect::<Vec<u8>>();
+        roundtrip(&data);
+        roundtrip(b"abc");
+    }
+
+    #[test]
+    fn truncated() {
+        let data = b"abracadabra".repeat(20);
+        let mut compressed = Vec::new();
+        tans_compress(&data, &mut compressed).unwrap();
+        let mut uncompressed = Vec::new();
+        let e = tans_decompress(
+            &compressed[..compressed.len() - 10],
+            &mut uncompressed,
+            DecompressOptions::new(),
+        )
+        .unwrap_err();
+        assert!(matches!(e.kind(), Some(ErrorKind::Truncated(_))));
+        assert_eq!(e.codec(), Some("tans"));
+    }
+
+    #[test]
+    fn corrupt() {
+        let data = b"abracadabra".repeat(20);
+        let mut compressed = Vec::new();
+        tans_compress(&data, &mut compressed).unwrap();
+        let middle = compressed.len() / 2;
+        compressed[middle] ^= 0x10;
+        let mut uncompressed = Vec::new();
+        let e =
+            tans_decompress(&compressed, &mut uncompressed, DecompressOptions::new()).unwrap_err();
+        assert!(matches!(e.kind(), Some(ErrorKind::BadCode(_))));
+    }
+
+    #[test]
+    fn max_output_bytes() {
+        let mut compressed = Vec::new();
+        tans_compress(b"aaa", &mut compressed).unwrap();
+        let mut uncompressed = Vec::new();
+        let options = DecompressOptions::new().max_output_bytes(2);
+        let e = tans_decompress(&compressed, &mut uncompressed, options).unwrap_err();
+        assert_eq!(
+            e.kind(),
+            Some(ErrorKind::LimitExceeded(Limit::OutputBytes(2)))
+        );
+        assert!(uncompressed.is_empty());
+    }
+}
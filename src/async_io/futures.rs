@@ -0,0 +1,106 @@
+//! The `futures-io` traits for the adapters.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    async_io::{AsyncReader, AsyncWriter},
+    stream::Detachable,
+};
+
+fn write<W: AsyncWrite + Unpin>(
+    inner: &mut W,
+    cx: &mut Context<'_>,
+    data: &[u8],
+) -> Poll<io::Result<usize>> {
+    Pin::new(inner).poll_write(cx, data)
+}
+
+impl<W: AsyncWrite + Unpin, C: Detachable + Unpin> AsyncWrite for AsyncWriter<W, C> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().poll_push(cx, data, write)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx, write))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_finish(cx, write))?;
+        Pin::new(&mut this.inner).poll_close(cx)
+    }
+}
+
+impl<R: AsyncRead + Unpin, C: Detachable + Unpin> AsyncRead for AsyncReader<R, C> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().poll_fill(cx, buf, |inner, cx, input| {
+            Pin::new(inner).poll_read(cx, input)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{
+        executor::block_on,
+        io::{AsyncReadExt, AsyncWriteExt, Cursor},
+    };
+
+    use super::*;
+    use crate::{
+        range_coder::{RangeCompressor, RangeDecompressor},
+        DecompressOptions,
+    };
+
+    #[test]
+    fn round_trip() {
+        let data = b"abracadabra ".repeat(500);
+        block_on(async {
+            let mut w = AsyncWriter::new(Cursor::new(Vec::new()), RangeCompressor::new);
+            for chunk in data.chunks(33) {
+                w.write_all(chunk).await.unwrap();
+            }
+            w.close().await.unwrap();
+            let compressed = w.into_inner().into_inner();
+
+            let mut r = AsyncReader::new(&compressed[..], RangeDecompressor::new);
+            let mut uncompressed = Vec::new();
+            r.read_to_end(&mut uncompressed).await.unwrap();
+            assert!(uncompressed == data);
+        });
+    }
+
+    #[test]
+    fn limits() {
+        let data = vec![0; 10_000];
+        block_on(async {
+            let mut w = AsyncWriter::new(Vec::new(), RangeCompressor::new);
+            w.write_all(&data).await.unwrap();
+            w.close().await.unwrap();
+            let compressed = w.into_inner();
+
+            let options = DecompressOptions::new().max_output_bytes(1000);
+            let mut r = AsyncReader::new(&compressed[..], |s| {
+                RangeDecompressor::with_options(s, options)
+            });
+            let e = r.read_to_end(&mut Vec::new()).await.unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        });
+    }
+}
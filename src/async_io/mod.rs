@@ -0,0 +1,222 @@
+//! Async adapters for the codecs, behind the `async` feature.
+//!
+//! [`AsyncWriter`] passes everything written to it through a codec to an
+//! inner writer, and [`AsyncReader`] passes everything read from an inner
+//! reader through a codec. Either runs a [`Stream`] over a buffer of its
+//! own, so takes any [`Detachable`] codec, compressor or decompressor, built
+//! over the [`Held`] the adapter hands it. Both implement the tokio and the
+//! `futures-io` traits:
+//!
+//! ```
+//! use kompression::{async_io::{AsyncReader, AsyncWriter}, LZ78Compressor, LZ78Decompressor};
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//!
+//! # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+//! let mut w = AsyncWriter::new(Vec::new(), LZ78Compressor::new);
+//! w.write_all(b"banana bandana").await?;
+//! // Finalizes the compressor
+//! w.shutdown().await?;
+//! let compressed = w.into_inner();
+//!
+//! let mut r = AsyncReader::new(&compressed[..], LZ78Decompressor::new);
+//! let mut uncompressed = Vec::new();
+//! r.read_to_end(&mut uncompressed).await?;
+//! assert_eq!(uncompressed, b"banana bandana");
+//! # std::io::Result::Ok(())
+//! # }).unwrap();
+//! ```
+
+mod futures;
+mod tokio;
+
+use std::{
+    io,
+    task::{ready, Context, Poll},
+};
+
+use crate::stream::{Detachable, Held, Status, Stream};
+
+/// How much an [`AsyncReader`] reads from its reader at a time.
+const READ_SIZE: usize = 8192;
+
+/// How much output an [`AsyncWriter`] gathers before writing it on.
+const WRITE_SIZE: usize = 8192;
+
+/// Writes through a codec to `W`.
+///
+/// Flushing passes on whatever the codec has let out so far, which may not
+/// be everything written: most codecs hold some input back until they
+/// finish. Shutting down finalizes the codec, then shuts down `W`. Writing
+/// after that fails.
+#[derive(Debug)]
+pub struct AsyncWriter<W, C> {
+    inner: W,
+    stream: Stream<C>,
+    output: Box<[u8]>,
+    /// The part of `output` filled but not yet written on.
+    start: usize,
+    end: usize,
+}
+
+impl<W, C: Detachable> AsyncWriter<W, C> {
+    /// Write to `inner` through the codec `make` builds.
+    pub fn new(inner: W, make: impl FnOnce(Held) -> C) -> Self {
+        Self {
+            inner,
+            stream: Stream::new(make),
+            output: vec![0; WRITE_SIZE].into_boxed_slice(),
+            start: 0,
+            end: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// The inner writer. Anything not yet flushed is lost.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Pass on the filled part of the output buffer to `W` with `write`.
+    fn poll_write_out(
+        &mut self,
+        cx: &mut Context<'_>,
+        write: &mut impl FnMut(&mut W, &mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    ) -> Poll<io::Result<()>> {
+        while self.start < self.end {
+            match ready!(write(
+                &mut self.inner,
+                cx,
+                &self.output[self.start..self.end]
+            ))? {
+                0 => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                n => self.start += n,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Pass on all output the codec has let out so far.
+    fn poll_drain(
+        &mut self,
+        cx: &mut Context<'_>,
+        mut write: impl FnMut(&mut W, &mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            ready!(self.poll_write_out(cx, &mut write))?;
+            if self.stream.pending().is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+            let (_, produced, _) = self.stream.process(&[], &mut self.output)?;
+            (self.start, self.end) = (0, produced);
+        }
+    }
+
+    /// Push `data` through the codec into the output buffer once it's been
+    /// passed on.
+    fn poll_push(
+        &mut self,
+        cx: &mut Context<'_>,
+        data: &[u8],
+        mut write: impl FnMut(&mut W, &mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            ready!(self.poll_write_out(cx, &mut write))?;
+            let (consumed, produced, _) = self.stream.process(data, &mut self.output)?;
+            (self.start, self.end) = (0, produced);
+            if consumed > 0 || data.is_empty() {
+                return Poll::Ready(Ok(consumed));
+            }
+        }
+    }
+
+    /// Finalize the codec and pass on the rest of its output.
+    fn poll_finish(
+        &mut self,
+        cx: &mut Context<'_>,
+        mut write: impl FnMut(&mut W, &mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            ready!(self.poll_write_out(cx, &mut write))?;
+            if self.stream.status() == Status::Done {
+                return Poll::Ready(Ok(()));
+            }
+            let (produced, _) = self.stream.finish(&mut self.output)?;
+            (self.start, self.end) = (0, produced);
+        }
+    }
+}
+
+/// Reads through a codec from `R`. The codec is finalized when `R` runs out.
+#[derive(Debug)]
+pub struct AsyncReader<R, C> {
+    inner: R,
+    stream: Stream<C>,
+    input: Box<[u8]>,
+    /// The part of `input` read but not yet pushed through.
+    start: usize,
+    end: usize,
+}
+
+impl<R, C: Detachable> AsyncReader<R, C> {
+    /// Read from `inner` through the codec `make` builds.
+    pub fn new(inner: R, make: impl FnOnce(Held) -> C) -> Self {
+        Self {
+            inner,
+            stream: Stream::new(make),
+            input: vec![0; READ_SIZE].into_boxed_slice(),
+            start: 0,
+            end: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Fill `output` from the codec, reading more from `R` with `read` as
+    /// needed. Returns how many bytes were filled, `0` at the end.
+    fn poll_fill(
+        &mut self,
+        cx: &mut Context<'_>,
+        output: &mut [u8],
+        mut read: impl FnMut(&mut R, &mut Context<'_>, &mut [u8]) -> Poll<io::Result<usize>>,
+    ) -> Poll<io::Result<usize>> {
+        if output.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        loop {
+            let (produced, status) =
+                if self.start == self.end && self.stream.status() == Status::NeedInput {
+                    self.start = 0;
+                    self.end = ready!(read(&mut self.inner, cx, &mut self.input))?;
+                    if self.end > 0 {
+                        continue;
+                    }
+                    self.stream.finish(output)?
+                } else {
+                    let input = &self.input[self.start..self.end];
+                    let (consumed, produced, status) = self.stream.process(input, output)?;
+                    self.start += consumed;
+                    (produced, status)
+                };
+            if produced > 0 || status == Status::Done {
+                return Poll::Ready(Ok(produced));
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! The tokio traits for the adapters.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{
+    async_io::{AsyncReader, AsyncWriter},
+    stream::Detachable,
+};
+
+fn write<W: AsyncWrite + Unpin>(
+    inner: &mut W,
+    cx: &mut Context<'_>,
+    data: &[u8],
+) -> Poll<io::Result<usize>> {
+    Pin::new(inner).poll_write(cx, data)
+}
+
+impl<W: AsyncWrite + Unpin, C: Detachable + Unpin> AsyncWrite for AsyncWriter<W, C> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().poll_push(cx, data, write)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx, write))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_finish(cx, write))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+impl<R: AsyncRead + Unpin, C: Detachable + Unpin> AsyncRead for AsyncReader<R, C> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let n =
+            ready!(self
+                .get_mut()
+                .poll_fill(cx, buf.initialize_unfilled(), |inner, cx, input| {
+                    let mut input = ReadBuf::new(input);
+                    ready!(Pin::new(inner).poll_read(cx, &mut input))?;
+                    Poll::Ready(Ok(input.filled().len()))
+                }))?;
+        buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::{
+        bzip2::{Bzip2Compressor, Bzip2Decompressor},
+        error::{Error, ErrorKind},
+        huffman::{HuffmanCompressor, HuffmanDecompressor},
+        LZ78Compressor, LZ78Decompressor,
+    };
+
+    const DATA: &[u8] =
+        b"She sells sea shells by the sea shore, the shells she sells are sea shells for sure. ";
+
+    #[tokio::test]
+    async fn round_trip() {
+        let data = DATA.repeat(200);
+        let mut w = AsyncWriter::new(Vec::new(), LZ78Compressor::new);
+        for chunk in data.chunks(100) {
+            w.write_all(chunk).await.unwrap();
+        }
+        w.shutdown().await.unwrap();
+        let compressed = w.into_inner();
+
+        let mut expected = Vec::new();
+        let mut c = LZ78Compressor::new(&mut expected);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+        assert_eq!(compressed, expected);
+
+        let mut r = AsyncReader::new(&compressed[..], LZ78Decompressor::new);
+        let mut uncompressed = Vec::new();
+        r.read_to_end(&mut uncompressed).await.unwrap();
+        assert!(uncompressed == data);
+    }
+
+    #[tokio::test]
+    async fn small_reads() {
+        let data = vec![b'a'; 10_000];
+        let mut compressed = Vec::new();
+        let mut c = LZ78Compressor::new(&mut compressed);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+
+        let mut r = AsyncReader::new(&compressed[..], LZ78Decompressor::new);
+        let mut uncompressed = Vec::new();
+        let mut buffer = [0; 5];
+        loop {
+            match r.read(&mut buffer).await.unwrap() {
+                0 => break,
+                n => uncompressed.extend_from_slice(&buffer[..n]),
+            }
+        }
+        assert!(uncompressed == data);
+    }
+
+    #[tokio::test]
+    async fn both_directions() {
+        // Compress on the way in, decompress on the way out
+        let data = DATA.repeat(50);
+        let mut r = AsyncReader::new(&data[..], Bzip2Compressor::new);
+        let mut w = AsyncWriter::new(Vec::new(), Bzip2Decompressor::new);
+        tokio::io::copy(&mut r, &mut w).await.unwrap();
+        w.shutdown().await.unwrap();
+        assert!(w.into_inner() == data);
+    }
+
+    #[tokio::test]
+    async fn back_pressure() {
+        // A pipe smaller than the output, read on the other end as it fills
+        let data = DATA.repeat(100);
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut w = AsyncWriter::new(client, HuffmanCompressor::new);
+        let write = async {
+            w.write_all(&data).await.unwrap();
+            w.flush().await.unwrap();
+            w.shutdown().await.unwrap();
+        };
+        let mut compressed = Vec::new();
+        let read = server.read_to_end(&mut compressed);
+        let (_, read) = tokio::join!(write, read);
+        read.unwrap();
+
+        let mut uncompressed = Vec::new();
+        let mut r = AsyncReader::new(&compressed[..], HuffmanDecompressor::new);
+        r.read_to_end(&mut uncompressed).await.unwrap();
+        assert!(uncompressed == data);
+    }
+
+    #[tokio::test]
+    async fn after_shutdown() {
+        let mut w = AsyncWriter::new(Vec::new(), LZ78Compressor::new);
+        w.write_all(b"abc").await.unwrap();
+        w.shutdown().await.unwrap();
+        w.shutdown().await.unwrap();
+        let e = w.write_all(b"d").await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[tokio::test]
+    async fn bad_stream() {
+        let mut r = AsyncReader::new(&b"BZh9\x00"[..], Bzip2Decompressor::new);
+        let e = r.read_to_end(&mut Vec::new()).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        let e = e.into_inner().unwrap().downcast::<Error>().unwrap();
+        assert!(matches!(e.kind(), Some(ErrorKind::Truncated(_))));
+    }
+}
@@ -0,0 +1,155 @@
+//! Command line parsing. Flags follow gzip's where there's one to follow.
+
+use std::path::PathBuf;
+
+use kompression::bzip2::MAX_LEVEL;
+
+use crate::format::Algorithm;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Compress,
+    Decompress,
+    Test,
+    Info,
+    Bench,
+    Dump,
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Args {
+    pub command: Command,
+    /// Files to work on, `-` for stdin. Empty means stdin.
+    pub files: Vec<PathBuf>,
+    pub algorithm: Algorithm,
+    /// Whether the algorithm was picked rather than the default, for `bench`.
+    pub picked: bool,
+    pub stdout: bool,
+    pub keep: bool,
+    pub force: bool,
+    pub quiet: bool,
+    /// `dump` as JSON.
+    pub json: bool,
+    pub output: Option<PathBuf>,
+}
+
+/// Parse the arguments after the program name.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut args = args.into_iter();
+    let command = match args.next().as_deref() {
+        Some("compress") | Some("c") => Command::Compress,
+        Some("decompress") | Some("d") => Command::Decompress,
+        Some("test") | Some("t") => Command::Test,
+        Some("info") | Some("i") => Command::Info,
+        Some("bench") | Some("b") => Command::Bench,
+        Some("dump") => Command::Dump,
+        Some("help") | Some("-h") | Some("--help") | None => Command::Help,
+        Some(other) => return Err(format!("unknown command {:?}", other)),
+    };
+
+    let mut name = None;
+    let mut steps = None;
+    let mut level = MAX_LEVEL;
+    let mut parsed = Args {
+        command,
+        files: Vec::new(),
+        algorithm: Algorithm::Bzip2(level),
+        picked: false,
+        stdout: false,
+        keep: false,
+        force: false,
+        quiet: false,
+        json: false,
+        output: None,
+    };
+    let mut only_files = false;
+    while let Some(arg) = args.next() {
+        if only_files || arg == "-" || !arg.starts_with('-') {
+            parsed.files.push(arg.into());
+            continue;
+        }
+        let mut value = |flag: &str| args.next().ok_or_else(|| format!("{} needs a value", flag));
+        match arg.as_str() {
+            "--" => only_files = true,
+            "-a" | "--algorithm" => name = Some(value(&arg)?),
+            "-p" | "--pipeline" => steps = Some(value(&arg)?),
+            "-o" | "--output" => parsed.output = Some(value(&arg)?.into()),
+            "-c" | "--stdout" => parsed.stdout = true,
+            "-k" | "--keep" => parsed.keep = true,
+            "-f" | "--force" => parsed.force = true,
+            "-q" | "--quiet" => parsed.quiet = true,
+            "--json" => parsed.json = true,
+            "-h" | "--help" => parsed.command = Command::Help,
+            _ => match arg[1..].parse() {
+                Ok(l) if (1..=MAX_LEVEL).contains(&l) => level = l,
+                _ => return Err(format!("unknown option {:?}", arg)),
+            },
+        }
+    }
+
+    parsed.picked = name.is_some() || steps.is_some();
+    parsed.algorithm = match (name, steps) {
+        (Some(_), Some(_)) => return Err("pick --algorithm or --pipeline, not both".into()),
+        (Some(name), None) => Algorithm::from_name(&name, level)
+            .ok_or_else(|| format!("unknown algorithm {:?}", name))?,
+        (None, Some(steps)) => Algorithm::from_steps(&steps)?,
+        (None, None) => Algorithm::Bzip2(level),
+    };
+    if parsed.output.is_some() && (parsed.files.len() > 1 || parsed.stdout) {
+        return Err("--output takes one input and can't go with --stdout".into());
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(args: &str) -> Result<Args, String> {
+        parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn defaults() {
+        let args = parse_str("compress a.txt b.txt").unwrap();
+        assert_eq!(args.command, Command::Compress);
+        assert_eq!(args.files, [PathBuf::from("a.txt"), PathBuf::from("b.txt")]);
+        assert_eq!(args.algorithm, Algorithm::Bzip2(9));
+        assert!(!args.picked && !args.stdout && !args.keep && !args.force);
+        assert_eq!(parse_str("").unwrap().command, Command::Help);
+    }
+
+    #[test]
+    fn flags() {
+        let args = parse_str("c -k -c -f -q -3 -a lz78 - -- -weird").unwrap();
+        assert!(args.keep && args.stdout && args.force && args.quiet && args.picked);
+        assert_eq!(args.algorithm, Algorithm::from_name("lz78", 3).unwrap());
+        assert_eq!(args.files, [PathBuf::from("-"), PathBuf::from("-weird")]);
+
+        let args = parse_str("compress -1 x").unwrap();
+        assert_eq!(args.algorithm, Algorithm::Bzip2(1));
+        let args = parse_str("bench -a bzip2 x").unwrap();
+        assert!(args.picked);
+        let args = parse_str("dump --json x").unwrap();
+        assert!(args.command == Command::Dump && args.json);
+
+        let args = parse_str("compress --pipeline bwt,mtf,huffman -o out x").unwrap();
+        assert_eq!(
+            args.algorithm,
+            Algorithm::from_steps("bwt,mtf,huffman").unwrap()
+        );
+        assert_eq!(args.output, Some("out".into()));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse_str("squash").is_err());
+        assert!(parse_str("compress -0").is_err());
+        assert!(parse_str("compress --fast").is_err());
+        assert!(parse_str("compress -a").is_err());
+        assert!(parse_str("compress -a zip").is_err());
+        assert!(parse_str("compress -a lz78 -p mtf").is_err());
+        assert!(parse_str("compress -o out a b").is_err());
+    }
+}
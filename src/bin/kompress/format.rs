@@ -0,0 +1,381 @@
+//! The formats `kompress` writes, and how to tell them apart when reading.
+
+use std::io::{self, Read, Write};
+
+use kompression::{
+    bzip2::{Bzip2Compressor, Bzip2Decompressor, MAX_LEVEL, MIN_LEVEL},
+    io::Codec,
+    lz78::{self, Mode, Token},
+    pipeline::{
+        AdaptiveHuffman, Huffman, Lz78, Pipeline, PipelineCompressor, PipelineDecompressor,
+        RangeCoder, Step,
+    },
+    transform::{BitShuffle, Bwt, Delta, Mtf, Shuffle, Xor, ZeroRun},
+    DecompressOptions,
+};
+
+/// Bytes read from the input at a time.
+const CHUNK_SIZE: usize = 1 << 16;
+
+/// Limits on what a dump decodes. Every token holds its phrase, so without
+/// them a small file could take gigabytes to list.
+const DUMP_LIMITS: DecompressOptions = DecompressOptions {
+    max_output_bytes: Some(1 << 28),
+    max_dictionary_bytes: Some(1 << 28),
+    max_ratio: None,
+};
+
+/// Names accepted by `--algorithm`, in the order `bench` runs them.
+pub const ALGORITHMS: [&str; 7] = [
+    "bzip2",
+    "lz78",
+    "lz78-entropy",
+    "huffman",
+    "adaptive-huffman",
+    "range",
+    "bwt",
+];
+
+/// What to compress with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Algorithm {
+    /// A plain bzip2 stream, readable by `bzip2` itself.
+    Bzip2(u8),
+    /// Anything else goes in a pipeline stream, which records its steps.
+    Pipeline(Pipeline),
+}
+
+impl Algorithm {
+    /// The algorithm called `name`, bzip2 at `level`.
+    pub fn from_name(name: &str, level: u8) -> Option<Self> {
+        let pipeline = match name {
+            "bzip2" => return Some(Algorithm::Bzip2(level)),
+            "lz78" => Pipeline::new().then(Lz78),
+            "lz78-entropy" => Pipeline::new().then(Step::Lz78(Mode::Entropy)),
+            "huffman" => Pipeline::new().then(Huffman),
+            "adaptive-huffman" => Pipeline::new().then(AdaptiveHuffman),
+            "range" => Pipeline::new().then(RangeCoder),
+            "bwt" => Pipeline::new()
+                .then(Bwt)
+                .then(Mtf)
+                .then(ZeroRun)
+                .then(AdaptiveHuffman),
+            _ => return None,
+        };
+        Some(Algorithm::Pipeline(pipeline))
+    }
+
+    /// A pipeline from step names separated by commas.
+    pub fn from_steps(steps: &str) -> Result<Self, String> {
+        let mut pipeline = Pipeline::new();
+        for name in steps.split(',') {
+            if pipeline.steps().len() == kompression::pipeline::MAX_STEPS {
+                return Err(format!("too many steps in {:?}", steps));
+            }
+            let step = parse_step(name).ok_or_else(|| format!("unknown step {:?}", name))?;
+            pipeline = pipeline.then(step);
+        }
+        Ok(Algorithm::Pipeline(pipeline))
+    }
+
+    /// The suffix added to compressed file names.
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Algorithm::Bzip2(_) => "bz2",
+            Algorithm::Pipeline(_) => "kpl",
+        }
+    }
+
+    /// Compress all of `input` to `output`.
+    pub fn compress(&self, input: &mut dyn Read, output: &mut dyn Write) -> io::Result<()> {
+        match self {
+            Algorithm::Bzip2(level) => {
+                pump(Bzip2Compressor::with_level(output, *level), &[], input)
+            }
+            Algorithm::Pipeline(p) => pump(PipelineCompressor::new(output, p), &[], input),
+        }
+    }
+}
+
+/// A step from its name, with the filters taking a width after a colon, as
+/// in `delta:4`.
+fn parse_step(name: &str) -> Option<Step> {
+    let (name, width) = match name.split_once(':') {
+        Some((name, width)) => (name, Some(width.parse().ok()?)),
+        None => (name, None),
+    };
+    let width = || width.unwrap_or(1);
+    Some(match name {
+        "rle1" => Step::Rle1,
+        "mtf" => Step::Mtf,
+        "zero-run" => Step::ZeroRun,
+        "bwt" => Step::Bwt,
+        "lz78" => Step::Lz78(Mode::Raw),
+        "lz78-entropy" => Step::Lz78(Mode::Entropy),
+        "huffman" => Step::Huffman,
+        "adaptive-huffman" => Step::AdaptiveHuffman,
+        "range" => Step::RangeCoder,
+        "bzip2" => Step::Bzip2,
+        "delta" => Delta::new(width()).into(),
+        "xor" => Xor::new(width()).into(),
+        "shuffle" => Shuffle::new(width()).into(),
+        "bitshuffle" => BitShuffle::new(width()).into(),
+        _ => return None,
+    })
+}
+
+/// The name [`parse_step`] takes for `step`.
+pub fn step_name(step: &Step) -> String {
+    match step {
+        Step::Rle1 => "rle1".into(),
+        Step::Mtf => "mtf".into(),
+        Step::ZeroRun => "zero-run".into(),
+        Step::Bwt => "bwt".into(),
+        Step::Lz78(Mode::Raw) => "lz78".into(),
+        Step::Lz78(Mode::Entropy) => "lz78-entropy".into(),
+        Step::Huffman => "huffman".into(),
+        Step::AdaptiveHuffman => "adaptive-huffman".into(),
+        Step::RangeCoder => "range".into(),
+        Step::Bzip2 => "bzip2".into(),
+        Step::Delta(delta) => format!("delta:{}", delta.stride()),
+        Step::Xor(xor) => format!("xor:{}", xor.width()),
+        Step::Shuffle(shuffle) => format!("shuffle:{}", shuffle.width()),
+        Step::BitShuffle(shuffle) => format!("bitshuffle:{}", shuffle.width()),
+        step => format!("{:?}", step),
+    }
+}
+
+/// A compressed stream's format, from its first bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Bzip2,
+    Pipeline,
+}
+
+impl Format {
+    fn detect(magic: &[u8]) -> Option<Self> {
+        match magic {
+            b"BZh" => Some(Format::Bzip2),
+            b"KPL" => Some(Format::Pipeline),
+            _ => None,
+        }
+    }
+}
+
+/// Read up to `buffer.len()` bytes, stopping early only at the end.
+fn read_full(input: &mut dyn Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match input.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+fn unknown_format() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "not in a known format")
+}
+
+/// Decompress all of `input` to `output`, whichever format it's in.
+pub fn decompress(input: &mut dyn Read, output: &mut dyn Write) -> io::Result<Format> {
+    let mut magic = [0; 3];
+    let n = read_full(input, &mut magic)?;
+    let format = Format::detect(&magic[..n]).ok_or_else(unknown_format)?;
+    match format {
+        Format::Bzip2 => pump(Bzip2Decompressor::new(output), &magic, input)?,
+        Format::Pipeline => pump(PipelineDecompressor::new(output), &magic, input)?,
+    }
+    Ok(format)
+}
+
+/// What a stream's header says about it.
+pub fn describe(input: &mut dyn Read) -> io::Result<String> {
+    // Long enough for a pipeline header with every step
+    let mut header = [0; 64];
+    let n = read_full(input, &mut header)?;
+    let header = &header[..n];
+    match Format::detect(header.get(..3).unwrap_or(header)) {
+        Some(Format::Bzip2) => match header.get(3) {
+            Some(&level) if (b'0' + MIN_LEVEL..=b'0' + MAX_LEVEL).contains(&level) => {
+                let level = level - b'0';
+                Ok(format!(
+                    "bzip2, level {} ({}k blocks)",
+                    level,
+                    level as u32 * 100
+                ))
+            }
+            _ => Err(unknown_format()),
+        },
+        Some(Format::Pipeline) => {
+            let mut d = PipelineDecompressor::new(io::sink());
+            // The data after the header may be cut short, only the header
+            // has to be good
+            let read = d.read(header);
+            let pipeline = match d.pipeline() {
+                Some(pipeline) => pipeline,
+                None => return Err(read.err().map_or_else(unknown_format, Into::into)),
+            };
+            let steps = pipeline
+                .steps()
+                .iter()
+                .map(step_name)
+                .collect::<Vec<_>>()
+                .join(", ");
+            match steps.as_str() {
+                "" => Ok("pipeline, stored".into()),
+                _ => Ok(format!("pipeline: {}", steps)),
+            }
+        }
+        None => Err(unknown_format()),
+    }
+}
+
+/// Decode an LZ78 stream into its tokens. A pipeline stream has to be LZ78
+/// alone, anything else is taken as a bare stream of `algorithm`, raw
+/// LZ78 unless it was `picked`.
+pub fn inspect(data: &[u8], algorithm: &Algorithm, picked: bool) -> io::Result<Vec<Token>> {
+    let not_lz78 = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "only LZ78 streams can be dumped",
+        )
+    };
+    let (mode, stream) = if Format::detect(data.get(..3).unwrap_or(data)) == Some(Format::Pipeline)
+    {
+        // Magic, version, step count and the step
+        let header = data.len().min(6);
+        let mut d = PipelineDecompressor::new(io::sink());
+        d.read(&data[..header])?;
+        match d.pipeline().map(Pipeline::steps) {
+            Some(&[Step::Lz78(mode)]) => (mode, &data[header..]),
+            Some(_) => return Err(not_lz78()),
+            None => return Err(unknown_format()),
+        }
+    } else {
+        match algorithm {
+            Algorithm::Pipeline(p) => match p.steps() {
+                &[Step::Lz78(mode)] => (mode, data),
+                _ => return Err(not_lz78()),
+            },
+            Algorithm::Bzip2(_) if picked => return Err(not_lz78()),
+            Algorithm::Bzip2(_) => (Mode::Raw, data),
+        }
+    };
+    Ok(lz78::inspect(stream, mode, DUMP_LIMITS)?)
+}
+
+/// Push `prefix` then the rest of `input` through `codec`.
+fn pump<C: Codec>(mut codec: C, prefix: &[u8], input: &mut dyn Read) -> io::Result<()> {
+    codec.push(prefix)?;
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => codec.push(&buffer[..n])?,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+    }
+    codec.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: &[u8] = b"She sells sea shells by the sea shore. She sells sea shells. ";
+
+    fn round_trip(algorithm: &Algorithm) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        algorithm.compress(&mut &DATA[..], &mut compressed).unwrap();
+        let mut uncompressed = Vec::new();
+        decompress(&mut &compressed[..], &mut uncompressed).unwrap();
+        assert_eq!(uncompressed, DATA, "{:?}", algorithm);
+        compressed
+    }
+
+    #[test]
+    fn every_algorithm() {
+        for name in ALGORITHMS {
+            round_trip(&Algorithm::from_name(name, 9).unwrap());
+        }
+        assert_eq!(Algorithm::from_name("zip", 9), None);
+    }
+
+    #[test]
+    fn steps() {
+        let algorithm = Algorithm::from_steps("delta:4,bwt,mtf,huffman").unwrap();
+        let compressed = round_trip(&algorithm);
+        assert_eq!(
+            describe(&mut &compressed[..]).unwrap(),
+            "pipeline: delta:4, bwt, mtf, huffman"
+        );
+        assert!(Algorithm::from_steps("bwt,zip").is_err());
+        assert!(Algorithm::from_steps("delta:x").is_err());
+        assert!(Algorithm::from_steps(&["mtf"; 17].join(",")).is_err());
+    }
+
+    #[test]
+    fn step_names() {
+        for name in [
+            "rle1",
+            "mtf",
+            "zero-run",
+            "bwt",
+            "lz78",
+            "lz78-entropy",
+            "huffman",
+            "adaptive-huffman",
+            "range",
+            "bzip2",
+            "delta:2",
+            "xor:8",
+            "shuffle:4",
+            "bitshuffle:1",
+        ] {
+            assert_eq!(step_name(&parse_step(name).unwrap()), name);
+        }
+    }
+
+    #[test]
+    fn describe_bzip2() {
+        let compressed = round_trip(&Algorithm::Bzip2(3));
+        assert_eq!(
+            describe(&mut &compressed[..]).unwrap(),
+            "bzip2, level 3 (300k blocks)"
+        );
+    }
+
+    #[test]
+    fn dump() {
+        let lz78 = Algorithm::from_name("lz78-entropy", 9).unwrap();
+        let compressed = round_trip(&lz78);
+        let tokens = inspect(&compressed, &Algorithm::Bzip2(9), false).unwrap();
+        let phrases = tokens.iter().fold(Vec::new(), |mut data, t| {
+            data.extend_from_slice(&t.phrase);
+            data.extend(t.literal);
+            data
+        });
+        assert_eq!(phrases, DATA);
+
+        // A bare stream takes the mode from the algorithm
+        let tokens = inspect(&compressed[6..], &lz78, true).unwrap();
+        assert!(!tokens.is_empty());
+        assert!(inspect(&compressed[6..], &Algorithm::Bzip2(9), true).is_err());
+        let compressed = round_trip(&Algorithm::from_name("bwt", 9).unwrap());
+        assert!(inspect(&compressed, &lz78, true).is_err());
+    }
+
+    #[test]
+    fn unknown() {
+        let e = decompress(&mut &b"PK\x03\x04"[..], &mut Vec::new()).unwrap_err();
+        assert_eq!(e.to_string(), "not in a known format");
+        assert!(decompress(&mut &b""[..], &mut Vec::new()).is_err());
+        assert!(describe(&mut &b"BZh0"[..]).is_err());
+    }
+}
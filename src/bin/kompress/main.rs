@@ -0,0 +1,365 @@
+//! `kompress`, the codecs from the command line.
+
+mod args;
+mod format;
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Instant,
+};
+
+use kompression::lz78;
+
+use crate::{
+    args::{Args, Command},
+    format::{Algorithm, ALGORITHMS},
+};
+
+const USAGE: &str = "\
+usage: kompress <command> [options] [files...]
+
+commands:
+  compress, c     compress files, adding .bz2 or .kpl
+  decompress, d   decompress files, dropping the suffix
+  test, t         check that files decompress
+  info, i         print what a file's header says
+  bench, b        time every algorithm, or the one picked, on files
+  dump            list the phrases in an LZ78 stream, raw unless picked
+
+options:
+  -a, --algorithm NAME  bzip2 (default), lz78, lz78-entropy, huffman,
+                        adaptive-huffman, range or bwt
+  -p, --pipeline STEPS  steps separated by commas: rle1, mtf, zero-run, bwt,
+                        lz78, lz78-entropy, huffman, adaptive-huffman, range,
+                        bzip2, delta:N, xor:N, shuffle:N, bitshuffle:N
+  -1 .. -9              bzip2 block size, in 100k
+  -c, --stdout          write to stdout, keeping the input
+  -o, --output FILE     write to FILE, keeping the input
+  -k, --keep            keep the input file
+  -f, --force           overwrite outputs, write binary to a terminal
+  -q, --quiet           only print errors
+      --json            dump as JSON
+  -h, --help            print this
+
+With no files, or `-`, reads stdin and writes stdout.";
+
+/// Suffixes `decompress` strips, and what it puts in their place.
+const SUFFIXES: [(&str, &str); 4] = [
+    (".bz2", ""),
+    (".kpl", ""),
+    (".tbz2", ".tar"),
+    (".tbz", ".tar"),
+];
+
+fn main() -> ExitCode {
+    let args = match args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("kompress: {}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    if args.command == Command::Help {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let stdin = PathBuf::from("-");
+    let files = match args.files.as_slice() {
+        [] => std::slice::from_ref(&stdin),
+        files => files,
+    };
+    let mut failed = false;
+    for file in files {
+        let result = match args.command {
+            Command::Compress => compress(&args, file),
+            Command::Decompress => decompress(&args, file),
+            Command::Test => test(&args, file),
+            Command::Info => info(file),
+            Command::Bench => bench(&args, file),
+            Command::Dump => dump(&args, file),
+            Command::Help => unreachable!(),
+        };
+        if let Err(e) = result {
+            eprintln!("kompress: {}: {}", file.display(), e);
+            failed = true;
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn is_stdin(file: &Path) -> bool {
+    file == Path::new("-")
+}
+
+fn open(file: &Path) -> io::Result<Box<dyn Read>> {
+    if is_stdin(file) {
+        Ok(Box::new(io::stdin().lock()))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(file)?)))
+    }
+}
+
+/// Where the output for `file` goes, `None` for stdout.
+fn output_path(args: &Args, file: &Path, name: Option<PathBuf>) -> io::Result<Option<PathBuf>> {
+    if args.stdout || (is_stdin(file) && args.output.is_none()) {
+        return Ok(None);
+    }
+    if let Some(output) = &args.output {
+        return Ok(Some(output.clone()));
+    }
+    name.map(Some)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "unknown suffix, use -c or -o"))
+}
+
+/// `file` with `.suffix` added.
+fn compressed_name(file: &Path, suffix: &str) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    name.into()
+}
+
+/// `file` with a known suffix taken off, `None` if it hasn't got one.
+fn decompressed_name(file: &Path) -> Option<PathBuf> {
+    let name = file.to_str()?;
+    SUFFIXES.iter().find_map(|(suffix, replacement)| {
+        let stem = name.strip_suffix(suffix).filter(|s| !s.is_empty())?;
+        Some(PathBuf::from(format!("{}{}", stem, replacement)))
+    })
+}
+
+/// Run `f` from `file` to wherever its output goes, removing `file` after
+/// unless asked to keep it, or a half written output if `f` fails.
+fn convert(
+    args: &Args,
+    file: &Path,
+    output: Option<PathBuf>,
+    binary: bool,
+    f: impl FnOnce(&mut dyn Read, &mut dyn Write) -> io::Result<()>,
+) -> io::Result<()> {
+    if output.is_none() && binary && !args.force && io::stdout().is_terminal() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "won't write compressed data to a terminal, use -f to force",
+        ));
+    }
+    if !is_stdin(file) && !fs::metadata(file)?.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "not a regular file",
+        ));
+    }
+    let mut input = open(file)?;
+    let output = match output {
+        None => {
+            let mut stdout = io::stdout().lock();
+            f(&mut input, &mut stdout)?;
+            return stdout.flush();
+        }
+        Some(output) => output,
+    };
+
+    if output.exists() && !args.force {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists, use -f to overwrite", output.display()),
+        ));
+    }
+    let result = File::create(&output).and_then(|out| {
+        let mut out = BufWriter::new(out);
+        f(&mut input, &mut out)?;
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()
+    });
+    if let Err(e) = result {
+        let _ = fs::remove_file(&output);
+        return Err(e);
+    }
+    if !args.keep && !args.stdout && args.output.is_none() && !is_stdin(file) {
+        fs::remove_file(file)?;
+    }
+    Ok(())
+}
+
+fn compress(args: &Args, file: &Path) -> io::Result<()> {
+    let name = compressed_name(file, args.algorithm.suffix());
+    let output = output_path(args, file, Some(name))?;
+    convert(args, file, output, true, |input, output| {
+        args.algorithm.compress(input, output)
+    })
+}
+
+fn decompress(args: &Args, file: &Path) -> io::Result<()> {
+    let output = output_path(args, file, decompressed_name(file))?;
+    convert(args, file, output, false, |input, output| {
+        format::decompress(input, output).map(drop)
+    })
+}
+
+fn test(args: &Args, file: &Path) -> io::Result<()> {
+    let mut output = Counter(0);
+    format::decompress(&mut open(file)?, &mut output)?;
+    if !args.quiet {
+        println!("{}: OK, {} bytes", file.display(), output.0);
+    }
+    Ok(())
+}
+
+fn info(file: &Path) -> io::Result<()> {
+    let description = format::describe(&mut open(file)?)?;
+    println!("{}: {}", file.display(), description);
+    Ok(())
+}
+
+fn bench(args: &Args, file: &Path) -> io::Result<()> {
+    let mut data = Vec::new();
+    open(file)?.read_to_end(&mut data)?;
+    let algorithms = match args.picked {
+        true => vec![(algorithm_name(&args.algorithm), args.algorithm.clone())],
+        false => ALGORITHMS
+            .iter()
+            .filter_map(|name| Some((name.to_string(), Algorithm::from_name(name, 9)?)))
+            .collect(),
+    };
+
+    println!("{}: {} bytes", file.display(), data.len());
+    println!(
+        "{:<30} {:>12} {:>8} {:>12} {:>12}",
+        "algorithm", "compressed", "ratio", "comp MB/s", "decomp MB/s"
+    );
+    for (name, algorithm) in &algorithms {
+        let start = Instant::now();
+        let mut compressed = Vec::new();
+        algorithm.compress(&mut &data[..], &mut compressed)?;
+        let compress_time = start.elapsed();
+
+        let start = Instant::now();
+        let mut uncompressed = Vec::with_capacity(data.len());
+        format::decompress(&mut &compressed[..], &mut uncompressed)?;
+        let decompress_time = start.elapsed();
+        if uncompressed != data {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "round trip didn't match",
+            ));
+        }
+
+        let speed = |seconds: f64| data.len() as f64 / 1e6 / seconds.max(1e-9);
+        println!(
+            "{:<30} {:>12} {:>8.3} {:>12.2} {:>12.2}",
+            name,
+            compressed.len(),
+            data.len() as f64 / compressed.len() as f64,
+            speed(compress_time.as_secs_f64()),
+            speed(decompress_time.as_secs_f64()),
+        );
+    }
+    Ok(())
+}
+
+fn dump(args: &Args, file: &Path) -> io::Result<()> {
+    let mut data = Vec::new();
+    open(file)?.read_to_end(&mut data)?;
+    let tokens = format::inspect(&data, &args.algorithm, args.picked)?;
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    if args.json {
+        writeln!(stdout, "{}", lz78::to_json(&tokens))?;
+    } else {
+        writeln!(stdout, "{}: {} tokens", file.display(), tokens.len())?;
+        writeln!(stdout, "{}", lz78::HEADINGS)?;
+        for token in &tokens {
+            writeln!(stdout, "{}", token)?;
+        }
+    }
+    stdout.flush()
+}
+
+fn algorithm_name(algorithm: &Algorithm) -> String {
+    match algorithm {
+        Algorithm::Bzip2(level) => format!("bzip2 -{}", level),
+        Algorithm::Pipeline(p) => p
+            .steps()
+            .iter()
+            .map(format::step_name)
+            .collect::<Vec<_>>()
+            .join(","),
+    }
+}
+
+/// Counts what's written and throws it away.
+struct Counter(u64);
+
+impl Write for Counter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0 += data.len() as u64;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(
+            compressed_name(Path::new("a/b.txt"), "bz2"),
+            Path::new("a/b.txt.bz2")
+        );
+        assert_eq!(
+            decompressed_name(Path::new("a/b.txt.bz2")),
+            Some("a/b.txt".into())
+        );
+        assert_eq!(decompressed_name(Path::new("b.kpl")), Some("b".into()));
+        assert_eq!(decompressed_name(Path::new("b.tbz2")), Some("b.tar".into()));
+        assert_eq!(decompressed_name(Path::new("b.tbz")), Some("b.tar".into()));
+        assert_eq!(decompressed_name(Path::new(".bz2")), None);
+        assert_eq!(decompressed_name(Path::new("b.gz")), None);
+    }
+
+    #[test]
+    fn files() {
+        let dir = std::env::temp_dir().join(format!("kompress-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("data.txt");
+        let data = b"hello, hello, hello world\n".repeat(10);
+        fs::write(&file, &data).unwrap();
+
+        let parse = |args: &[&str]| args::parse(args.iter().map(|s| s.to_string())).unwrap();
+        let args = parse(&["compress", "-a", "lz78", "-q"]);
+        compress(&args, &file).unwrap();
+        assert!(!file.exists());
+        let compressed = dir.join("data.txt.kpl");
+        assert!(compressed.exists());
+        test(&args, &compressed).unwrap();
+
+        let args = parse(&["decompress", "-k"]);
+        decompress(&args, &compressed).unwrap();
+        assert!(compressed.exists());
+        assert_eq!(fs::read(&file).unwrap(), data);
+
+        // No overwriting without -f, and the output is left alone
+        let e = decompress(&args, &compressed).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&file).unwrap(), data);
+
+        // A failed decompress leaves no output behind
+        let bad = dir.join("bad.bz2");
+        fs::write(&bad, b"BZh9 not really").unwrap();
+        assert!(decompress(&args, &bad).is_err());
+        assert!(!dir.join("bad").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
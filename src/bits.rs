@@ -0,0 +1,198 @@
+//! Bit level I/O shared by the codecs. Bits are packed most significant bit
+//! first, and the last byte of a stream is padded out with zeros.
+
+use crate::{error::Error, io::Sink};
+
+const MAX_BYTES: usize = core::mem::size_of::<u64>();
+const MAX_BITS: u32 = MAX_BYTES as u32 * 8;
+
+/// Packs codes of up to 32 bits into a [`Sink`], a 64 bit word at a time.
+#[derive(Debug)]
+pub struct BitWriter<S> {
+    sink: S,
+    buffer: u128,
+    bits: u32,
+}
+
+impl<S: Sink> BitWriter<S> {
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            buffer: 0,
+            bits: 0,
+        }
+    }
+
+    /// Append the low `bits` bits of `data`. Returns how many bytes went out
+    /// to the sink.
+    pub fn write_bits(&mut self, data: u32, bits: u32) -> Result<usize, Error> {
+        debug_assert!(bits <= 32);
+        if bits == 0 {
+            return Ok(0);
+        }
+        let data = data as u128 & ((1 << bits) - 1);
+        self.buffer |= data << (128 - self.bits - bits);
+        self.bits += bits;
+        if self.bits > MAX_BITS {
+            let word = (self.buffer >> 64) as u64;
+            self.sink.write_all(&word.to_be_bytes())?;
+            self.buffer <<= MAX_BITS;
+            self.bits -= MAX_BITS;
+            return Ok(MAX_BYTES);
+        }
+        Ok(0)
+    }
+
+    /// Write out whatever is left, padding the last byte with zeros.
+    pub fn flush(&mut self) -> Result<usize, Error> {
+        let num_bytes = self.bits.div_ceil(8) as usize;
+        let bytes = self.buffer.to_be_bytes();
+        self.sink.write_all(&bytes[..num_bytes])?;
+        self.buffer = 0;
+        self.bits = 0;
+        Ok(num_bytes)
+    }
+
+    /// Bits written but not out to the sink yet.
+    pub fn pending_bits(&self) -> u64 {
+        self.bits as u64
+    }
+
+    /// The bits not out to the sink yet, right aligned, and how many there
+    /// are. There are never more than 64 between writes.
+    pub(crate) fn pending(&self) -> (u64, u32) {
+        let value = self.buffer.checked_shr(128 - self.bits).unwrap_or(0);
+        (value as u64, self.bits)
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+
+    /// Carry on over `sink` instead, handing back the old one.
+    pub(crate) fn replace_sink<T>(self, sink: T) -> (BitWriter<T>, S) {
+        let writer = BitWriter {
+            sink,
+            buffer: self.buffer,
+            bits: self.bits,
+        };
+        (writer, self.sink)
+    }
+}
+
+/// Reads codes of up to 32 bits from a byte slice.
+#[derive(Debug, Clone)]
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    /// Offset of the next unread bit.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() * 8 - self.position
+    }
+
+    /// Look at the next `bits` bits without consuming them. Reading past the
+    /// end gives zeros.
+    pub fn peek_bits(&self, bits: u32) -> u32 {
+        debug_assert!(bits <= 32);
+        if bits == 0 {
+            return 0;
+        }
+        let byte = self.position / 8;
+        let mut v = [0; MAX_BYTES];
+        let available = usize::min(self.data.len().saturating_sub(byte), MAX_BYTES);
+        v[..available].copy_from_slice(&self.data[byte..byte + available]);
+        let word = u64::from_be_bytes(v) << (self.position % 8);
+        (word >> (64 - bits)) as u32
+    }
+
+    /// Read the next `bits` bits, `None` if the data runs out first.
+    pub fn read_bits(&mut self, bits: u32) -> Option<u32> {
+        if bits as usize > self.remaining() {
+            return None;
+        }
+        let v = self.peek_bits(bits);
+        self.position += bits as usize;
+        Some(v)
+    }
+
+    /// Whether everything left is zero padding.
+    pub fn is_padding(&self) -> bool {
+        let mut r = self.clone();
+        while r.remaining() > 0 {
+            let bits = usize::min(r.remaining(), 32) as u32;
+            if r.read_bits(bits) != Some(0) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_codes() {
+        let mut out = Vec::new();
+        let mut w = BitWriter::new(&mut out);
+        assert_eq!(w.write_bits(0b101, 3).unwrap(), 0);
+        assert_eq!(w.write_bits(0xffff_0001, 32).unwrap(), 0);
+        assert_eq!(w.flush().unwrap(), 5);
+        assert_eq!(out, [0b1011_1111, 0xff, 0xe0, 0x00, 0b0010_0000]);
+    }
+
+    #[test]
+    fn write_full_words() {
+        let mut out = Vec::new();
+        let mut w = BitWriter::new(&mut out);
+        assert_eq!(w.write_bits(u32::MAX, 32).unwrap(), 0);
+        assert_eq!(w.write_bits(0, 32).unwrap(), 0);
+        assert_eq!(w.write_bits(1, 1).unwrap(), 8);
+        assert_eq!(w.flush().unwrap(), 1);
+        assert_eq!(out, [0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0, 0x80]);
+    }
+
+    #[test]
+    fn read_codes() {
+        let data = [0b1011_1111, 0xff, 0xe0, 0x00, 0b0010_0000];
+        let mut r = BitReader::new(&data);
+        assert_eq!(r.read_bits(3), Some(0b101));
+        assert_eq!(r.peek_bits(32), 0xffff_0001);
+        assert_eq!(r.read_bits(32), Some(0xffff_0001));
+        assert_eq!(r.position(), 35);
+        assert!(r.is_padding());
+        assert_eq!(r.read_bits(6), None);
+        assert_eq!(r.read_bits(5), Some(0));
+        assert_eq!(r.remaining(), 0);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut out = Vec::new();
+        let mut w = BitWriter::new(&mut out);
+        for bits in 0..=32 {
+            w.write_bits(0x5555_5555, bits).unwrap();
+        }
+        w.flush().unwrap();
+        let mut r = BitReader::new(&out);
+        for bits in 0..=32 {
+            assert_eq!(
+                r.read_bits(bits),
+                Some(0x5555_5555 & ((1u64 << bits) - 1) as u32)
+            );
+        }
+        assert!(r.is_padding());
+    }
+}
@@ -0,0 +1,150 @@
+//! Burrows-Wheeler transform.
+//!
+//! [`forward`] sorts every rotation of a block and keeps the last column,
+//! which groups bytes by what follows them. The rotations are sorted with a
+//! [`suffix_array`] built by SA-IS, so blocks of [`BLOCK_SIZE`] and well
+//! beyond take linear time. [`inverse`] undoes it given the row the block
+//! itself ended up on.
+
+mod sais;
+
+use alloc::{vec, vec::Vec};
+
+use crate::error::ErrorKind;
+
+pub use self::sais::suffix_array;
+
+/// bzip2's largest block size, 900k.
+pub const BLOCK_SIZE: usize = 900_000;
+
+/// Transform `block`, returning the last column of its sorted rotations and
+/// the row the block itself sorts to.
+pub fn forward(block: &[u8]) -> (Vec<u8>, usize) {
+    let n = block.len();
+    if n == 0 {
+        return (Vec::new(), 0);
+    }
+
+    // The first `n` suffixes of the block twice over sort the same as its
+    // rotations. Rotations that compare equal are identical and so is their
+    // last byte, which makes their order irrelevant.
+    let mut doubled = Vec::with_capacity(2 * n);
+    doubled.extend_from_slice(block);
+    doubled.extend_from_slice(block);
+    let sa = suffix_array(&doubled);
+
+    let mut last = Vec::with_capacity(n);
+    let mut origin = 0;
+    for i in sa.into_iter().map(|i| i as usize).filter(|&i| i < n) {
+        if i == 0 {
+            origin = last.len();
+        }
+        last.push(block[(i + n - 1) % n]);
+    }
+    (last, origin)
+}
+
+/// Undo [`forward`], given the last column and the original row.
+pub fn inverse(last: &[u8], origin: usize) -> Result<Vec<u8>, ErrorKind> {
+    let n = last.len();
+    if n == 0 {
+        return Ok(Vec::new());
+    }
+    if origin >= n {
+        return Err(ErrorKind::BadCode(origin as u32));
+    }
+
+    // Each row's rotation, shifted left by one, starts where the same byte
+    // lands in the first column. Map first column rows to the last column
+    // rows holding the same occurrence of each byte.
+    let mut starts = [0; 256];
+    for &b in last {
+        starts[b as usize] += 1;
+    }
+    let mut sum = 0;
+    for s in starts.iter_mut() {
+        sum += *s;
+        *s = sum - *s;
+    }
+    let mut next = vec![0_u32; n];
+    for (i, &b) in last.iter().enumerate() {
+        next[starts[b as usize]] = i as u32;
+        starts[b as usize] += 1;
+    }
+
+    let mut block = Vec::with_capacity(n);
+    let mut p = next[origin] as usize;
+    for _ in 0..n {
+        block.push(last[p]);
+        p = next[p] as usize;
+    }
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::quickcheck;
+
+    #[test]
+    fn banana() {
+        // abanan anaban ananab banana nabana nanaba
+        assert_eq!(forward(b"banana"), (b"nnbaaa".to_vec(), 3));
+        assert_eq!(inverse(b"nnbaaa", 3).unwrap(), b"banana");
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(forward(b""), (Vec::new(), 0));
+        assert_eq!(inverse(b"", 0).unwrap(), b"");
+    }
+
+    #[test]
+    fn periodic() {
+        let (last, origin) = forward(b"abcabcabc");
+        assert_eq!(last, b"cccaaabbb");
+        assert_eq!(inverse(&last, origin).unwrap(), b"abcabcabc");
+        let (last, origin) = forward(b"aaaa");
+        assert_eq!(inverse(&last, origin).unwrap(), b"aaaa");
+    }
+
+    #[test]
+    fn bad_origin() {
+        assert_eq!(inverse(b"nnbaaa", 6), Err(ErrorKind::BadCode(6)));
+    }
+
+    #[test]
+    fn large_block() {
+        let mut state = 1_u32;
+        let block = (0..BLOCK_SIZE + 100_000)
+            .map(|i| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                // Mostly text-like runs with some noise
+                if i % 7 == 0 {
+                    (state >> 16) as u8
+                } else {
+                    b"the quick brown fox "[i % 20]
+                }
+            })
+            .collect::<Vec<_>>();
+        let (last, origin) = forward(&block);
+        assert_eq!(inverse(&last, origin).unwrap(), block);
+    }
+
+    #[quickcheck]
+    fn forward_inverse(block: Vec<u8>) -> bool {
+        let (last, origin) = forward(&block);
+        inverse(&last, origin).unwrap() == block
+    }
+
+    #[quickcheck]
+    fn matches_sorted_rotations(block: Vec<u8>) -> bool {
+        let n = block.len();
+        let mut rotations = (0..n)
+            .map(|i| [&block[i..], &block[..i]].concat())
+            .collect::<Vec<_>>();
+        rotations.sort();
+        let expected = rotations.iter().map(|r| r[n - 1]).collect::<Vec<_>>();
+        forward(&block).0 == expected
+    }
+}
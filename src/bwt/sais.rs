@@ -0,0 +1,202 @@
+use alloc::{vec, vec::Vec};
+
+const EMPTY: u32 = u32::MAX;
+
+/// A character of a text being sorted, bytes at the top level and names of
+/// LMS substrings in the recursion.
+trait Symbol: Copy + Ord {
+    fn index(self) -> usize;
+}
+
+impl Symbol for u8 {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl Symbol for u32 {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Build the suffix array of `text` with SA-IS, in linear time. Suffixes
+/// compare as if the text ended in a character smaller than any other, so
+/// a suffix sorts before every longer suffix it's a prefix of.
+pub fn suffix_array(text: &[u8]) -> Vec<u32> {
+    assert!(text.len() < EMPTY as usize, "text too long to index");
+    sais(text, 256)
+}
+
+/// Start of each character's bucket, or its end with `tails`.
+fn buckets<T: Symbol>(text: &[T], alphabet: usize, tails: bool) -> Vec<u32> {
+    let mut counts = vec![0; alphabet];
+    for &c in text {
+        counts[c.index()] += 1;
+    }
+    let mut sum = 0;
+    for c in &mut counts {
+        sum += *c;
+        *c = if tails { sum } else { sum - *c };
+    }
+    counts
+}
+
+/// Sort the L suffixes from the sorted LMS suffixes, then the S suffixes
+/// from the L ones.
+fn induce<T: Symbol>(text: &[T], alphabet: usize, stype: &[bool], sa: &mut [u32]) {
+    let n = text.len();
+    let mut heads = buckets(text, alphabet, false);
+    // The suffix before the virtual sentinel comes first in its bucket
+    let c = text[n - 1].index();
+    sa[heads[c] as usize] = (n - 1) as u32;
+    heads[c] += 1;
+    for i in 0..n {
+        let j = sa[i];
+        if j != EMPTY && j > 0 && !stype[j as usize - 1] {
+            let c = text[j as usize - 1].index();
+            sa[heads[c] as usize] = j - 1;
+            heads[c] += 1;
+        }
+    }
+
+    let mut tails = buckets(text, alphabet, true);
+    for i in (0..n).rev() {
+        let j = sa[i];
+        if j != EMPTY && j > 0 && stype[j as usize - 1] {
+            let c = text[j as usize - 1].index();
+            tails[c] -= 1;
+            sa[tails[c] as usize] = j - 1;
+        }
+    }
+}
+
+fn sais<T: Symbol>(text: &[T], alphabet: usize) -> Vec<u32> {
+    let n = text.len();
+    if n <= 1 {
+        return (0..n as u32).collect();
+    }
+
+    // Whether each suffix is S type, smaller than the one after it. The
+    // virtual sentinel at `n` is S, which makes the suffix before it L.
+    let mut stype = vec![false; n + 1];
+    stype[n] = true;
+    for i in (0..n - 1).rev() {
+        stype[i] = text[i] < text[i + 1] || (text[i] == text[i + 1] && stype[i + 1]);
+    }
+    let is_lms = |i: usize| i > 0 && stype[i] && !stype[i - 1];
+
+    // Sort the LMS substrings by dropping them at the ends of their buckets
+    // and inducing
+    let mut sa = vec![EMPTY; n];
+    let mut tails = buckets(text, alphabet, true);
+    for i in (1..n).rev().filter(|&i| is_lms(i)) {
+        let c = text[i].index();
+        tails[c] -= 1;
+        sa[tails[c] as usize] = i as u32;
+    }
+    induce(text, alphabet, &stype, &mut sa);
+
+    // Name the LMS substrings in sorted order, equal ones getting the same
+    // name
+    let equal = |p: usize, q: usize| {
+        for d in 0.. {
+            if p + d == n || q + d == n {
+                // Only one of them can reach the sentinel
+                return false;
+            }
+            if text[p + d] != text[q + d] || stype[p + d] != stype[q + d] {
+                return false;
+            }
+            if d > 0 && (is_lms(p + d) || is_lms(q + d)) {
+                return is_lms(p + d) && is_lms(q + d);
+            }
+        }
+        unreachable!()
+    };
+    let mut names = vec![EMPTY; n];
+    let mut name = 0;
+    let mut previous: Option<usize> = None;
+    for &p in sa.iter().filter(|&&p| is_lms(p as usize)) {
+        let p = p as usize;
+        if let Some(q) = previous {
+            if !equal(p, q) {
+                name += 1;
+            }
+        }
+        names[p] = name;
+        previous = Some(p);
+    }
+    let name_count = name as usize + 1;
+
+    // Sort the LMS suffixes, recursing on the string of names if any of
+    // them repeat
+    let lms = (1..n).filter(|&i| is_lms(i)).collect::<Vec<_>>();
+    let reduced = lms.iter().map(|&p| names[p]).collect::<Vec<u32>>();
+    let order = if name_count == lms.len() {
+        let mut order = vec![0; lms.len()];
+        for (i, &name) in reduced.iter().enumerate() {
+            order[name as usize] = i as u32;
+        }
+        order
+    } else {
+        sais(&reduced, name_count)
+    };
+
+    // Induce the full order from the sorted LMS suffixes
+    sa.fill(EMPTY);
+    let mut tails = buckets(text, alphabet, true);
+    for &i in order.iter().rev() {
+        let p = lms[i as usize];
+        let c = text[p].index();
+        tails[c] -= 1;
+        sa[tails[c] as usize] = p as u32;
+    }
+    induce(text, alphabet, &stype, &mut sa);
+    sa
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::quickcheck;
+
+    fn naive(text: &[u8]) -> Vec<u32> {
+        let mut sa = (0..text.len() as u32).collect::<Vec<_>>();
+        sa.sort_by_key(|&i| &text[i as usize..]);
+        sa
+    }
+
+    #[test]
+    fn banana() {
+        assert_eq!(suffix_array(b"banana"), [5, 3, 1, 0, 4, 2]);
+    }
+
+    #[test]
+    fn small() {
+        assert!(suffix_array(b"").is_empty());
+        assert_eq!(suffix_array(b"a"), [0]);
+        assert_eq!(suffix_array(b"aaaa"), [3, 2, 1, 0]);
+        assert_eq!(suffix_array(b"ba"), [1, 0]);
+    }
+
+    #[test]
+    fn repetitive() {
+        // Lots of equal LMS substrings, so it has to recurse
+        let text = b"mississippi".repeat(50);
+        assert_eq!(suffix_array(&text), naive(&text));
+        let text = b"abaabaaabaaaab".repeat(20);
+        assert_eq!(suffix_array(&text), naive(&text));
+    }
+
+    #[quickcheck]
+    fn matches_naive(text: Vec<u8>) -> bool {
+        suffix_array(&text) == naive(&text)
+    }
+
+    #[quickcheck]
+    fn matches_naive_small_alphabet(text: Vec<bool>) -> bool {
+        let text = text.into_iter().map(|b| b as u8).collect::<Vec<_>>();
+        suffix_array(&text) == naive(&text)
+    }
+}
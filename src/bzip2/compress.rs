@@ -0,0 +1,363 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    bits::BitWriter,
+    bwt,
+    bzip2::{
+        crc::{combine, Crc},
+        rand::randomize,
+        BLOCK_MAGIC, END_MAGIC, GROUP_SIZE, LEVEL_SIZE, MAX_LEVEL, MAX_TABLES, MIN_LEVEL,
+        MIN_TABLES, STREAM_MAGIC,
+    },
+    error::Error,
+    huffman::{code_lengths, write_deltas, write_used, Codebook},
+    io::Sink,
+    stats::Stats,
+    transform::{MtfList, Rle1, Rle1Encoder, Stage, Transform, ZeroRunEncoder},
+};
+
+/// Longest code the encoder makes, as the reference encoder does.
+const CODE_LEN: u8 = 17;
+/// Rounds of refining the tables against the groups that picked them.
+const ITERATIONS: usize = 4;
+/// Room left at the end of a block for a run the last byte finishes.
+const BLOCK_SLACK: usize = 19;
+
+/// Compresses to the bzip2 format, readable by `bzip2 -d`. Input is cut
+/// into blocks as it arrives, each written out once it fills.
+pub struct Bzip2Compressor<W> {
+    writer: BitWriter<W>,
+    level: u8,
+    /// Whether the stream header is out yet.
+    started: bool,
+
+    rle: Rle1Encoder,
+    /// The current block, after [`Rle1`].
+    block: Vec<u8>,
+    /// CRC of the input that went into `block`.
+    crc: Crc,
+    combined: u32,
+
+    input: u64,
+    output: u64,
+}
+
+impl<W: Sink> Bzip2Compressor<W> {
+    /// Compress with the largest blocks, like `bzip2 -9`.
+    pub fn new(writer: W) -> Self {
+        Self::with_level(writer, MAX_LEVEL)
+    }
+
+    /// Compress with blocks of `level` times 100k, `level` is clamped to
+    /// [`MIN_LEVEL`] and [`MAX_LEVEL`].
+    ///
+    /// [`MIN_LEVEL`]: crate::bzip2::MIN_LEVEL
+    /// [`MAX_LEVEL`]: crate::bzip2::MAX_LEVEL
+    pub fn with_level(writer: W, level: u8) -> Self {
+        Self {
+            writer: BitWriter::new(writer),
+            level: level.clamp(MIN_LEVEL, MAX_LEVEL),
+            started: false,
+
+            rle: Rle1.encoder(),
+            block: Vec::new(),
+            crc: Crc::new(),
+            combined: 0,
+
+            input: 0,
+            output: 0,
+        }
+    }
+
+    fn write_header(&mut self) -> Result<usize, Error> {
+        if self.started {
+            return Ok(0);
+        }
+        self.started = true;
+        let header = STREAM_MAGIC << 8 | (b'0' + self.level) as u32;
+        self.writer.write_bits(header, 32)
+    }
+
+    pub fn write(&mut self, mut data: &[u8]) -> Result<usize, Error> {
+        self.input += data.len() as u64;
+        let mut written = self.write_header()?;
+        let max_block = self.level as usize * LEVEL_SIZE - BLOCK_SLACK;
+        while !data.is_empty() {
+            // A byte can finish a run and let out up to five bytes, so take
+            // half of what's left at a time, then a byte at a time near the
+            // end
+            let n = ((max_block - self.block.len()) / 2).clamp(1, data.len());
+            let (chunk, rest) = data.split_at(n);
+            self.crc.update(chunk);
+            self.rle.push(chunk, &mut self.block)?;
+            data = rest;
+            if self.block.len() >= max_block {
+                written += self.end_block()?;
+            }
+        }
+        self.output += written as u64;
+        Ok(written)
+    }
+
+    /// What's gone in and out so far. The block being filled hasn't gone
+    /// out yet.
+    pub fn stats(&self) -> Stats {
+        Stats::new(self.input, self.output * 8 + self.writer.pending_bits())
+    }
+
+    fn end_block(&mut self) -> Result<usize, Error> {
+        self.rle.finish(&mut self.block)?;
+        if self.block.is_empty() {
+            return Ok(0);
+        }
+        let crc = self.crc.value();
+        let written = write_block(&mut self.writer, &self.block, crc, false)?;
+        self.combined = combine(self.combined, crc);
+        self.block.clear();
+        self.crc = Crc::new();
+        Ok(written)
+    }
+
+    /// Carry on over `writer` instead, handing back the old one.
+    pub(crate) fn replace_writer<V>(self, writer: V) -> (Bzip2Compressor<V>, W) {
+        let (writer, old) = self.writer.replace_sink(writer);
+        let codec = Bzip2Compressor {
+            writer,
+            level: self.level,
+            started: self.started,
+            rle: self.rle,
+            block: self.block,
+            crc: self.crc,
+            combined: self.combined,
+            input: self.input,
+            output: self.output,
+        };
+        (codec, old)
+    }
+
+    /// Write out the last block and the end of the stream.
+    pub fn finalize(mut self) -> Result<usize, Error> {
+        let mut written = self.write_header()?;
+        written += self.end_block()?;
+        written += self.writer.write_bits((END_MAGIC >> 24) as u32, 24)?;
+        written += self.writer.write_bits(END_MAGIC as u32, 24)?;
+        written += self.writer.write_bits(self.combined, 32)?;
+        Ok(written + self.writer.flush()?)
+    }
+}
+
+/// Worst case size of `len` bytes compressed by a [`Bzip2Compressor`] at
+/// any level. [`Rle1`] can stretch the input by a quarter, and
+/// [`MIN_LEVEL`] cuts that into the most blocks. Each block's tables are
+/// fit to the groups that picked them, counting every symbol at least once,
+/// so they code no worse than a flat 9 bit code over the up to 258 symbols
+/// plus one of each.
+pub const fn compress_bound(len: usize) -> usize {
+    let len = len as u64;
+    let alphabet = 258;
+    let expanded = len + len / 4;
+    let blocks = expanded / (MIN_LEVEL as u64 * LEVEL_SIZE as u64 - BLOCK_SLACK as u64) + 1;
+    // Zero runs never take more symbols than the run, then there's the
+    // end of block
+    let symbols = expanded + blocks;
+    let selectors = symbols / GROUP_SIZE as u64 + blocks;
+
+    // Magic, CRC, randomized bit, origin, the bitmap and the table counts
+    let header = 48 + 32 + 1 + 24 + 16 + 16 * 16 + 3 + 15;
+    // A start length, then every length up to 16 steps from the last
+    let table = 5 + alphabet + 2 * (CODE_LEN as u64 - 1) * (alphabet - 1);
+    let tables = MAX_TABLES as u64 * (table + 9 * alphabet);
+    let bits =
+        32 + blocks * (header + tables) + selectors * MAX_TABLES as u64 + 9 * symbols + 48 + 32;
+    bits.div_ceil(8) as usize
+}
+
+/// Write one block, already through [`Rle1`], with `crc` the CRC of the
+/// input it came from. A `randomized` block has the pattern from
+/// [`randomize`] applied before sorting, as very old encoders did.
+pub(super) fn write_block<S: Sink>(
+    writer: &mut BitWriter<S>,
+    block: &[u8],
+    crc: u32,
+    randomized: bool,
+) -> Result<usize, Error> {
+    let (last, origin) = if randomized {
+        let mut block = block.to_vec();
+        randomize(&mut block);
+        bwt::forward(&block)
+    } else {
+        bwt::forward(block)
+    };
+
+    // Move-to-front runs over the bytes in use only
+    let mut used = [false; 256];
+    for &b in &last {
+        used[b as usize] = true;
+    }
+    let mut index = [0_u8; 256];
+    let mut in_use = 0;
+    for (b, _) in used.iter().enumerate().filter(|(_, &u)| u) {
+        index[b] = in_use as u8;
+        in_use += 1;
+    }
+    let mut mtf = MtfList::new();
+    let indices = last
+        .iter()
+        .map(|&b| mtf.encode(index[b as usize]))
+        .collect::<Vec<_>>();
+    let mut symbols = Vec::with_capacity(indices.len() + 1);
+    let mut zero_run = ZeroRunEncoder::new();
+    zero_run.push_symbols(&indices, &mut symbols);
+    zero_run.flush_symbols(&mut symbols);
+    let end_of_block = in_use + 1;
+    symbols.push(end_of_block as u16);
+
+    let (books, selectors) = choose_tables(&symbols, in_use + 2);
+
+    let mut written = writer.write_bits((BLOCK_MAGIC >> 24) as u32, 24)?;
+    written += writer.write_bits(BLOCK_MAGIC as u32, 24)?;
+    written += writer.write_bits(crc, 32)?;
+    written += writer.write_bits(randomized as u32, 1)?;
+    written += writer.write_bits(origin as u32, 24)?;
+    written += write_used(writer, &used)?;
+
+    written += writer.write_bits(books.len() as u32, 3)?;
+    written += writer.write_bits(selectors.len() as u32, 15)?;
+    let mut tables = MtfList::new();
+    for &s in &selectors {
+        // In unary, after move-to-front
+        let i = tables.encode(s) as u32;
+        written += writer.write_bits((1 << (i + 1)) - 2, i + 1)?;
+    }
+    for book in &books {
+        written += write_deltas(writer, book.lengths())?;
+    }
+
+    for (group, &s) in symbols.chunks(GROUP_SIZE).zip(&selectors) {
+        for &symbol in group {
+            written += books[s as usize].write_symbol(writer, symbol as usize)?;
+        }
+    }
+    Ok(written)
+}
+
+/// Build the Huffman tables for a block and pick one for each group of
+/// symbols, the way the reference encoder does. The tables start out each
+/// covering a slice of the alphabet, then are rebuilt a few times from the
+/// groups that picked them.
+fn choose_tables(symbols: &[u16], alphabet: usize) -> (Vec<Codebook>, Vec<u8>) {
+    let tables = match symbols.len() {
+        0..=199 => MIN_TABLES,
+        200..=599 => 3,
+        600..=1199 => 4,
+        1200..=2399 => 5,
+        _ => MAX_TABLES,
+    };
+    let mut frequencies = vec![0_u32; alphabet];
+    for &s in symbols {
+        frequencies[s as usize] += 1;
+    }
+
+    // Split the alphabet into ranges of about equal frequency, each table
+    // cheap inside its range and expensive outside it
+    let mut lengths = vec![vec![0_u8; alphabet]; tables];
+    let mut remaining = symbols.len() as u32;
+    let mut start = 0;
+    for part in (1..=tables).rev() {
+        let target = remaining / part as u32;
+        let mut end = start;
+        let mut sum = 0;
+        while sum < target && end < alphabet {
+            sum += frequencies[end];
+            end += 1;
+        }
+        if end > start + 1 && part != tables && part != 1 && (tables - part) % 2 == 1 {
+            end -= 1;
+            sum -= frequencies[end];
+        }
+        for (s, l) in lengths[part - 1].iter_mut().enumerate() {
+            *l = if (start..end).contains(&s) { 0 } else { 15 };
+        }
+        start = end;
+        remaining -= sum;
+    }
+
+    let mut selectors = Vec::with_capacity(symbols.len().div_ceil(GROUP_SIZE));
+    for _ in 0..ITERATIONS {
+        let mut frequencies = vec![vec![0_u32; alphabet]; tables];
+        selectors.clear();
+        for group in symbols.chunks(GROUP_SIZE) {
+            let cost = |t: &Vec<u8>| group.iter().map(|&s| t[s as usize] as u32).sum::<u32>();
+            let best = (0..tables).min_by_key(|&t| cost(&lengths[t])).unwrap();
+            for &s in group {
+                frequencies[best][s as usize] += 1;
+            }
+            selectors.push(best as u8);
+        }
+        for (l, f) in lengths.iter_mut().zip(&mut frequencies) {
+            // Every symbol needs a code, used or not
+            for f in f.iter_mut() {
+                *f = (*f).max(1);
+            }
+            *l = code_lengths(f, CODE_LEN);
+        }
+    }
+
+    let books = lengths
+        .iter()
+        .map(|l| Codebook::from_lengths(l).expect("package-merge makes valid lengths"))
+        .collect();
+    (books, selectors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{io::SliceSink, utils::noise};
+
+    #[test]
+    fn bound() {
+        assert!(compress_bound(0) >= 14);
+        // Runs of four that Rle1 stretches to five bytes
+        let runs = (0..50_000).flat_map(|i| [i as u8; 4]).collect::<Vec<_>>();
+        for data in [noise(300_000), noise(1000), runs] {
+            for level in [MIN_LEVEL, MAX_LEVEL] {
+                let mut out = vec![0; compress_bound(data.len())];
+                let mut c = Bzip2Compressor::with_level(SliceSink::new(&mut out), level);
+                c.write(&data).unwrap();
+                c.finalize().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn empty() {
+        let mut compressed = Vec::new();
+        let c = Bzip2Compressor::new(&mut compressed);
+        assert_eq!(c.finalize().unwrap(), 14);
+        // What `bzip2 -9` makes of nothing
+        assert_eq!(
+            compressed,
+            [0x42, 0x5a, 0x68, 0x39, 0x17, 0x72, 0x45, 0x38, 0x50, 0x90, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn level() {
+        let mut compressed = Vec::new();
+        let c = Bzip2Compressor::with_level(&mut compressed, 0);
+        c.finalize().unwrap();
+        assert_eq!(&compressed[..4], b"BZh1");
+    }
+
+    #[test]
+    fn tables() {
+        let symbols = (0..3000).map(|i| (i % 7) as u16).collect::<Vec<_>>();
+        let (books, selectors) = choose_tables(&symbols, 9);
+        assert_eq!(books.len(), MAX_TABLES);
+        assert_eq!(selectors.len(), 60);
+        for book in &books {
+            assert!(book.lengths().iter().all(|&l| (1..=CODE_LEN).contains(&l)));
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! The CRC-32 bzip2 uses: polynomial `0x04c11db7` shifted in most
+//! significant bit first, unlike the reflected CRC-32 of gzip and zip.
+
+const POLYNOMIAL: u32 = 0x04c1_1db7;
+
+const TABLE: [u32; 256] = table();
+
+const fn table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ POLYNOMIAL
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Running CRC of a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Crc(u32);
+
+impl Crc {
+    pub fn new() -> Self {
+        Self(u32::MAX)
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.0 = (self.0 << 8) ^ TABLE[((self.0 >> 24) as u8 ^ b) as usize];
+        }
+    }
+
+    pub fn value(&self) -> u32 {
+        !self.0
+    }
+}
+
+/// Fold a block's CRC into the CRC of the whole stream.
+pub(crate) fn combine(combined: u32, block: u32) -> u32 {
+    combined.rotate_left(1) ^ block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        // The standard check value for CRC-32/BZIP2
+        let mut crc = Crc::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.value(), 0xfc89_1918);
+    }
+
+    #[test]
+    fn incremental() {
+        let mut whole = Crc::new();
+        whole.update(b"hello world");
+        let mut parts = Crc::new();
+        parts.update(b"hello");
+        parts.update(b" world");
+        assert_eq!(whole, parts);
+    }
+}
@@ -0,0 +1,537 @@
+use alloc::vec::Vec;
+
+use crate::{
+    bits::BitReader,
+    bwt,
+    bzip2::{
+        crc::{combine, Crc},
+        rand::randomize,
+        BLOCK_MAGIC, CODEC, END_MAGIC, GROUP_SIZE, LEVEL_SIZE, MAX_CODE_LEN, MAX_LEVEL,
+        MAX_SELECTORS, MAX_TABLES, MIN_LEVEL, MIN_TABLES, STREAM_MAGIC,
+    },
+    error::{Error, ErrorKind},
+    huffman::{read_deltas, read_used, Decoder},
+    io::Sink,
+    options::DecompressOptions,
+    transform::{MtfList, Rle1, Stage, Transform, ZeroRunDecoder},
+};
+
+/// Bytes of a block run through the inverse of [`Rle1`] at a time, so
+/// output limits are checked before a highly compressible block is
+/// expanded all at once.
+const CHUNK: usize = 1 << 16;
+
+/// Decompresses a bzip2 stream, or several concatenated ones.
+///
+/// bzip2 blocks have no length up front, so compressed data is buffered
+/// until a whole block is there. Each block is written out once decoded, the
+/// error for a bad CRC comes after its contents.
+pub struct Bzip2Decompressor<W> {
+    writer: W,
+    options: DecompressOptions,
+
+    /// Compressed bytes not fully decoded yet.
+    buffer: Vec<u8>,
+    /// Bit offset into `buffer` of the next unread bit.
+    position: usize,
+    /// Bytes dropped from the front of `buffer` so far.
+    dropped: u64,
+    produced: u64,
+    /// Length of `buffer` the last time a block was found to be incomplete.
+    /// Decoding isn't tried again until the buffer doubles.
+    attempted: usize,
+
+    state: State,
+    /// Streams read to the end so far.
+    streams: u64,
+    combined: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Waiting for a stream header.
+    Header,
+    /// Reading the blocks of a stream with blocks of `level` times 100k.
+    Blocks { level: u8 },
+}
+
+/// A block as read from the stream, up to the last column of its BWT.
+struct Block {
+    crc: u32,
+    randomized: bool,
+    origin: usize,
+    last: Vec<u8>,
+}
+
+enum Piece {
+    Block(Block),
+    End { crc: u32 },
+}
+
+impl<W: Sink> Bzip2Decompressor<W> {
+    pub fn new(writer: W) -> Self {
+        Self::with_options(writer, DecompressOptions::default())
+    }
+
+    pub fn with_options(writer: W, options: DecompressOptions) -> Self {
+        Self {
+            writer,
+            options,
+
+            buffer: Vec::new(),
+            position: 0,
+            dropped: 0,
+            produced: 0,
+            attempted: 0,
+
+            state: State::Header,
+            streams: 0,
+            combined: 0,
+        }
+    }
+
+    fn error(&self, kind: ErrorKind, position: usize) -> Error {
+        Error::stream(
+            CODEC,
+            kind,
+            self.dropped * 8 + position as u64,
+            self.produced,
+        )
+    }
+
+    pub fn read(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.buffer.extend_from_slice(data);
+        self.decode(false)
+    }
+
+    /// Decode everything complete in the buffer. Unless `finishing`, a
+    /// block is only tried again once the buffer has doubled since it was
+    /// last found incomplete.
+    fn decode(&mut self, finishing: bool) -> Result<usize, Error> {
+        let buffer = core::mem::take(&mut self.buffer);
+        let mut reader = BitReader::new(&buffer);
+        reader.read_bits(self.position as u32);
+        let result = self.decode_pieces(&mut reader, finishing);
+
+        // Keep the partial byte around
+        let position = reader.position();
+        self.buffer = buffer;
+        let keep = position / 8;
+        self.buffer.drain(..keep);
+        self.dropped += keep as u64;
+        self.position = position % 8;
+        result
+    }
+
+    fn decode_pieces(&mut self, reader: &mut BitReader, finishing: bool) -> Result<usize, Error> {
+        let consumed = self.dropped + (reader.position() + reader.remaining()) as u64 / 8;
+        let mut written = 0;
+        loop {
+            match self.state {
+                State::Header => {
+                    let mut r = reader.clone();
+                    let magic = match r.read_bits(24) {
+                        Some(magic) => magic,
+                        None => return Ok(written),
+                    };
+                    if magic != STREAM_MAGIC {
+                        return Err(self.error(ErrorKind::BadCode(magic), reader.position()));
+                    }
+                    let level = match r.read_bits(8) {
+                        Some(digit) => (digit as u8).wrapping_sub(b'0'),
+                        None => return Ok(written),
+                    };
+                    if !(MIN_LEVEL..=MAX_LEVEL).contains(&level) {
+                        let digit = level.wrapping_add(b'0');
+                        return Err(self.error(ErrorKind::UnsupportedVersion(digit), r.position()));
+                    }
+                    *reader = r;
+                    self.state = State::Blocks { level };
+                    self.combined = 0;
+                }
+                State::Blocks { level } => {
+                    let available = reader.remaining() / 8;
+                    if !finishing && available < 2 * self.attempted {
+                        return Ok(written);
+                    }
+                    let mut r = reader.clone();
+                    match read_piece(&mut r, level as usize * LEVEL_SIZE) {
+                        Ok(Piece::Block(block)) => {
+                            let start = reader.position();
+                            *reader = r;
+                            self.attempted = 0;
+                            written += self.write_block(block, start, consumed)?;
+                        }
+                        Ok(Piece::End { crc }) => {
+                            if crc != self.combined {
+                                let kind = ErrorKind::ChecksumMismatch {
+                                    expected: crc,
+                                    found: self.combined,
+                                };
+                                return Err(self.error(kind, r.position() - 32));
+                            }
+                            // Streams end on a byte boundary
+                            let padding = (8 - r.position() % 8) % 8;
+                            r.read_bits(padding as u32);
+                            *reader = r;
+                            self.attempted = 0;
+                            self.streams += 1;
+                            self.state = State::Header;
+                        }
+                        Err(ErrorKind::Truncated(_)) if !finishing => {
+                            self.attempted = available.max(1);
+                            return Ok(written);
+                        }
+                        Err(k) => return Err(self.error(k, r.position())),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Undo the BWT and [`Rle1`] for a block starting at bit `start`, write
+    /// it out and check its CRC. `consumed` is how much compressed data
+    /// there's been so far.
+    fn write_block(&mut self, block: Block, start: usize, consumed: u64) -> Result<usize, Error> {
+        self.options
+            .check_dictionary(block.last.len())
+            .map_err(|l| self.error(ErrorKind::LimitExceeded(l), start))?;
+        let mut data = bwt::inverse(&block.last, block.origin).map_err(|k| self.error(k, start))?;
+        drop(block.last);
+        if block.randomized {
+            randomize(&mut data);
+        }
+
+        let mut rle = Rle1.decoder();
+        let mut crc = Crc::new();
+        let mut output = Vec::new();
+        let mut written = 0;
+        for chunk in data.chunks(CHUNK) {
+            output.clear();
+            rle.push(chunk, &mut output)?;
+            self.options
+                .check_output(consumed, self.produced, output.len() as u64)
+                .map_err(|l| self.error(ErrorKind::LimitExceeded(l), start))?;
+            crc.update(&output);
+            self.writer.write_all(&output)?;
+            self.produced += output.len() as u64;
+            written += output.len();
+        }
+        rle.finish(&mut output).map_err(|e| {
+            let kind = e.kind().unwrap_or(ErrorKind::Truncated(0));
+            self.error(kind, start)
+        })?;
+
+        if crc.value() != block.crc {
+            let kind = ErrorKind::ChecksumMismatch {
+                expected: block.crc,
+                found: crc.value(),
+            };
+            return Err(self.error(kind, start + 48));
+        }
+        self.combined = combine(self.combined, block.crc);
+        Ok(written)
+    }
+
+    /// Carry on over `writer` instead, handing back the old one.
+    pub(crate) fn replace_writer<V>(self, writer: V) -> (Bzip2Decompressor<V>, W) {
+        let codec = Bzip2Decompressor {
+            writer,
+            options: self.options,
+            buffer: self.buffer,
+            position: self.position,
+            dropped: self.dropped,
+            produced: self.produced,
+            attempted: self.attempted,
+            state: self.state,
+            streams: self.streams,
+            combined: self.combined,
+        };
+        (codec, self.writer)
+    }
+
+    /// Finish decoding, failing if the last stream is incomplete.
+    pub fn finalize(mut self) -> Result<usize, Error> {
+        let written = self.decode(true)?;
+        match self.state {
+            State::Header if self.streams > 0 && self.buffer.is_empty() => Ok(written),
+            _ => {
+                let bits = self.buffer.len() * 8 - self.position;
+                Err(self.error(ErrorKind::Truncated(bits), self.position))
+            }
+        }
+    }
+}
+
+fn read(reader: &mut BitReader, bits: u32) -> Result<u32, ErrorKind> {
+    reader
+        .read_bits(bits)
+        .ok_or(ErrorKind::Truncated(reader.remaining()))
+}
+
+fn read_magic(reader: &mut BitReader) -> Result<u64, ErrorKind> {
+    Ok((read(reader, 24)? as u64) << 24 | read(reader, 24)? as u64)
+}
+
+/// Read the next block, or the end of the stream, from a stream with blocks
+/// of up to `max_block` bytes.
+fn read_piece(reader: &mut BitReader, max_block: usize) -> Result<Piece, ErrorKind> {
+    match read_magic(reader)? {
+        BLOCK_MAGIC => {}
+        END_MAGIC => {
+            return Ok(Piece::End {
+                crc: read(reader, 32)?,
+            })
+        }
+        magic => return Err(ErrorKind::BadCode((magic >> 16) as u32)),
+    }
+    let crc = read(reader, 32)?;
+    let randomized = read(reader, 1)? == 1;
+    let origin = read(reader, 24)? as usize;
+
+    let used = read_used(reader, 256)?;
+    let symbols = used
+        .iter()
+        .enumerate()
+        .filter(|(_, &u)| u)
+        .map(|(b, _)| b as u8)
+        .collect::<Vec<_>>();
+    if symbols.is_empty() {
+        return Err(ErrorKind::BadTable);
+    }
+    let alphabet = symbols.len() + 2;
+    let end_of_block = symbols.len() as u16 + 1;
+
+    let tables = read(reader, 3)? as usize;
+    if !(MIN_TABLES..=MAX_TABLES).contains(&tables) {
+        return Err(ErrorKind::BadTable);
+    }
+    let count = read(reader, 15)? as usize;
+    if count == 0 {
+        return Err(ErrorKind::BadTable);
+    }
+    let mut mtf = MtfList::new();
+    let mut selectors = Vec::with_capacity(count.min(MAX_SELECTORS));
+    for i in 0..count {
+        let mut j = 0;
+        while read(reader, 1)? == 1 {
+            j += 1;
+            if j >= tables {
+                return Err(ErrorKind::BadTable);
+            }
+        }
+        // Like the reference decoder, read the extras but don't keep them
+        if i < MAX_SELECTORS {
+            selectors.push(mtf.decode(j as u8) as usize);
+        }
+    }
+
+    let mut decoders = Vec::with_capacity(tables);
+    for _ in 0..tables {
+        let lengths = read_deltas(reader, alphabet)?;
+        if lengths.iter().any(|&l| l > MAX_CODE_LEN) {
+            return Err(ErrorKind::BadTable);
+        }
+        decoders.push(Decoder::new(&lengths)?);
+    }
+
+    // Huffman codes to RUNA/RUNB and move-to-front indices, then indices to
+    // bytes
+    let mut zero_run = ZeroRunDecoder::new();
+    let mut indices = Vec::new();
+    let mut groups = selectors.iter().map(|&s| &decoders[s]);
+    let mut decoder = &decoders[0];
+    for n in 0.. {
+        if n % GROUP_SIZE == 0 {
+            decoder = groups.next().ok_or(ErrorKind::BadCode(n as u32))?;
+        }
+        let symbol = match decoder.read(reader) {
+            Some(s) => s,
+            None if reader.remaining() < decoder.max_len() as usize => {
+                return Err(ErrorKind::Truncated(reader.remaining()))
+            }
+            None => {
+                return Err(ErrorKind::BadCode(
+                    reader.peek_bits(decoder.max_len() as u32),
+                ))
+            }
+        };
+        if symbol == end_of_block {
+            break;
+        }
+        zero_run
+            .push_symbol(symbol, &mut indices)
+            .ok_or(ErrorKind::BadCode(symbol as u32))?;
+        if indices.len() > max_block {
+            return Err(ErrorKind::BadCode(symbol as u32));
+        }
+    }
+    zero_run.flush_zeros(&mut indices);
+    if indices.len() > max_block {
+        return Err(ErrorKind::BadCode(end_of_block as u32));
+    }
+
+    let mut mtf = MtfList::new();
+    let last = indices
+        .into_iter()
+        .map(|i| symbols[mtf.decode(i) as usize])
+        .collect::<Vec<_>>();
+    if origin >= last.len() {
+        return Err(ErrorKind::BadCode(origin as u32));
+    }
+    Ok(Piece::Block(Block {
+        crc,
+        randomized,
+        origin,
+        last,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bits::BitWriter,
+        bzip2::{compress::write_block, Bzip2Compressor},
+        options::Limit,
+    };
+
+    /// `printf 'hello, hello, hello world\n' | bzip2 -9`
+    const HELLO: [u8; 54] = [
+        0x42, 0x5a, 0x68, 0x39, 0x31, 0x41, 0x59, 0x26, 0x53, 0x59, 0xcc, 0x2e, 0x03, 0xa1, 0x00,
+        0x00, 0x06, 0x51, 0x80, 0x00, 0x10, 0x40, 0x04, 0x06, 0x44, 0x90, 0x80, 0x20, 0x00, 0x21,
+        0x90, 0x32, 0x04, 0x00, 0xc2, 0xa8, 0x32, 0xd9, 0x41, 0x6e, 0x9c, 0x07, 0x9a, 0xf1, 0x77,
+        0x24, 0x53, 0x85, 0x09, 0x0c, 0xc2, 0xe0, 0x3a, 0x10,
+    ];
+
+    fn decompress(data: &[u8], options: DecompressOptions) -> Result<Vec<u8>, Error> {
+        let mut uncompressed = Vec::new();
+        let mut d = Bzip2Decompressor::with_options(&mut uncompressed, options);
+        d.read(data)?;
+        d.finalize()?;
+        Ok(uncompressed)
+    }
+
+    #[test]
+    fn reference() {
+        let uncompressed = decompress(&HELLO, DecompressOptions::new()).unwrap();
+        assert_eq!(uncompressed, b"hello, hello, hello world\n");
+    }
+
+    #[test]
+    fn serial() {
+        let mut uncompressed = Vec::new();
+        let mut d = Bzip2Decompressor::new(&mut uncompressed);
+        let written = HELLO.iter().map(|b| d.read(&[*b]).unwrap()).sum::<usize>();
+        assert_eq!(written + d.finalize().unwrap(), 26);
+        assert_eq!(uncompressed, b"hello, hello, hello world\n");
+    }
+
+    #[test]
+    fn round_trip() {
+        // Several level 1 blocks, the first of them full of runs
+        let mut data = b"abcd".repeat(20_000);
+        data.extend(vec![b'x'; 100_000]);
+        let mut state = 1_u32;
+        data.extend((0..150_000).map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 28) as u8
+        }));
+
+        let mut compressed = Vec::new();
+        let mut c = Bzip2Compressor::with_level(&mut compressed, 1);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+
+        let mut uncompressed = Vec::new();
+        let mut d = Bzip2Decompressor::new(&mut uncompressed);
+        for chunk in compressed.chunks(1000) {
+            d.read(chunk).unwrap();
+        }
+        d.finalize().unwrap();
+        assert!(uncompressed == data);
+    }
+
+    #[test]
+    fn concatenated() {
+        let mut data = HELLO.to_vec();
+        data.extend_from_slice(&HELLO);
+        let uncompressed = decompress(&data, DecompressOptions::new()).unwrap();
+        assert_eq!(uncompressed, b"hello, hello, hello world\n".repeat(2));
+    }
+
+    #[test]
+    fn randomized() {
+        let data = (0..5000).map(|i| (i % 13) as u8 + b'a').collect::<Vec<_>>();
+        let mut crc = Crc::new();
+        crc.update(&data);
+
+        let mut compressed = Vec::new();
+        let mut w = BitWriter::new(&mut compressed);
+        w.write_bits(STREAM_MAGIC << 8 | b'9' as u32, 32).unwrap();
+        let block = Rle1.forward(&data).unwrap();
+        write_block(&mut w, &block, crc.value(), true).unwrap();
+        w.write_bits((END_MAGIC >> 24) as u32, 24).unwrap();
+        w.write_bits(END_MAGIC as u32, 24).unwrap();
+        w.write_bits(combine(0, crc.value()), 32).unwrap();
+        w.flush().unwrap();
+
+        let uncompressed = decompress(&compressed, DecompressOptions::new()).unwrap();
+        assert_eq!(uncompressed, data);
+    }
+
+    #[test]
+    fn bad_crc() {
+        let mut data = HELLO;
+        data[10] ^= 1;
+        let e = decompress(&data, DecompressOptions::new()).unwrap_err();
+        assert_eq!(
+            e.kind(),
+            Some(ErrorKind::ChecksumMismatch {
+                expected: 0xcd2e_03a1,
+                found: 0xcc2e_03a1
+            })
+        );
+        assert_eq!(e.codec(), Some("bzip2"));
+        assert_eq!(e.bit_offset(), Some(80));
+    }
+
+    #[test]
+    fn truncated() {
+        let mut uncompressed = Vec::new();
+        let mut d = Bzip2Decompressor::new(&mut uncompressed);
+        assert_eq!(d.read(&HELLO[..40]).unwrap(), 0);
+        let e = d.finalize().unwrap_err();
+        assert!(matches!(e.kind(), Some(ErrorKind::Truncated(_))));
+
+        let d = Bzip2Decompressor::new(Vec::new());
+        let e = d.finalize().unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::Truncated(0)));
+    }
+
+    #[test]
+    fn bad_header() {
+        let e = decompress(b"BZh0", DecompressOptions::new()).unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::UnsupportedVersion(b'0')));
+        let e = decompress(b"PK\x03\x04", DecompressOptions::new()).unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::BadCode(0x504b03)));
+        assert_eq!(e.bit_offset(), Some(0));
+    }
+
+    #[test]
+    fn limits() {
+        let options = DecompressOptions::new().max_output_bytes(10);
+        let e = decompress(&HELLO, options).unwrap_err();
+        assert_eq!(
+            e.kind(),
+            Some(ErrorKind::LimitExceeded(Limit::OutputBytes(10)))
+        );
+
+        let options = DecompressOptions::new().max_dictionary_bytes(10);
+        let e = decompress(&HELLO, options).unwrap_err();
+        assert_eq!(
+            e.kind(),
+            Some(ErrorKind::LimitExceeded(Limit::DictionaryBytes(10)))
+        );
+    }
+}
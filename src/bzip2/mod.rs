@@ -0,0 +1,51 @@
+//! The bzip2 (`.bz2`) format, compatible with the reference `bzip2`.
+//!
+//! A stream is a `BZh` header naming the block size, then blocks of up to
+//! 900k. Each block goes through [`Rle1`], the [`bwt`], move-to-front and
+//! [`ZeroRun`], and the result is Huffman coded with up to six tables,
+//! switching between them every 50 symbols. Blocks start with a 48 bit
+//! magic number and aren't byte aligned, and each carries a CRC of its
+//! contents. The stream ends with another magic number and a CRC combining
+//! all of them.
+//!
+//! [`Bzip2Decompressor`] also reads concatenated streams, as `bzip2 -d`
+//! does, and randomized blocks from very old encoders.
+//!
+//! [`Rle1`]: crate::transform::Rle1
+//! [`bwt`]: crate::bwt
+//! [`ZeroRun`]: crate::transform::ZeroRun
+
+mod compress;
+pub(crate) mod crc;
+mod decompress;
+mod rand;
+
+pub use self::{
+    compress::{compress_bound, Bzip2Compressor},
+    decompress::Bzip2Decompressor,
+};
+
+/// Name reported in errors from the bzip2 codec.
+const CODEC: &str = "bzip2";
+
+/// `BZh`, the `h` marking Huffman coding.
+const STREAM_MAGIC: u32 = 0x42_5a_68;
+/// The digits of pi, marking the start of a block.
+const BLOCK_MAGIC: u64 = 0x3141_5926_5359;
+/// The square root of pi, marking the end of the stream.
+const END_MAGIC: u64 = 0x1772_4538_5090;
+
+pub const MIN_LEVEL: u8 = 1;
+pub const MAX_LEVEL: u8 = 9;
+/// Block size is the level times this.
+const LEVEL_SIZE: usize = 100_000;
+
+/// Symbols are coded in groups of this many, each group picking a table.
+const GROUP_SIZE: usize = 50;
+const MIN_TABLES: usize = 2;
+const MAX_TABLES: usize = 6;
+/// Most selectors the reference decoder keeps, any more are read and
+/// dropped.
+const MAX_SELECTORS: usize = 18002;
+/// Longest code a decoder accepts.
+const MAX_CODE_LEN: u8 = 20;
@@ -1,11 +1,171 @@
+use crate::options::Limit;
+
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum Error {
+    #[cfg(feature = "std")]
     #[error("I/O error")]
     IoError(#[from] std::io::Error),
 
-    #[error("Incomplete code, need {0} more bits")]
-    Incomplete(usize),
+    #[error("Output buffer is full")]
+    OutputFull,
+
+    /// Input pushed after the codec was finalized.
+    #[error("Stream already finished")]
+    Finished,
+
+    /// The compressed stream couldn't be decoded. Carries where in the stream
+    /// the problem was found, so corrupted files can be diagnosed.
+    #[error("{codec}: {kind} (at compressed bit {bit_offset}, decompressed byte {byte_offset})")]
+    Stream {
+        codec: &'static str,
+        kind: ErrorKind,
+        /// Offset in bits into the compressed stream.
+        bit_offset: u64,
+        /// Offset in bytes into the decompressed output.
+        byte_offset: u64,
+    },
+}
+
+/// What went wrong in a compressed stream.
+#[derive(Debug, Clone, Copy, PartialEq, thiserror::Error)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    #[error("stream truncated, {0} bits left over")]
+    Truncated(usize),
+
+    #[error("bad code received: {0}")]
+    BadCode(u32),
+
+    #[error("invalid code table")]
+    BadTable,
+
+    #[error("checksum mismatch, expected {expected:#010x} found {found:#010x}")]
+    ChecksumMismatch { expected: u32, found: u32 },
+
+    #[error("unsupported version: {0}")]
+    UnsupportedVersion(u8),
+
+    /// A saved codec state that can't be restored.
+    #[error("invalid snapshot")]
+    BadSnapshot,
+
+    /// A stream written against a preset dictionary other than the one
+    /// given.
+    #[error("wrong dictionary, stream wants {expected:#010x} given {found:#010x}")]
+    WrongDictionary { expected: u32, found: u32 },
+
+    /// Not the kind of stream expected, going by the magic number at its
+    /// start, which this carries.
+    #[error("wrong magic number {0:#010x}")]
+    BadMagic(u32),
+
+    /// A block that doesn't decompress to the length its header gives,
+    /// or gives one it can't have.
+    #[error("block not the {0} bytes its header says")]
+    BadBlockLength(u32),
+
+    #[error("limit exceeded: {0}")]
+    LimitExceeded(Limit),
+}
+
+impl Error {
+    pub(crate) fn stream(
+        codec: &'static str,
+        kind: ErrorKind,
+        bit_offset: u64,
+        byte_offset: u64,
+    ) -> Self {
+        Error::Stream {
+            codec,
+            kind,
+            bit_offset,
+            byte_offset,
+        }
+    }
+
+    /// The kind of stream error, `None` for I/O errors.
+    pub fn kind(&self) -> Option<ErrorKind> {
+        match self {
+            Error::Stream { kind, .. } => Some(*kind),
+            _ => None,
+        }
+    }
+
+    /// Name of the codec that found the error.
+    pub fn codec(&self) -> Option<&'static str> {
+        match self {
+            Error::Stream { codec, .. } => Some(codec),
+            _ => None,
+        }
+    }
+
+    pub fn bit_offset(&self) -> Option<u64> {
+        match self {
+            Error::Stream { bit_offset, .. } => Some(*bit_offset),
+            _ => None,
+        }
+    }
+
+    pub fn byte_offset(&self) -> Option<u64> {
+        match self {
+            Error::Stream { byte_offset, .. } => Some(*byte_offset),
+            _ => None,
+        }
+    }
+}
+
+/// I/O errors come back out as they went in. Stream errors are bad input,
+/// as [`std::io::ErrorKind::InvalidData`].
+#[cfg(feature = "std")]
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> Self {
+        use std::io::ErrorKind as Kind;
+        match e {
+            Error::IoError(e) => e,
+            Error::OutputFull => std::io::Error::new(Kind::WriteZero, e),
+            Error::Finished => std::io::Error::new(Kind::BrokenPipe, e),
+            Error::Stream { .. } => std::io::Error::new(Kind::InvalidData, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let e = Error::stream("lz78", ErrorKind::BadCode(7), 17, 3);
+        assert_eq!(
+            e.to_string(),
+            "lz78: bad code received: 7 (at compressed bit 17, decompressed byte 3)"
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn io_error() {
+        let e = Error::from(std::io::Error::from(std::io::ErrorKind::Other));
+        assert_eq!(e.kind(), None);
+        assert_eq!(e.bit_offset(), None);
+        let e = std::io::Error::from(e);
+        assert_eq!(e.kind(), std::io::ErrorKind::Other);
+
+        let e = std::io::Error::from(Error::stream("lz78", ErrorKind::BadTable, 0, 0));
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(
+            e.to_string(),
+            "lz78: invalid code table (at compressed bit 0, decompressed byte 0)"
+        );
+    }
 
-    #[error("Bad Code received: {0}")]
-    BadCode(u16),
+    #[test]
+    fn accessors() {
+        let e = Error::stream("lz78", ErrorKind::Truncated(3), 17, 3);
+        assert_eq!(e.kind(), Some(ErrorKind::Truncated(3)));
+        assert_eq!(e.codec(), Some("lz78"));
+        assert_eq!(e.bit_offset(), Some(17));
+        assert_eq!(e.byte_offset(), Some(3));
+    }
 }
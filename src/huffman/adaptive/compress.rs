@@ -0,0 +1,157 @@
+use crate::{
+    bits::BitWriter,
+    error::Error,
+    huffman::adaptive::{Tree, END_OF_STREAM, RAW_BITS, SYMBOLS},
+    io::Sink,
+    stats::Stats,
+};
+
+/// Compresses a byte stream with adaptive Huffman codes, writing each byte
+/// out as it comes in.
+pub struct AdaptiveHuffmanCompressor<W> {
+    tree: Tree,
+    writer: BitWriter<W>,
+    input: u64,
+    output: u64,
+}
+
+impl<W: Sink> AdaptiveHuffmanCompressor<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            tree: Tree::new(),
+            writer: BitWriter::new(writer),
+            input: 0,
+            output: 0,
+        }
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        let mut written = 0;
+        for &b in data {
+            written += self.encode(b as u16)?;
+        }
+        self.input += data.len() as u64;
+        self.output += written as u64;
+        Ok(written)
+    }
+
+    /// What's gone in and out so far.
+    pub fn stats(&self) -> Stats {
+        Stats::new(self.input, self.output * 8 + self.writer.pending_bits())
+    }
+
+    fn encode(&mut self, symbol: u16) -> Result<usize, Error> {
+        let (path, known) = self.tree.path(symbol);
+        let mut written = 0;
+        for &bit in path.iter().rev() {
+            written += self.writer.write_bits(bit as u32, 1)?;
+        }
+        if !known {
+            written += self.writer.write_bits(symbol as u32, RAW_BITS)?;
+        }
+        self.tree.update(symbol);
+        Ok(written)
+    }
+
+    /// Carry on over `writer` instead, handing back the old one.
+    pub(crate) fn replace_writer<V>(self, writer: V) -> (AdaptiveHuffmanCompressor<V>, W) {
+        let (writer, old) = self.writer.replace_sink(writer);
+        let codec = AdaptiveHuffmanCompressor {
+            tree: self.tree,
+            writer,
+            input: self.input,
+            output: self.output,
+        };
+        (codec, old)
+    }
+
+    /// Write the end of stream symbol and pad out the last byte.
+    pub fn finalize(mut self) -> Result<usize, Error> {
+        let written = self.encode(END_OF_STREAM)?;
+        Ok(written + self.writer.flush()?)
+    }
+}
+
+/// Worst case size of `len` bytes compressed by an
+/// [`AdaptiveHuffmanCompressor`]. The tree always has the sibling property,
+/// so the sibling of a node's parent weighs at least as much as the node,
+/// and weights grow at least as fast as the Fibonacci numbers going up from
+/// a leaf. A code `d` bits long needs a total weight of `F(d + 1)`, and no
+/// code is longer than the 256 a tree over every symbol allows. Each symbol
+/// sent raw costs another 9 bits.
+pub const fn adaptive_compress_bound(len: usize) -> usize {
+    let symbols = len as u64 + 1;
+    let raw = if symbols < SYMBOLS as u64 {
+        symbols
+    } else {
+        SYMBOLS as u64
+    };
+    let mut bits = raw * RAW_BITS as u64;
+
+    // Symbol `t` is coded with a total weight of `t`. Codes are `depth`
+    // bits long at most for totals from `F(depth + 1)` up to `F(depth + 2)`
+    let mut depth = 1;
+    let (mut low, mut high) = (1_u64, 2_u64);
+    while low < symbols {
+        let end = if depth == SYMBOLS as u64 - 1 || high > symbols {
+            symbols
+        } else {
+            high
+        };
+        bits += (end - low) * depth;
+        (low, high) = (end, low.saturating_add(high));
+        depth += 1;
+    }
+    bits.div_ceil(8) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{io::SliceSink, utils::noise};
+
+    fn compress(data: &[u8], out: &mut [u8]) -> Result<usize, Error> {
+        let mut c = AdaptiveHuffmanCompressor::new(SliceSink::new(out));
+        let written = c.write(data)?;
+        Ok(written + c.finalize()?)
+    }
+
+    #[test]
+    fn bound() {
+        assert_eq!(adaptive_compress_bound(0), 2);
+        let mut fibonacci = Vec::new();
+        let (mut a, mut b) = (1, 1);
+        for s in 0..25 {
+            fibonacci.extend(core::iter::repeat_n(s, a));
+            (a, b) = (b, a + b);
+        }
+        // Fibonacci weights make the deepest tree there is
+        let skewed = fibonacci.iter().rev().copied().collect::<Vec<u8>>();
+        for data in [noise(100_000), noise(300), vec![0; 1000], fibonacci, skewed] {
+            let mut out = vec![0; adaptive_compress_bound(data.len())];
+            compress(&data, &mut out).unwrap();
+        }
+    }
+
+    #[test]
+    fn empty() {
+        let mut compressed = Vec::new();
+        let c = AdaptiveHuffmanCompressor::new(&mut compressed);
+        assert_eq!(c.finalize().unwrap(), 2);
+        // Just the raw end of stream symbol
+        assert_eq!(compressed, [0b1000_0000, 0]);
+    }
+
+    #[test]
+    fn repeated() {
+        let mut compressed = Vec::new();
+        let mut c = AdaptiveHuffmanCompressor::new(&mut compressed);
+        assert_eq!(c.write(b"aaa").unwrap(), 0);
+        let stats = c.stats();
+        assert_eq!((stats.input_bytes, stats.output_bits), (3, 11));
+        assert_eq!(c.finalize().unwrap(), 3);
+        // 'a' raw, then 1, 1, then NYT (0) and 256 raw
+        let expected = [0b0011_0000, 0b1110_1000, 0b0000_0000];
+        assert_eq!(compressed, expected);
+    }
+}
@@ -0,0 +1,219 @@
+use alloc::vec::Vec;
+
+use crate::{
+    error::{Error, ErrorKind},
+    huffman::adaptive::{Tree, CODEC, END_OF_STREAM, RAW_BITS, SYMBOLS},
+    io::Sink,
+    options::{DecompressOptions, Limit},
+};
+
+/// Decompresses a stream from [`AdaptiveHuffmanCompressor`], updating its
+/// tree in step with the compressor.
+///
+/// [`AdaptiveHuffmanCompressor`]: crate::huffman::AdaptiveHuffmanCompressor
+pub struct AdaptiveHuffmanDecompressor<W> {
+    tree: Tree,
+    writer: W,
+    options: DecompressOptions,
+
+    consumed: u64,
+    produced: u64,
+    state: State,
+}
+
+enum State {
+    /// Walking down the tree, at this node.
+    Code(usize),
+    /// Reading a raw symbol after the NYT code.
+    Raw {
+        symbol: u16,
+        bits: u32,
+    },
+    Done,
+}
+
+impl<W: Sink> AdaptiveHuffmanDecompressor<W> {
+    pub fn new(writer: W) -> Self {
+        Self::with_options(writer, DecompressOptions::default())
+    }
+
+    pub fn with_options(writer: W, options: DecompressOptions) -> Self {
+        let tree = Tree::new();
+        let state = State::Code(tree.root());
+        Self {
+            tree,
+            writer,
+            options,
+
+            consumed: 0,
+            produced: 0,
+            state,
+        }
+    }
+
+    fn error(&self, kind: ErrorKind, bit_offset: u64) -> Error {
+        Error::stream(CODEC, kind, bit_offset, self.produced)
+    }
+
+    pub fn read(&mut self, data: &[u8]) -> Result<usize, Error> {
+        let mut output = Vec::new();
+        for &byte in data {
+            self.consumed += 1;
+            for i in (0..8).rev() {
+                // The root is a leaf when nothing has been seen, in which case
+                // the NYT node is reached without reading anything.
+                let offset = self.consumed * 8 - 1 - i;
+                self.walk_leaf(&mut output, offset)?;
+                let bit = (byte >> i) & 1 == 1;
+                match self.state {
+                    State::Code(node) => self.state = State::Code(self.tree.child(node, bit)),
+                    State::Raw { symbol, bits } => {
+                        let symbol = symbol << 1 | bit as u16;
+                        if bits + 1 < RAW_BITS {
+                            self.state = State::Raw {
+                                symbol,
+                                bits: bits + 1,
+                            };
+                            continue;
+                        }
+                        if symbol as usize >= SYMBOLS {
+                            return Err(self.error(ErrorKind::BadCode(symbol as u32), offset));
+                        }
+                        self.emit(symbol, &mut output, offset + 1)?;
+                    }
+                    State::Done => break,
+                }
+                self.walk_leaf(&mut output, offset + 1)?;
+            }
+        }
+
+        self.writer.write_all(&output)?;
+        self.produced += output.len() as u64;
+        Ok(output.len())
+    }
+
+    /// If the walk reached a leaf, output its symbol or start reading a raw
+    /// one. `offset` is the bit the walk got to.
+    fn walk_leaf(&mut self, output: &mut Vec<u8>, offset: u64) -> Result<(), Error> {
+        if let State::Code(node) = self.state {
+            if self.tree.is_nyt(node) {
+                self.state = State::Raw { symbol: 0, bits: 0 };
+            } else if self.tree.is_leaf(node) {
+                let symbol = self.tree.symbol(node).expect("leaves have symbols");
+                self.emit(symbol, output, offset)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, symbol: u16, output: &mut Vec<u8>, offset: u64) -> Result<(), Error> {
+        if symbol == END_OF_STREAM {
+            self.tree.update(symbol);
+            self.state = State::Done;
+            return Ok(());
+        }
+        let limit =
+            self.options
+                .check_output(self.consumed, self.produced, output.len() as u64 + 1);
+        if let Err(l) = limit {
+            return Err(self.limit_exceeded(output, l, offset));
+        }
+        self.tree.update(symbol);
+        output.push(symbol as u8);
+        self.state = State::Code(self.tree.root());
+        Ok(())
+    }
+
+    /// Write out what's been decoded and fail with `limit`, so output stops
+    /// right where it was crossed.
+    fn limit_exceeded(&mut self, output: &[u8], limit: Limit, offset: u64) -> Error {
+        if let Err(e) = self.writer.write_all(output) {
+            return e;
+        }
+        self.produced += output.len() as u64;
+        self.error(ErrorKind::LimitExceeded(limit), offset)
+    }
+
+    /// Carry on over `writer` instead, handing back the old one.
+    pub(crate) fn replace_writer<V>(self, writer: V) -> (AdaptiveHuffmanDecompressor<V>, W) {
+        let codec = AdaptiveHuffmanDecompressor {
+            tree: self.tree,
+            writer,
+            options: self.options,
+            consumed: self.consumed,
+            produced: self.produced,
+            state: self.state,
+        };
+        (codec, self.writer)
+    }
+
+    /// Finish the stream, failing if the end of stream symbol never arrived.
+    pub fn finalize(self) -> Result<usize, Error> {
+        match self.state {
+            State::Done => Ok(0),
+            _ => Err(self.error(ErrorKind::Truncated(0), self.consumed * 8)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        let mut uncompressed = Vec::new();
+        let mut d = AdaptiveHuffmanDecompressor::new(&mut uncompressed);
+        assert_eq!(d.read(&[0b1000_0000, 0]).unwrap(), 0);
+        assert_eq!(d.finalize().unwrap(), 0);
+        assert!(uncompressed.is_empty());
+    }
+
+    #[test]
+    fn repeated() {
+        let mut uncompressed = Vec::new();
+        let mut d = AdaptiveHuffmanDecompressor::new(&mut uncompressed);
+        let data = [0b0011_0000, 0b1110_1000, 0b0000_0000];
+        assert_eq!(d.read(&data[..1]).unwrap(), 0);
+        assert_eq!(d.read(&data[1..]).unwrap(), 3);
+        assert_eq!(d.finalize().unwrap(), 0);
+        assert_eq!(uncompressed, b"aaa");
+    }
+
+    #[test]
+    fn truncated() {
+        let mut uncompressed = Vec::new();
+        let mut d = AdaptiveHuffmanDecompressor::new(&mut uncompressed);
+        assert_eq!(d.read(&[0b0011_0000, 0b1110_1000]).unwrap(), 3);
+        let e = d.finalize().unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::Truncated(0)));
+        assert_eq!(e.codec(), Some("adaptive-huffman"));
+    }
+
+    #[test]
+    fn bad_symbol() {
+        let mut uncompressed = Vec::new();
+        let mut d = AdaptiveHuffmanDecompressor::new(&mut uncompressed);
+        let e = d.read(&[0b1111_1111, 0b1000_0000]).unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::BadCode(511)));
+        assert_eq!(e.bit_offset(), Some(8));
+    }
+
+    #[test]
+    fn max_output_bytes() {
+        let mut uncompressed = Vec::new();
+        let options = DecompressOptions::new().max_output_bytes(2);
+        let mut d = AdaptiveHuffmanDecompressor::with_options(&mut uncompressed, options);
+        let e = d
+            .read(&[0b0011_0000, 0b1110_1000, 0b0000_0000])
+            .unwrap_err();
+        assert_eq!(
+            e.kind(),
+            Some(ErrorKind::LimitExceeded(Limit::OutputBytes(2)))
+        );
+        // Stopped at the third 'a', with the first two written
+        assert_eq!(e.bit_offset(), Some(11));
+        assert_eq!(e.byte_offset(), Some(2));
+        assert_eq!(uncompressed, b"aa");
+    }
+}
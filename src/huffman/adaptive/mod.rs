@@ -0,0 +1,239 @@
+//! One pass adaptive Huffman coding (FGK). Both sides start from a tree with
+//! only the not-yet-transmitted (NYT) node and update it after every symbol,
+//! so no table is ever sent. New symbols are sent as the NYT code followed
+//! by the raw symbol.
+
+mod compress;
+mod decompress;
+
+use alloc::{vec, vec::Vec};
+
+pub use self::{
+    compress::{adaptive_compress_bound, AdaptiveHuffmanCompressor},
+    decompress::AdaptiveHuffmanDecompressor,
+};
+
+/// Name reported in errors from the adaptive Huffman codec.
+const CODEC: &str = "adaptive-huffman";
+
+/// Every byte value plus an end of stream symbol.
+const SYMBOLS: usize = 257;
+const END_OF_STREAM: u16 = 256;
+/// Width of a symbol sent raw after the NYT code.
+const RAW_BITS: u32 = 9;
+
+const NONE: usize = usize::MAX;
+
+#[derive(Debug, Clone)]
+struct Node {
+    weight: u64,
+    parent: usize,
+    /// Children of internal nodes, `NONE` for leaves.
+    left: usize,
+    right: usize,
+    symbol: Option<u16>,
+}
+
+/// The FGK tree. Nodes are stored in order of their order number, highest
+/// first, so the root is always at 0 and weights never increase along the
+/// array. The NYT node is always last.
+#[derive(Debug, Clone)]
+pub(crate) struct Tree {
+    nodes: Vec<Node>,
+    /// Index of each symbol's leaf, `NONE` until it's been seen.
+    leaves: Vec<usize>,
+}
+
+impl Tree {
+    pub(crate) fn new() -> Self {
+        Self {
+            nodes: vec![Node {
+                weight: 0,
+                parent: NONE,
+                left: NONE,
+                right: NONE,
+                symbol: None,
+            }],
+            leaves: vec![NONE; SYMBOLS],
+        }
+    }
+
+    pub(crate) fn root(&self) -> usize {
+        0
+    }
+
+    fn nyt(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    pub(crate) fn is_nyt(&self, node: usize) -> bool {
+        node == self.nyt()
+    }
+
+    /// The symbol at a leaf, `None` for internal nodes and the NYT node.
+    pub(crate) fn symbol(&self, node: usize) -> Option<u16> {
+        self.nodes[node].symbol
+    }
+
+    pub(crate) fn is_leaf(&self, node: usize) -> bool {
+        self.nodes[node].left == NONE
+    }
+
+    /// Follow a bit down from an internal node.
+    pub(crate) fn child(&self, node: usize, bit: bool) -> usize {
+        if bit {
+            self.nodes[node].right
+        } else {
+            self.nodes[node].left
+        }
+    }
+
+    /// Code for `symbol`, or for the NYT node if it hasn't been seen yet,
+    /// as the path from the root with the first bit last.
+    pub(crate) fn path(&self, symbol: u16) -> (Vec<bool>, bool) {
+        let leaf = self.leaves[symbol as usize];
+        let known = leaf != NONE;
+        let mut node = if known { leaf } else { self.nyt() };
+        let mut path = Vec::new();
+        while node != self.root() {
+            let parent = self.nodes[node].parent;
+            path.push(self.nodes[parent].right == node);
+            node = parent;
+        }
+        (path, known)
+    }
+
+    /// Count one more of `symbol`, keeping the sibling property.
+    pub(crate) fn update(&mut self, symbol: u16) {
+        let mut q = self.leaves[symbol as usize];
+        if q == NONE {
+            q = self.split_nyt(symbol);
+        }
+        while q != NONE {
+            let leader = self.leader(q);
+            if leader != q && leader != self.nodes[q].parent {
+                self.swap(q, leader);
+                q = leader;
+            }
+            self.nodes[q].weight += 1;
+            q = self.nodes[q].parent;
+        }
+    }
+
+    /// Give the NYT node two children, a leaf for `symbol` and a new NYT
+    /// node. Returns the new leaf.
+    fn split_nyt(&mut self, symbol: u16) -> usize {
+        let parent = self.nyt();
+        let leaf = parent + 1;
+        let nyt = parent + 2;
+        self.nodes[parent].right = leaf;
+        self.nodes[parent].left = nyt;
+        for s in [Some(symbol), None] {
+            self.nodes.push(Node {
+                weight: 0,
+                parent,
+                left: NONE,
+                right: NONE,
+                symbol: s,
+            });
+        }
+        self.leaves[symbol as usize] = leaf;
+        leaf
+    }
+
+    /// Highest ordered node with the same weight as `node`.
+    fn leader(&self, node: usize) -> usize {
+        let weight = self.nodes[node].weight;
+        let mut leader = node;
+        while leader > 0 && self.nodes[leader - 1].weight == weight {
+            leader -= 1;
+        }
+        leader
+    }
+
+    /// Swap the subtrees at `a` and `b`, leaving their parents in place.
+    fn swap(&mut self, a: usize, b: usize) {
+        let (pa, pb) = (self.nodes[a].parent, self.nodes[b].parent);
+        self.nodes.swap(a, b);
+        self.nodes[a].parent = pa;
+        self.nodes[b].parent = pb;
+        for n in [a, b] {
+            let Node {
+                left,
+                right,
+                symbol,
+                ..
+            } = self.nodes[n];
+            if left != NONE {
+                self.nodes[left].parent = n;
+                self.nodes[right].parent = n;
+            }
+            if let Some(s) = symbol {
+                self.leaves[s as usize] = n;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(tree: &Tree, symbol: u16) -> Vec<bool> {
+        let (mut path, _) = tree.path(symbol);
+        path.reverse();
+        path
+    }
+
+    /// Weights never increase along the array, and every internal node
+    /// weighs as much as its children.
+    fn check(tree: &Tree) {
+        for pair in tree.nodes.windows(2) {
+            assert!(pair[0].weight >= pair[1].weight);
+        }
+        for (i, n) in tree.nodes.iter().enumerate() {
+            if n.left != NONE {
+                assert_eq!(
+                    n.weight,
+                    tree.nodes[n.left].weight + tree.nodes[n.right].weight
+                );
+                assert_eq!(tree.nodes[n.left].parent, i);
+                assert_eq!(tree.nodes[n.right].parent, i);
+            }
+        }
+    }
+
+    #[test]
+    fn first_symbol() {
+        let mut t = Tree::new();
+        assert_eq!(t.path(b'a' as u16), (vec![], false));
+        t.update(b'a' as u16);
+        check(&t);
+        assert_eq!(code(&t, b'a' as u16), [true]);
+        assert_eq!(t.path(b'b' as u16), (vec![false], false));
+    }
+
+    #[test]
+    fn abracadabra() {
+        let mut t = Tree::new();
+        for &b in b"abracadabra" {
+            t.update(b as u16);
+            check(&t);
+        }
+        // 'a' is the most common by far
+        let a = code(&t, b'a' as u16).len();
+        for &b in b"bcdr" {
+            assert!(code(&t, b as u16).len() > a);
+        }
+    }
+
+    #[test]
+    fn swaps() {
+        let mut t = Tree::new();
+        for &b in b"abbbccc" {
+            t.update(b as u16);
+            check(&t);
+        }
+        assert_eq!(t.nodes[t.root()].weight, 7);
+    }
+}
@@ -0,0 +1,250 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    bits::{BitReader, BitWriter},
+    error::{Error, ErrorKind},
+    huffman::lengths::code_lengths,
+    io::Sink,
+};
+
+/// Longest code the crate will build or accept.
+pub const MAX_CODE_LEN: u8 = 24;
+
+/// Codes up to this long are decoded with a single table lookup.
+const PRIMARY_BITS: u8 = 10;
+
+/// Canonical Huffman codes for an alphabet, built from code lengths. Codes
+/// of the same length count up in symbol order, so the lengths alone are
+/// enough to rebuild them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Codebook {
+    lengths: Vec<u8>,
+    codes: Vec<u32>,
+}
+
+impl Codebook {
+    /// Build length limited codes for symbols with the given frequencies.
+    pub fn from_frequencies(frequencies: &[u32], max_len: u8) -> Self {
+        Self::from_lengths(&code_lengths(frequencies, max_len.min(MAX_CODE_LEN)))
+            .expect("package-merge makes valid lengths")
+    }
+
+    /// Assign canonical codes to the given lengths, a length of zero means
+    /// the symbol isn't used. Fails with [`ErrorKind::BadTable`] if there are
+    /// more codes than fit.
+    pub fn from_lengths(lengths: &[u8]) -> Result<Self, ErrorKind> {
+        let (first, _) = first_codes(lengths)?;
+        let mut next = first;
+        let codes = lengths
+            .iter()
+            .map(|&l| {
+                let c = next[l as usize];
+                next[l as usize] += 1;
+                c
+            })
+            .collect();
+        Ok(Self {
+            lengths: lengths.to_vec(),
+            codes,
+        })
+    }
+
+    pub fn lengths(&self) -> &[u8] {
+        &self.lengths
+    }
+
+    /// The code for `symbol` and how many bits long it is.
+    pub fn code(&self, symbol: usize) -> (u32, u8) {
+        (self.codes[symbol], self.lengths[symbol])
+    }
+
+    pub fn write_symbol<S: Sink>(
+        &self,
+        writer: &mut BitWriter<S>,
+        symbol: usize,
+    ) -> Result<usize, Error> {
+        debug_assert!(self.lengths[symbol] > 0, "symbol {} has no code", symbol);
+        writer.write_bits(self.codes[symbol], self.lengths[symbol] as u32)
+    }
+}
+
+/// Smallest code of each length, and how many codes of each length there
+/// are.
+fn first_codes(lengths: &[u8]) -> Result<([u32; 33], [u32; 33]), ErrorKind> {
+    let mut counts = [0_u32; 33];
+    for &l in lengths {
+        if l > MAX_CODE_LEN {
+            return Err(ErrorKind::BadTable);
+        }
+        counts[l as usize] += 1;
+    }
+    counts[0] = 0;
+    let mut first = [0_u32; 33];
+    let mut code = 0_u64;
+    for len in 1..=MAX_CODE_LEN as usize {
+        code = (code + counts[len - 1] as u64) << 1;
+        if code + counts[len] as u64 > 1 << len {
+            // Over-subscribed
+            return Err(ErrorKind::BadTable);
+        }
+        first[len] = code as u32;
+    }
+    Ok((first, counts))
+}
+
+/// Table driven decoder for canonical codes. Short codes take one lookup,
+/// longer ones fall back to walking the lengths.
+#[derive(Debug, Clone)]
+pub struct Decoder {
+    /// Indexed by the next `primary_bits` bits, `(symbol, length)` with a
+    /// length of zero for codes that don't fit.
+    primary: Vec<(u16, u8)>,
+    primary_bits: u8,
+    max_len: u8,
+    first: [u32; 33],
+    counts: [u32; 33],
+    /// Index into `symbols` of the first code of each length.
+    offsets: [u32; 33],
+    /// Symbols sorted by code.
+    symbols: Vec<u16>,
+}
+
+impl Decoder {
+    pub fn new(lengths: &[u8]) -> Result<Self, ErrorKind> {
+        let book = Codebook::from_lengths(lengths)?;
+        let (first, counts) = first_codes(lengths)?;
+        let max_len = lengths.iter().copied().max().unwrap_or(0);
+
+        let mut offsets = [0; 33];
+        for len in 1..=MAX_CODE_LEN as usize {
+            offsets[len] = offsets[len - 1] + counts[len - 1];
+        }
+        let mut symbols = vec![0; lengths.iter().filter(|&&l| l > 0).count()];
+        let mut next = offsets;
+        for (s, &l) in lengths.iter().enumerate().filter(|(_, &l)| l > 0) {
+            symbols[next[l as usize] as usize] = s as u16;
+            next[l as usize] += 1;
+        }
+
+        let primary_bits = max_len.min(PRIMARY_BITS);
+        let mut primary = vec![(0, 0); 1 << primary_bits];
+        for (s, &l) in lengths.iter().enumerate() {
+            if l == 0 || l > primary_bits {
+                continue;
+            }
+            let (code, _) = book.code(s);
+            let shift = primary_bits - l;
+            let start = (code << shift) as usize;
+            for entry in &mut primary[start..start + (1 << shift)] {
+                *entry = (s as u16, l);
+            }
+        }
+
+        Ok(Self {
+            primary,
+            primary_bits,
+            max_len,
+            first,
+            counts,
+            offsets,
+            symbols,
+        })
+    }
+
+    /// Longest code in the table.
+    pub fn max_len(&self) -> u8 {
+        self.max_len
+    }
+
+    /// Decode the symbol at the front of `reader` without consuming it.
+    /// Returns the symbol and its code length, `None` if the bits don't
+    /// match a code or there aren't enough of them.
+    pub fn peek(&self, reader: &BitReader) -> Option<(u16, u8)> {
+        let bits = reader.peek_bits(self.max_len as u32);
+        let (symbol, len) = self.primary[(bits >> (self.max_len - self.primary_bits)) as usize];
+        let found = if len > 0 {
+            Some((symbol, len))
+        } else {
+            (self.primary_bits + 1..=self.max_len).find_map(|len| {
+                let code = bits >> (self.max_len - len);
+                let index = code.checked_sub(self.first[len as usize])?;
+                if index < self.counts[len as usize] {
+                    let i = self.offsets[len as usize] + index;
+                    Some((self.symbols[i as usize], len))
+                } else {
+                    None
+                }
+            })
+        };
+        found.filter(|&(_, len)| len as usize <= reader.remaining())
+    }
+
+    /// Decode and consume the symbol at the front of `reader`.
+    pub fn read(&self, reader: &mut BitReader) -> Option<u16> {
+        let (symbol, len) = self.peek(reader)?;
+        reader.read_bits(len as u32);
+        Some(symbol)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical() {
+        // The example from RFC 1951
+        let book = Codebook::from_lengths(&[3, 3, 3, 3, 3, 2, 4, 4]).unwrap();
+        let codes = (0..8).map(|s| book.code(s).0).collect::<Vec<_>>();
+        assert_eq!(codes, [2, 3, 4, 5, 6, 0, 14, 15]);
+    }
+
+    #[test]
+    fn over_subscribed() {
+        assert_eq!(Codebook::from_lengths(&[1, 1, 1]), Err(ErrorKind::BadTable));
+        assert_eq!(Codebook::from_lengths(&[25]), Err(ErrorKind::BadTable));
+        assert!(Decoder::new(&[1, 2, 2, 2]).is_err());
+    }
+
+    #[test]
+    fn incomplete() {
+        let d = Decoder::new(&[1, 0, 2]).unwrap();
+        // "0" is symbol 0, "10" is symbol 2 and "11" isn't anything
+        let data = [0b0101_1000];
+        let mut r = BitReader::new(&data);
+        assert_eq!(d.read(&mut r), Some(0));
+        assert_eq!(d.read(&mut r), Some(2));
+        assert_eq!(d.read(&mut r), None);
+        assert_eq!(r.position(), 3);
+    }
+
+    #[test]
+    fn round_trip_long_codes() {
+        let frequencies = (0..40).map(|i| 1 << (i / 2)).collect::<Vec<u32>>();
+        let book = Codebook::from_frequencies(&frequencies, 20);
+        assert!(book.lengths().iter().any(|&l| l > PRIMARY_BITS));
+
+        let mut out = Vec::new();
+        let mut w = BitWriter::new(&mut out);
+        for s in 0..40 {
+            book.write_symbol(&mut w, s).unwrap();
+        }
+        w.flush().unwrap();
+
+        let d = Decoder::new(book.lengths()).unwrap();
+        let mut r = BitReader::new(&out);
+        for s in 0..40 {
+            assert_eq!(d.read(&mut r), Some(s));
+        }
+        assert!(r.is_padding());
+    }
+
+    #[test]
+    fn not_enough_bits() {
+        let d = Decoder::new(&[1, 2, 2]).unwrap();
+        let data = [0b1100_0001];
+        let mut r = BitReader::new(&data);
+        r.read_bits(7);
+        assert_eq!(d.peek(&r), None);
+    }
+}
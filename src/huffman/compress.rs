@@ -0,0 +1,155 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    bits::BitWriter,
+    error::Error,
+    huffman::{write_lengths, Codebook, BYTE_CODE_LEN, END_OF_BLOCK, SYMBOLS},
+    io::Sink,
+    stats::Stats,
+};
+
+/// Compresses a byte stream with a single Huffman table. The table depends
+/// on the whole input, so nothing is written until [`finalize`].
+///
+/// [`finalize`]: HuffmanCompressor::finalize
+pub struct HuffmanCompressor<W> {
+    writer: BitWriter<W>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Sink> HuffmanCompressor<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: BitWriter::new(writer),
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.buffer.extend_from_slice(data);
+        Ok(0)
+    }
+
+    /// What's gone in so far. Nothing comes out until [`finalize`].
+    ///
+    /// [`finalize`]: HuffmanCompressor::finalize
+    pub fn stats(&self) -> Stats {
+        Stats::new(self.buffer.len() as u64, 0)
+    }
+
+    /// Carry on over `writer` instead, handing back the old one.
+    pub(crate) fn replace_writer<V>(self, writer: V) -> (HuffmanCompressor<V>, W) {
+        let (writer, old) = self.writer.replace_sink(writer);
+        let codec = HuffmanCompressor {
+            writer,
+            buffer: self.buffer,
+        };
+        (codec, old)
+    }
+
+    /// Write the code length table, then every byte, then the end of block
+    /// symbol.
+    pub fn finalize(mut self) -> Result<usize, Error> {
+        let mut frequencies = vec![0_u32; SYMBOLS];
+        for &b in &self.buffer {
+            frequencies[b as usize] = frequencies[b as usize].saturating_add(1);
+        }
+        frequencies[END_OF_BLOCK] = 1;
+        let book = Codebook::from_frequencies(&frequencies, BYTE_CODE_LEN);
+
+        let mut written = write_lengths(&mut self.writer, book.lengths())?;
+        for &b in &self.buffer {
+            written += book.write_symbol(&mut self.writer, b as usize)?;
+        }
+        written += book.write_symbol(&mut self.writer, END_OF_BLOCK)?;
+        Ok(written + self.writer.flush()?)
+    }
+}
+
+/// Worst case size of `len` bytes compressed by a [`HuffmanCompressor`].
+/// The codes are optimal for the input, so they cost no more than a flat
+/// code over the symbols it uses, and the table no more than every length
+/// stepping between one and the longest code a tree over them can have.
+pub const fn compress_bound(len: usize) -> usize {
+    let len = len as u64;
+    // Bytes in use, plus the end of block symbol
+    let used = if len < 256 { len + 1 } else { SYMBOLS as u64 };
+    let groups = SYMBOLS.div_ceil(16) as u64;
+    let used_groups = if used < groups { used } else { groups };
+    let longest = if used <= 2 {
+        1
+    } else if used - 1 < BYTE_CODE_LEN as u64 {
+        used - 1
+    } else {
+        BYTE_CODE_LEN as u64
+    };
+    let flat = (u64::BITS - (used - 1).leading_zeros()) as u64;
+    let flat = if flat == 0 { 1 } else { flat };
+
+    let table = groups + 16 * used_groups + 5 + used + 2 * (used - 1) * (longest - 1);
+    let bits = table + (len + 1) * flat;
+    bits.div_ceil(8) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{io::SliceSink, utils::noise};
+
+    #[test]
+    fn empty() {
+        let mut compressed = Vec::new();
+        let c = HuffmanCompressor::new(&mut compressed);
+        assert_eq!(c.finalize().unwrap(), 4);
+        // Only group 16 and symbol 256 are used, with a length of 1 and
+        // the code 0
+        assert_eq!(compressed, [0, 0, 0b1100_0010, 0]);
+    }
+
+    #[test]
+    fn bound() {
+        assert!(compress_bound(0) >= 4);
+        assert!(compress_bound(3) >= 7);
+        for data in [
+            noise(100_000),
+            noise(200),
+            (0..=255).collect(),
+            vec![7; 1000],
+        ] {
+            let mut out = vec![0; compress_bound(data.len())];
+            let mut c = HuffmanCompressor::new(SliceSink::new(&mut out));
+            c.write(&data).unwrap();
+            c.finalize().unwrap();
+        }
+        // Doubling frequencies make the longest codes and the widest walk
+        // through the lengths
+        let mut data = Vec::new();
+        for b in 0..20 {
+            data.extend(core::iter::repeat_n(b, 1 << b));
+        }
+        let mut out = vec![0; compress_bound(data.len())];
+        let mut c = HuffmanCompressor::new(SliceSink::new(&mut out));
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+    }
+
+    #[test]
+    fn two_symbols() {
+        let mut compressed = Vec::new();
+        let mut c = HuffmanCompressor::new(&mut compressed);
+        assert_eq!(c.write(b"aab").unwrap(), 0);
+        assert_eq!(c.finalize().unwrap(), 7);
+        // Groups 6 and 16 hold 'a', 'b' and 256, with lengths 1, 2 and 2
+        // and codes 0, 10 and 11
+        let expected = [
+            0b0000_0010,
+            0b0000_0000,
+            0b1011_0000,
+            0b0000_0000,
+            0b0100_0010,
+            0b1000_0010,
+            0b1100_0000,
+        ];
+        assert_eq!(compressed, expected);
+    }
+}
@@ -0,0 +1,279 @@
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::{
+    bits::BitReader,
+    error::{Error, ErrorKind},
+    huffman::{read_lengths, Decoder, CODEC, END_OF_BLOCK, SYMBOLS},
+    io::Sink,
+    options::{DecompressOptions, Limit},
+};
+
+/// Decompresses a stream from [`HuffmanCompressor`], writing bytes out as
+/// soon as their codes arrive.
+///
+/// [`HuffmanCompressor`]: crate::huffman::HuffmanCompressor
+pub struct HuffmanDecompressor<W> {
+    writer: W,
+    options: DecompressOptions,
+
+    /// Compressed bytes not fully decoded yet.
+    buffer: Vec<u8>,
+    /// Bit offset into `buffer` of the next unread bit.
+    position: usize,
+    /// Bytes dropped from the front of `buffer` so far.
+    dropped: u64,
+    produced: u64,
+
+    state: State,
+}
+
+enum State {
+    Table,
+    Symbols(Box<Decoder>),
+    Done,
+}
+
+impl<W: Sink> HuffmanDecompressor<W> {
+    pub fn new(writer: W) -> Self {
+        Self::with_options(writer, DecompressOptions::default())
+    }
+
+    pub fn with_options(writer: W, options: DecompressOptions) -> Self {
+        Self {
+            writer,
+            options,
+
+            buffer: Vec::new(),
+            position: 0,
+            dropped: 0,
+            produced: 0,
+
+            state: State::Table,
+        }
+    }
+
+    fn error(&self, kind: ErrorKind, position: usize) -> Error {
+        Error::stream(
+            CODEC,
+            kind,
+            self.dropped * 8 + position as u64,
+            self.produced,
+        )
+    }
+
+    pub fn read(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.buffer.extend_from_slice(data);
+        let consumed = self.dropped + self.buffer.len() as u64;
+        let mut reader = BitReader::new(&self.buffer);
+        reader.read_bits(self.position as u32);
+        let mut output = Vec::new();
+
+        loop {
+            match &self.state {
+                State::Table => {
+                    let mut r = reader.clone();
+                    match read_lengths(&mut r, SYMBOLS) {
+                        Ok(lengths) => {
+                            let decoder = Decoder::new(&lengths)
+                                .map_err(|k| self.error(k, reader.position()))?;
+                            reader = r;
+                            self.state = State::Symbols(Box::new(decoder));
+                        }
+                        // Wait for the rest of the table
+                        Err(ErrorKind::Truncated(_)) => break,
+                        Err(k) => return Err(self.error(k, reader.position())),
+                    }
+                }
+                State::Symbols(decoder) => match decoder.read(&mut reader) {
+                    Some(s) if s as usize == END_OF_BLOCK => self.state = State::Done,
+                    Some(s) => {
+                        let limit = self.options.check_output(
+                            consumed,
+                            self.produced,
+                            output.len() as u64 + 1,
+                        );
+                        if let Err(l) = limit {
+                            return Err(self.limit_exceeded(&output, l, reader.position()));
+                        }
+                        output.push(s as u8);
+                    }
+                    None if reader.remaining() < decoder.max_len() as usize => break,
+                    None => {
+                        let code = reader.peek_bits(decoder.max_len() as u32);
+                        return Err(self.error(ErrorKind::BadCode(code), reader.position()));
+                    }
+                },
+                State::Done => {
+                    // Only the zero bits padding out the last byte can follow
+                    let pad = (8 - reader.position() % 8) % 8;
+                    let bits = reader.read_bits(pad as u32).unwrap_or(0);
+                    if bits != 0 {
+                        let position = reader.position() - pad;
+                        return Err(self.error(ErrorKind::BadCode(bits), position));
+                    }
+                    if reader.remaining() > 0 {
+                        let byte = reader.peek_bits(8);
+                        return Err(self.error(ErrorKind::BadCode(byte), reader.position()));
+                    }
+                    break;
+                }
+            }
+        }
+
+        let position = reader.position();
+        self.writer.write_all(&output)?;
+        self.produced += output.len() as u64;
+
+        // Keep the partial byte around
+        let keep = position / 8;
+        self.buffer.drain(..keep);
+        self.dropped += keep as u64;
+        self.position = position % 8;
+        Ok(output.len())
+    }
+
+    /// Write out what's been decoded and fail with `limit`, so output stops
+    /// right where it was crossed.
+    fn limit_exceeded(&mut self, output: &[u8], limit: Limit, position: usize) -> Error {
+        if let Err(e) = self.writer.write_all(output) {
+            return e;
+        }
+        self.produced += output.len() as u64;
+        self.error(ErrorKind::LimitExceeded(limit), position)
+    }
+
+    /// Carry on over `writer` instead, handing back the old one.
+    pub(crate) fn replace_writer<V>(self, writer: V) -> (HuffmanDecompressor<V>, W) {
+        let codec = HuffmanDecompressor {
+            writer,
+            options: self.options,
+            buffer: self.buffer,
+            position: self.position,
+            dropped: self.dropped,
+            produced: self.produced,
+            state: self.state,
+        };
+        (codec, self.writer)
+    }
+
+    /// Finish the stream, failing if the end of block symbol never arrived.
+    pub fn finalize(self) -> Result<usize, Error> {
+        match self.state {
+            State::Done => Ok(0),
+            _ => {
+                let bits = self.buffer.len() * 8 - self.position;
+                Err(self.error(ErrorKind::Truncated(bits), self.position))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TWO_SYMBOLS: [u8; 7] = [
+        0b0000_0010,
+        0b0000_0000,
+        0b1011_0000,
+        0b0000_0000,
+        0b0100_0010,
+        0b1000_0010,
+        0b1100_0000,
+    ];
+
+    #[test]
+    fn empty() {
+        let mut uncompressed = Vec::new();
+        let mut d = HuffmanDecompressor::new(&mut uncompressed);
+        assert_eq!(d.read(&[0, 0, 0b1100_0010, 0]).unwrap(), 0);
+        assert_eq!(d.finalize().unwrap(), 0);
+        assert!(uncompressed.is_empty());
+    }
+
+    #[test]
+    fn two_symbols() {
+        let mut uncompressed = Vec::new();
+        let mut d = HuffmanDecompressor::new(&mut uncompressed);
+        assert_eq!(d.read(&TWO_SYMBOLS).unwrap(), 3);
+        assert_eq!(d.finalize().unwrap(), 0);
+        assert_eq!(uncompressed, b"aab");
+    }
+
+    #[test]
+    fn two_symbols_serial() {
+        let mut uncompressed = Vec::new();
+        let mut d = HuffmanDecompressor::new(&mut uncompressed);
+        let written = TWO_SYMBOLS
+            .iter()
+            .map(|b| d.read(&[*b]).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(written, [0, 0, 0, 0, 0, 3, 0]);
+        assert_eq!(d.finalize().unwrap(), 0);
+        assert_eq!(uncompressed, b"aab");
+    }
+
+    #[test]
+    fn truncated() {
+        let mut uncompressed = Vec::new();
+        let mut d = HuffmanDecompressor::new(&mut uncompressed);
+        assert_eq!(d.read(&TWO_SYMBOLS[..6]).unwrap(), 3);
+        let e = d.finalize().unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::Truncated(0)));
+        assert_eq!(e.bit_offset(), Some(48));
+    }
+
+    #[test]
+    fn trailing() {
+        let mut uncompressed = Vec::new();
+        let mut d = HuffmanDecompressor::new(&mut uncompressed);
+        d.read(&TWO_SYMBOLS).unwrap();
+        let e = d.read(&[7]).unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::BadCode(7)));
+        assert_eq!(e.bit_offset(), Some(56));
+
+        // A stray bit in the padding
+        let mut data = TWO_SYMBOLS;
+        data[6] |= 1;
+        let mut d = HuffmanDecompressor::new(&mut uncompressed);
+        let e = d.read(&data).unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::BadCode(1)));
+    }
+
+    #[test]
+    fn bad_table() {
+        let mut uncompressed = Vec::new();
+        let mut d = HuffmanDecompressor::new(&mut uncompressed);
+        // 'a' and 'b' both with a length of one, plus 256
+        let data = [0b0000_0010, 0, 0b1011_0000, 0, 0b0100_0010, 0, 0];
+        let e = d.read(&data).unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::BadTable));
+        assert_eq!(e.codec(), Some("huffman"));
+    }
+
+    #[test]
+    fn bad_code() {
+        let mut uncompressed = Vec::new();
+        let mut d = HuffmanDecompressor::new(&mut uncompressed);
+        // Only 'a' and 'b' with a length of 2, so "1x" isn't a code
+        let data = [0b0000_0010, 0, 0b0011_0000, 0, 0b0000_1000, 0b1100_0000];
+        let e = d.read(&data).unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::BadCode(3)));
+        assert_eq!(e.bit_offset(), Some(40));
+    }
+
+    #[test]
+    fn max_output_bytes() {
+        let mut uncompressed = Vec::new();
+        let options = DecompressOptions::new().max_output_bytes(2);
+        let mut d = HuffmanDecompressor::with_options(&mut uncompressed, options);
+        let e = d.read(&TWO_SYMBOLS).unwrap_err();
+        assert_eq!(
+            e.kind(),
+            Some(ErrorKind::LimitExceeded(Limit::OutputBytes(2)))
+        );
+        // Stopped at the limit, with what came before it written
+        assert_eq!(e.byte_offset(), Some(2));
+        assert_eq!(uncompressed, b"aa");
+    }
+}
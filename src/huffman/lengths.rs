@@ -0,0 +1,124 @@
+use alloc::{vec, vec::Vec};
+
+/// Optimal code lengths for `frequencies`, none longer than `max_len`, using
+/// package-merge. Symbols with a frequency of zero get a length of zero. A
+/// lone symbol still gets a one bit code.
+///
+/// Panics if `max_len` is too short to give every used symbol a code.
+pub fn code_lengths(frequencies: &[u32], max_len: u8) -> Vec<u8> {
+    let mut lengths = vec![0; frequencies.len()];
+    let mut leaves = frequencies
+        .iter()
+        .enumerate()
+        .filter(|(_, &f)| f > 0)
+        .map(|(s, &f)| (f as u64, s))
+        .collect::<Vec<_>>();
+    leaves.sort_unstable();
+
+    match leaves.len() {
+        0 => return lengths,
+        1 => {
+            lengths[leaves[0].1] = 1;
+            return lengths;
+        }
+        n => assert!(
+            (n as u64) <= 1 << max_len,
+            "{} symbols don't fit in {} bit codes",
+            n,
+            max_len
+        ),
+    }
+
+    // Every level is the leaves merged with the packages made by pairing up
+    // the level below. Items are `(weight, Some(symbol))` for leaves and
+    // `(weight, None)` for packages.
+    let mut levels: Vec<Vec<(u64, Option<usize>)>> = Vec::with_capacity(max_len as usize);
+    levels.push(leaves.iter().map(|&(w, s)| (w, Some(s))).collect());
+    for _ in 1..max_len {
+        let below = levels.last().unwrap();
+        let packages = below.chunks_exact(2).map(|p| (p[0].0 + p[1].0, None));
+        let mut level = Vec::with_capacity(leaves.len() * 2);
+        let mut leaves = leaves.iter().map(|&(w, s)| (w, Some(s))).peekable();
+        let mut packages = packages.peekable();
+        loop {
+            let item = match (leaves.peek(), packages.peek()) {
+                (Some(l), Some(p)) if l.0 <= p.0 => leaves.next(),
+                (Some(_), Some(_)) => packages.next(),
+                (Some(_), None) => leaves.next(),
+                (None, Some(_)) => packages.next(),
+                (None, None) => break,
+            };
+            level.extend(item);
+        }
+        levels.push(level);
+    }
+
+    // Take the cheapest 2n - 2 items from the top level. Each package taken
+    // from a level means taking the two items it was made from one level
+    // down, and every time a leaf is taken its code gets a bit longer.
+    let mut take = 2 * leaves.len() - 2;
+    for level in levels.iter().rev() {
+        let mut packages = 0;
+        for item in &level[..take] {
+            match item.1 {
+                Some(s) => lengths[s] += 1,
+                None => packages += 1,
+            }
+        }
+        take = 2 * packages;
+    }
+    lengths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kraft(lengths: &[u8]) -> f64 {
+        lengths
+            .iter()
+            .filter(|&&l| l > 0)
+            .map(|&l| 0.5_f64.powi(l as i32))
+            .sum()
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(code_lengths(&[0, 0], 15), [0, 0]);
+    }
+
+    #[test]
+    fn one_symbol() {
+        assert_eq!(code_lengths(&[0, 7, 0], 15), [0, 1, 0]);
+    }
+
+    #[test]
+    fn unlimited() {
+        assert_eq!(code_lengths(&[1, 1, 2, 4], 15), [3, 3, 2, 1]);
+        assert_eq!(code_lengths(&[5, 5, 5, 5], 15), [2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn limited() {
+        // Fibonacci weights make the deepest possible tree
+        let frequencies = [1, 1, 2, 3, 5, 8, 13, 21, 34, 55];
+        let lengths = code_lengths(&frequencies, 15);
+        assert_eq!(lengths, [9, 9, 8, 7, 6, 5, 4, 3, 2, 1]);
+
+        let lengths = code_lengths(&frequencies, 4);
+        assert!(lengths.iter().all(|&l| l <= 4));
+        assert_eq!(kraft(&lengths), 1.0);
+    }
+
+    #[test]
+    fn exactly_full() {
+        let lengths = code_lengths(&[1; 256], 8);
+        assert!(lengths.iter().all(|&l| l == 8));
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_short() {
+        code_lengths(&[1; 257], 8);
+    }
+}
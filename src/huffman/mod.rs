@@ -0,0 +1,38 @@
+//! Static Huffman coding with canonical, length limited codes.
+//!
+//! The pieces work on their own: [`code_lengths`] picks lengths with
+//! package-merge, [`Codebook`] assigns canonical codes, [`write_lengths`]
+//! stores the lengths compactly and [`Decoder`] reads codes back with a
+//! lookup table. [`HuffmanCompressor`] and [`HuffmanDecompressor`] put them
+//! together for byte streams.
+//!
+//! [`AdaptiveHuffmanCompressor`] and [`AdaptiveHuffmanDecompressor`] adapt
+//! their codes as they go instead, so there's no table to send.
+
+mod adaptive;
+mod code;
+mod compress;
+mod decompress;
+mod lengths;
+mod table;
+
+pub use self::{
+    adaptive::{adaptive_compress_bound, AdaptiveHuffmanCompressor, AdaptiveHuffmanDecompressor},
+    code::{Codebook, Decoder, MAX_CODE_LEN},
+    compress::{compress_bound, HuffmanCompressor},
+    decompress::HuffmanDecompressor,
+    lengths::code_lengths,
+    table::{read_lengths, write_lengths},
+};
+
+pub(crate) use self::table::{read_deltas, read_used, write_deltas, write_used};
+
+/// Name reported in errors from the Huffman codec.
+const CODEC: &str = "huffman";
+
+/// Byte streams use every byte value plus an end of block symbol.
+const SYMBOLS: usize = 257;
+const END_OF_BLOCK: usize = 256;
+
+/// Longest code used for byte streams.
+const BYTE_CODE_LEN: u8 = 15;
@@ -0,0 +1,186 @@
+//! Compact serialization of code length tables, as bzip2 does it. First a
+//! bitmap of which symbols are used, then the lengths of the used symbols
+//! as a walk up and down from a starting length.
+
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    bits::{BitReader, BitWriter},
+    error::{Error, ErrorKind},
+    huffman::code::MAX_CODE_LEN,
+    io::Sink,
+};
+
+const GROUP: usize = 16;
+
+/// Write which symbols are in use: a bit for each group of 16 symbols, then
+/// 16 bits for each group with anything in it.
+pub(crate) fn write_used<S: Sink>(
+    writer: &mut BitWriter<S>,
+    used: &[bool],
+) -> Result<usize, Error> {
+    let mut written = 0;
+    for group in used.chunks(GROUP) {
+        written += writer.write_bits(group.contains(&true) as u32, 1)?;
+    }
+    for group in used.chunks(GROUP).filter(|g| g.contains(&true)) {
+        for &u in group {
+            written += writer.write_bits(u as u32, 1)?;
+        }
+    }
+    Ok(written)
+}
+
+/// Read back the bitmap from [`write_used`] for an alphabet of `symbols`.
+pub(crate) fn read_used(reader: &mut BitReader, symbols: usize) -> Result<Vec<bool>, ErrorKind> {
+    let groups = (0..symbols.div_ceil(GROUP))
+        .map(|_| read_bit(reader))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut used = vec![false; symbols];
+    for (group, _) in groups.iter().enumerate().filter(|(_, &g)| g) {
+        let end = usize::min(group * GROUP + GROUP, symbols);
+        for u in &mut used[group * GROUP..end] {
+            *u = read_bit(reader)?;
+        }
+    }
+    Ok(used)
+}
+
+/// Write lengths as a 5 bit starting length, then for every symbol `10` to
+/// step up, `11` to step down and `0` to move on to the next symbol.
+pub(crate) fn write_deltas<S: Sink>(
+    writer: &mut BitWriter<S>,
+    lengths: &[u8],
+) -> Result<usize, Error> {
+    let mut current = lengths.first().copied().unwrap_or(0);
+    let mut written = writer.write_bits(current as u32, 5)?;
+    for &len in lengths {
+        while current < len {
+            written += writer.write_bits(0b10, 2)?;
+            current += 1;
+        }
+        while current > len {
+            written += writer.write_bits(0b11, 2)?;
+            current -= 1;
+        }
+        written += writer.write_bits(0, 1)?;
+    }
+    Ok(written)
+}
+
+/// Read back `count` lengths written by [`write_deltas`]. Every length has
+/// to be between 1 and [`MAX_CODE_LEN`].
+pub(crate) fn read_deltas(reader: &mut BitReader, count: usize) -> Result<Vec<u8>, ErrorKind> {
+    let mut current = read(reader, 5)? as u8;
+    let mut lengths = Vec::with_capacity(count);
+    for _ in 0..count {
+        while read_bit(reader)? {
+            if read_bit(reader)? {
+                current = current.wrapping_sub(1);
+            } else {
+                current += 1;
+            }
+            if current == 0 || current > MAX_CODE_LEN {
+                return Err(ErrorKind::BadTable);
+            }
+        }
+        if current == 0 {
+            return Err(ErrorKind::BadTable);
+        }
+        lengths.push(current);
+    }
+    Ok(lengths)
+}
+
+/// Write the code lengths for a whole alphabet, zero for unused symbols.
+pub fn write_lengths<S: Sink>(writer: &mut BitWriter<S>, lengths: &[u8]) -> Result<usize, Error> {
+    let used = lengths.iter().map(|&l| l > 0).collect::<Vec<_>>();
+    let used_lengths = lengths
+        .iter()
+        .copied()
+        .filter(|&l| l > 0)
+        .collect::<Vec<_>>();
+    Ok(write_used(writer, &used)? + write_deltas(writer, &used_lengths)?)
+}
+
+/// Read back the lengths from [`write_lengths`] for an alphabet of `symbols`.
+pub fn read_lengths(reader: &mut BitReader, symbols: usize) -> Result<Vec<u8>, ErrorKind> {
+    let used = read_used(reader, symbols)?;
+    let mut deltas = read_deltas(reader, used.iter().filter(|&&u| u).count())?.into_iter();
+    Ok(used
+        .iter()
+        .map(|&u| if u { deltas.next().unwrap() } else { 0 })
+        .collect())
+}
+
+fn read(reader: &mut BitReader, bits: u32) -> Result<u32, ErrorKind> {
+    reader
+        .read_bits(bits)
+        .ok_or_else(|| ErrorKind::Truncated(reader.remaining()))
+}
+
+fn read_bit(reader: &mut BitReader) -> Result<bool, ErrorKind> {
+    Ok(read(reader, 1)? == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(lengths: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut w = BitWriter::new(&mut out);
+        write_lengths(&mut w, lengths).unwrap();
+        w.flush().unwrap();
+        let mut r = BitReader::new(&out);
+        let read = read_lengths(&mut r, lengths.len()).unwrap();
+        assert!(r.is_padding());
+        assert_eq!(read, lengths);
+        out
+    }
+
+    #[test]
+    fn deltas() {
+        let mut out = Vec::new();
+        let mut w = BitWriter::new(&mut out);
+        write_deltas(&mut w, &[3, 4, 2]).unwrap();
+        w.flush().unwrap();
+        // 00011, 0, 10 0, 11 11 0
+        assert_eq!(out, [0b0001_1010, 0b0111_1000]);
+    }
+
+    #[test]
+    fn sparse() {
+        let mut lengths = vec![0; 257];
+        lengths[b'a' as usize] = 1;
+        lengths[b'b' as usize] = 2;
+        lengths[256] = 2;
+        let out = round_trip(&lengths);
+        // 17 group bits, a group of 16 and the last group of 1, then
+        // 5 + 1 + 3 + 1 for the lengths
+        assert_eq!(out.len(), (17 + 16 + 1 + 10_usize).div_ceil(8));
+    }
+
+    #[test]
+    fn dense() {
+        round_trip(&(0..300).map(|i| 1 + (i % 24) as u8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn bad_lengths() {
+        // Start at 1 and step down to 0
+        let data = [0b0000_1110, 0];
+        let mut r = BitReader::new(&data);
+        assert_eq!(read_deltas(&mut r, 1), Err(ErrorKind::BadTable));
+    }
+
+    #[test]
+    fn truncated() {
+        let data = [0b0001_1101];
+        let mut r = BitReader::new(&data);
+        assert!(matches!(
+            read_deltas(&mut r, 4),
+            Err(ErrorKind::Truncated(_))
+        ));
+    }
+}
@@ -0,0 +1,182 @@
+//! Minimal stand-ins for `std::io::Write` and `std::io::Read`, so the codecs
+//! work without `std`. With the `std` feature every `Write` is a [`Sink`] and
+//! every `Read` is a [`Source`].
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use crate::{
+    bzip2::{Bzip2Compressor, Bzip2Decompressor},
+    error::Error,
+    huffman::{
+        AdaptiveHuffmanCompressor, AdaptiveHuffmanDecompressor, HuffmanCompressor,
+        HuffmanDecompressor,
+    },
+    pipeline::{PipelineCompressor, PipelineDecompressor},
+    range_coder::{RangeCompressor, RangeDecompressor},
+    LZ78Compressor, LZ78Decompressor,
+};
+
+/// Somewhere to put compressed or decompressed bytes.
+pub trait Sink {
+    /// Write some of `data`, returning how many bytes were taken.
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error>;
+
+    /// Write all of `data`, failing with [`Error::OutputFull`] if the sink
+    /// stops taking bytes.
+    fn write_all(&mut self, mut data: &[u8]) -> Result<(), Error> {
+        while !data.is_empty() {
+            match self.write(data)? {
+                0 => return Err(Error::OutputFull),
+                n => data = &data[n..],
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Somewhere to get bytes from.
+pub trait Source {
+    /// Read into `buffer`, returning how many bytes were filled. `0` means
+    /// there's nothing left.
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error>;
+}
+
+/// Any compressor or decompressor, as the two calls they all take: `write`
+/// or `read` to push input through, and `finalize`. Lets adapters drive a
+/// codec without knowing which one it is.
+pub trait Codec {
+    /// Push `data` through, returning how many bytes went to the sink.
+    fn push(&mut self, data: &[u8]) -> Result<usize, Error>;
+
+    /// Finish the stream, returning how many bytes went to the sink.
+    fn finish(self) -> Result<usize, Error>;
+}
+
+macro_rules! impl_codec {
+    ($method:ident: $($t:ident),*) => {
+        $(
+            impl<W: Sink> Codec for $t<W> {
+                fn push(&mut self, data: &[u8]) -> Result<usize, Error> {
+                    self.$method(data)
+                }
+
+                fn finish(self) -> Result<usize, Error> {
+                    self.finalize()
+                }
+            }
+        )*
+    };
+}
+
+impl_codec!(write:
+    LZ78Compressor,
+    HuffmanCompressor,
+    AdaptiveHuffmanCompressor,
+    RangeCompressor,
+    Bzip2Compressor,
+    PipelineCompressor
+);
+impl_codec!(read:
+    LZ78Decompressor,
+    HuffmanDecompressor,
+    AdaptiveHuffmanDecompressor,
+    RangeDecompressor,
+    Bzip2Decompressor,
+    PipelineDecompressor
+);
+
+/// A [`Sink`] over a fixed buffer, for when there's no allocator to grow a
+/// `Vec`.
+#[derive(Debug)]
+pub struct SliceSink<'a> {
+    buffer: &'a mut [u8],
+    position: usize,
+}
+
+impl<'a> SliceSink<'a> {
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        Self {
+            buffer,
+            position: 0,
+        }
+    }
+
+    /// Number of bytes written so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl Sink for SliceSink<'_> {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        let n = usize::min(data.len(), self.buffer.len() - self.position);
+        self.buffer[self.position..self.position + n].copy_from_slice(&data[..n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> Sink for W {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        Ok(std::io::Write::write(self, data)?)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> Source for R {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        Ok(std::io::Read::read(self, buffer)?)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Sink for Vec<u8> {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.extend_from_slice(data);
+        Ok(data.len())
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<S: Sink + ?Sized> Sink for &mut S {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        (**self).write(data)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Source for &[u8] {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let n = usize::min(buffer.len(), self.len());
+        let (head, tail) = self.split_at(n);
+        buffer[..n].copy_from_slice(head);
+        *self = tail;
+        Ok(n)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<S: Source + ?Sized> Source for &mut S {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        (**self).read(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slice_sink() {
+        let mut buffer = [0; 4];
+        let mut s = SliceSink::new(&mut buffer);
+        s.write_all(b"abc").unwrap();
+        assert_eq!(s.write(b"de").unwrap(), 1);
+        assert_eq!(s.write(b"f").unwrap(), 0);
+        assert!(matches!(s.write_all(b"f"), Err(Error::OutputFull)));
+        assert_eq!(s.position(), 4);
+        assert_eq!(&buffer, b"abcd");
+    }
+}
@@ -1,18 +1,48 @@
-mod lz78;
+#![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
+
+extern crate alloc;
+
 mod utils;
 
+pub mod ans;
+#[cfg(feature = "async")]
+pub mod async_io;
+pub mod bits;
+pub mod bwt;
+pub mod bzip2;
 pub mod error;
+pub mod huffman;
+pub mod io;
+pub mod lz78;
+pub mod options;
+pub mod pipeline;
+pub mod range_coder;
+pub mod stats;
+pub mod stream;
+pub mod transform;
 
-pub use crate::{lz78::LZ78Compressor, lz78::LZ78Decompressor};
+pub use crate::{lz78::LZ78Compressor, lz78::LZ78Decompressor, options::DecompressOptions};
 
 #[cfg(test)]
 mod tests {
 
     use quickcheck_macros::quickcheck;
 
-    use crate::{LZ78Compressor, LZ78Decompressor};
+    use crate::{
+        ans::{rans_compress, rans_decompress, tans_compress, tans_decompress},
+        bzip2::{Bzip2Compressor, Bzip2Decompressor},
+        huffman::{
+            AdaptiveHuffmanCompressor, AdaptiveHuffmanDecompressor, HuffmanCompressor,
+            HuffmanDecompressor,
+        },
+        lz78::{self, Mode},
+        pipeline::{Huffman, Lz78, Pipeline, PipelineCompressor, PipelineDecompressor, RangeCoder},
+        range_coder::{default_model, ContextModel, RangeCompressor, RangeDecompressor},
+        stream::{Status, Stream},
+        transform::{BitShuffle, Bwt, Delta, Mtf, Rle1, Shuffle, Transform, Xor, ZeroRun},
+        DecompressOptions, LZ78Compressor, LZ78Decompressor,
+    };
 
-    #[ignore]
     #[quickcheck]
     fn forward_reverse(data: Vec<u8>) -> bool {
         let mut compressed = Vec::new();
@@ -27,22 +57,340 @@ mod tests {
         data == uncompressed
     }
 
-    /// TODO: Support more than 64KiB of uncompressed data.
-    /// This needs to happen by clearing the table, and starting from scratch
-    #[ignore]
-    #[test]
-    fn large_value() {
+    #[quickcheck]
+    fn entropy_forward_reverse(data: Vec<u8>) -> bool {
         let mut compressed = Vec::new();
         let mut uncompressed = Vec::new();
-        let data = vec![b'!'; 0x100000];
+        let mut c = LZ78Compressor::with_mode(&mut compressed, Mode::Entropy);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
 
-        let mut c = LZ78Compressor::new(&mut compressed);
+        let mut d =
+            LZ78Decompressor::with_mode(&mut uncompressed, Mode::Entropy, DecompressOptions::new());
+        d.read(&compressed).unwrap();
+        d.finalize().unwrap();
+        data == uncompressed
+    }
+
+    #[quickcheck]
+    fn inspect_phrases(data: Vec<u8>, entropy: bool) -> bool {
+        let mode = if entropy { Mode::Entropy } else { Mode::Raw };
+        let mut compressed = Vec::new();
+        let mut c = LZ78Compressor::with_mode(&mut compressed, mode);
         c.write(&data).unwrap();
         c.finalize().unwrap();
 
-        let mut d = LZ78Decompressor::new(&mut uncompressed);
+        let mut phrases = Vec::new();
+        for token in lz78::inspect(&compressed, mode, DecompressOptions::new()).unwrap() {
+            phrases.extend_from_slice(&token.phrase);
+            phrases.extend(token.literal);
+        }
+        data == phrases
+    }
+
+    #[quickcheck]
+    fn snapshot_forward_reverse(data: Vec<u8>, split: usize, entropy: bool) -> bool {
+        let mode = if entropy { Mode::Entropy } else { Mode::Raw };
+        let split = split % (data.len() + 1);
+        let mut compressed = Vec::new();
+        let mut c = LZ78Compressor::with_mode(&mut compressed, mode);
+        c.write(&data[..split]).unwrap();
+        let snapshot = c.snapshot();
+        let mut c = LZ78Compressor::restore(&mut compressed, &snapshot).unwrap();
+        c.write(&data[split..]).unwrap();
+        c.finalize().unwrap();
+
+        let split = split % (compressed.len() + 1);
+        let mut uncompressed = Vec::new();
+        let mut d = LZ78Decompressor::with_mode(&mut uncompressed, mode, DecompressOptions::new());
+        d.read(&compressed[..split]).unwrap();
+        let snapshot = d.snapshot();
+        let mut d =
+            LZ78Decompressor::restore(&mut uncompressed, &snapshot, DecompressOptions::new())
+                .unwrap();
+        d.read(&compressed[split..]).unwrap();
+        d.finalize().unwrap();
+        data == uncompressed
+    }
+
+    #[quickcheck]
+    fn dictionary_forward_reverse(corpus: Vec<u8>, data: Vec<u8>, entropy: bool) -> bool {
+        let mode = if entropy { Mode::Entropy } else { Mode::Raw };
+        let dictionary = lz78::Dictionary::from_corpus(&corpus);
+        let mut compressed = Vec::new();
+        let mut c = LZ78Compressor::with_dictionary(&mut compressed, mode, &dictionary).unwrap();
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+
+        let dictionary = lz78::Dictionary::from_bytes(&dictionary.to_bytes()).unwrap();
+        let mut uncompressed = Vec::new();
+        let options = DecompressOptions::new();
+        let mut d =
+            LZ78Decompressor::with_dictionary(&mut uncompressed, mode, &dictionary, options);
+        d.read(&compressed).unwrap();
+        d.finalize().unwrap();
+        data == uncompressed
+    }
+
+    #[quickcheck]
+    fn trained_forward_reverse(samples: Vec<Vec<u8>>, data: Vec<u8>) -> bool {
+        let samples: Vec<&[u8]> = samples.iter().map(|s| &s[..]).collect();
+        let dictionary = lz78::train_dictionary(&samples, 256);
+        let mut compressed = Vec::new();
+        let mut c =
+            LZ78Compressor::with_dictionary(&mut compressed, Mode::Raw, &dictionary).unwrap();
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+
+        let mut uncompressed = Vec::new();
+        let options = DecompressOptions::new();
+        let mut d =
+            LZ78Decompressor::with_dictionary(&mut uncompressed, Mode::Raw, &dictionary, options);
+        d.read(&compressed).unwrap();
+        d.finalize().unwrap();
+        dictionary.len() <= 256 && data == uncompressed
+    }
+
+    #[quickcheck]
+    fn blocks_forward_reverse(data: Vec<u8>, block_size: u8, entropy: bool) -> bool {
+        let mode = if entropy { Mode::Entropy } else { Mode::Raw };
+        let mut compressed = Vec::new();
+        let mut c =
+            lz78::BlockCompressor::with_block_size(&mut compressed, mode, block_size as usize);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+
+        let mut uncompressed = Vec::new();
+        let mut d = lz78::BlockDecompressor::new(&mut uncompressed);
+        d.read(&compressed).unwrap();
+        d.finalize().unwrap();
+        data == uncompressed
+    }
+
+    #[cfg(feature = "std")]
+    #[quickcheck]
+    fn seekable_ranges(data: Vec<u8>, block_size: u8, start: usize, len: usize) -> bool {
+        use std::io::{Cursor, Read, Seek, SeekFrom};
+
+        let mut compressed = Vec::new();
+        let mut c =
+            lz78::BlockCompressor::seekable(&mut compressed, Mode::Raw, block_size as usize);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+
+        let start = start % (data.len() + 1);
+        let end = (start + len % 300).min(data.len());
+        let mut r = lz78::SeekableReader::new(Cursor::new(compressed)).unwrap();
+        r.seek(SeekFrom::Start(start as u64)).unwrap();
+        let mut range = vec![0; end - start];
+        r.read_exact(&mut range).unwrap();
+        range == data[start..end]
+    }
+
+    #[quickcheck]
+    fn huffman_forward_reverse(data: Vec<u8>) -> bool {
+        let mut compressed = Vec::new();
+        let mut uncompressed = Vec::new();
+        let mut c = HuffmanCompressor::new(&mut compressed);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+
+        let mut d = HuffmanDecompressor::new(&mut uncompressed);
+        d.read(&compressed).unwrap();
+        d.finalize().unwrap();
+        data == uncompressed
+    }
+
+    #[quickcheck]
+    fn adaptive_huffman_forward_reverse(data: Vec<u8>) -> bool {
+        let mut compressed = Vec::new();
+        let mut uncompressed = Vec::new();
+        let mut c = AdaptiveHuffmanCompressor::new(&mut compressed);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+
+        let mut d = AdaptiveHuffmanDecompressor::new(&mut uncompressed);
         d.read(&compressed).unwrap();
         d.finalize().unwrap();
-        assert_eq!(data, uncompressed);
+        data == uncompressed
+    }
+
+    #[quickcheck]
+    fn range_forward_reverse(data: Vec<u8>) -> bool {
+        let mut compressed = Vec::new();
+        let mut uncompressed = Vec::new();
+        let mut c = RangeCompressor::new(&mut compressed);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+
+        let mut d = RangeDecompressor::new(&mut uncompressed);
+        d.read(&compressed).unwrap();
+        d.finalize().unwrap();
+        data == uncompressed
+    }
+
+    #[quickcheck]
+    fn range_context_forward_reverse(data: Vec<u8>) -> bool {
+        let model = ContextModel::new(default_model(), 2, 9);
+        let mut compressed = Vec::new();
+        let mut uncompressed = Vec::new();
+        let mut c = RangeCompressor::with_model(&mut compressed, model.clone());
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+
+        let mut d =
+            RangeDecompressor::with_model(&mut uncompressed, model, DecompressOptions::new());
+        d.read(&compressed).unwrap();
+        d.finalize().unwrap();
+        data == uncompressed
+    }
+
+    #[quickcheck]
+    fn rans_forward_reverse(data: Vec<u8>) -> bool {
+        let mut compressed = Vec::new();
+        let mut uncompressed = Vec::new();
+        rans_compress(&data, &mut compressed).unwrap();
+        rans_decompress(&compressed, &mut uncompressed, DecompressOptions::new()).unwrap();
+        data == uncompressed
+    }
+
+    #[quickcheck]
+    fn tans_forward_reverse(data: Vec<u8>) -> bool {
+        let mut compressed = Vec::new();
+        let mut uncompressed = Vec::new();
+        tans_compress(&data, &mut compressed).unwrap();
+        tans_decompress(&compressed, &mut uncompressed, DecompressOptions::new()).unwrap();
+        data == uncompressed
+    }
+
+    #[quickcheck]
+    fn transform_forward_reverse(data: Vec<u8>) -> bool {
+        let transform = Rle1.then(Mtf).then(ZeroRun);
+        let forward = transform.forward(&data).unwrap();
+        transform.inverse(&forward).unwrap() == data
+    }
+
+    #[quickcheck]
+    fn filter_forward_reverse(data: Vec<u8>, width: u8) -> bool {
+        let width = width as usize;
+        let transform = Delta::new(width)
+            .then(Xor::new(width))
+            .then(Shuffle::new(width))
+            .then(BitShuffle::new(width));
+        let forward = transform.forward(&data).unwrap();
+        transform.inverse(&forward).unwrap() == data
+    }
+
+    #[quickcheck]
+    fn bzip2_forward_reverse(data: Vec<u8>, level: u8) -> bool {
+        let mut compressed = Vec::new();
+        let mut uncompressed = Vec::new();
+        let mut c = Bzip2Compressor::with_level(&mut compressed, level);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+        let mut d = Bzip2Decompressor::new(&mut uncompressed);
+        d.read(&compressed).unwrap();
+        d.finalize().unwrap();
+        data == uncompressed
+    }
+
+    #[quickcheck]
+    fn pipeline_forward_reverse(data: Vec<u8>) -> bool {
+        let pipelines = [
+            Pipeline::new().then(Bwt).then(Mtf).then(Huffman),
+            Pipeline::new().then(Rle1).then(Lz78).then(RangeCoder),
+            Pipeline::new()
+                .then(Delta::new(4))
+                .then(Shuffle::new(4))
+                .then(Huffman),
+        ];
+        pipelines.iter().all(|pipeline| {
+            let mut compressed = Vec::new();
+            let mut uncompressed = Vec::new();
+            let mut c = PipelineCompressor::new(&mut compressed, pipeline);
+            c.write(&data).unwrap();
+            c.finalize().unwrap();
+            let mut d = PipelineDecompressor::new(&mut uncompressed);
+            d.read(&compressed).unwrap();
+            d.finalize().unwrap();
+            data == uncompressed
+        })
+    }
+
+    #[quickcheck]
+    fn stream_forward_reverse(data: Vec<u8>, room: u8) -> bool {
+        let run = |input: &[u8], mut stream: Stream<_>| {
+            let mut output = vec![0; room as usize + 1];
+            let mut result = Vec::new();
+            let mut input = input;
+            while !input.is_empty() {
+                let (consumed, produced, _) = stream.process(input, &mut output).unwrap();
+                input = &input[consumed..];
+                result.extend_from_slice(&output[..produced]);
+            }
+            loop {
+                let (produced, status) = stream.finish(&mut output).unwrap();
+                result.extend_from_slice(&output[..produced]);
+                if status == Status::Done {
+                    return result;
+                }
+            }
+        };
+        let compressed = run(&data, Stream::new(AdaptiveHuffmanCompressor::new));
+        let mut uncompressed = Vec::new();
+        let mut d = AdaptiveHuffmanDecompressor::new(&mut uncompressed);
+        d.read(&compressed).unwrap();
+        d.finalize().unwrap();
+        data == uncompressed
+    }
+
+    #[test]
+    fn large_value() {
+        let mut compressed = Vec::new();
+        let mut uncompressed = Vec::new();
+        let data = vec![b'!'; 0x100000];
+
+        for mode in [Mode::Raw, Mode::Entropy] {
+            compressed.clear();
+            uncompressed.clear();
+            let mut c = LZ78Compressor::with_mode(&mut compressed, mode);
+            c.write(&data).unwrap();
+            c.finalize().unwrap();
+
+            let mut d =
+                LZ78Decompressor::with_mode(&mut uncompressed, mode, DecompressOptions::new());
+            d.read(&compressed).unwrap();
+            d.finalize().unwrap();
+            assert_eq!(data, uncompressed);
+        }
+    }
+
+    /// Enough phrases to use up every code, after which the table stops
+    /// growing.
+    #[test]
+    fn full_table() {
+        let mut compressed = Vec::new();
+        let mut uncompressed = Vec::new();
+        let mut state = 1_u32;
+        let data = (0..0x100000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect::<Vec<_>>();
+
+        for mode in [Mode::Raw, Mode::Entropy] {
+            compressed.clear();
+            uncompressed.clear();
+            let mut c = LZ78Compressor::with_mode(&mut compressed, mode);
+            c.write(&data).unwrap();
+            c.finalize().unwrap();
+
+            let mut d =
+                LZ78Decompressor::with_mode(&mut uncompressed, mode, DecompressOptions::new());
+            d.read(&compressed).unwrap();
+            d.finalize().unwrap();
+            assert_eq!(data, uncompressed);
+        }
     }
 }
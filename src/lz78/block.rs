@@ -0,0 +1,644 @@
+//! LZ78 in independent blocks, so they can be compressed and decompressed
+//! at the same time. With the `parallel` feature they are, on rayon's
+//! thread pool, a batch of one block per thread at a time.
+//!
+//! A stream is `KLZB`, a version byte, the [`Mode`] and the block size as a
+//! little endian `u32`. Each block follows as its compressed and its
+//! uncompressed length, both little endian `u32`s, then an LZ78 stream of
+//! its own. Every block but the last is the block size uncompressed, and
+//! none is empty. A compressed length of 0 ends the stream.
+//!
+//! A seekable stream starts `KLZX` instead, and after the end has an index:
+//! where each block's lengths start in the stream, as little endian `u64`s,
+//! then the number of blocks and the uncompressed length as `u64`s, and
+//! `KLZI`. [`SeekableReader`] reads it from the end to find any block
+//! without going through the ones before.
+//!
+//! Each block starts over with an empty dictionary, so smaller blocks
+//! compress a little worse.
+//!
+//! [`SeekableReader`]: crate::lz78::SeekableReader
+
+use alloc::vec::Vec;
+
+use crate::{
+    error::{Error, ErrorKind},
+    io::Sink,
+    lz78::{Mode, CODEC},
+    options::{DecompressOptions, Limit},
+    stats::Stats,
+    LZ78Compressor, LZ78Decompressor,
+};
+
+pub(super) const MAGIC: &[u8; 4] = b"KLZB";
+const INDEXED_MAGIC: &[u8; 4] = b"KLZX";
+const INDEX_MAGIC: &[u8; 4] = b"KLZI";
+const VERSION: u8 = 1;
+pub(super) const HEADER_LEN: usize = 10;
+/// Bytes of a block's two lengths.
+pub(super) const FRAME_LEN: usize = 8;
+/// Bytes after the index's offsets.
+pub(super) const FOOTER_LEN: usize = 20;
+
+/// Block size [`BlockCompressor::new`] uses.
+pub const DEFAULT_BLOCK_SIZE: usize = 1 << 20;
+/// Largest block size, block sizes are clamped to `1..=MAX_BLOCK_SIZE`.
+pub const MAX_BLOCK_SIZE: usize = 1 << 26;
+
+/// Blocks to work on at once, one per thread.
+#[cfg(feature = "parallel")]
+fn batch() -> usize {
+    rayon::current_num_threads()
+}
+
+#[cfg(not(feature = "parallel"))]
+fn batch() -> usize {
+    1
+}
+
+/// Run `f` on each of `items`, in parallel with the `parallel` feature.
+#[cfg(feature = "parallel")]
+fn map<T: Sync, U: Send>(items: &[T], f: impl Fn(&T) -> U + Sync + Send) -> Vec<U> {
+    use rayon::prelude::*;
+    items.par_iter().map(f).collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+fn map<T, U>(items: &[T], f: impl Fn(&T) -> U) -> Vec<U> {
+    items.iter().map(f).collect()
+}
+
+/// What a stream's header says.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Header {
+    pub mode: Mode,
+    pub block_size: u32,
+    /// Whether the stream ends with an index.
+    pub indexed: bool,
+}
+
+impl Header {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(if self.indexed { INDEXED_MAGIC } else { MAGIC });
+        out.push(VERSION);
+        out.push(match self.mode {
+            Mode::Raw => 0,
+            Mode::Entropy => 1,
+        });
+        out.extend_from_slice(&self.block_size.to_le_bytes());
+    }
+
+    /// Read the first [`HEADER_LEN`] bytes of `data`. Errors come with
+    /// their offset in the header.
+    pub fn read(data: &[u8]) -> Result<Self, (ErrorKind, u64)> {
+        let magic = &data[..4];
+        let indexed = match magic {
+            m if m == MAGIC => false,
+            m if m == INDEXED_MAGIC => true,
+            _ => {
+                let magic = magic.iter().fold(0, |m, &b| m << 8 | b as u32);
+                return Err((ErrorKind::BadMagic(magic), 0));
+            }
+        };
+        if data[4] != VERSION {
+            return Err((ErrorKind::UnsupportedVersion(data[4]), 4));
+        }
+        let mode = match data[5] {
+            0 => Mode::Raw,
+            1 => Mode::Entropy,
+            b => return Err((ErrorKind::BadCode(b as u32), 5)),
+        };
+        let block_size = le_u32(data, 6).unwrap_or(0);
+        if block_size == 0 || block_size as usize > MAX_BLOCK_SIZE {
+            return Err((ErrorKind::BadCode(block_size), 6));
+        }
+        Ok(Self {
+            mode,
+            block_size,
+            indexed,
+        })
+    }
+}
+
+/// The end of a seekable stream's index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Footer {
+    pub blocks: u64,
+    /// Uncompressed length of the whole stream.
+    pub len: u64,
+}
+
+impl Footer {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.blocks.to_le_bytes());
+        out.extend_from_slice(&self.len.to_le_bytes());
+        out.extend_from_slice(INDEX_MAGIC);
+    }
+
+    /// Read the last [`FOOTER_LEN`] bytes of `data`, `None` if they're not
+    /// a footer.
+    pub fn read(data: &[u8]) -> Option<Self> {
+        let footer = data.get(data.len().checked_sub(FOOTER_LEN)?..)?;
+        if &footer[16..] != INDEX_MAGIC {
+            return None;
+        }
+        Some(Self {
+            blocks: le_u64(footer, 0)?,
+            len: le_u64(footer, 8)?,
+        })
+    }
+
+    /// Whether a stream of `block_size` blocks could have this many blocks
+    /// for its length.
+    #[cfg(feature = "std")]
+    pub fn fits(&self, block_size: u32) -> bool {
+        self.len.div_ceil(block_size as u64) == self.blocks
+    }
+}
+
+/// Compresses input in blocks of a fixed size, a batch at a time. Holds
+/// the input for a batch until it's all there.
+pub struct BlockCompressor<W> {
+    writer: W,
+    mode: Mode,
+    block_size: usize,
+    /// Input not compressed yet.
+    pending: Vec<u8>,
+    started: bool,
+    /// Where each block so far starts, for a seekable stream.
+    index: Option<Vec<u64>>,
+
+    input: u64,
+    output: u64,
+}
+
+impl<W: Sink> BlockCompressor<W> {
+    /// Compress in [`DEFAULT_BLOCK_SIZE`] blocks.
+    pub fn new(writer: W, mode: Mode) -> Self {
+        Self::with_block_size(writer, mode, DEFAULT_BLOCK_SIZE)
+    }
+
+    pub fn with_block_size(writer: W, mode: Mode, block_size: usize) -> Self {
+        Self {
+            writer,
+            mode,
+            block_size: block_size.clamp(1, MAX_BLOCK_SIZE),
+            pending: Vec::new(),
+            started: false,
+            index: None,
+
+            input: 0,
+            output: 0,
+        }
+    }
+
+    /// Write a seekable stream, for `SeekableReader` with the `std`
+    /// feature, which also decompresses from start to end as any other.
+    pub fn seekable(writer: W, mode: Mode, block_size: usize) -> Self {
+        Self {
+            index: Some(Vec::new()),
+            ..Self::with_block_size(writer, mode, block_size)
+        }
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.pending.extend_from_slice(data);
+        self.input += data.len() as u64;
+        let batch = self.block_size * batch();
+        let mut written = 0;
+        if self.pending.len() >= batch {
+            let whole = self.pending.len() / batch * batch;
+            let blocks: Vec<u8> = self.pending.drain(..whole).collect();
+            written += self.write_blocks(&blocks)?;
+        }
+        Ok(written)
+    }
+
+    /// What's gone in and out so far. Input waiting for the rest of its
+    /// batch hasn't come out as anything yet.
+    pub fn stats(&self) -> Stats {
+        Stats::new(self.input, self.output * 8)
+    }
+
+    /// Compress `data` in blocks and write them out, after the header if
+    /// it's not out yet.
+    fn write_blocks(&mut self, data: &[u8]) -> Result<usize, Error> {
+        let mut out = Vec::new();
+        if !self.started {
+            let header = Header {
+                mode: self.mode,
+                block_size: self.block_size as u32,
+                indexed: self.index.is_some(),
+            };
+            header.write(&mut out);
+            self.started = true;
+        }
+        let blocks: Vec<&[u8]> = data.chunks(self.block_size).collect();
+        let mode = self.mode;
+        for (block, compressed) in blocks.iter().zip(map(&blocks, |b| compress(b, mode))) {
+            if let Some(index) = &mut self.index {
+                index.push(self.output + out.len() as u64);
+            }
+            out.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(block.len() as u32).to_le_bytes());
+            out.extend_from_slice(&compressed);
+        }
+        self.writer.write_all(&out)?;
+        self.output += out.len() as u64;
+        Ok(out.len())
+    }
+
+    /// Compress what's left and end the stream, with the index after if
+    /// it's seekable.
+    pub fn finalize(mut self) -> Result<usize, Error> {
+        let pending = core::mem::take(&mut self.pending);
+        let written = self.write_blocks(&pending)?;
+        let mut out = 0_u32.to_le_bytes().to_vec();
+        if let Some(index) = &self.index {
+            for offset in index {
+                out.extend_from_slice(&offset.to_le_bytes());
+            }
+            let footer = Footer {
+                blocks: index.len() as u64,
+                len: self.input,
+            };
+            footer.write(&mut out);
+        }
+        self.writer.write_all(&out)?;
+        Ok(written + out.len())
+    }
+}
+
+fn compress(block: &[u8], mode: Mode) -> Vec<u8> {
+    let mut compressed = Vec::new();
+    let mut c = LZ78Compressor::with_mode(&mut compressed, mode);
+    // Writing to a `Vec` can't fail
+    c.write(block).and_then(|_| c.finalize()).unwrap();
+    compressed
+}
+
+/// Decompresses a stream from [`BlockCompressor`], a batch of blocks at a
+/// time. The [`DecompressOptions`] hold for the whole stream, and each
+/// block's dictionary on its own.
+pub struct BlockDecompressor<W> {
+    writer: W,
+    options: DecompressOptions,
+
+    /// Input not decompressed yet, from the start of a block.
+    buffer: Vec<u8>,
+    header: Option<Header>,
+    /// Where each block's lengths start, in a seekable stream.
+    frames: Vec<u64>,
+    done: bool,
+
+    consumed: u64,
+    produced: u64,
+}
+
+/// A block found in the input, waiting its turn.
+struct Block {
+    /// Where its LZ78 stream starts in the buffer, and in the whole stream.
+    start: usize,
+    offset: u64,
+    compressed: usize,
+    len: u32,
+}
+
+impl<W: Sink> BlockDecompressor<W> {
+    pub fn new(writer: W) -> Self {
+        Self::with_options(writer, DecompressOptions::default())
+    }
+
+    pub fn with_options(writer: W, options: DecompressOptions) -> Self {
+        Self {
+            writer,
+            options,
+
+            buffer: Vec::new(),
+            header: None,
+            frames: Vec::new(),
+            done: false,
+
+            consumed: 0,
+            produced: 0,
+        }
+    }
+
+    /// Offset in the stream of the start of the buffer.
+    fn buffer_offset(&self) -> u64 {
+        self.consumed - self.buffer.len() as u64
+    }
+
+    fn error(&self, kind: ErrorKind, offset: u64) -> Error {
+        Error::stream(CODEC, kind, offset * 8, self.produced)
+    }
+
+    /// Whether everything after the end is the index.
+    fn indexed(&self) -> bool {
+        self.header.is_some_and(|h| h.indexed)
+    }
+
+    pub fn read(&mut self, data: &[u8]) -> Result<usize, Error> {
+        if self.done && !self.indexed() {
+            if let Some(&b) = data.first() {
+                return Err(self.error(ErrorKind::BadCode(b as u32), self.consumed));
+            }
+        }
+        self.buffer.extend_from_slice(data);
+        self.consumed += data.len() as u64;
+        self.decode(batch())
+    }
+
+    /// Read the header if it's not been read, `None` until it's all there.
+    fn read_header(&mut self) -> Result<Option<Header>, Error> {
+        if self.header.is_some() || self.buffer.len() < HEADER_LEN {
+            return Ok(self.header);
+        }
+        let header = Header::read(&self.buffer)
+            .map_err(|(kind, at)| self.error(kind, self.buffer_offset() + at))?;
+        self.buffer.drain(..HEADER_LEN);
+        self.header = Some(header);
+        Ok(self.header)
+    }
+
+    /// Decompress whole blocks in the buffer once there are `batch` of them
+    /// or the stream's end is there.
+    fn decode(&mut self, batch: usize) -> Result<usize, Error> {
+        let Header {
+            mode,
+            block_size,
+            indexed,
+        } = match self.read_header()? {
+            Some(header) => header,
+            None => return Ok(0),
+        };
+        let mut written = 0;
+        loop {
+            let mut blocks = Vec::new();
+            let mut position = 0;
+            while blocks.len() < batch && !self.done {
+                let compressed = match le_u32(&self.buffer, position) {
+                    Some(compressed) => compressed,
+                    None => break,
+                };
+                let offset = self.buffer_offset() + position as u64;
+                if compressed == 0 {
+                    // The end, which is just the compressed length
+                    position += 4;
+                    self.done = true;
+                    break;
+                }
+                let len = match le_u32(&self.buffer, position + 4) {
+                    Some(len) => len,
+                    None => break,
+                };
+                if len == 0 || len > block_size {
+                    return Err(self.error(ErrorKind::BadBlockLength(len), offset + 4));
+                }
+                let start = position + FRAME_LEN;
+                if self.buffer.len() - start < compressed as usize {
+                    break;
+                }
+                position = start + compressed as usize;
+                if indexed {
+                    self.frames.push(offset);
+                }
+                blocks.push(Block {
+                    start,
+                    offset: offset + FRAME_LEN as u64,
+                    compressed: compressed as usize,
+                    len,
+                });
+            }
+            if blocks.len() < batch && !self.done {
+                return Ok(written);
+            }
+            written += self.decode_blocks(&blocks, mode)?;
+            self.buffer.drain(..position);
+            if self.done {
+                if !self.buffer.is_empty() && !indexed {
+                    let b = self.buffer[0] as u32;
+                    return Err(self.error(ErrorKind::BadCode(b), self.buffer_offset()));
+                }
+                return Ok(written);
+            }
+        }
+    }
+
+    fn decode_blocks(&mut self, blocks: &[Block], mode: Mode) -> Result<usize, Error> {
+        // Checked up front, so a batch of blocks can't run past the limits
+        let mut output = 0;
+        for block in blocks {
+            let consumed = block.offset + block.compressed as u64;
+            self.options
+                .check_output(consumed, self.produced + output, block.len as u64)
+                .map_err(|l| self.error(ErrorKind::LimitExceeded(l), block.offset))?;
+            output += block.len as u64;
+        }
+
+        let mut options = DecompressOptions::new();
+        options.max_dictionary_bytes = self.options.max_dictionary_bytes;
+        let buffer = &self.buffer;
+        let decoded = map(blocks, |b| {
+            let data = &buffer[b.start..b.start + b.compressed];
+            decompress(data, mode, b.len, options)
+        });
+        let mut written = 0;
+        for (block, decoded) in blocks.iter().zip(decoded) {
+            let decoded = decoded.map_err(|e| rebase(e, block.offset, self.produced))?;
+            self.writer.write_all(&decoded)?;
+            self.produced += decoded.len() as u64;
+            written += decoded.len();
+        }
+        Ok(written)
+    }
+
+    /// Decompress what's left, failing if the stream hasn't ended.
+    pub fn finalize(mut self) -> Result<usize, Error> {
+        let written = self.decode(usize::MAX)?;
+        if !self.done {
+            let offset = self.buffer_offset();
+            let left = self.buffer.len() * 8;
+            return Err(self.error(ErrorKind::Truncated(left), offset));
+        }
+        if self.indexed() {
+            self.check_index()?;
+        }
+        Ok(written)
+    }
+
+    /// Check that what's after the end is an index of the blocks read.
+    fn check_index(&self) -> Result<(), Error> {
+        let bad = || self.error(ErrorKind::BadTable, self.buffer_offset());
+        let footer = Footer::read(&self.buffer).ok_or_else(bad)?;
+        let expected = Footer {
+            blocks: self.frames.len() as u64,
+            len: self.produced,
+        };
+        if footer != expected || self.buffer.len() != self.frames.len() * 8 + FOOTER_LEN {
+            return Err(bad());
+        }
+        let offsets = self.buffer.chunks(8).map(|b| le_u64(b, 0));
+        if offsets.zip(&self.frames).any(|(o, &f)| o != Some(f)) {
+            return Err(bad());
+        }
+        Ok(())
+    }
+}
+
+/// Make the offsets in an error from a block's decompressor, which are
+/// from the block's start, from the stream's start. `offset` is where the
+/// block's LZ78 stream starts and `produced` is the output before it.
+pub(super) fn rebase(e: Error, offset: u64, produced: u64) -> Error {
+    match e {
+        Error::Stream {
+            kind,
+            bit_offset,
+            byte_offset,
+            ..
+        } => Error::stream(CODEC, kind, offset * 8 + bit_offset, produced + byte_offset),
+        e => e,
+    }
+}
+
+pub(super) fn le_u32(data: &[u8], at: usize) -> Option<u32> {
+    let b = data.get(at..at + 4)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+pub(super) fn le_u64(data: &[u8], at: usize) -> Option<u64> {
+    let mut b = [0; 8];
+    b.copy_from_slice(data.get(at..at + 8)?);
+    Some(u64::from_le_bytes(b))
+}
+
+/// Decompress one block, which has to come to `len` bytes.
+pub(super) fn decompress(
+    data: &[u8],
+    mode: Mode,
+    len: u32,
+    options: DecompressOptions,
+) -> Result<Vec<u8>, Error> {
+    let mut decoded = Vec::new();
+    let too_long = ErrorKind::LimitExceeded(Limit::OutputBytes(len as u64));
+    let options = options.max_output_bytes(len as u64);
+    let mut d = LZ78Decompressor::with_mode(&mut decoded, mode, options);
+    match d.read(data).and_then(|_| d.finalize()) {
+        Err(e) if e.kind() == Some(too_long) => {
+            let offset = e.bit_offset().unwrap_or(0);
+            Err(Error::stream(
+                CODEC,
+                ErrorKind::BadBlockLength(len),
+                offset,
+                len as u64,
+            ))
+        }
+        Err(e) => Err(e),
+        Ok(_) if decoded.len() != len as usize => {
+            let kind = ErrorKind::BadBlockLength(len);
+            Err(Error::stream(
+                CODEC,
+                kind,
+                data.len() as u64 * 8,
+                decoded.len() as u64,
+            ))
+        }
+        Ok(_) => Ok(decoded),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compress_blocks(data: &[u8], mode: Mode, block_size: usize) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        let mut c = BlockCompressor::with_block_size(&mut compressed, mode, block_size);
+        for chunk in data.chunks(100) {
+            c.write(chunk).unwrap();
+        }
+        c.finalize().unwrap();
+        compressed
+    }
+
+    fn decompress_blocks(data: &[u8], options: DecompressOptions) -> Result<Vec<u8>, Error> {
+        let mut uncompressed = Vec::new();
+        let mut d = BlockDecompressor::with_options(&mut uncompressed, options);
+        for chunk in data.chunks(7) {
+            d.read(chunk)?;
+        }
+        d.finalize()?;
+        Ok(uncompressed)
+    }
+
+    #[test]
+    fn empty() {
+        let compressed = compress_blocks(b"", Mode::Raw, 1000);
+        assert_eq!(compressed, b"KLZB\x01\x00\xe8\x03\x00\x00\x00\x00\x00\x00");
+        assert_eq!(
+            decompress_blocks(&compressed, DecompressOptions::new()).unwrap(),
+            b""
+        );
+    }
+
+    #[test]
+    fn blocks() {
+        let data = b"She sells sea shells by the sea shore. She sells sea shells. ".repeat(40);
+        for mode in [Mode::Raw, Mode::Entropy] {
+            for block_size in [1, 64, 1000, data.len(), 1 << 20] {
+                let compressed = compress_blocks(&data, mode, block_size);
+                let uncompressed = decompress_blocks(&compressed, DecompressOptions::new());
+                assert!(uncompressed.unwrap() == data, "{:?} {}", mode, block_size);
+            }
+        }
+
+        // The first block is 64 bytes of the data on its own
+        let compressed = compress_blocks(&data, Mode::Raw, 64);
+        let mut first = Vec::new();
+        let mut c = LZ78Compressor::new(&mut first);
+        c.write(&data[..64]).unwrap();
+        c.finalize().unwrap();
+        assert_eq!(compressed[10..14], (first.len() as u32).to_le_bytes());
+        assert_eq!(compressed[14..18], 64_u32.to_le_bytes());
+        assert_eq!(compressed[18..18 + first.len()], first);
+    }
+
+    #[test]
+    fn bad_streams() {
+        let data = b"abracadabra".repeat(10);
+        let compressed = compress_blocks(&data, Mode::Raw, 50);
+        let options = DecompressOptions::new();
+        let kind = |data: &[u8], options| decompress_blocks(data, options).unwrap_err().kind();
+
+        let truncated = &compressed[..compressed.len() - 1];
+        assert!(matches!(
+            kind(truncated, options),
+            Some(ErrorKind::Truncated(_))
+        ));
+        let mut trailing = compressed.clone();
+        trailing.push(7);
+        assert_eq!(kind(&trailing, options), Some(ErrorKind::BadCode(7)));
+        let mut magic = compressed.clone();
+        magic[3] = b'S';
+        assert_eq!(
+            kind(&magic, options),
+            Some(ErrorKind::BadMagic(0x4b4c_5a53))
+        );
+
+        // The first block said to be a byte short, then a byte long
+        let mut short = compressed.clone();
+        short[14] = 49;
+        assert_eq!(kind(&short, options), Some(ErrorKind::BadBlockLength(49)));
+        let mut long = compressed.clone();
+        long[14] = 51;
+        assert_eq!(kind(&long, options), Some(ErrorKind::BadBlockLength(51)));
+
+        let e = decompress_blocks(&short, options).unwrap_err();
+        assert_eq!(e.byte_offset(), Some(49));
+        let options = options.max_output_bytes(99);
+        assert_eq!(
+            kind(&compressed, options),
+            Some(ErrorKind::LimitExceeded(Limit::OutputBytes(99)))
+        );
+    }
+}
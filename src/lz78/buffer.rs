@@ -0,0 +1,306 @@
+//! Buffer to buffer compression that never touches the allocator. The
+//! dictionary lives in a [`Workspace`] covering the whole 16 bit code space,
+//! given to [`compress_into_with`] and [`decompress_into_with`]. At about
+//! 320KiB it's too big for most stacks, so make one once, in a `static` or
+//! a `Box`, and reuse it for every call.
+
+use crate::{
+    bits::{BitReader, BitWriter},
+    error::{Error, ErrorKind},
+    io::SliceSink,
+    lz78::{code::Code, CODEC},
+};
+
+const CODES: usize = u16::MAX as usize + 1;
+const MAX_WIDTH: usize = 16;
+
+/// Dictionary storage for [`compress_into_with`] and
+/// [`decompress_into_with`]. It's about 320KiB, and nothing in it needs
+/// clearing between calls.
+pub struct Workspace {
+    /// Compressing: first child of each phrase. Decompressing: the prefix
+    /// of each phrase.
+    links: [u16; CODES],
+    /// Compressing: next sibling of each phrase. Decompressing: the length
+    /// of each phrase.
+    siblings: [u16; CODES],
+    /// Last byte of each phrase.
+    bytes: [u8; CODES],
+}
+
+impl Workspace {
+    pub const fn new() -> Self {
+        Self {
+            links: [0; CODES],
+            siblings: [0; CODES],
+            bytes: [0; CODES],
+        }
+    }
+}
+
+impl Default for Workspace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Worst case size of `len` bytes compressed by [`compress_into_with`], or
+/// by an [`LZ78Compressor`] in [`Mode::Raw`]. That's every byte starting a
+/// new phrase, costing its code at the current `Code::min_bits` width plus
+/// an 8 bit literal.
+///
+/// The other codecs have bounds of their own, for compressing into a
+/// [`SliceSink`] of a fixed size.
+///
+/// [`LZ78Compressor`]: crate::lz78::LZ78Compressor
+/// [`Mode::Raw`]: crate::lz78::Mode::Raw
+pub const fn compress_bound(len: usize) -> usize {
+    let mut bits = 0_u64;
+    let mut remaining = len as u64;
+    let mut width = 0;
+    // Number of codes that are written at `width` bits
+    let mut count = 1;
+    while remaining > 0 {
+        let n = if width == MAX_WIDTH || remaining < count {
+            remaining
+        } else {
+            count
+        };
+        bits += n * (width as u64 + 8);
+        remaining -= n;
+        width += 1;
+        count = 1 << (width - 1);
+    }
+    bits.div_ceil(8) as usize
+}
+
+/// Compress `input` into `out` with the dictionary in `workspace`,
+/// returning the number of bytes written. Fails with [`Error::OutputFull`]
+/// if `out` is too small, [`compress_bound`] is always enough.
+pub fn compress_into_with(
+    workspace: &mut Workspace,
+    input: &[u8],
+    out: &mut [u8],
+) -> Result<usize, Error> {
+    let mut writer = BitWriter::new(SliceSink::new(out));
+    let mut max_code = Code(0);
+    let mut prefix = Code(0);
+    workspace.links[0] = 0;
+
+    for &b in input {
+        if let Some(c) = workspace.child(prefix, b) {
+            prefix = c;
+            continue;
+        }
+        writer.write_bits(prefix.0 as u32, max_code.min_bits() as u32)?;
+        writer.write_bits(b as u32, 8)?;
+        if !max_code.is_max() {
+            max_code += 1;
+            workspace.insert(prefix, b, max_code);
+        }
+        prefix = Code(0);
+    }
+    if prefix != Code(0) {
+        writer.write_bits(prefix.0 as u32, max_code.min_bits() as u32)?;
+    }
+    writer.flush()?;
+    Ok(writer.into_inner().position())
+}
+
+/// Decompress `input` into `out` with the dictionary in `workspace`,
+/// returning the number of bytes written. Fails with [`Error::OutputFull`]
+/// if `out` is too small.
+pub fn decompress_into_with(
+    workspace: &mut Workspace,
+    input: &[u8],
+    out: &mut [u8],
+) -> Result<usize, Error> {
+    let mut reader = BitReader::new(input);
+    let mut max_code = Code(0);
+    let mut produced = 0;
+    workspace.siblings[0] = 0;
+
+    let error = |kind, bit_offset: usize, produced: usize| {
+        Error::stream(CODEC, kind, bit_offset as u64, produced as u64)
+    };
+
+    loop {
+        let offset = reader.position();
+        let code = match reader.read_bits(max_code.min_bits() as u32) {
+            Some(c) => Code(c as u16),
+            None => break,
+        };
+        if code > max_code {
+            return Err(error(ErrorKind::BadCode(code.0 as u32), offset, produced));
+        }
+        let len = workspace.siblings[code.0 as usize] as usize;
+        if out.len() - produced < len {
+            return Err(Error::OutputFull);
+        }
+        workspace.copy_phrase(code, &mut out[produced..produced + len]);
+        produced += len;
+
+        let b = match reader.read_bits(8) {
+            Some(b) => b as u8,
+            None => break,
+        };
+        if out.len() == produced {
+            return Err(Error::OutputFull);
+        }
+        out[produced] = b;
+        produced += 1;
+        if !max_code.is_max() {
+            max_code += 1;
+            workspace.links[max_code.0 as usize] = code.0;
+            workspace.bytes[max_code.0 as usize] = b;
+            workspace.siblings[max_code.0 as usize] = len as u16 + 1;
+        }
+    }
+
+    if !reader.is_padding() {
+        return Err(error(
+            ErrorKind::Truncated(reader.remaining()),
+            reader.position(),
+            produced,
+        ));
+    }
+    Ok(produced)
+}
+
+impl Workspace {
+    fn child(&self, prefix: Code, b: u8) -> Option<Code> {
+        let mut c = self.links[prefix.0 as usize];
+        while c != 0 {
+            if self.bytes[c as usize] == b {
+                return Some(Code(c));
+            }
+            c = self.siblings[c as usize];
+        }
+        None
+    }
+
+    fn insert(&mut self, prefix: Code, b: u8, code: Code) {
+        let (p, c) = (prefix.0 as usize, code.0 as usize);
+        self.bytes[c] = b;
+        self.links[c] = 0;
+        self.siblings[c] = self.links[p];
+        self.links[p] = code.0;
+    }
+
+    /// Fill `out` with the phrase for `code`, back to front.
+    fn copy_phrase(&self, mut code: Code, out: &mut [u8]) {
+        for o in out.iter_mut().rev() {
+            *o = self.bytes[code.0 as usize];
+            code = Code(self.links[code.0 as usize]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+    use crate::LZ78Compressor;
+
+    fn compress(input: &[u8], out: &mut [u8]) -> Result<usize, Error> {
+        compress_into_with(&mut Workspace::new(), input, out)
+    }
+
+    fn decompress(input: &[u8], out: &mut [u8]) -> Result<usize, Error> {
+        decompress_into_with(&mut Workspace::new(), input, out)
+    }
+
+    #[test]
+    fn bound() {
+        assert_eq!(compress_bound(0), 0);
+        assert_eq!(compress_bound(1), 1);
+        // 8 + 9 + 10 + 10 bits
+        assert_eq!(compress_bound(4), 5);
+        // Every byte is a new phrase, so the bound is exact
+        let data = (0..=255).collect::<Vec<u8>>();
+        let mut out = vec![0; compress_bound(data.len())];
+        assert_eq!(compress(&data, &mut out).unwrap(), out.len());
+    }
+
+    #[test]
+    fn bound_past_the_last_code() {
+        let len = 100_000;
+        let codes = 65536_u64;
+        let bits = (1..codes)
+            .map(|k| Code(k as u16).min_bits() as u64)
+            .sum::<u64>()
+            + (len - codes) * 16
+            + len * 8;
+        assert_eq!(compress_bound(len as usize), bits.div_ceil(8) as usize);
+    }
+
+    #[test]
+    fn four_equal_bytes() {
+        let mut out = [0; 8];
+        assert_eq!(compress(b"aaaa", &mut out).unwrap(), 3);
+        assert_eq!(out[..3], [0b0110_0001, 0b1011_0000, 0b1010_0000]);
+        let mut uncompressed = [0; 4];
+        assert_eq!(decompress(&out[..3], &mut uncompressed).unwrap(), 4);
+        assert_eq!(&uncompressed, b"aaaa");
+    }
+
+    #[test]
+    fn output_full() {
+        let mut out = [0; 2];
+        assert!(matches!(
+            compress(b"aaaa", &mut out),
+            Err(Error::OutputFull)
+        ));
+        let mut uncompressed = [0; 3];
+        let data = [0b0110_0001, 0b1011_0000, 0b1010_0000];
+        assert!(matches!(
+            decompress(&data, &mut uncompressed),
+            Err(Error::OutputFull)
+        ));
+    }
+
+    #[test]
+    fn bad_code() {
+        let mut out = [0; 8];
+        let data = [0b0110_0001, 0b1011_0000, 0b1110_0000];
+        let e = decompress(&data, &mut out).unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::BadCode(3)));
+        assert_eq!(e.bit_offset(), Some(17));
+    }
+
+    #[test]
+    fn truncated() {
+        let mut out = [0; 8];
+        let data = [0b0110_0001, 0b1011_0000];
+        let e = decompress(&data, &mut out).unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::Truncated(7)));
+        assert_eq!(e.bit_offset(), Some(9));
+    }
+
+    #[test]
+    fn reuse_workspace() {
+        let mut workspace = Workspace::new();
+        let mut out = [0; 16];
+        let mut uncompressed = [0; 16];
+        for data in [&b"abcabcabc"[..], b"zzzzzz", b"abcabcabc"] {
+            let n = compress_into_with(&mut workspace, data, &mut out).unwrap();
+            let m = decompress_into_with(&mut workspace, &out[..n], &mut uncompressed).unwrap();
+            assert_eq!(&uncompressed[..m], data);
+        }
+    }
+
+    #[quickcheck]
+    fn matches_streaming(data: Vec<u8>) -> bool {
+        let mut expected = Vec::new();
+        let mut c = LZ78Compressor::new(&mut expected);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+
+        let mut out = vec![0; compress_bound(data.len())];
+        let n = compress(&data, &mut out).unwrap();
+        let mut uncompressed = vec![0; data.len()];
+        let m = decompress(&out[..n], &mut uncompressed).unwrap();
+        out[..n] == expected[..] && uncompressed[..m] == data[..]
+    }
+}
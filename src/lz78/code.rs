@@ -1,4 +1,4 @@
-use std::ops::{Add, AddAssign, Sub};
+use core::ops::{Add, AddAssign, Sub};
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash)]
 pub(crate) struct Code(pub(crate) u16);
@@ -19,6 +19,11 @@ impl Code {
 
         bits + value as usize
     }
+
+    /// Whether this is the last code there's room for.
+    pub fn is_max(self) -> bool {
+        self.0 == u16::MAX
+    }
 }
 
 impl AddAssign<u16> for Code {
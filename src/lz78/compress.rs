@@ -1,39 +1,105 @@
-use std::io::Write;
-
-use bitvec::{
-    array::BitArray, bitarr, bitvec, field::BitField, order::Msb0, prelude::BitVec,
-    slice::BitSlice, store::BitStore, view::BitView,
-};
-use radix_trie::Trie;
+use alloc::{boxed::Box, collections::BTreeMap, vec, vec::Vec};
 
 use crate::{
-    error::Error,
-    lz78::{code::Code, BitChunk, MAX_BITS},
+    bits::BitWriter,
+    error::{Error, ErrorKind},
+    io::{Sink, Source},
+    lz78::{
+        code::Code,
+        dictionary::Dictionary,
+        entropy::PhraseModel,
+        snapshot::{Side, SnapshotReader, SnapshotWriter},
+        Mode,
+    },
+    range_coder::RangeEncoder,
+    stats::{DictionaryStats, Stats},
 };
 
-use super::MAX_BYTES;
-
 pub struct LZ78Compressor<W> {
-    table: Trie<Vec<u8>, Code>,
-    writer: W,
+    /// Maps a phrase, as the code of its prefix plus one more byte, to its
+    /// own code.
+    table: BTreeMap<(Code, u8), Code>,
+    writer: Writer<W>,
 
-    seq_buffer: Vec<u8>,
-    bits: BitVec<Msb0, u8>,
-    // chunk: BitChunk,
+    /// Code of the phrase matched so far, `Code(0)` is the empty phrase.
+    prefix: Code,
     max_code: Code,
+    /// The last byte eaten, context for the next literal.
+    last_byte: u8,
+
+    input: u64,
+    /// Bytes out to the writer.
+    output: u64,
+    phrases: u64,
+    widths: [u64; 17],
+}
+
+enum Writer<W> {
+    Raw(BitWriter<W>),
+    Entropy(RangeEncoder<W>, Box<PhraseModel>),
+}
+
+impl<W: Sink> Writer<W> {
+    fn replace_writer<V>(self, writer: V) -> (Writer<V>, W) {
+        match self {
+            Writer::Raw(w) => {
+                let (w, old) = w.replace_sink(writer);
+                (Writer::Raw(w), old)
+            }
+            Writer::Entropy(e, model) => {
+                let (e, old) = e.replace_writer(writer);
+                (Writer::Entropy(e, model), old)
+            }
+        }
+    }
 }
 
-impl<W: Write> LZ78Compressor<W> {
+impl<W: Sink> LZ78Compressor<W> {
     pub fn new(writer: W) -> Self {
+        Self::with_mode(writer, Mode::Raw)
+    }
+
+    /// Create a compressor writing phrases as `mode` says. The decompressor
+    /// has to be given the same mode.
+    pub fn with_mode(writer: W, mode: Mode) -> Self {
+        let writer = match mode {
+            Mode::Raw => Writer::Raw(BitWriter::new(writer)),
+            Mode::Entropy => {
+                Writer::Entropy(RangeEncoder::new(writer), Box::new(PhraseModel::new()))
+            }
+        };
         Self {
-            table: Trie::new(),
+            table: BTreeMap::new(),
             writer,
 
-            seq_buffer: Vec::new(),
-            bits: BitVec::with_capacity(MAX_BITS),
-            // chunk: BitChunk,
+            prefix: Code(0),
             max_code: Code(0),
+            last_byte: 0,
+
+            input: 0,
+            output: 0,
+            phrases: 0,
+            widths: [0; 17],
+        }
+    }
+
+    /// Create a compressor starting from `dictionary`'s phrase table
+    /// instead of an empty one. The stream starts with the dictionary's id,
+    /// written out here.
+    pub fn with_dictionary(
+        mut writer: W,
+        mode: Mode,
+        dictionary: &Dictionary,
+    ) -> Result<Self, Error> {
+        let header = dictionary.header();
+        writer.write_all(&header)?;
+        let mut c = Self::with_mode(writer, mode);
+        for &entry in dictionary.entries() {
+            c.max_code += 1;
+            c.table.insert(entry, c.max_code);
         }
+        c.output = header.len() as u64;
+        Ok(c)
     }
 
     pub fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
@@ -42,111 +108,240 @@ impl<W: Write> LZ78Compressor<W> {
             log::info!("Reading {:08b}\n", b);
             written += self.eat_byte(b)?;
         }
+        self.input += data.len() as u64;
+        self.output += written as u64;
         Ok(written)
     }
 
-    fn output(&mut self, data: u16, bits: usize) -> Result<usize, Error> {
-        if bits == 0 {
-            return Ok(0);
+    /// What's gone in and out so far, and how the phrase table is doing.
+    /// Doesn't count the phrase under way, which goes out on [`finalize`].
+    ///
+    /// [`finalize`]: LZ78Compressor::finalize
+    pub fn stats(&self) -> Stats {
+        let pending = match &self.writer {
+            Writer::Raw(w) => w.pending_bits(),
+            Writer::Entropy(e, _) => e.pending_bits(),
+        };
+        Stats {
+            dictionary: Some(DictionaryStats {
+                phrases: self.phrases,
+                entries: self.max_code.0 as u64,
+                capacity: u16::MAX as u64,
+                resets: 0,
+                widths: self.widths,
+            }),
+            ..Stats::new(self.input, self.output * 8 + pending)
+        }
+    }
+
+    /// Save everything the compressor holds: the dictionary, the phrase
+    /// under way, bits not out to the writer yet and, in
+    /// [`Mode::Entropy`], the models. [`restore`] carries on the same
+    /// stream from it, in this process or another.
+    ///
+    /// [`restore`]: LZ78Compressor::restore
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mode = match self.writer {
+            Writer::Raw(_) => Mode::Raw,
+            Writer::Entropy(..) => Mode::Entropy,
+        };
+        let mut w = SnapshotWriter::new(Side::Compressor, mode);
+        let mut entries = vec![(Code(0), 0); self.max_code.0 as usize];
+        for (&entry, code) in &self.table {
+            entries[code.0 as usize - 1] = entry;
+        }
+        w.dictionary(&entries);
+        w.u16(self.prefix.0);
+        w.u8(self.last_byte);
+        for &v in [self.input, self.output, self.phrases]
+            .iter()
+            .chain(&self.widths)
+        {
+            w.u64(v);
+        }
+        match &self.writer {
+            Writer::Raw(bits) => {
+                let (value, n) = bits.pending();
+                w.u8(n as u8);
+                w.u64(value);
+            }
+            Writer::Entropy(e, model) => {
+                let (low, range, cache, cache_size) = e.state();
+                w.u64(low);
+                w.u32(range);
+                w.u8(cache);
+                w.u64(cache_size);
+                model.save(&mut w);
+            }
+        }
+        w.finish()
+    }
+
+    /// Carry on from a [`snapshot`], writing what comes next to `writer`.
+    /// Everything written before the snapshot is taken to be out already.
+    ///
+    /// [`snapshot`]: LZ78Compressor::snapshot
+    pub fn restore(writer: W, snapshot: &[u8]) -> Result<Self, Error> {
+        let (mut r, mode) = SnapshotReader::new(snapshot, Side::Compressor)?;
+        let entries = r.dictionary()?;
+        let max_code = Code(entries.len() as u16);
+        let table: BTreeMap<_, _> = (1..)
+            .map(Code)
+            .zip(&entries)
+            .map(|(c, &e)| (e, c))
+            .collect();
+        if table.len() != entries.len() {
+            return Err(r.error(ErrorKind::BadTable));
+        }
+        let prefix = Code(r.u16()?);
+        r.check((prefix <= max_code).then_some(()))?;
+        let last_byte = r.u8()?;
+        let (input, output, phrases) = (r.u64()?, r.u64()?, r.u64()?);
+        let mut widths = [0; 17];
+        for w in &mut widths {
+            *w = r.u64()?;
         }
+        let writer = match mode {
+            Mode::Raw => {
+                let (n, value) = (r.u8()? as u32, r.u64()?);
+                r.check((n <= 64 && value.checked_shr(n).unwrap_or(0) == 0).then_some(()))?;
+                let mut bits = BitWriter::new(writer);
+                if n > 32 {
+                    bits.write_bits((value >> 32) as u32, n - 32)?;
+                }
+                bits.write_bits(value as u32, n.min(32))?;
+                Writer::Raw(bits)
+            }
+            Mode::Entropy => {
+                let (low, range, cache, cache_size) = (r.u64()?, r.u32()?, r.u8()?, r.u64()?);
+                let e = RangeEncoder::resume(writer, low, range, cache, cache_size);
+                let e = r.check(e)?;
+                Writer::Entropy(e, Box::new(PhraseModel::load(&mut r)?))
+            }
+        };
+        r.finish()?;
+        Ok(Self {
+            table,
+            writer,
+
+            prefix,
+            max_code,
+            last_byte,
+
+            input,
+            output,
+            phrases,
+            widths,
+        })
+    }
+
+    /// Compress everything `source` has to give, until it runs dry.
+    pub fn write_from(&mut self, source: &mut impl Source) -> Result<usize, Error> {
+        let mut buffer = [0; 512];
         let mut written = 0;
-        log::info!("Bits: {:?}", self.bits);
-        self.bits
-            .extend_from_bitslice(&data.view_bits::<Msb0>()[16 - bits..16]);
-        // let i = self.bits.len();
-        // self.bits.reserve(bits);
-        // self.bits[i..i + bits].store_be(data);
-        // match self.chunk.store(data, bits) {
-        //     Ok(bits_left) => {
-        //         if bits_left == 0 {
-        //             log::info!("Writing full chunk");
-        //             written += self.chunk.write(&mut self.writer)?;
-        //             log::info!("written: {}", written);
-        //         }
-        //     }
-        //     Err(partial) => {
-        //         log::info!("Overflowed; chunk written: {:#?}", self.chunk);
-        //         log::info!("next chunk: {:#?}", partial);
-        //         written += self.chunk.write(&mut self.writer)?;
-        //         log::info!("written: {}", written);
-        //         self.chunk = partial;
-        //     }
-        // }
-        if self.bits.len() > MAX_BITS {
-            log::info!("Before remove Bits: {:?}", self.bits);
-            written += self
-                .writer
-                .write(&self.bits[..MAX_BITS].load_be::<u64>().to_be_bytes())?;
-            self.bits.retain(|i, _| i >= MAX_BITS);
-            log::info!("After remove Bits: {:?}", self.bits);
+        loop {
+            let n = source.read(&mut buffer)?;
+            if n == 0 {
+                return Ok(written);
+            }
+            written += self.write(&buffer[..n])?;
         }
-        Ok(written)
     }
 
-    fn eat_byte(&mut self, b: u8) -> Result<usize, Error> {
-        // TODO: Use SubTrieMut here for faster lookups
-        self.seq_buffer.push(b);
-        log::info!("buffer: {:?}", self.seq_buffer);
+    /// Write out a phrase's prefix code, coded against the table as it was
+    /// before the phrase.
+    fn output_code(&mut self, code: Code, max_code: Code) -> Result<usize, Error> {
+        match &mut self.writer {
+            Writer::Raw(w) => w.write_bits(code.0 as u32, max_code.min_bits() as u32),
+            Writer::Entropy(e, model) => model.encode_code(e, code, max_code),
+        }
+    }
 
-        match self.get_code() {
+    fn output_literal(&mut self, b: u8) -> Result<usize, Error> {
+        match &mut self.writer {
+            Writer::Raw(w) => w.write_bits(b as u32, 8),
+            Writer::Entropy(e, model) => model.encode_literal(e, self.last_byte, b),
+        }
+    }
+
+    fn eat_byte(&mut self, b: u8) -> Result<usize, Error> {
+        let max_code = self.max_code;
+        let written = match self.get_code(b) {
             None => {
-                log::info!("Found in trie, saving for later");
-                Ok(0)
+                log::info!("Found in table, saving for later");
+                0
             }
-            Some(Code(c)) => {
-                log::info!("{:?} not found in trie: {:?}", self.seq_buffer, c);
-                let o = self.output(c, (self.max_code - 1).min_bits())?;
-                let o = o + self.output(b as u16, 8)?;
-                self.seq_buffer.clear();
-                Ok(o)
+            Some(c) => {
+                log::info!("({:?}, {}) not found in table", c, b);
+                self.phrases += 1;
+                self.widths[max_code.min_bits()] += 1;
+                self.output_code(c, max_code)? + self.output_literal(b)?
             }
-        }
+        };
+        self.last_byte = b;
+        Ok(written)
     }
 
-    fn get_code<'a>(&'a mut self) -> Option<Code> {
-        if let Some(c) = self.table.get(&self.seq_buffer) {
+    /// Extend the current phrase with `b`. Returns the code of the phrase
+    /// before `b` if the extended phrase is new, and starts a fresh phrase.
+    /// Once every code is taken the table stops growing.
+    fn get_code(&mut self, b: u8) -> Option<Code> {
+        if let Some(&c) = self.table.get(&(self.prefix, b)) {
             // Not done eating bytes
-            log::info!("{:?} found in trie: {:?}", self.seq_buffer, c);
+            log::info!("({:?}, {}) found in table: {:?}", self.prefix, b, c);
+            self.prefix = c;
             None
         } else {
             // Found a sequence that needs to be added to the table
-            let (_, buffer) = self
-                .seq_buffer
-                .split_last()
-                .expect("Should be at least one byte");
-            self.max_code += 1;
-            self.table.insert(self.seq_buffer.clone(), self.max_code);
-            if buffer.len() == 0 {
-                return Some(Code(0));
+            if !self.max_code.is_max() {
+                self.max_code += 1;
+                self.table.insert((self.prefix, b), self.max_code);
             }
-            let code = if let Some(c) = self.table.get(buffer) {
-                *c
-            } else {
-                panic!("Smaller sequence not found, {:?}", buffer);
-            };
-
-            Some(code)
+            Some(core::mem::replace(&mut self.prefix, Code(0)))
         }
     }
 
+    /// Carry on over `writer` instead, handing back the old one.
+    pub(crate) fn replace_writer<V>(self, writer: V) -> (LZ78Compressor<V>, W) {
+        let (writer, old) = self.writer.replace_writer(writer);
+        let codec = LZ78Compressor {
+            table: self.table,
+            writer,
+            prefix: self.prefix,
+            max_code: self.max_code,
+            last_byte: self.last_byte,
+            input: self.input,
+            output: self.output,
+            phrases: self.phrases,
+            widths: self.widths,
+        };
+        (codec, old)
+    }
+
+    /// Write out any unfinished phrase and flush. In [`Mode::Entropy`] the
+    /// end of the stream is marked, followed by the unfinished phrase's code.
     pub fn finalize(mut self) -> Result<usize, Error> {
         log::info!("\nFinalizing");
-        if !self.seq_buffer.is_empty() {
-            let code = self.table.get(&self.seq_buffer);
-            match code {
-                Some(&Code(c)) => self.output(c, self.max_code.min_bits())?,
-                None => todo!("sequence doesn't exist in table, during finalize"),
-            };
+        match &mut self.writer {
+            Writer::Raw(w) => {
+                let mut written = 0;
+                if self.prefix != Code(0) {
+                    written +=
+                        w.write_bits(self.prefix.0 as u32, self.max_code.min_bits() as u32)?;
+                }
+                Ok(written + w.flush()?)
+            }
+            Writer::Entropy(e, model) => {
+                let mut written = model.encode_end(e)?;
+                written += model.encode_code(e, self.prefix, self.max_code)?;
+                Ok(written + e.flush()?)
+            }
         }
-
-        let num_bytes = (self.bits.len() as f32 / 8.0).ceil() as usize;
-        let v = &self.bits.as_raw_slice()[..num_bytes];
-        Ok(self.writer.write(&v)?)
-        // Ok(self.chunk.write(&mut self.writer)?)
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::unusual_byte_groupings)]
 mod tests {
     use super::*;
 
@@ -194,6 +389,81 @@ mod tests {
         assert_eq!(compressed, expected);
     }
 
+    #[test]
+    fn stats() {
+        let mut compressed = Vec::new();
+        let mut c = LZ78Compressor::new(&mut compressed);
+        c.write(b"aaaa").unwrap();
+        let stats = c.stats();
+        // "a" and "aa" are out, the second "a" is under way
+        assert_eq!((stats.input_bytes, stats.output_bits), (4, 17));
+        let dictionary = stats.dictionary.unwrap();
+        assert_eq!((dictionary.phrases, dictionary.entries), (2, 2));
+        assert_eq!(dictionary.widths[..3], [1, 1, 0]);
+        assert_eq!(stats.average_phrase_length(), Some(2.0));
+
+        // Whole words go out every 64 bits
+        c.write(&[b'b'; 100]).unwrap();
+        let stats = c.stats();
+        assert_eq!(stats.input_bytes, 104);
+        assert!(stats.output_bits > 64);
+        let written = stats.output_bits as usize / 8;
+        c.finalize().unwrap();
+        assert!(compressed.len() >= written);
+    }
+
+    #[test]
+    fn snapshot() {
+        let data = b"She sells sea shells by the sea shore. She sells sea shells. ".repeat(30);
+        for mode in [Mode::Raw, Mode::Entropy] {
+            let mut expected = Vec::new();
+            let mut c = LZ78Compressor::with_mode(&mut expected, mode);
+            c.write(&data).unwrap();
+            c.finalize().unwrap();
+
+            for split in [0, 1, 7, 100, data.len()] {
+                let mut first = Vec::new();
+                let mut c = LZ78Compressor::with_mode(&mut first, mode);
+                c.write(&data[..split]).unwrap();
+                let stats = c.stats();
+                let snapshot = c.snapshot();
+                drop(c);
+
+                let mut rest = Vec::new();
+                let mut c = LZ78Compressor::restore(&mut rest, &snapshot).unwrap();
+                assert_eq!(c.stats(), stats);
+                assert_eq!(c.snapshot(), snapshot);
+                c.write(&data[split..]).unwrap();
+                c.finalize().unwrap();
+                first.extend_from_slice(&rest);
+                assert_eq!(first, expected, "{:?} at {}", mode, split);
+            }
+        }
+    }
+
+    #[test]
+    fn bad_snapshot() {
+        let mut c = LZ78Compressor::new(Vec::new());
+        c.write(b"abcabcabc").unwrap();
+        let snapshot = c.snapshot();
+        let e = LZ78Compressor::restore(Vec::new(), &snapshot[..snapshot.len() - 1]);
+        assert!(matches!(
+            e.err().unwrap().kind(),
+            Some(ErrorKind::Truncated(_))
+        ));
+
+        let mut long = snapshot.clone();
+        long.push(0);
+        let e = LZ78Compressor::restore(Vec::new(), &long).err().unwrap();
+        assert_eq!(e.kind(), Some(ErrorKind::BadSnapshot));
+
+        // The same entry twice
+        let mut twice = snapshot;
+        twice[12..15].copy_from_slice(&[0, 0, b'a']);
+        let e = LZ78Compressor::restore(Vec::new(), &twice).err().unwrap();
+        assert_eq!(e.kind(), Some(ErrorKind::BadTable));
+    }
+
     #[test]
     fn four_equal_bytes_serial() {
         let mut compressed = Vec::new();
@@ -229,6 +499,47 @@ mod tests {
         assert_eq!(compressed, expected);
     }
 
+    #[test]
+    fn write_from() {
+        let mut compressed = Vec::new();
+        let mut c = LZ78Compressor::new(&mut compressed);
+        let mut data = &b"aaaa"[..];
+        assert_eq!(c.write_from(&mut data).unwrap(), 0);
+        assert_eq!(c.finalize().unwrap(), 3);
+        let expected = vec![0b0110_0001, 0b1011_0000, 0b1010_0000];
+        assert_eq!(compressed, expected);
+    }
+
+    #[test]
+    fn entropy() {
+        let mut compressed = Vec::new();
+        let mut c = LZ78Compressor::with_mode(&mut compressed, Mode::Entropy);
+        let data = b"She sells sea shells by the sea shore, the shells she sells are sea shells for sure. "
+            .repeat(20);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+
+        let mut raw = Vec::new();
+        let mut c = LZ78Compressor::new(&mut raw);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+        assert!(
+            compressed.len() < raw.len(),
+            "{} vs {}",
+            compressed.len(),
+            raw.len()
+        );
+    }
+
+    #[test]
+    fn entropy_empty() {
+        let mut compressed = Vec::new();
+        let c = LZ78Compressor::with_mode(&mut compressed, Mode::Entropy);
+        // The end of the stream and an empty last phrase
+        assert_eq!(c.finalize().unwrap(), 6);
+        assert_eq!(compressed, [0, 0xf1, 0xc7, 0x1c, 0x6e, 0]);
+    }
+
     #[test]
     fn zero_forty_forty() {
         let mut compressed = Vec::new();
@@ -239,4 +550,30 @@ mod tests {
         let expected = vec![0b00000000_, 0b0_0010100, 0b0_10_00000];
         assert_eq!(compressed, expected);
     }
+
+    #[test]
+    fn dictionary() {
+        let dictionary = Dictionary::from_corpus(b"{\"id\":1,\"name\":\"kompression\"}");
+        let message = b"{\"id\":2,\"name\":\"kompress\"}";
+        for mode in [Mode::Raw, Mode::Entropy] {
+            let mut plain = Vec::new();
+            let mut c = LZ78Compressor::with_mode(&mut plain, mode);
+            c.write(message).unwrap();
+            c.finalize().unwrap();
+
+            let mut compressed = Vec::new();
+            let mut c =
+                LZ78Compressor::with_dictionary(&mut compressed, mode, &dictionary).unwrap();
+            let start = LZ78Compressor::with_mode(Vec::new(), mode).stats();
+            assert_eq!(c.stats().output_bits, start.output_bits + 64);
+            assert_eq!(
+                c.stats().dictionary.unwrap().entries,
+                dictionary.len() as u64
+            );
+            c.write(message).unwrap();
+            c.finalize().unwrap();
+            assert_eq!(compressed[..8], dictionary.header());
+            assert!(compressed.len() - 8 < plain.len(), "{:?}", mode);
+        }
+    }
 }
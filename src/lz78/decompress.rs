@@ -1,123 +1,556 @@
-use std::{collections::HashMap, io::Write};
+use alloc::{boxed::Box, collections::BTreeMap, vec, vec::Vec};
 
-use bitvec::{field::BitField, order::Msb0, prelude::BitVec};
+use crate::{
+    error::{Error, ErrorKind},
+    io::{Sink, Source},
+    lz78::{
+        code::Code,
+        dictionary::{Dictionary, HEADER_LEN, STREAM_MAGIC},
+        entropy::{PhraseModel, END},
+        snapshot::{Side, SnapshotReader, SnapshotWriter},
+        Mode, CODEC,
+    },
+    options::DecompressOptions,
+    range_coder::{RangeDecoder, TOP},
+};
 
-use crate::{error::Error, lz78::code::Code};
+/// Compressed bytes to hold back in [`Mode::Entropy`] before decoding a
+/// symbol, so a symbol never needs more input than has arrived.
+const LOOKAHEAD: usize = 32;
 
 pub struct LZ78Decompressor<W> {
-    table: HashMap<Code, Vec<u8>>,
+    table: BTreeMap<Code, Vec<u8>>,
     writer: W,
+    options: DecompressOptions,
+
+    consumed: u64,
+    produced: u64,
+    table_bytes: usize,
 
     // seq_buffer: Vec<u8>,
     max_code: Code,
     last_code: Code,
 
-    bits: BitVec<Msb0, u8>,
+    /// The last byte written, context for the next literal.
+    last_byte: u8,
+
+    /// Bits read but not decoded yet, right aligned.
+    buffer: u64,
+    bits: usize,
     state: State,
+
+    /// Range decoder input in [`Mode::Entropy`].
+    entropy: Option<Box<EntropyInput>>,
+    /// With a preset dictionary, its id and as much of the header as has
+    /// arrived, until the header's all been checked.
+    header: Option<(u32, Vec<u8>)>,
 }
 
 enum State {
     Code,
     Byte,
+    /// The code of the unfinished phrase after the end of an entropy coded
+    /// stream.
+    Last,
+    Done,
+}
+
+enum Symbol {
+    /// A code, `None` at the end of the stream.
+    Code(Option<Code>),
+    Literal(u8),
 }
 
-impl<W: Write> LZ78Decompressor<W> {
+struct EntropyInput {
+    model: PhraseModel,
+    /// Compressed bytes not read by the range decoder yet.
+    buffer: Vec<u8>,
+    /// Range decoder state, `None` until it's had its first bytes.
+    decoder: Option<(u32, u32)>,
+}
+
+impl<W: Sink> LZ78Decompressor<W> {
     pub fn new(writer: W) -> Self {
-        let mut table = HashMap::new();
+        Self::with_options(writer, DecompressOptions::default())
+    }
+
+    /// Create a decompressor that stops with [`ErrorKind::LimitExceeded`]
+    /// once the stream crosses any of the limits in `options`.
+    pub fn with_options(writer: W, options: DecompressOptions) -> Self {
+        Self::with_mode(writer, Mode::Raw, options)
+    }
+
+    /// Create a decompressor for a stream written in `mode`.
+    pub fn with_mode(writer: W, mode: Mode, options: DecompressOptions) -> Self {
+        let entropy = match mode {
+            Mode::Raw => None,
+            Mode::Entropy => Some(Box::new(EntropyInput {
+                model: PhraseModel::new(),
+                buffer: Vec::new(),
+                decoder: None,
+            })),
+        };
+        let mut table = BTreeMap::new();
         table.insert(Code(0), vec![]);
         Self {
             table,
             writer,
+            options,
+
+            consumed: 0,
+            produced: 0,
+            table_bytes: 0,
 
             // seq_buffer: Vec::new(),
             max_code: Code(0),
             last_code: Code(0),
+            last_byte: 0,
 
-            bits: Default::default(),
+            buffer: 0,
+            bits: 0,
             state: State::Code,
+
+            entropy,
+            header: None,
+        }
+    }
+
+    /// Create a decompressor for a stream written by
+    /// [`LZ78Compressor::with_dictionary`], starting from the same
+    /// dictionary. The dictionary counts toward
+    /// [`DecompressOptions::max_dictionary_bytes`].
+    ///
+    /// [`LZ78Compressor::with_dictionary`]: crate::lz78::LZ78Compressor::with_dictionary
+    pub fn with_dictionary(
+        writer: W,
+        mode: Mode,
+        dictionary: &Dictionary,
+        options: DecompressOptions,
+    ) -> Self {
+        let mut d = Self::with_mode(writer, mode, options);
+        for &(prefix, b) in dictionary.entries() {
+            d.add_entry(prefix, b);
         }
+        d.header = Some((dictionary.id(), Vec::new()));
+        d
+    }
+
+    /// Add `prefix` plus `b` to the table, which mustn't be full.
+    fn add_entry(&mut self, prefix: Code, b: u8) {
+        let mut seq = self.table[&prefix].clone();
+        seq.push(b);
+        self.table_bytes += seq.len();
+        self.max_code += 1;
+        self.table.insert(self.max_code, seq);
     }
 
     fn code_size(&self) -> usize {
         self.max_code.min_bits()
     }
 
-    fn get_code(&mut self, bits: usize) -> Result<Code, Error> {
+    /// Pull `bits` bits off the front of the buffer, the caller makes sure
+    /// there are enough.
+    fn get_code(&mut self, bits: usize) -> Code {
         if bits == 0 {
-            return Ok(Code(0));
+            return Code(0);
+        }
+        self.bits -= bits;
+        let code = (self.buffer >> self.bits) & ((1 << bits) - 1);
+        Code(code as u16)
+    }
+
+    /// Offset in bits of the next unread bit in the compressed stream.
+    fn bit_offset(&self) -> u64 {
+        self.consumed * 8 - self.bits as u64
+    }
+
+    fn error(&self, kind: ErrorKind, bit_offset: u64) -> Error {
+        Error::stream(CODEC, kind, bit_offset, self.produced)
+    }
+
+    /// Write out the phrase for `c`, which will be the prefix of the next
+    /// phrase.
+    fn emit_code(&mut self, c: Code, offset: u64) -> Result<usize, Error> {
+        let len = match self.table.get(&c) {
+            Some(seq) => seq.len() as u64,
+            None => return Err(self.error(ErrorKind::BadCode(c.0 as u32), offset)),
+        };
+        self.options
+            .check_output(self.consumed, self.produced, len)
+            .map_err(|l| self.error(ErrorKind::LimitExceeded(l), offset))?;
+        let seq = &self.table[&c];
+        log::info!("Seq found: {:?}", seq);
+        self.writer.write_all(seq)?;
+        if let Some(&b) = seq.last() {
+            self.last_byte = b;
         }
-        if self.bits.len() < bits {
-            return Err(Error::Incomplete(self.bits.len() - self.code_size()));
+        self.produced += len;
+        self.last_code = c;
+        Ok(len as usize)
+    }
+
+    /// Write out the literal ending the current phrase, and add the phrase
+    /// to the table.
+    fn emit_byte(&mut self, b: u8, offset: u64) -> Result<usize, Error> {
+        let mut seq = self.table[&self.last_code].clone();
+        log::info!("Last seq: {:?}", seq);
+        log::info!("pushing: {:?}", b);
+        seq.push(b);
+        self.options
+            .check_dictionary(self.table_bytes + seq.len())
+            .and_then(|_| self.options.check_output(self.consumed, self.produced, 1))
+            .map_err(|l| self.error(ErrorKind::LimitExceeded(l), offset))?;
+        // The table stops growing once every code is taken
+        if !self.max_code.is_max() {
+            self.table_bytes += seq.len();
+            self.max_code += 1;
+            self.table.insert(self.max_code, seq);
         }
-        let code = self.bits[0..bits].load_be();
-        self.bits.retain(|i, _| i >= bits);
-        Ok(Code(code))
+        self.writer.write_all(&[b])?;
+        self.last_byte = b;
+        self.produced += 1;
+        Ok(1)
     }
 
-    pub fn read(&mut self, data: &[u8]) -> Result<usize, Error> {
+    pub fn read(&mut self, mut data: &[u8]) -> Result<usize, Error> {
+        if let Some((id, read)) = self.header.take() {
+            data = self.read_header(id, read, data)?;
+        }
+        if let Some(mut input) = self.entropy.take() {
+            input.buffer.extend_from_slice(data);
+            self.consumed += data.len() as u64;
+            let result = self.decode_entropy(&mut input, LOOKAHEAD);
+            self.entropy = Some(input);
+            return result;
+        }
+
         let mut written = 0;
-        'outer: for chunk in data.chunks(8) {
-            self.bits.extend_from_raw_slice(&chunk);
+        'outer: for &byte in data {
+            self.consumed += 1;
+            self.buffer = (self.buffer << 8) | byte as u64;
+            self.bits += 8;
             loop {
+                let offset = self.bit_offset();
                 match self.state {
                     State::Code => {
-                        if self.bits.len() < self.code_size() {
+                        if self.bits < self.code_size() {
                             continue 'outer;
                         }
                         log::info!("Reading code");
-                        match self.get_code(self.code_size()) {
-                            Ok(c) => {
-                                log::info!("Code found: {:?}", c);
-                                match self.table.get(&c) {
-                                    Some(seq) => {
-                                        log::info!("Seq found: {:?}", seq);
-                                        written += self.writer.write(&seq)?;
-                                    }
-                                    None => return Err(Error::BadCode(c.0)),
-                                }
-                                self.last_code = c;
-                                self.state = State::Byte;
-                            }
-                            Err(Error::Incomplete(s)) => continue 'outer,
-                            e => return Err(e.unwrap_err()),
-                        };
+                        let c = self.get_code(self.code_size());
+                        log::info!("Code found: {:?}", c);
+                        written += self.emit_code(c, offset)?;
+                        self.state = State::Byte;
                     }
                     State::Byte => {
-                        if self.bits.len() < 8 {
+                        if self.bits < 8 {
                             continue 'outer;
                         }
                         log::info!("Reading byte");
-                        match self.get_code(8) {
-                            Ok(c) => {
-                                log::info!("Got byte: {:?}", c);
-                                let mut seq = self.table.get(&self.last_code).unwrap().clone();
-                                log::info!("Last seq: {:?}", seq);
-                                log::info!("pushing: {:?}", c.0 as u8);
-                                seq.push(c.0 as u8);
-                                self.max_code += 1;
-                                self.table.insert(self.max_code, seq);
-                                written += self.writer.write(&[c.0 as u8])?;
-                                self.state = State::Code;
-                            }
-                            Err(Error::Incomplete(s)) => continue 'outer,
-                            e => return Err(e.unwrap_err()),
-                        };
+                        let c = self.get_code(8);
+                        log::info!("Got byte: {:?}", c);
+                        written += self.emit_byte(c.0 as u8, offset)?;
+                        self.state = State::Code;
                     }
+                    State::Last | State::Done => unreachable!("only entropy coded streams end"),
+                }
+            }
+        }
+        Ok(written)
+    }
+
+    /// Take what's missing of the dictionary id at the start of the
+    /// stream from `data`, checking it once it's all there. Returns the
+    /// rest of `data`.
+    fn read_header<'a>(
+        &mut self,
+        id: u32,
+        mut read: Vec<u8>,
+        data: &'a [u8],
+    ) -> Result<&'a [u8], Error> {
+        let n = (HEADER_LEN - read.len()).min(data.len());
+        read.extend_from_slice(&data[..n]);
+        self.consumed += n as u64;
+        if read.len() < HEADER_LEN {
+            self.header = Some((id, read));
+        } else if &read[..4] != STREAM_MAGIC {
+            let magic = read[..4].iter().fold(0, |m, &b| m << 8 | b as u32);
+            return Err(self.error(ErrorKind::BadCode(magic), 0));
+        } else {
+            let found = u32::from_le_bytes([read[4], read[5], read[6], read[7]]);
+            if found != id {
+                let kind = ErrorKind::WrongDictionary {
+                    expected: found,
+                    found: id,
+                };
+                return Err(self.error(kind, 32));
+            }
+        }
+        Ok(&data[n..])
+    }
+
+    /// Decode codes and literals while at least `lookahead` bytes are left
+    /// after the range decoder's position, or until the end of the stream.
+    fn decode_entropy(
+        &mut self,
+        input: &mut EntropyInput,
+        lookahead: usize,
+    ) -> Result<usize, Error> {
+        let EntropyInput {
+            model,
+            buffer,
+            decoder: state,
+        } = input;
+        // Bytes before `buffer`, for offsets
+        let dropped = self.consumed - buffer.len() as u64;
+        let mut decoder = match *state {
+            Some((code, range)) => RangeDecoder::resume(buffer, 0, code, range),
+            None if buffer.len() >= 5 + lookahead => RangeDecoder::new(buffer),
+            None => return Ok(0),
+        };
+
+        let mut written = 0;
+        loop {
+            let position = decoder.position();
+            if matches!(self.state, State::Done) || buffer.len() < position + lookahead {
+                break;
+            }
+            let offset = (dropped + position as u64) * 8;
+            let symbol = match self.state {
+                State::Byte => model
+                    .decode_literal(&mut decoder, self.last_byte)
+                    .map(Symbol::Literal),
+                _ => model
+                    .decode_code(&mut decoder, self.max_code)
+                    .map(Symbol::Code),
+            }
+            .map_err(|k| self.error(k, offset))?;
+            if decoder.overrun() {
+                let bits = (buffer.len() - position) * 8;
+                return Err(self.error(ErrorKind::Truncated(bits), offset));
+            }
+            match (&self.state, symbol) {
+                (State::Code, Symbol::Code(None)) => self.state = State::Last,
+                (State::Code, Symbol::Code(Some(c))) => {
+                    written += self.emit_code(c, offset)?;
+                    self.state = State::Byte;
+                }
+                (State::Last, Symbol::Code(Some(c))) => {
+                    written += self.emit_code(c, offset)?;
+                    self.state = State::Done;
+                }
+                (State::Last, Symbol::Code(None)) => {
+                    return Err(self.error(ErrorKind::BadCode(END as u32), offset));
+                }
+                (_, Symbol::Code(_)) => unreachable!("literals are decoded in `State::Byte`"),
+                (_, Symbol::Literal(b)) => {
+                    written += self.emit_byte(b, offset)?;
+                    self.state = State::Code;
                 }
             }
         }
+
+        let (position, code, range) = decoder.state();
+        let keep = position.min(buffer.len());
+        buffer.drain(..keep);
+        *state = Some((code, range));
         Ok(written)
     }
 
-    pub fn finalize(self) -> Result<usize, Error> {
-        // TODO: actually do something
+    /// Save everything the decompressor holds: the dictionary, where it is
+    /// in the stream, bits read but not decoded and, in [`Mode::Entropy`],
+    /// the models. [`restore`] carries on from it, in this process or
+    /// another. The options aren't saved.
+    ///
+    /// [`restore`]: LZ78Decompressor::restore
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mode = match self.entropy {
+            None => Mode::Raw,
+            Some(_) => Mode::Entropy,
+        };
+        let mut w = SnapshotWriter::new(Side::Decompressor, mode);
+        // Entries only keep their bytes, so find each one's prefix by them.
+        // Should two codes have the same bytes either will do.
+        let mut codes = BTreeMap::new();
+        let mut entries = Vec::with_capacity(self.max_code.0 as usize);
+        for (&code, seq) in &self.table {
+            if let Some((&b, prefix)) = seq.split_last() {
+                entries.push((codes[prefix], b));
+            }
+            codes.entry(&seq[..]).or_insert(code);
+        }
+        w.dictionary(&entries);
+        w.u16(self.last_code.0);
+        w.u8(self.last_byte);
+        w.u64(self.consumed);
+        w.u64(self.produced);
+        w.u8(match self.state {
+            State::Code => 0,
+            State::Byte => 1,
+            State::Last => 2,
+            State::Done => 3,
+        });
+        match &self.header {
+            None => w.u8(0),
+            Some((id, read)) => {
+                w.u8(1);
+                w.u32(*id);
+                w.u8(read.len() as u8);
+                w.bytes(read);
+            }
+        }
+        match &self.entropy {
+            None => {
+                w.u8(self.bits as u8);
+                w.u64(self.buffer & ((1 << self.bits) - 1));
+            }
+            Some(input) => {
+                w.u32(input.buffer.len() as u32);
+                w.bytes(&input.buffer);
+                match input.decoder {
+                    None => w.u8(0),
+                    Some((code, range)) => {
+                        w.u8(1);
+                        w.u32(code);
+                        w.u32(range);
+                    }
+                }
+                input.model.save(&mut w);
+            }
+        }
+        w.finish()
+    }
+
+    /// Carry on from a [`snapshot`], writing what comes next to `writer`
+    /// and holding to the limits in `options`. The next input is what
+    /// followed the input before the snapshot.
+    ///
+    /// [`snapshot`]: LZ78Decompressor::snapshot
+    pub fn restore(writer: W, snapshot: &[u8], options: DecompressOptions) -> Result<Self, Error> {
+        let (mut r, mode) = SnapshotReader::new(snapshot, Side::Decompressor)?;
+        let mut d = Self::with_mode(writer, mode, options);
+        for (prefix, b) in r.dictionary()? {
+            d.add_entry(prefix, b);
+            options
+                .check_dictionary(d.table_bytes)
+                .map_err(|l| r.error(ErrorKind::LimitExceeded(l)))?;
+        }
+        d.last_code = Code(r.u16()?);
+        r.check((d.last_code <= d.max_code).then_some(()))?;
+        d.last_byte = r.u8()?;
+        d.consumed = r.u64()?;
+        d.produced = r.u64()?;
+        d.state = match (r.u8()?, mode) {
+            (0, _) => State::Code,
+            (1, _) => State::Byte,
+            (2, Mode::Entropy) => State::Last,
+            (3, Mode::Entropy) => State::Done,
+            _ => return Err(r.error(ErrorKind::BadSnapshot)),
+        };
+        d.header = match r.u8()? {
+            0 => None,
+            1 => {
+                let id = r.u32()?;
+                let len = r.u8()? as usize;
+                r.check((len < HEADER_LEN).then_some(()))?;
+                Some((id, r.bytes(len)?.to_vec()))
+            }
+            _ => return Err(r.error(ErrorKind::BadSnapshot)),
+        };
+        match &mut d.entropy {
+            None => {
+                d.bits = r.u8()? as usize;
+                d.buffer = r.u64()?;
+                // Room for the next byte, and nothing above the bits
+                r.check((d.bits <= 56 && d.buffer >> d.bits == 0).then_some(()))?;
+            }
+            Some(input) => {
+                let len = r.u32()? as usize;
+                input.buffer = r.bytes(len)?.to_vec();
+                input.decoder = match r.u8()? {
+                    0 => None,
+                    1 => Some((r.u32()?, r.u32()?)),
+                    _ => return Err(r.error(ErrorKind::BadSnapshot)),
+                };
+                r.check(
+                    input
+                        .decoder
+                        .map_or(Some(()), |(_, range)| (range >= TOP).then_some(())),
+                )?;
+                input.model = PhraseModel::load(&mut r)?;
+            }
+        }
+        // Everything held has to have been read
+        let held = match &d.entropy {
+            None => d.bits.div_ceil(8),
+            Some(input) => input.buffer.len(),
+        } + d.header.as_ref().map_or(0, |(_, read)| read.len());
+        r.check((d.consumed >= held as u64 && d.consumed <= u64::MAX / 8).then_some(()))?;
+        r.finish()?;
+        Ok(d)
+    }
+
+    /// Decompress everything `source` has to give, until it runs dry.
+    pub fn read_from(&mut self, source: &mut impl Source) -> Result<usize, Error> {
+        let mut buffer = [0; 512];
+        let mut written = 0;
+        loop {
+            let n = source.read(&mut buffer)?;
+            if n == 0 {
+                return Ok(written);
+            }
+            written += self.read(&buffer[..n])?;
+        }
+    }
+
+    /// Carry on over `writer` instead, handing back the old one.
+    pub(crate) fn replace_writer<V>(self, writer: V) -> (LZ78Decompressor<V>, W) {
+        let codec = LZ78Decompressor {
+            table: self.table,
+            writer,
+            options: self.options,
+            consumed: self.consumed,
+            produced: self.produced,
+            table_bytes: self.table_bytes,
+            max_code: self.max_code,
+            last_code: self.last_code,
+            last_byte: self.last_byte,
+            buffer: self.buffer,
+            bits: self.bits,
+            state: self.state,
+            entropy: self.entropy,
+            header: self.header,
+        };
+        (codec, self.writer)
+    }
+
+    /// Finish the stream. Anything left over has to be the zero padding
+    /// at the end of the last byte, otherwise the stream was cut short.
+    pub fn finalize(mut self) -> Result<usize, Error> {
+        if let Some((_, read)) = &self.header {
+            return Err(self.error(ErrorKind::Truncated(read.len() * 8), 0));
+        }
+        if let Some(mut input) = self.entropy.take() {
+            let written = self.decode_entropy(&mut input, 0)?;
+            if !matches!(self.state, State::Done) {
+                let left = input.buffer.len() as u64;
+                let offset = (self.consumed - left) * 8;
+                return Err(self.error(ErrorKind::Truncated(left as usize * 8), offset));
+            }
+            return Ok(written);
+        }
+        if self.buffer & ((1 << self.bits) - 1) != 0 {
+            return Err(self.error(ErrorKind::Truncated(self.bits), self.bit_offset()));
+        }
         Ok(0)
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::unusual_byte_groupings)]
 mod tests {
     use super::*;
+    use crate::{error::ErrorKind, options::Limit};
 
     #[test]
     fn one_byte() {
@@ -134,7 +567,7 @@ mod tests {
     fn two_equal_bytes() {
         let mut uncompressed = Vec::new();
         let mut d = LZ78Decompressor::new(&mut uncompressed);
-        let data = vec![0b0110_0001, 0b1000_0000];
+        let data = [0b0110_0001, 0b1000_0000];
         let expected = b"aa";
         assert_eq!(d.read(&data[..]).unwrap(), 2);
         let _ = d.finalize();
@@ -145,7 +578,7 @@ mod tests {
     fn three_equal_bytes() {
         let mut uncompressed = Vec::new();
         let mut d = LZ78Decompressor::new(&mut uncompressed);
-        let data = vec![0b0110_0001, 0b1011_0000, 0b1000_0000];
+        let data = [0b0110_0001, 0b1011_0000, 0b1000_0000];
         let expected = b"aaa";
         assert_eq!(d.read(&data[..]).unwrap(), 3);
         let _ = d.finalize();
@@ -156,7 +589,7 @@ mod tests {
     fn four_equal_bytes() {
         let mut uncompressed = Vec::new();
         let mut d = LZ78Decompressor::new(&mut uncompressed);
-        let data = vec![0b0110_0001, 0b1011_0000, 0b1010_0000];
+        let data = [0b0110_0001, 0b1011_0000, 0b1010_0000];
         let expected = b"aaaa";
         assert_eq!(d.read(&data[..]).unwrap(), 4);
         assert_eq!(d.finalize().unwrap(), 0);
@@ -167,7 +600,7 @@ mod tests {
     fn four_equal_bytes_serial() {
         let mut uncompressed = Vec::new();
         let mut d = LZ78Decompressor::new(&mut uncompressed);
-        let data = vec![0b0110_0001, 0b1011_0000, 0b1010_0000];
+        let data = [0b0110_0001, 0b1011_0000, 0b1010_0000];
         let expected = b"aaaa";
         assert_eq!(d.read(&data[..1]).unwrap(), 1);
         assert_eq!(d.read(&data[1..2]).unwrap(), 1);
@@ -180,7 +613,7 @@ mod tests {
     fn zero_through_six() {
         let mut uncompressed = Vec::new();
         let mut d = LZ78Decompressor::new(&mut uncompressed);
-        let data = vec![
+        let data = [
             0b00000000_,
             0b0_0000000,
             0b1_00_00000,
@@ -201,10 +634,289 @@ mod tests {
     fn zero_forty_forty() {
         let mut uncompressed = Vec::new();
         let mut d = LZ78Decompressor::new(&mut uncompressed);
-        let data = vec![0b00000000_, 0b0_0010100, 0b0_10_00000];
+        let data = [0b00000000_, 0b0_0010100, 0b0_10_00000];
         let expected = [0, 40, 40];
         assert_eq!(d.read(&data[..]).unwrap(), 3);
         assert_eq!(d.finalize().unwrap(), 0);
         assert_eq!(uncompressed, expected);
     }
+
+    #[test]
+    fn read_from() {
+        let mut uncompressed = Vec::new();
+        let mut d = LZ78Decompressor::new(&mut uncompressed);
+        let mut data = &[0b0110_0001, 0b1011_0000, 0b1010_0000][..];
+        assert_eq!(d.read_from(&mut data).unwrap(), 4);
+        assert_eq!(d.finalize().unwrap(), 0);
+        assert_eq!(uncompressed, b"aaaa");
+    }
+
+    #[test]
+    fn max_output_bytes() {
+        let mut uncompressed = Vec::new();
+        let options = DecompressOptions::new().max_output_bytes(3);
+        let mut d = LZ78Decompressor::with_options(&mut uncompressed, options);
+        let data = [0b0110_0001, 0b1011_0000, 0b1010_0000];
+        let e = d.read(&data[..]).unwrap_err();
+        assert_eq!(
+            e.kind(),
+            Some(ErrorKind::LimitExceeded(Limit::OutputBytes(3)))
+        );
+        assert_eq!(e.bit_offset(), Some(17));
+        assert_eq!(e.byte_offset(), Some(3));
+        assert_eq!(uncompressed, b"aaa");
+    }
+
+    #[test]
+    fn max_dictionary_bytes() {
+        let mut uncompressed = Vec::new();
+        let options = DecompressOptions::new().max_dictionary_bytes(3);
+        let mut d = LZ78Decompressor::with_options(&mut uncompressed, options);
+        // "a", "aa", "aaa": the third phrase takes the table to 6 bytes
+        let data = [0b0110_0001, 0b1011_0000, 0b1100_1100, 0b0010_0000];
+        let e = d.read(&data[..]).unwrap_err();
+        assert_eq!(
+            e.kind(),
+            Some(ErrorKind::LimitExceeded(Limit::DictionaryBytes(3)))
+        );
+        assert_eq!(e.bit_offset(), Some(19));
+        assert_eq!(uncompressed, b"aaaaa");
+    }
+
+    #[test]
+    fn max_ratio() {
+        let mut uncompressed = Vec::new();
+        let options = DecompressOptions::new().max_ratio(1.0);
+        let mut d = LZ78Decompressor::with_options(&mut uncompressed, options);
+        let data = [0b0110_0001, 0b1011_0000, 0b1010_0000];
+        let e = d.read(&data[..]).unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::LimitExceeded(Limit::Ratio(1.0))));
+        assert_eq!(uncompressed, b"aaa");
+    }
+
+    #[test]
+    fn within_limits() {
+        let mut uncompressed = Vec::new();
+        let options = DecompressOptions::new()
+            .max_output_bytes(4)
+            .max_dictionary_bytes(3)
+            .max_ratio(2.0);
+        let mut d = LZ78Decompressor::with_options(&mut uncompressed, options);
+        let data = [0b0110_0001, 0b1011_0000, 0b1010_0000];
+        assert_eq!(d.read(&data[..]).unwrap(), 4);
+        assert_eq!(uncompressed, b"aaaa");
+    }
+
+    #[test]
+    fn bad_code() {
+        let mut uncompressed = Vec::new();
+        let mut d = LZ78Decompressor::new(&mut uncompressed);
+        // "a" followed by code 1 is fine, but code 3 in the third phrase
+        // hasn't been defined yet
+        let data = [0b0110_0001, 0b1011_0000, 0b1110_0000];
+        let e = d.read(&data[..]).unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::BadCode(3)));
+        assert_eq!(e.codec(), Some("lz78"));
+        assert_eq!(e.bit_offset(), Some(17));
+        assert_eq!(e.byte_offset(), Some(3));
+    }
+
+    #[test]
+    fn truncated() {
+        let mut uncompressed = Vec::new();
+        let mut d = LZ78Decompressor::new(&mut uncompressed);
+        let data = [0b0110_0001, 0b1011_0000];
+        assert_eq!(d.read(&data[..]).unwrap(), 2);
+        let e = d.finalize().unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::Truncated(7)));
+        assert_eq!(e.bit_offset(), Some(9));
+    }
+
+    fn entropy_compressed(data: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        let mut c = crate::LZ78Compressor::with_mode(&mut compressed, Mode::Entropy);
+        c.write(data).unwrap();
+        c.finalize().unwrap();
+        compressed
+    }
+
+    #[test]
+    fn entropy() {
+        let data = b"how much wood would a woodchuck chuck".repeat(20);
+        let compressed = entropy_compressed(&data);
+        let mut uncompressed = Vec::new();
+        let mut d =
+            LZ78Decompressor::with_mode(&mut uncompressed, Mode::Entropy, DecompressOptions::new());
+        let written = d.read(&compressed).unwrap();
+        assert!(written > 0);
+        assert_eq!(written + d.finalize().unwrap(), data.len());
+        assert_eq!(uncompressed, data);
+    }
+
+    #[test]
+    fn entropy_serial() {
+        // Ends in the middle of a phrase
+        let data = b"abcabcabcabcab";
+        let compressed = entropy_compressed(data);
+        let mut uncompressed = Vec::new();
+        let mut d =
+            LZ78Decompressor::with_mode(&mut uncompressed, Mode::Entropy, DecompressOptions::new());
+        for b in &compressed {
+            d.read(&[*b]).unwrap();
+        }
+        d.finalize().unwrap();
+        assert_eq!(uncompressed, data);
+    }
+
+    #[test]
+    fn entropy_truncated() {
+        let compressed = entropy_compressed(b"abcabcabcabcab");
+        let mut uncompressed = Vec::new();
+        let mut d =
+            LZ78Decompressor::with_mode(&mut uncompressed, Mode::Entropy, DecompressOptions::new());
+        d.read(&compressed[..compressed.len() - 1]).unwrap();
+        let e = d.finalize().unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::Truncated(0)));
+        assert_eq!(e.bit_offset(), Some((compressed.len() as u64 - 1) * 8));
+
+        let mut d =
+            LZ78Decompressor::with_mode(&mut uncompressed, Mode::Entropy, DecompressOptions::new());
+        d.read(&compressed[..3]).unwrap();
+        let e = d.finalize().unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::Truncated(24)));
+        assert_eq!(e.bit_offset(), Some(0));
+    }
+
+    #[test]
+    fn entropy_limits() {
+        let compressed = entropy_compressed(&[7; 1000]);
+        let mut uncompressed = Vec::new();
+        let options = DecompressOptions::new().max_output_bytes(100);
+        let mut d = LZ78Decompressor::with_mode(&mut uncompressed, Mode::Entropy, options);
+        d.read(&compressed).unwrap();
+        let e = d.finalize().unwrap_err();
+        assert_eq!(
+            e.kind(),
+            Some(ErrorKind::LimitExceeded(Limit::OutputBytes(100)))
+        );
+    }
+
+    #[test]
+    fn snapshot() {
+        let data = b"She sells sea shells by the sea shore. She sells sea shells. ".repeat(30);
+        for mode in [Mode::Raw, Mode::Entropy] {
+            let mut compressed = Vec::new();
+            let mut c = crate::LZ78Compressor::with_mode(&mut compressed, mode);
+            c.write(&data).unwrap();
+            c.finalize().unwrap();
+
+            for split in [0, 1, 7, 100, compressed.len()] {
+                let mut uncompressed = Vec::new();
+                let mut d =
+                    LZ78Decompressor::with_mode(&mut uncompressed, mode, DecompressOptions::new());
+                d.read(&compressed[..split]).unwrap();
+                let snapshot = d.snapshot();
+                drop(d);
+
+                let mut rest = Vec::new();
+                let mut d =
+                    LZ78Decompressor::restore(&mut rest, &snapshot, DecompressOptions::new())
+                        .unwrap();
+                assert_eq!(d.snapshot(), snapshot);
+                d.read(&compressed[split..]).unwrap();
+                d.finalize().unwrap();
+                uncompressed.extend_from_slice(&rest);
+                assert!(uncompressed == data, "{:?} at {}", mode, split);
+            }
+        }
+    }
+
+    #[test]
+    fn restore_limits() {
+        let mut uncompressed = Vec::new();
+        let mut d = LZ78Decompressor::new(&mut uncompressed);
+        d.read(&[0b0110_0001, 0b1011_0000]).unwrap();
+        let snapshot = d.snapshot();
+
+        // "a" is in the dictionary
+        let options = DecompressOptions::new().max_dictionary_bytes(0);
+        let e = LZ78Decompressor::restore(Vec::new(), &snapshot, options)
+            .err()
+            .unwrap();
+        assert_eq!(
+            e.kind(),
+            Some(ErrorKind::LimitExceeded(Limit::DictionaryBytes(0)))
+        );
+        let e = crate::LZ78Compressor::restore(Vec::new(), &snapshot)
+            .err()
+            .unwrap();
+        assert_eq!(e.kind(), Some(ErrorKind::BadSnapshot));
+    }
+
+    #[test]
+    fn dictionary() {
+        let dictionary = Dictionary::from_corpus(b"She sells sea shells by the sea shore.");
+        let data = b"She sells sea shells. ".repeat(3);
+        for mode in [Mode::Raw, Mode::Entropy] {
+            let mut compressed = Vec::new();
+            let mut c =
+                crate::LZ78Compressor::with_dictionary(&mut compressed, mode, &dictionary).unwrap();
+            c.write(&data).unwrap();
+            c.finalize().unwrap();
+
+            // A byte at a time, and snapshots partway through the header
+            let mut uncompressed = Vec::new();
+            let options = DecompressOptions::new();
+            let mut d =
+                LZ78Decompressor::with_dictionary(&mut uncompressed, mode, &dictionary, options);
+            for b in &compressed[..3] {
+                d.read(&[*b]).unwrap();
+            }
+            let snapshot = d.snapshot();
+            let mut d = LZ78Decompressor::restore(&mut uncompressed, &snapshot, options).unwrap();
+            for b in &compressed[3..] {
+                d.read(&[*b]).unwrap();
+            }
+            d.finalize().unwrap();
+            assert!(uncompressed == data, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn wrong_dictionary() {
+        let dictionary = Dictionary::from_corpus(b"abracadabra");
+        let mut compressed = Vec::new();
+        let mut c = crate::LZ78Compressor::with_dictionary(&mut compressed, Mode::Raw, &dictionary)
+            .unwrap();
+        c.write(b"abracadabra").unwrap();
+        c.finalize().unwrap();
+
+        let options = DecompressOptions::new();
+        let other = Dictionary::from_corpus(b"alakazam");
+        let mut d = LZ78Decompressor::with_dictionary(Vec::new(), Mode::Raw, &other, options);
+        let e = d.read(&compressed).unwrap_err();
+        assert_eq!(
+            e.kind(),
+            Some(ErrorKind::WrongDictionary {
+                expected: dictionary.id(),
+                found: other.id(),
+            })
+        );
+
+        // No header at all
+        let mut d = LZ78Decompressor::with_dictionary(Vec::new(), Mode::Raw, &dictionary, options);
+        let e = d.read(b"abracadabra").unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::BadCode(0x6162_7261)));
+
+        let mut d = LZ78Decompressor::with_dictionary(Vec::new(), Mode::Raw, &dictionary, options);
+        d.read(&compressed[..5]).unwrap();
+        let e = d.finalize().unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::Truncated(40)));
+
+        // The dictionary counts toward the limit
+        let options = options.max_dictionary_bytes(1);
+        let mut d = LZ78Decompressor::with_dictionary(Vec::new(), Mode::Raw, &dictionary, options);
+        let e = d.read(&compressed).unwrap_err();
+        assert!(matches!(e.kind(), Some(ErrorKind::LimitExceeded(_))));
+    }
 }
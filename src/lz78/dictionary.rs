@@ -0,0 +1,210 @@
+//! Preset dictionaries, so a stream starts with a phrase table instead of
+//! an empty one.
+//!
+//! A stream written with one starts with `KLZD` and the dictionary's id,
+//! a little endian CRC-32 of its entries, so a decompressor can tell it's
+//! been given the wrong one. A saved dictionary is `KLZT`, a version byte,
+//! the id, then the entries as in a [snapshot](super::snapshot).
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
+use core::convert::TryInto;
+
+use crate::{
+    bzip2::crc::Crc,
+    error::{Error, ErrorKind},
+    lz78::{
+        code::Code,
+        snapshot::{SnapshotReader, SnapshotWriter},
+    },
+};
+
+/// Starts a stream written with a preset dictionary, before the id.
+pub(crate) const STREAM_MAGIC: &[u8; 4] = b"KLZD";
+/// Bytes of the magic and the id.
+pub(crate) const HEADER_LEN: usize = 8;
+
+const MAGIC: &[u8; 4] = b"KLZT";
+const VERSION: u8 = 1;
+
+/// A phrase table for [`LZ78Compressor::with_dictionary`] and
+/// [`LZ78Decompressor::with_dictionary`] to start from. Worth it for
+/// small inputs much like each other, which otherwise spend most of their
+/// length filling the table.
+///
+/// Both sides need the same dictionary, and the same [`Mode`] as ever.
+///
+/// [`LZ78Compressor::with_dictionary`]: crate::lz78::LZ78Compressor::with_dictionary
+/// [`LZ78Decompressor::with_dictionary`]: crate::lz78::LZ78Decompressor::with_dictionary
+/// [`Mode`]: crate::lz78::Mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dictionary {
+    /// Each entry's prefix code and last byte, for codes from 1 up.
+    entries: Vec<(Code, u8)>,
+    id: u32,
+}
+
+impl Dictionary {
+    /// The table the compressor would have built compressing `corpus`,
+    /// stopping once it's full.
+    pub fn from_corpus(corpus: &[u8]) -> Self {
+        let mut table = BTreeMap::new();
+        let mut entries = Vec::new();
+        let mut prefix = Code(0);
+        for &b in corpus {
+            if let Some(&c) = table.get(&(prefix, b)) {
+                prefix = c;
+                continue;
+            }
+            if entries.len() == u16::MAX as usize {
+                break;
+            }
+            entries.push((prefix, b));
+            table.insert((prefix, b), Code(entries.len() as u16));
+            prefix = Code(0);
+        }
+        Self::from_entries(entries)
+    }
+
+    /// `entries` have to be in code order, each prefix before the entry.
+    pub(crate) fn from_entries(entries: Vec<(Code, u8)>) -> Self {
+        let mut crc = Crc::new();
+        for &(prefix, b) in &entries {
+            crc.update(&prefix.0.to_le_bytes());
+            crc.update(&[b]);
+        }
+        Self {
+            entries,
+            id: crc.value(),
+        }
+    }
+
+    /// Read a dictionary saved by [`to_bytes`](Dictionary::to_bytes).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut r = SnapshotReader::with_header(bytes, MAGIC, VERSION)?;
+        let id = r.u32()?;
+        let entries = r.dictionary()?;
+        // The compressor can't tell two codes for the same phrase apart
+        if entries.iter().collect::<BTreeSet<_>>().len() != entries.len() {
+            return Err(r.error(ErrorKind::BadTable));
+        }
+        let dictionary = Self::from_entries(entries);
+        if dictionary.id != id {
+            return Err(r.error(ErrorKind::ChecksumMismatch {
+                expected: id,
+                found: dictionary.id,
+            }));
+        }
+        r.finish()?;
+        Ok(dictionary)
+    }
+
+    /// Save the dictionary, to share it between the two sides.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = SnapshotWriter::with_header(MAGIC, VERSION);
+        w.u32(self.id);
+        w.dictionary(&self.entries);
+        w.finish()
+    }
+
+    /// What streams written with the dictionary are marked with.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Entries in the table, not counting the empty phrase.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub(crate) fn entries(&self) -> &[(Code, u8)] {
+        &self.entries
+    }
+
+    /// The start of a stream written with the dictionary.
+    pub(crate) fn header(&self) -> [u8; HEADER_LEN] {
+        let mut header = [0; HEADER_LEN];
+        header[..4].copy_from_slice(STREAM_MAGIC);
+        header[4..].copy_from_slice(&self.id.to_le_bytes());
+        header
+    }
+}
+
+/// The id of the dictionary `stream` was written with, `None` if it
+/// doesn't start like a stream written with one.
+pub fn dictionary_id(stream: &[u8]) -> Option<u32> {
+    match stream.get(..HEADER_LEN)?.split_at(4) {
+        (magic, id) if magic == STREAM_MAGIC => Some(u32::from_le_bytes(id.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corpus() {
+        let dictionary = Dictionary::from_corpus(b"abababa");
+        // a, b, ab, aba
+        assert_eq!(
+            dictionary.entries(),
+            [
+                (Code(0), b'a'),
+                (Code(0), b'b'),
+                (Code(1), b'b'),
+                (Code(3), b'a')
+            ]
+        );
+        assert_eq!(dictionary.len(), 4);
+        assert!(Dictionary::from_corpus(b"").is_empty());
+
+        let full = Dictionary::from_corpus(
+            &(0..=u16::MAX)
+                .flat_map(u16::to_be_bytes)
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(full.len(), u16::MAX as usize);
+    }
+
+    #[test]
+    fn bytes() {
+        let dictionary = Dictionary::from_corpus(b"abababa");
+        let bytes = dictionary.to_bytes();
+        assert_eq!(&bytes[..5], b"KLZT\x01");
+        assert_eq!(&bytes[5..9], dictionary.id().to_le_bytes());
+        assert_eq!(Dictionary::from_bytes(&bytes).unwrap(), dictionary);
+        assert_ne!(Dictionary::from_corpus(b"abba").id(), dictionary.id());
+
+        let kind = |bytes: &[u8]| Dictionary::from_bytes(bytes).unwrap_err().kind();
+        let mut bad = bytes.clone();
+        *bad.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            kind(&bad),
+            Some(ErrorKind::ChecksumMismatch { .. })
+        ));
+        assert_eq!(
+            kind(&bytes[..bytes.len() - 1]),
+            Some(ErrorKind::Truncated(0))
+        );
+        assert_eq!(kind(b"KLZT\x02"), Some(ErrorKind::UnsupportedVersion(2)));
+
+        // "a" twice
+        let twice = Dictionary::from_entries([(Code(0), b'a'), (Code(0), b'a')].to_vec());
+        assert_eq!(kind(&twice.to_bytes()), Some(ErrorKind::BadTable));
+    }
+
+    #[test]
+    fn id() {
+        let dictionary = Dictionary::from_corpus(b"abababa");
+        assert_eq!(dictionary_id(&dictionary.header()), Some(dictionary.id()));
+        assert_eq!(dictionary_id(b"KLZD\x01\x02\x03"), None);
+        assert_eq!(dictionary_id(b"KLZS\x01\x02\x03\x04"), None);
+    }
+}
@@ -0,0 +1,193 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    error::{Error, ErrorKind},
+    io::Sink,
+    lz78::{
+        code::Code,
+        snapshot::{SnapshotReader, SnapshotWriter},
+    },
+    range_coder::{BitModel, BitTreeModel, FrequencyModel, Model, RangeDecoder, RangeEncoder},
+};
+
+/// How phrases are written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Mode {
+    /// Each phrase is its prefix code at the current width plus a raw byte.
+    #[default]
+    Raw,
+    /// Codes and literals go through a range coder with adaptive models.
+    /// Codes are coded by how recently their phrase was added, and
+    /// literals by the byte before them.
+    Entropy,
+}
+
+/// Code symbol for the empty prefix.
+const EMPTY: usize = 0;
+/// Code symbol for the end of the stream, followed by the code of any
+/// unfinished phrase.
+pub(crate) const END: usize = 17;
+/// The empty prefix, 16 distance buckets and the end of the stream.
+const CODE_SYMBOLS: usize = 18;
+
+/// Models for the phrase stream in [`Mode::Entropy`].
+///
+/// A code is coded as its distance back from the newest code, plus one. The
+/// bit length of the distance goes through an adaptive frequency model and
+/// the bits under the top one go out as they are.
+#[derive(Debug, Clone)]
+pub(crate) struct PhraseModel {
+    buckets: FrequencyModel,
+    /// Indexed by the byte before the literal.
+    literals: Vec<BitTreeModel>,
+}
+
+impl PhraseModel {
+    pub fn new() -> Self {
+        Self {
+            buckets: FrequencyModel::new(CODE_SYMBOLS),
+            literals: vec![BitTreeModel::new(8); 256],
+        }
+    }
+
+    pub fn encode_code<W: Sink>(
+        &mut self,
+        encoder: &mut RangeEncoder<W>,
+        code: Code,
+        max_code: Code,
+    ) -> Result<usize, Error> {
+        if code == Code(0) {
+            return self.buckets.encode(encoder, EMPTY);
+        }
+        let distance = (max_code.0 - code.0) as u32 + 1;
+        let bits = 32 - distance.leading_zeros();
+        let mut written = self.buckets.encode(encoder, bits as usize)?;
+        if bits > 1 {
+            let top = 1 << (bits - 1);
+            written += encoder.encode(distance - top, 1, top)?;
+        }
+        Ok(written)
+    }
+
+    pub fn encode_end<W: Sink>(&mut self, encoder: &mut RangeEncoder<W>) -> Result<usize, Error> {
+        self.buckets.encode(encoder, END)
+    }
+
+    pub fn encode_literal<W: Sink>(
+        &mut self,
+        encoder: &mut RangeEncoder<W>,
+        context: u8,
+        literal: u8,
+    ) -> Result<usize, Error> {
+        self.literals[context as usize].encode(encoder, literal as usize)
+    }
+
+    /// Decode a code, `None` at the end of the stream.
+    pub fn decode_code(
+        &mut self,
+        decoder: &mut RangeDecoder,
+        max_code: Code,
+    ) -> Result<Option<Code>, ErrorKind> {
+        let bits = match self.buckets.decode(decoder)? {
+            END => return Ok(None),
+            EMPTY => return Ok(Some(Code(0))),
+            bits => bits as u32,
+        };
+        let top = 1 << (bits - 1);
+        let mut distance = top;
+        if bits > 1 {
+            distance += decoder.decode(top, |v| (v as usize, v, 1))? as u32;
+        }
+        if distance > max_code.0 as u32 {
+            return Err(ErrorKind::BadCode(distance));
+        }
+        Ok(Some(Code(max_code.0 - (distance - 1) as u16)))
+    }
+
+    /// Add the models to a snapshot: the bucket counts, then every
+    /// literal tree's probabilities.
+    pub fn save(&self, w: &mut SnapshotWriter) {
+        for &f in self.buckets.frequencies() {
+            w.u32(f);
+        }
+        for tree in &self.literals {
+            for model in tree.models() {
+                w.u16(model.probability());
+            }
+        }
+    }
+
+    pub fn load(r: &mut SnapshotReader) -> Result<Self, Error> {
+        let frequencies = (0..CODE_SYMBOLS)
+            .map(|_| r.u32())
+            .collect::<Result<_, _>>()?;
+        let buckets = r.check(FrequencyModel::from_frequencies(frequencies))?;
+        let mut literals = Vec::with_capacity(256);
+        for _ in 0..256 {
+            let mut models = Vec::with_capacity(256);
+            for _ in 0..256 {
+                let p = r.u16()?;
+                models.push(r.check(BitModel::with_probability(p))?);
+            }
+            literals.push(r.check(BitTreeModel::from_models(8, models))?);
+        }
+        Ok(Self { buckets, literals })
+    }
+
+    pub fn decode_literal(
+        &mut self,
+        decoder: &mut RangeDecoder,
+        context: u8,
+    ) -> Result<u8, ErrorKind> {
+        self.literals[context as usize]
+            .decode(decoder)
+            .map(|b| b as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes() {
+        let max_code = Code(u16::MAX);
+        let codes = [0, 1, 2, 3, 100, 65533, 65534, 65535];
+        let mut out = Vec::new();
+        let mut e = RangeEncoder::new(&mut out);
+        let mut m = PhraseModel::new();
+        for &c in &codes {
+            m.encode_code(&mut e, Code(c), max_code).unwrap();
+            m.encode_literal(&mut e, c as u8, b'x').unwrap();
+        }
+        m.encode_end(&mut e).unwrap();
+        e.flush().unwrap();
+
+        let mut d = RangeDecoder::new(&out);
+        let mut m = PhraseModel::new();
+        for &c in &codes {
+            assert_eq!(m.decode_code(&mut d, max_code), Ok(Some(Code(c))));
+            assert_eq!(m.decode_literal(&mut d, c as u8), Ok(b'x'));
+        }
+        assert_eq!(m.decode_code(&mut d, max_code), Ok(None));
+        assert!(!d.overrun());
+    }
+
+    #[test]
+    fn code_past_max() {
+        let mut out = Vec::new();
+        let mut e = RangeEncoder::new(&mut out);
+        PhraseModel::new()
+            .encode_code(&mut e, Code(1), Code(8))
+            .unwrap();
+        e.flush().unwrap();
+
+        // A distance of 8 only fits with 8 codes or more
+        let mut d = RangeDecoder::new(&out);
+        assert_eq!(
+            PhraseModel::new().decode_code(&mut d, Code(7)),
+            Err(ErrorKind::BadCode(8))
+        );
+    }
+}
@@ -0,0 +1,392 @@
+use alloc::{format, string::String, vec, vec::Vec};
+use core::fmt::{self, Write};
+
+use crate::{
+    error::{Error, ErrorKind},
+    lz78::{
+        code::Code,
+        entropy::{PhraseModel, END},
+        Mode, CODEC,
+    },
+    options::DecompressOptions,
+    range_coder::RangeDecoder,
+    utils::format_bits,
+};
+
+/// One phrase of an LZ78 stream, as [`inspect`] finds it: the code of a
+/// phrase already in the dictionary, then a literal byte. The two together
+/// are the next dictionary entry.
+///
+/// Printing a token gives one line of a listing: the bit offset, the code
+/// width, the code in binary and in decimal, the new entry's index, then
+/// the phrase and the literal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    /// Where the code starts in the compressed stream. In [`Mode::Entropy`]
+    /// this is where the range decoder was, so always a whole byte.
+    pub bit_offset: u64,
+    /// Bits a code takes with the dictionary at its size before the token.
+    /// In [`Mode::Entropy`] codes are range coded instead, but this still
+    /// says how big the dictionary was.
+    pub width: usize,
+    pub code: u16,
+    /// What `code` stands for.
+    pub phrase: Vec<u8>,
+    /// `None` for the unfinished phrase at the end of a stream.
+    pub literal: Option<u8>,
+    /// Where the phrase plus the literal went in the dictionary, `None`
+    /// without a literal or once the dictionary is full.
+    pub index: Option<u16>,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let index = self.index.map_or_else(|| "-".into(), |i| format!("{}", i));
+        write!(
+            f,
+            "{:>10}  {:>5}  {:>17}  {:>5}  {:>5}  \"{}\"",
+            self.bit_offset,
+            self.width,
+            format_bits(self.code as u64, self.width),
+            self.code,
+            index,
+            escape(&self.phrase),
+        )?;
+        match self.literal {
+            Some(b) => write!(f, " + '{}'", escape(&[b])),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Column headings lining up with a printed [`Token`].
+pub const HEADINGS: &str = "    offset  width               bits   code  index  phrase + literal";
+
+/// Decode all of an LZ78 stream written in `mode` into its tokens. Fails
+/// where [`LZ78Decompressor`] would, `options` included. Every token holds
+/// a copy of its phrase, so the tokens take about as much memory as the
+/// stream decompresses to. Set limits for untrusted input.
+///
+/// [`LZ78Decompressor`]: crate::lz78::LZ78Decompressor
+pub fn inspect(data: &[u8], mode: Mode, options: DecompressOptions) -> Result<Vec<Token>, Error> {
+    let mut listing = Listing::new(data.len() as u64, options);
+    match mode {
+        Mode::Raw => listing.raw(data)?,
+        Mode::Entropy => listing.entropy(data)?,
+    }
+    Ok(listing.tokens)
+}
+
+/// `tokens` as a JSON array, one object per token. Phrases are arrays of
+/// byte values, and a missing literal or index is `null`.
+pub fn to_json(tokens: &[Token]) -> String {
+    let mut json = String::from("[");
+    for (i, token) in tokens.iter().enumerate() {
+        let phrase = token
+            .phrase
+            .iter()
+            .map(|b| format!("{}", b))
+            .collect::<Vec<_>>()
+            .join(",");
+        let null = |v: Option<u16>| v.map_or_else(|| "null".into(), |v| format!("{}", v));
+        let _ = write!(
+            json,
+            "{}\n  {{\"bit_offset\":{},\"width\":{},\"code\":{},\"phrase\":[{}],\"literal\":{},\"index\":{}}}",
+            if i > 0 { "," } else { "" },
+            token.bit_offset,
+            token.width,
+            token.code,
+            phrase,
+            null(token.literal.map(u16::from)),
+            null(token.index),
+        );
+    }
+    json.push_str("\n]");
+    json
+}
+
+fn escape(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .flat_map(|&b| core::ascii::escape_default(b))
+        .map(char::from)
+        .collect()
+}
+
+/// The dictionary and the tokens so far.
+struct Listing {
+    options: DecompressOptions,
+    /// Length of the stream.
+    consumed: u64,
+    /// Indexed by code.
+    table: Vec<Vec<u8>>,
+    /// Bytes in all the phrases in `table`.
+    table_bytes: usize,
+    tokens: Vec<Token>,
+    /// Bytes the tokens so far decompress to.
+    produced: u64,
+    /// The last byte of that, context for the next literal.
+    last_byte: u8,
+}
+
+impl Listing {
+    fn new(consumed: u64, options: DecompressOptions) -> Self {
+        Self {
+            options,
+            consumed,
+            table: vec![vec![]],
+            table_bytes: 0,
+            tokens: Vec::new(),
+            produced: 0,
+            last_byte: 0,
+        }
+    }
+
+    fn max_code(&self) -> Code {
+        Code((self.table.len() - 1) as u16)
+    }
+
+    fn error(&self, kind: ErrorKind, bit_offset: u64) -> Error {
+        Error::stream(CODEC, kind, bit_offset, self.produced)
+    }
+
+    /// A range coded stream cut short, noticed decoding at `offset`.
+    fn truncated(&self, data: &[u8], offset: u64) -> Error {
+        let left = data.len().saturating_sub(offset as usize / 8);
+        self.error(ErrorKind::Truncated(left * 8), offset)
+    }
+
+    fn push(&mut self, bit_offset: u64, code: Code, literal: Option<u8>) -> Result<(), Error> {
+        let max_code = self.max_code();
+        let len = match self.table.get(code.0 as usize) {
+            Some(phrase) => phrase.len(),
+            None => return Err(self.error(ErrorKind::BadCode(code.0 as u32), bit_offset)),
+        };
+        // Held to the limits just as the decompressor is
+        let output = len as u64 + literal.is_some() as u64;
+        let dictionary = match literal {
+            Some(_) => self.table_bytes + len + 1,
+            None => 0,
+        };
+        self.options
+            .check_output(self.consumed, self.produced, output)
+            .and_then(|_| self.options.check_dictionary(dictionary))
+            .map_err(|l| self.error(ErrorKind::LimitExceeded(l), bit_offset))?;
+
+        let phrase = self.table[code.0 as usize].clone();
+        let mut index = None;
+        if let Some(b) = literal {
+            // The table stops growing once every code is taken
+            if !max_code.is_max() {
+                let mut entry = phrase.clone();
+                entry.push(b);
+                self.table_bytes += entry.len();
+                self.table.push(entry);
+                index = Some(max_code.0 + 1);
+            }
+        }
+        if let Some(&b) = literal.as_ref().or_else(|| phrase.last()) {
+            self.last_byte = b;
+        }
+        self.produced += output;
+        self.tokens.push(Token {
+            bit_offset,
+            width: max_code.min_bits(),
+            code: code.0,
+            phrase,
+            literal,
+            index,
+        });
+        Ok(())
+    }
+
+    fn raw(&mut self, data: &[u8]) -> Result<(), Error> {
+        let total = data.len() as u64 * 8;
+        let bit = |p: u64| (data[(p / 8) as usize] >> (7 - p % 8) & 1) as u16;
+        let read = |position: u64, bits: usize| {
+            (position..position + bits as u64).fold(0, |v, p| v << 1 | bit(p))
+        };
+        let mut position = 0;
+        loop {
+            let offset = position;
+            let width = self.max_code().min_bits();
+            if total - position < width as u64 {
+                break;
+            }
+            let code = Code(read(position, width));
+            position += width as u64;
+            if total - position < 8 {
+                // The unfinished phrase at the end, unless it's padding
+                if code != Code(0) {
+                    self.push(offset, code, None)?;
+                }
+                break;
+            }
+            let literal = read(position, 8) as u8;
+            position += 8;
+            self.push(offset, code, Some(literal))?;
+        }
+
+        // Anything after the last token has to be zero padding
+        if (position..total).any(|p| bit(p) != 0) {
+            let left = (total - position) as usize;
+            return Err(self.error(ErrorKind::Truncated(left), position));
+        }
+        Ok(())
+    }
+
+    fn entropy(&mut self, data: &[u8]) -> Result<(), Error> {
+        let mut model = PhraseModel::new();
+        let mut decoder = RangeDecoder::new(data);
+        let mut ended = false;
+        loop {
+            let offset = decoder.position() as u64 * 8;
+            let code = model
+                .decode_code(&mut decoder, self.max_code())
+                .map_err(|k| self.error(k, offset))?;
+            if decoder.overrun() {
+                return Err(self.truncated(data, offset));
+            }
+            match (ended, code) {
+                (false, None) => ended = true,
+                (true, None) => return Err(self.error(ErrorKind::BadCode(END as u32), offset)),
+                (true, Some(code)) => {
+                    if code != Code(0) {
+                        self.push(offset, code, None)?;
+                    }
+                    return Ok(());
+                }
+                (false, Some(code)) => {
+                    let context = self.table[code.0 as usize]
+                        .last()
+                        .copied()
+                        .unwrap_or(self.last_byte);
+                    let literal = model
+                        .decode_literal(&mut decoder, context)
+                        .map_err(|k| self.error(k, offset))?;
+                    if decoder.overrun() {
+                        return Err(self.truncated(data, offset));
+                    }
+                    self.push(offset, code, Some(literal))?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unusual_byte_groupings)]
+mod tests {
+    use super::*;
+    use crate::{LZ78Compressor, LZ78Decompressor};
+
+    fn compress(data: &[u8], mode: Mode) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        let mut c = LZ78Compressor::with_mode(&mut compressed, mode);
+        c.write(data).unwrap();
+        c.finalize().unwrap();
+        compressed
+    }
+
+    #[test]
+    fn four_equal_bytes() {
+        let tokens = inspect(
+            &[0b0110_0001, 0b1011_0000, 0b1010_0000],
+            Mode::Raw,
+            DecompressOptions::new(),
+        )
+        .unwrap();
+        let token = |bit_offset, width, code, phrase: &[u8], literal, index| Token {
+            bit_offset,
+            width,
+            code,
+            phrase: phrase.to_vec(),
+            literal,
+            index,
+        };
+        assert_eq!(
+            tokens,
+            [
+                token(0, 0, 0, b"", Some(b'a'), Some(1)),
+                token(8, 1, 1, b"a", Some(b'a'), Some(2)),
+                token(17, 2, 1, b"a", None, None),
+            ]
+        );
+        assert_eq!(
+            tokens[1].to_string(),
+            "         8      1                  1      1      2  \"a\" + 'a'"
+        );
+        assert_eq!(
+            to_json(&tokens[2..]),
+            "[\n  {\"bit_offset\":17,\"width\":2,\"code\":1,\"phrase\":[97],\"literal\":null,\"index\":null}\n]"
+        );
+    }
+
+    #[test]
+    fn phrases_make_the_data() {
+        let data = b"She sells sea shells by the sea shore. She sells sea shells. ".repeat(20);
+        for mode in [Mode::Raw, Mode::Entropy] {
+            let tokens = inspect(&compress(&data, mode), mode, DecompressOptions::new()).unwrap();
+            let mut uncompressed = Vec::new();
+            for (i, t) in tokens.iter().enumerate() {
+                uncompressed.extend_from_slice(&t.phrase);
+                uncompressed.extend(t.literal);
+                assert_eq!(t.index, t.literal.map(|_| i as u16 + 1));
+            }
+            assert_eq!(uncompressed, data, "{:?}", mode);
+            assert!(tokens
+                .windows(2)
+                .all(|t| t[0].bit_offset <= t[1].bit_offset));
+        }
+        assert_eq!(to_json(&[]), "[\n]");
+    }
+
+    #[test]
+    fn bad_streams() {
+        // Code 3 with only 2 in the table
+        let e = inspect(
+            &[0b0110_0001, 0b1011_0000, 0b1_11_00000],
+            Mode::Raw,
+            DecompressOptions::new(),
+        )
+        .unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::BadCode(3)));
+        // A stray bit after "a"
+        let e = inspect(
+            &[0b0110_0001, 0b0100_0000],
+            Mode::Raw,
+            DecompressOptions::new(),
+        )
+        .unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::Truncated(7)));
+
+        let compressed = compress(b"abracadabra", Mode::Entropy);
+        let e = inspect(
+            &compressed[..compressed.len() - 3],
+            Mode::Entropy,
+            DecompressOptions::new(),
+        )
+        .unwrap_err();
+        assert!(matches!(e.kind(), Some(ErrorKind::Truncated(_))));
+    }
+
+    #[test]
+    fn limits() {
+        let data = b"a".repeat(1000);
+        let compressed = compress(&data, Mode::Raw);
+        for options in [
+            DecompressOptions::new().max_output_bytes(999),
+            DecompressOptions::new().max_dictionary_bytes(100),
+            DecompressOptions::new().max_ratio(2.0),
+        ] {
+            let mut uncompressed = Vec::new();
+            let mut d = LZ78Decompressor::with_options(&mut uncompressed, options);
+            let expected = d.read(&compressed).and_then(|_| d.finalize()).unwrap_err();
+            let e = inspect(&compressed, Mode::Raw, options).unwrap_err();
+            assert_eq!(e.kind(), expected.kind());
+            assert!(matches!(e.kind(), Some(ErrorKind::LimitExceeded(_))));
+        }
+        let options = DecompressOptions::new().max_output_bytes(1000);
+        assert!(inspect(&compressed, Mode::Raw, options).is_ok());
+    }
+}
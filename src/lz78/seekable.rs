@@ -0,0 +1,307 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::{
+    error::{Error, ErrorKind},
+    lz78::{
+        block::{
+            decompress, le_u32, le_u64, rebase, Footer, Header, FOOTER_LEN, FRAME_LEN, HEADER_LEN,
+            MAGIC,
+        },
+        Mode, CODEC,
+    },
+    options::DecompressOptions,
+};
+
+/// Reads a stream from [`BlockCompressor::seekable`] as the bytes it
+/// decompresses to, going straight to the blocks each read needs by the
+/// index at the end of the stream. The block last read from is kept, so
+/// small reads in a row only decompress it once.
+///
+/// [`BlockCompressor::seekable`]: crate::lz78::BlockCompressor::seekable
+pub struct SeekableReader<R> {
+    reader: R,
+    /// Just the dictionary limit, blocks are checked against their length.
+    options: DecompressOptions,
+    mode: Mode,
+    block_size: u32,
+    /// Where each block's lengths start in the stream.
+    index: Vec<u64>,
+    /// Where the last block ends.
+    blocks_end: u64,
+    len: u64,
+    position: u64,
+    /// The block last read from, and which one it is.
+    cached: Option<(usize, Vec<u8>)>,
+}
+
+fn error(kind: ErrorKind, offset: u64) -> Error {
+    Error::stream(CODEC, kind, offset * 8, 0)
+}
+
+impl<R: Read + Seek> SeekableReader<R> {
+    pub fn new(reader: R) -> Result<Self, Error> {
+        Self::with_options(reader, DecompressOptions::default())
+    }
+
+    /// Open a stream, failing with [`ErrorKind::LimitExceeded`] if its
+    /// index says it's more than `options` allow. Each block's dictionary
+    /// is held to them too. A block stream written without an index fails
+    /// with [`ErrorKind::BadMagic`] carrying its `KLZB` magic.
+    pub fn with_options(mut reader: R, options: DecompressOptions) -> Result<Self, Error> {
+        let end = reader.seek(SeekFrom::End(0))?;
+        // The header, the end of the blocks and the footer at least
+        let least = (HEADER_LEN + 4 + FOOTER_LEN) as u64;
+        if end < least {
+            return Err(error(ErrorKind::Truncated(end as usize * 8), 0));
+        }
+        let mut header = [0; HEADER_LEN];
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_exact(&mut header)?;
+        let header = Header::read(&header).map_err(|(kind, at)| error(kind, at))?;
+        if !header.indexed {
+            let magic = u32::from_be_bytes(*MAGIC);
+            return Err(error(ErrorKind::BadMagic(magic), 0));
+        }
+
+        let mut footer = [0; FOOTER_LEN];
+        let footer_offset = end - FOOTER_LEN as u64;
+        reader.seek(SeekFrom::Start(footer_offset))?;
+        reader.read_exact(&mut footer)?;
+        let bad = |offset| error(ErrorKind::BadTable, offset);
+        let footer = Footer::read(&footer).ok_or_else(|| bad(footer_offset))?;
+        // Every block takes at least its offset, its lengths and a byte
+        let most = (end - least) / (8 + FRAME_LEN as u64 + 1);
+        if footer.blocks > most || !footer.fits(header.block_size) {
+            return Err(bad(footer_offset));
+        }
+        options
+            .check_output(end, 0, footer.len)
+            .map_err(|l| error(ErrorKind::LimitExceeded(l), footer_offset))?;
+
+        let index_offset = footer_offset - footer.blocks * 8;
+        let mut index = vec![0; footer.blocks as usize * 8];
+        reader.seek(SeekFrom::Start(index_offset - 4))?;
+        let mut end_marker = [0; 4];
+        reader.read_exact(&mut end_marker)?;
+        reader.read_exact(&mut index)?;
+        let index: Vec<u64> = index.chunks(8).filter_map(|b| le_u64(b, 0)).collect();
+        // Blocks start right after the header, one after another, and end
+        // where the stream does
+        let blocks_end = index_offset - 4;
+        let mut starts = index.iter().chain(Some(&blocks_end));
+        let in_order = starts.next() == Some(&(HEADER_LEN as u64))
+            && index
+                .iter()
+                .zip(starts)
+                .all(|(&a, &b)| b.checked_sub(a).is_some_and(|n| n > FRAME_LEN as u64));
+        if le_u32(&end_marker, 0) != Some(0) || !in_order {
+            return Err(bad(index_offset));
+        }
+
+        let mut block_options = DecompressOptions::new();
+        block_options.max_dictionary_bytes = options.max_dictionary_bytes;
+        Ok(Self {
+            reader,
+            options: block_options,
+            mode: header.mode,
+            block_size: header.block_size,
+            index,
+            blocks_end,
+            len: footer.len,
+            position: 0,
+            cached: None,
+        })
+    }
+
+    /// Length of the whole stream uncompressed.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Block `i`, decompressed unless it's the one kept.
+    fn block(&mut self, i: usize) -> Result<&[u8], Error> {
+        if self.cached.as_ref().map(|(c, _)| *c) != Some(i) {
+            let start = self.index[i];
+            let end = self.index.get(i + 1).copied().unwrap_or(self.blocks_end);
+            let mut frame = vec![0; (end - start) as usize];
+            self.reader.seek(SeekFrom::Start(start))?;
+            self.reader.read_exact(&mut frame)?;
+
+            let compressed = le_u32(&frame, 0).unwrap_or(0);
+            if compressed as usize != frame.len() - FRAME_LEN {
+                return Err(error(ErrorKind::BadTable, start));
+            }
+            let block_start = i as u64 * self.block_size as u64;
+            let expected = (self.len - block_start).min(self.block_size as u64) as u32;
+            let len = le_u32(&frame, 4).unwrap_or(0);
+            if len != expected {
+                let kind = ErrorKind::BadBlockLength(len);
+                return Err(Error::stream(CODEC, kind, (start + 4) * 8, block_start));
+            }
+            let data = &frame[FRAME_LEN..];
+            let decoded = decompress(data, self.mode, len, self.options)
+                .map_err(|e| rebase(e, start + FRAME_LEN as u64, block_start))?;
+            self.cached = Some((i, decoded));
+        }
+        Ok(&self.cached.as_ref().unwrap().1)
+    }
+}
+
+impl<R: Read + Seek> Read for SeekableReader<R> {
+    /// Reads from one block at most.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.len {
+            return Ok(0);
+        }
+        let block_size = self.block_size as u64;
+        let offset = (self.position % block_size) as usize;
+        let block = self.block((self.position / block_size) as usize)?;
+        let n = (block.len() - offset).min(buf.len());
+        buf[..n].copy_from_slice(&block[offset..offset + n]);
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for SeekableReader<R> {
+    /// Seeking past the end is allowed, reads there just get nothing.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(d) => self.len.checked_add_signed(d),
+            SeekFrom::Current(d) => self.position.checked_add_signed(d),
+        };
+        match position {
+            Some(p) => {
+                self.position = p;
+                Ok(p)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before the start",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::lz78::{BlockCompressor, BlockDecompressor};
+
+    fn seekable(data: &[u8], block_size: usize) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        let mut c = BlockCompressor::seekable(&mut compressed, Mode::Entropy, block_size);
+        c.write(data).unwrap();
+        c.finalize().unwrap();
+        compressed
+    }
+
+    fn open(compressed: &[u8]) -> Result<SeekableReader<Cursor<&[u8]>>, Error> {
+        SeekableReader::new(Cursor::new(compressed))
+    }
+
+    fn read_error(r: &mut impl Read) -> Error {
+        let e = r.read(&mut [0; 10]).unwrap_err();
+        *e.into_inner().unwrap().downcast::<Error>().unwrap()
+    }
+
+    #[test]
+    fn reads() {
+        let data: Vec<u8> = (0..5000_u32).flat_map(|i| (i / 7).to_le_bytes()).collect();
+        let compressed = seekable(&data, 1000);
+        let mut r = open(&compressed).unwrap();
+        assert_eq!(r.len(), data.len() as u64);
+
+        let mut all = Vec::new();
+        r.read_to_end(&mut all).unwrap();
+        assert!(all == data);
+
+        // Across a block boundary, from the end and from here
+        let mut buf = [0; 100];
+        r.seek(SeekFrom::Start(950)).unwrap();
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[..], data[950..1050]);
+        assert_eq!(r.seek(SeekFrom::End(-10)).unwrap(), 19990);
+        assert_eq!(r.read(&mut buf).unwrap(), 10);
+        assert_eq!(buf[..10], data[19990..]);
+        r.seek(SeekFrom::Current(-5000)).unwrap();
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[..], data[15000..15100]);
+
+        assert_eq!(r.seek(SeekFrom::End(10)).unwrap(), 20010);
+        assert_eq!(r.read(&mut buf).unwrap(), 0);
+        assert!(r.seek(SeekFrom::Current(-30000)).is_err());
+
+        // It's still a block stream from start to end
+        let mut uncompressed = Vec::new();
+        let mut d = BlockDecompressor::new(&mut uncompressed);
+        d.read(&compressed).unwrap();
+        d.finalize().unwrap();
+        assert!(uncompressed == data);
+    }
+
+    #[test]
+    fn empty() {
+        let compressed = seekable(b"", 10);
+        let mut r = open(&compressed).unwrap();
+        assert!(r.is_empty());
+        assert_eq!(r.read(&mut [0; 10]).unwrap(), 0);
+    }
+
+    #[test]
+    fn bad_streams() {
+        let data = b"abracadabra".repeat(20);
+        let compressed = seekable(&data, 64);
+        let kind = |data: &[u8]| open(data).err().unwrap().kind();
+
+        let mut plain = Vec::new();
+        let mut c = BlockCompressor::new(&mut plain, Mode::Entropy);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+        assert_eq!(kind(&plain), Some(ErrorKind::BadMagic(0x4b4c_5a42)));
+        assert_eq!(kind(&[0; 40]), Some(ErrorKind::BadMagic(0)));
+        assert_eq!(kind(&compressed[..20]), Some(ErrorKind::Truncated(160)));
+
+        // Four blocks, but the footer says five
+        let footer = compressed.len() - FOOTER_LEN;
+        let mut blocks = compressed.clone();
+        blocks[footer] = 5;
+        assert_eq!(kind(&blocks), Some(ErrorKind::BadTable));
+        // The second block's offset one off, found when it's read
+        let mut offset = compressed.clone();
+        offset[footer - 24] += 1;
+        let mut r = open(&offset).unwrap();
+        r.seek(SeekFrom::Start(64)).unwrap();
+        assert_eq!(read_error(&mut r).kind(), Some(ErrorKind::BadTable));
+        let mut d = BlockDecompressor::new(Vec::new());
+        d.read(&offset).unwrap();
+        assert_eq!(d.finalize().unwrap_err().kind(), Some(ErrorKind::BadTable));
+
+        let options = DecompressOptions::new().max_output_bytes(200);
+        let e = SeekableReader::with_options(Cursor::new(&compressed), options).err();
+        assert!(matches!(
+            e.unwrap().kind(),
+            Some(ErrorKind::LimitExceeded(_))
+        ));
+
+        // The first block's length wrong, found when it's read
+        let mut len = compressed.clone();
+        len[HEADER_LEN + 4] = 63;
+        let mut r = open(&len).unwrap();
+        let e = read_error(&mut r);
+        assert_eq!(e.kind(), Some(ErrorKind::BadBlockLength(63)));
+        assert_eq!(e.bit_offset(), Some((HEADER_LEN as u64 + 4) * 8));
+        // The rest are fine
+        r.seek(SeekFrom::Start(64)).unwrap();
+        let mut rest = Vec::new();
+        r.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, data[64..]);
+    }
+}
@@ -0,0 +1,234 @@
+//! The byte format behind [`LZ78Compressor::snapshot`] and
+//! [`LZ78Decompressor::snapshot`].
+//!
+//! A snapshot is `KLZS`, a version byte, which side it's from and the
+//! mode, then the dictionary as each entry's prefix code and last byte, in
+//! code order. What follows is up to each side. Numbers are little endian.
+//!
+//! [`LZ78Compressor::snapshot`]: crate::lz78::LZ78Compressor::snapshot
+//! [`LZ78Decompressor::snapshot`]: crate::lz78::LZ78Decompressor::snapshot
+
+use alloc::vec::Vec;
+
+use crate::{
+    error::{Error, ErrorKind},
+    lz78::{code::Code, Mode, CODEC},
+};
+
+const MAGIC: &[u8; 4] = b"KLZS";
+const VERSION: u8 = 1;
+
+/// Which side a snapshot was taken of. A snapshot only restores the side
+/// it came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Side {
+    Compressor = 0,
+    Decompressor = 1,
+}
+
+pub(crate) struct SnapshotWriter(Vec<u8>);
+
+impl SnapshotWriter {
+    pub fn new(side: Side, mode: Mode) -> Self {
+        let mut w = Self::with_header(MAGIC, VERSION);
+        w.u8(side as u8);
+        w.u8(match mode {
+            Mode::Raw => 0,
+            Mode::Entropy => 1,
+        });
+        w
+    }
+
+    /// Start a file in the same encoding under another magic and version.
+    pub fn with_header(magic: &[u8; 4], version: u8) -> Self {
+        let mut w = Self(Vec::new());
+        w.bytes(magic);
+        w.u8(version);
+        w
+    }
+
+    pub fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    pub fn u16(&mut self, v: u16) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, v: u64) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, v: &[u8]) {
+        self.0.extend_from_slice(v);
+    }
+
+    /// The dictionary, given each entry's prefix code and last byte for
+    /// codes from 1 up.
+    pub fn dictionary(&mut self, entries: &[(Code, u8)]) {
+        self.u16(entries.len() as u16);
+        for &(prefix, b) in entries {
+            self.u16(prefix.0);
+            self.u8(b);
+        }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+pub(crate) struct SnapshotReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> SnapshotReader<'a> {
+    /// Read the header of a snapshot of `side`, returning the mode it was
+    /// taken in.
+    pub fn new(data: &'a [u8], side: Side) -> Result<(Self, Mode), Error> {
+        let mut r = Self::with_header(data, MAGIC, VERSION)?;
+        if r.u8()? != side as u8 {
+            return Err(r.error(ErrorKind::BadSnapshot));
+        }
+        let mode = match r.u8()? {
+            0 => Mode::Raw,
+            1 => Mode::Entropy,
+            _ => return Err(r.error(ErrorKind::BadSnapshot)),
+        };
+        Ok((r, mode))
+    }
+
+    /// Read the header of a file written by
+    /// [`SnapshotWriter::with_header`].
+    pub fn with_header(data: &'a [u8], magic: &[u8; 4], version: u8) -> Result<Self, Error> {
+        let mut r = Self { data, position: 0 };
+        let found = r.bytes(magic.len())?;
+        if found != magic {
+            let found = found.iter().fold(0, |m, &b| m << 8 | b as u32);
+            return Err(r.error(ErrorKind::BadCode(found)));
+        }
+        match r.u8()? {
+            v if v == version => Ok(r),
+            v => Err(r.error(ErrorKind::UnsupportedVersion(v))),
+        }
+    }
+
+    pub fn error(&self, kind: ErrorKind) -> Error {
+        Error::stream(CODEC, kind, self.position as u64 * 8, 0)
+    }
+
+    /// `v`, or a [`ErrorKind::BadSnapshot`] error if it's `None`.
+    pub fn check<T>(&self, v: Option<T>) -> Result<T, Error> {
+        v.ok_or_else(|| self.error(ErrorKind::BadSnapshot))
+    }
+
+    pub fn bytes(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        let left = self.data.len() - self.position;
+        if n > left {
+            return Err(self.error(ErrorKind::Truncated(left * 8)));
+        }
+        let bytes = &self.data[self.position..self.position + n];
+        self.position += n;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut a = [0; N];
+        a.copy_from_slice(self.bytes(N)?);
+        Ok(a)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Error> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Error> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Error> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    /// The dictionary, each entry's prefix code and last byte for codes
+    /// from 1 up. Every prefix has to come before the entry.
+    pub fn dictionary(&mut self) -> Result<Vec<(Code, u8)>, Error> {
+        let len = self.u16()?;
+        let mut entries = Vec::with_capacity(len as usize);
+        for code in 1..=len {
+            let prefix = self.u16()?;
+            if prefix >= code {
+                return Err(self.error(ErrorKind::BadTable));
+            }
+            entries.push((Code(prefix), self.u8()?));
+        }
+        Ok(entries)
+    }
+
+    /// Fail unless everything has been read.
+    pub fn finish(self) -> Result<(), Error> {
+        match self.position == self.data.len() {
+            true => Ok(()),
+            false => Err(self.error(ErrorKind::BadSnapshot)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header() {
+        let mut w = SnapshotWriter::new(Side::Decompressor, Mode::Entropy);
+        w.dictionary(&[(Code(0), b'a'), (Code(1), b'b')]);
+        w.u64(7);
+        let snapshot = w.finish();
+        assert_eq!(&snapshot[..7], b"KLZS\x01\x01\x01");
+
+        let (mut r, mode) = SnapshotReader::new(&snapshot, Side::Decompressor).unwrap();
+        assert_eq!(mode, Mode::Entropy);
+        assert_eq!(r.dictionary().unwrap(), [(Code(0), b'a'), (Code(1), b'b')]);
+        assert_eq!(r.u64().unwrap(), 7);
+        r.finish().unwrap();
+
+        let kind = |data: &[u8], side| SnapshotReader::new(data, side).err().unwrap().kind();
+        assert_eq!(
+            kind(&snapshot, Side::Compressor),
+            Some(ErrorKind::BadSnapshot)
+        );
+        assert_eq!(
+            kind(b"KLZS\x02", Side::Compressor),
+            Some(ErrorKind::UnsupportedVersion(2))
+        );
+        assert_eq!(
+            kind(b"KLZD\x01", Side::Compressor),
+            Some(ErrorKind::BadCode(0x4b4c_5a44))
+        );
+        assert_eq!(
+            kind(b"KLZ", Side::Compressor),
+            Some(ErrorKind::Truncated(24))
+        );
+    }
+
+    #[test]
+    fn bad_dictionary() {
+        let mut w = SnapshotWriter::new(Side::Compressor, Mode::Raw);
+        w.dictionary(&[(Code(1), b'a')]);
+        let snapshot = w.finish();
+        let (mut r, _) = SnapshotReader::new(&snapshot, Side::Compressor).unwrap();
+        assert_eq!(
+            r.dictionary().unwrap_err().kind(),
+            Some(ErrorKind::BadTable)
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use alloc::{
+    collections::{BTreeMap, BinaryHeap},
+    vec,
+    vec::Vec,
+};
+use core::cmp::Reverse;
+
+use crate::lz78::{code::Code, dictionary::Dictionary};
+
+/// Longest phrase training looks for.
+const MAX_PHRASE: usize = 32;
+
+/// A [`Dictionary`] of the phrases that turn up most across `samples`,
+/// with at most `max_size` entries. Samples should be like the messages
+/// the dictionary is for, and the more there are the better.
+///
+/// Each phrase is scored by how many bytes its occurrences cover, and the
+/// table is filled best first. A phrase only goes in after its prefix, as
+/// LZ78 needs, so a good long phrase can bring in prefixes that wouldn't
+/// have made it on their own. Phrases of more than one byte have to turn up
+/// at least twice. `max_size` is clamped to the 65535 entries a table has
+/// room for.
+pub fn train_dictionary(samples: &[&[u8]], max_size: usize) -> Dictionary {
+    let trie = Trie::new(samples);
+    let max_size = max_size.min(u16::MAX as usize);
+
+    let mut codes = BTreeMap::new();
+    codes.insert(0, Code(0));
+    let mut entries = Vec::new();
+    let mut best = BinaryHeap::new();
+    best.extend(trie.children(0));
+    while entries.len() < max_size {
+        let (_, Reverse(node)) = match best.pop() {
+            Some(best) => best,
+            None => break,
+        };
+        let (parent, b) = trie.nodes[node as usize];
+        entries.push((codes[&parent], b));
+        codes.insert(node, Code(entries.len() as u16));
+        best.extend(trie.children(node));
+    }
+    Dictionary::from_entries(entries)
+}
+
+/// Every substring of the samples up to [`MAX_PHRASE`] bytes that extends
+/// one seen at least twice, with how often it turns up.
+struct Trie {
+    /// A node's parent and last byte, node 0 being the empty string.
+    nodes: Vec<(u32, u8)>,
+    counts: Vec<u64>,
+    depths: Vec<u8>,
+    /// Maps a node and the next byte to the node for both.
+    edges: BTreeMap<(u32, u8), u32>,
+}
+
+impl Trie {
+    /// Built a level at a time, so a phrase is only extended once its
+    /// count is known to be worth it.
+    fn new(samples: &[&[u8]]) -> Self {
+        let mut trie = Self {
+            nodes: vec![(0, 0)],
+            counts: vec![0],
+            depths: vec![0],
+            edges: BTreeMap::new(),
+        };
+        // The node the substring from each position has reached, `None`
+        // once it's stopped
+        let mut reached: Vec<Vec<Option<u32>>> =
+            samples.iter().map(|s| vec![Some(0); s.len()]).collect();
+        for depth in 0..MAX_PHRASE {
+            for (sample, reached) in samples.iter().zip(&mut reached) {
+                for (start, node) in reached.iter_mut().enumerate() {
+                    let parent = match *node {
+                        Some(parent) if depth == 0 || trie.counts[parent as usize] > 1 => parent,
+                        _ => {
+                            *node = None;
+                            continue;
+                        }
+                    };
+                    *node = sample.get(start + depth).map(|&b| trie.child(parent, b));
+                }
+            }
+        }
+        trie
+    }
+
+    /// Count one more of `parent` followed by `b`, adding it if it's new.
+    fn child(&mut self, parent: u32, b: u8) -> u32 {
+        let next = self.nodes.len() as u32;
+        let node = *self.edges.entry((parent, b)).or_insert(next);
+        if node == next {
+            self.nodes.push((parent, b));
+            self.counts.push(0);
+            self.depths.push(self.depths[parent as usize] + 1);
+        }
+        self.counts[node as usize] += 1;
+        node
+    }
+
+    /// The children of `node` worth a place in the table, each with its
+    /// score. Earlier nodes win ties.
+    fn children(&self, node: u32) -> impl Iterator<Item = (u64, Reverse<u32>)> + '_ {
+        self.edges
+            .range((node, 0)..=(node, u8::MAX))
+            .map(|(_, &child)| child as usize)
+            .filter(move |&child| node == 0 || self.counts[child] > 1)
+            .map(move |child| {
+                let score = self.counts[child] * self.depths[child] as u64;
+                (score, Reverse(child as u32))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lz78::Mode, LZ78Compressor};
+
+    fn compressed_len(data: &[u8], dictionary: Option<&Dictionary>) -> usize {
+        let mut compressed = Vec::new();
+        let mut c = match dictionary {
+            Some(d) => LZ78Compressor::with_dictionary(&mut compressed, Mode::Raw, d).unwrap(),
+            None => LZ78Compressor::new(&mut compressed),
+        };
+        c.write(data).unwrap();
+        c.finalize().unwrap();
+        compressed.len()
+    }
+
+    #[test]
+    fn phrases() {
+        let dictionary = train_dictionary(&[b"abcabc", b"xabcx"], 100);
+        // "abc" three times over, then "bc" and the single bytes left
+        assert_eq!(
+            dictionary.entries(),
+            [
+                (Code(0), b'a'),
+                (Code(1), b'b'),
+                (Code(2), b'c'),
+                (Code(0), b'b'),
+                (Code(4), b'c'),
+                (Code(0), b'c'),
+                (Code(0), b'x'),
+            ]
+        );
+
+        assert_eq!(train_dictionary(&[b"abcabc"], 2).len(), 2);
+        assert!(train_dictionary(&[b"abcabc"], 0).is_empty());
+        assert!(train_dictionary(&[], 100).is_empty());
+    }
+
+    #[test]
+    fn helps() {
+        let samples: Vec<Vec<u8>> = (0..50)
+            .map(|i| {
+                let message =
+                    "{\"jsonrpc\":\"2.0\",\"method\":\"{}\",\"params\":{\"id\":{}},\"id\":{}}";
+                let method = ["get", "put", "delete"][i % 3];
+                message
+                    .replacen("{}", method, 1)
+                    .replacen("{}", &(i * 7).to_string(), 1)
+                    .replacen("{}", &i.to_string(), 1)
+                    .into_bytes()
+            })
+            .collect();
+        let samples: Vec<&[u8]> = samples.iter().map(|s| &s[..]).collect();
+        let dictionary = train_dictionary(&samples, 1000);
+        let message = br#"{"jsonrpc":"2.0","method":"put","params":{"id":1234},"id":99}"#;
+        // Header and all
+        assert!(compressed_len(message, Some(&dictionary)) < compressed_len(message, None) * 2 / 3);
+    }
+}
@@ -0,0 +1,111 @@
+use core::fmt;
+
+/// Limits a decompressor enforces on the data it produces. Every limit is
+/// off by default, set the ones that matter when decompressing untrusted
+/// input.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DecompressOptions {
+    /// Most bytes of decompressed output allowed.
+    pub max_output_bytes: Option<u64>,
+    /// Most bytes the decoder's dictionary may hold.
+    pub max_dictionary_bytes: Option<usize>,
+    /// Largest allowed ratio of output bytes to compressed input bytes.
+    pub max_ratio: Option<f64>,
+}
+
+impl DecompressOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_output_bytes(mut self, bytes: u64) -> Self {
+        self.max_output_bytes = Some(bytes);
+        self
+    }
+
+    pub fn max_dictionary_bytes(mut self, bytes: usize) -> Self {
+        self.max_dictionary_bytes = Some(bytes);
+        self
+    }
+
+    pub fn max_ratio(mut self, ratio: f64) -> Self {
+        self.max_ratio = Some(ratio);
+        self
+    }
+
+    /// Check that `output` more bytes can be written, having already written
+    /// `written` bytes from `input` bytes of compressed data.
+    pub(crate) fn check_output(&self, input: u64, written: u64, output: u64) -> Result<(), Limit> {
+        let total = written + output;
+        if let Some(max) = self.max_output_bytes {
+            if total > max {
+                return Err(Limit::OutputBytes(max));
+            }
+        }
+        if let Some(max) = self.max_ratio {
+            if total as f64 > max * input.max(1) as f64 {
+                return Err(Limit::Ratio(max));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_dictionary(&self, bytes: usize) -> Result<(), Limit> {
+        match self.max_dictionary_bytes {
+            Some(max) if bytes > max => Err(Limit::DictionaryBytes(max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The limit from [`DecompressOptions`] that was crossed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    OutputBytes(u64),
+    DictionaryBytes(usize),
+    Ratio(f64),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::OutputBytes(max) => write!(f, "output larger than {} bytes", max),
+            Limit::DictionaryBytes(max) => write!(f, "dictionary larger than {} bytes", max),
+            Limit::Ratio(max) => write!(f, "compression ratio above {}", max),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_limits() {
+        let o = DecompressOptions::new();
+        assert_eq!(o.check_output(1, u64::MAX - 1, 1), Ok(()));
+        assert_eq!(o.check_dictionary(usize::MAX), Ok(()));
+    }
+
+    #[test]
+    fn output_bytes() {
+        let o = DecompressOptions::new().max_output_bytes(10);
+        assert_eq!(o.check_output(1, 5, 5), Ok(()));
+        assert_eq!(o.check_output(1, 5, 6), Err(Limit::OutputBytes(10)));
+    }
+
+    #[test]
+    fn ratio() {
+        let o = DecompressOptions::new().max_ratio(2.0);
+        assert_eq!(o.check_output(0, 0, 2), Ok(()));
+        assert_eq!(o.check_output(4, 4, 4), Ok(()));
+        assert_eq!(o.check_output(4, 4, 5), Err(Limit::Ratio(2.0)));
+    }
+
+    #[test]
+    fn dictionary_bytes() {
+        let o = DecompressOptions::new().max_dictionary_bytes(3);
+        assert_eq!(o.check_dictionary(3), Ok(()));
+        assert_eq!(o.check_dictionary(4), Err(Limit::DictionaryBytes(3)));
+    }
+}
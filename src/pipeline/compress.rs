@@ -0,0 +1,130 @@
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::{error::Error, io::Sink, pipeline::Pipeline, stats::Stats, transform::Stage};
+
+/// Compresses with every step of a [`Pipeline`] in turn, after a header
+/// recording the steps.
+pub struct PipelineCompressor<W> {
+    writer: W,
+    stages: Vec<Box<dyn Stage>>,
+    /// The header, until it's written along with the first output.
+    header: Option<Vec<u8>>,
+    input: u64,
+    output: u64,
+}
+
+impl<W: Sink> PipelineCompressor<W> {
+    pub fn new(writer: W, pipeline: &Pipeline) -> Self {
+        Self {
+            writer,
+            stages: pipeline.steps().iter().map(|s| s.encoder()).collect(),
+            header: Some(pipeline.header()),
+            input: 0,
+            output: 0,
+        }
+    }
+
+    fn run(&mut self, data: &[u8], finish: bool) -> Result<usize, Error> {
+        self.input += data.len() as u64;
+        let mut data = data.to_vec();
+        for stage in &mut self.stages {
+            let mut output = Vec::new();
+            stage.push(&data, &mut output)?;
+            if finish {
+                stage.finish(&mut output)?;
+            }
+            data = output;
+        }
+        if let Some(mut header) = self.header.take() {
+            header.append(&mut data);
+            data = header;
+        }
+        self.writer.write_all(&data)?;
+        self.output += data.len() as u64;
+        Ok(data.len())
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.run(data, false)
+    }
+
+    /// What's gone in and out so far, header included. Output the steps
+    /// hold back isn't counted.
+    pub fn stats(&self) -> Stats {
+        Stats::new(self.input, self.output * 8)
+    }
+
+    /// Carry on over `writer` instead, handing back the old one.
+    pub(crate) fn replace_writer<V>(self, writer: V) -> (PipelineCompressor<V>, W) {
+        let codec = PipelineCompressor {
+            writer,
+            stages: self.stages,
+            header: self.header,
+            input: self.input,
+            output: self.output,
+        };
+        (codec, self.writer)
+    }
+
+    /// Finish each step in order, feeding what it lets out to the next.
+    pub fn finalize(mut self) -> Result<usize, Error> {
+        self.run(&[], true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pipeline::{Huffman, Lz78},
+        transform::Rle1,
+    };
+
+    #[test]
+    fn empty() {
+        let mut compressed = Vec::new();
+        let c = PipelineCompressor::new(&mut compressed, &Pipeline::new());
+        assert_eq!(c.finalize().unwrap(), 5);
+        assert_eq!(compressed, b"KPL\x01\x00");
+    }
+
+    #[test]
+    fn no_steps() {
+        let mut compressed = Vec::new();
+        let mut c = PipelineCompressor::new(&mut compressed, &Pipeline::new());
+        assert_eq!(c.write(b"abc").unwrap(), 8);
+        assert_eq!(c.write(b"def").unwrap(), 3);
+        let stats = c.stats();
+        assert_eq!((stats.input_bytes, stats.output_bits), (6, 11 * 8));
+        assert_eq!(stats.dictionary, None);
+        assert_eq!(c.finalize().unwrap(), 0);
+        assert_eq!(compressed, b"KPL\x01\x00abcdef");
+    }
+
+    #[test]
+    fn steps() {
+        let pipeline = Pipeline::new().then(Rle1).then(Huffman);
+        let mut compressed = Vec::new();
+        let mut c = PipelineCompressor::new(&mut compressed, &pipeline);
+        // Huffman holds everything back until the end
+        assert_eq!(c.write(b"aaaaaaaab").unwrap(), 7);
+        c.finalize().unwrap();
+
+        let mut huffman = Vec::new();
+        let mut h = crate::huffman::HuffmanCompressor::new(&mut huffman);
+        h.write(b"aaaa\x04b").unwrap();
+        h.finalize().unwrap();
+        assert_eq!(&compressed[..7], b"KPL\x01\x02\x01\x07");
+        assert_eq!(compressed[7..], huffman[..]);
+    }
+
+    #[test]
+    fn lz78() {
+        let pipeline = Pipeline::new().then(Lz78);
+        let mut compressed = Vec::new();
+        let mut c = PipelineCompressor::new(&mut compressed, &pipeline);
+        c.write(b"aaaa").unwrap();
+        c.finalize().unwrap();
+        assert_eq!(compressed, b"KPL\x01\x01\x05\x61\xb0\xa0");
+    }
+}
@@ -0,0 +1,323 @@
+use alloc::{boxed::Box, vec, vec::Vec};
+
+use crate::{
+    error::{Error, ErrorKind},
+    io::Sink,
+    options::DecompressOptions,
+    pipeline::{Pipeline, CODEC},
+    transform::Stage,
+};
+
+/// Most compressed bytes fed through the steps at once.
+const CHUNK: usize = 4096;
+
+/// Decompresses a stream from [`PipelineCompressor`], running the steps
+/// named in its header in reverse.
+///
+/// Every step that can expand its input gets the [`DecompressOptions`] and
+/// stops as soon as its output would cross them, so no step gets past the
+/// limits partway through a chunk. The output of each step is checked
+/// against them too, so the limits hold for the data between steps as well
+/// as the final output.
+///
+/// [`PipelineCompressor`]: crate::pipeline::PipelineCompressor
+pub struct PipelineDecompressor<W> {
+    writer: W,
+    options: DecompressOptions,
+
+    /// Input waiting for the header to be complete.
+    header: Vec<u8>,
+    pipeline: Option<Pipeline>,
+    stages: Vec<Box<dyn Stage>>,
+    /// Bytes each stage has let out, the last of them the output. With no
+    /// stages there's just the output.
+    produced: Vec<u64>,
+    consumed: u64,
+}
+
+impl<W: Sink> PipelineDecompressor<W> {
+    pub fn new(writer: W) -> Self {
+        Self::with_options(writer, DecompressOptions::default())
+    }
+
+    pub fn with_options(writer: W, options: DecompressOptions) -> Self {
+        Self {
+            writer,
+            options,
+
+            header: Vec::new(),
+            pipeline: None,
+            stages: Vec::new(),
+            produced: Vec::new(),
+            consumed: 0,
+        }
+    }
+
+    /// The pipeline from the stream header, once it's been read.
+    pub fn pipeline(&self) -> Option<&Pipeline> {
+        self.pipeline.as_ref()
+    }
+
+    fn error(&self, kind: ErrorKind) -> Error {
+        let output = self.produced.last().copied().unwrap_or(0);
+        Error::stream(CODEC, kind, self.consumed * 8, output)
+    }
+
+    pub fn read(&mut self, data: &[u8]) -> Result<usize, Error> {
+        if self.pipeline.is_some() {
+            return self.run(data, false);
+        }
+
+        self.header.extend_from_slice(data);
+        match Pipeline::read_header(&self.header) {
+            Ok((pipeline, len)) => {
+                self.stages = pipeline
+                    .steps()
+                    .iter()
+                    .rev()
+                    .map(|s| s.decoder(self.options))
+                    .collect();
+                self.produced = vec![0; self.stages.len().max(1)];
+                self.pipeline = Some(pipeline);
+                self.consumed = len as u64;
+                let rest = self.header.split_off(len);
+                self.run(&rest, false)
+            }
+            Err(ErrorKind::Truncated(_)) => Ok(0),
+            Err(kind) => Err(self.error(kind)),
+        }
+    }
+
+    /// Push `input` through the stages a chunk at a time, finishing them
+    /// after the last chunk if `finish`.
+    fn run(&mut self, input: &[u8], finish: bool) -> Result<usize, Error> {
+        let mut written = 0;
+        let mut chunks = input.chunks(CHUNK).peekable();
+        loop {
+            let chunk = chunks.next().unwrap_or(&[]);
+            let last = chunks.peek().is_none();
+            self.consumed += chunk.len() as u64;
+
+            let mut data = chunk.to_vec();
+            for i in 0..self.stages.len() {
+                let mut output = Vec::new();
+                self.stages[i].push(&data, &mut output)?;
+                if finish && last {
+                    self.stages[i].finish(&mut output)?;
+                }
+                self.check(i, output.len())?;
+                data = output;
+            }
+            if self.stages.is_empty() {
+                self.check(0, data.len())?;
+            }
+            self.writer.write_all(&data)?;
+            written += data.len();
+            if last {
+                return Ok(written);
+            }
+        }
+    }
+
+    /// Check that stage `i` can let out `len` more bytes.
+    fn check(&mut self, i: usize, len: usize) -> Result<(), Error> {
+        self.options
+            .check_output(self.consumed, self.produced[i], len as u64)
+            .map_err(|l| self.error(ErrorKind::LimitExceeded(l)))?;
+        self.produced[i] += len as u64;
+        Ok(())
+    }
+
+    /// Carry on over `writer` instead, handing back the old one.
+    pub(crate) fn replace_writer<V>(self, writer: V) -> (PipelineDecompressor<V>, W) {
+        let codec = PipelineDecompressor {
+            writer,
+            options: self.options,
+            header: self.header,
+            pipeline: self.pipeline,
+            stages: self.stages,
+            produced: self.produced,
+            consumed: self.consumed,
+        };
+        (codec, self.writer)
+    }
+
+    /// Finish every step, failing if the header or any step's stream is
+    /// incomplete.
+    pub fn finalize(mut self) -> Result<usize, Error> {
+        if self.pipeline.is_none() {
+            let bits = self.header.len() * 8;
+            return Err(self.error(ErrorKind::Truncated(bits)));
+        }
+        self.run(&[], true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        lz78::Mode,
+        options::Limit,
+        pipeline::{AdaptiveHuffman, Bzip2, Huffman, Lz78, PipelineCompressor, RangeCoder, Step},
+        transform::{BitShuffle, Bwt, Delta, Mtf, Rle1, Shuffle, Xor, ZeroRun},
+    };
+
+    fn compress(pipeline: &Pipeline, data: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        let mut c = PipelineCompressor::new(&mut compressed, pipeline);
+        c.write(data).unwrap();
+        c.finalize().unwrap();
+        compressed
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = b"She sells sea shells by the sea shore, the shells she sells are sea shells for sure. "
+            .repeat(50);
+        let pipelines = [
+            Pipeline::new(),
+            Pipeline::new().then(Bwt).then(Mtf).then(Huffman),
+            Pipeline::new().then(Lz78).then(RangeCoder),
+            Pipeline::new()
+                .then(Rle1)
+                .then(Bwt)
+                .then(Mtf)
+                .then(ZeroRun)
+                .then(AdaptiveHuffman),
+            Pipeline::new().then(Step::Lz78(Mode::Entropy)).then(Bzip2),
+            Pipeline::new()
+                .then(Xor::new(3))
+                .then(BitShuffle::new(3))
+                .then(Huffman),
+        ];
+        for pipeline in &pipelines {
+            let compressed = compress(pipeline, &data);
+            let mut uncompressed = Vec::new();
+            let mut d = PipelineDecompressor::new(&mut uncompressed);
+            for chunk in compressed.chunks(7) {
+                d.read(chunk).unwrap();
+            }
+            assert_eq!(d.pipeline(), Some(pipeline));
+            d.finalize().unwrap();
+            assert!(uncompressed == data, "{:?}", pipeline);
+        }
+    }
+
+    #[test]
+    fn bwt_helps() {
+        let data = b"She sells sea shells by the sea shore, the shells she sells are sea shells for sure. "
+            .repeat(50);
+        let plain = compress(&Pipeline::new().then(Huffman), &data);
+        let bwt = compress(&Pipeline::new().then(Bwt).then(Mtf).then(Huffman), &data);
+        assert!(
+            bwt.len() < plain.len() / 2,
+            "{} vs {}",
+            bwt.len(),
+            plain.len()
+        );
+    }
+
+    #[test]
+    fn delta_helps() {
+        let data = (0..20_000_u32)
+            .flat_map(|i| (i * 3).to_le_bytes())
+            .collect::<Vec<_>>();
+        let plain = compress(&Pipeline::new().then(Lz78).then(RangeCoder), &data);
+        let pipeline = Pipeline::new()
+            .then(Delta::new(4))
+            .then(Shuffle::new(4))
+            .then(Lz78)
+            .then(RangeCoder);
+        let delta = compress(&pipeline, &data);
+        assert!(
+            delta.len() < plain.len() / 10,
+            "{} vs {}",
+            delta.len(),
+            plain.len()
+        );
+
+        let mut uncompressed = Vec::new();
+        let mut d = PipelineDecompressor::new(&mut uncompressed);
+        d.read(&delta).unwrap();
+        d.finalize().unwrap();
+        assert!(uncompressed == data);
+    }
+
+    #[test]
+    fn truncated() {
+        let compressed = compress(&Pipeline::new().then(Huffman), b"abc");
+        let mut d = PipelineDecompressor::new(Vec::new());
+        d.read(&compressed[..3]).unwrap();
+        let e = d.finalize().unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::Truncated(24)));
+        assert_eq!(e.codec(), Some("pipeline"));
+
+        let mut d = PipelineDecompressor::new(Vec::new());
+        d.read(&compressed[..compressed.len() - 1]).unwrap();
+        let e = d.finalize().unwrap_err();
+        assert_eq!(e.codec(), Some("huffman"));
+    }
+
+    #[test]
+    fn bad_header() {
+        let mut d = PipelineDecompressor::new(Vec::new());
+        let e = d.read(b"KPL\x01\x01\x00").unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::BadCode(0)));
+    }
+
+    #[test]
+    fn limits() {
+        let data = [7; 10_000];
+        for pipeline in [Pipeline::new(), Pipeline::new().then(Rle1).then(Huffman)] {
+            let compressed = compress(&pipeline, &data);
+            let options = DecompressOptions::new().max_output_bytes(5000);
+            let mut d = PipelineDecompressor::with_options(Vec::new(), options);
+            let e = d.read(&compressed).and_then(|_| d.finalize()).unwrap_err();
+            assert_eq!(
+                e.kind(),
+                Some(ErrorKind::LimitExceeded(Limit::OutputBytes(5000)))
+            );
+        }
+    }
+
+    #[test]
+    fn limits_hold_inside_a_chunk() {
+        let data = vec![0; 16 << 20];
+        let limit = 1 << 20;
+        let options = DecompressOptions::new().max_output_bytes(limit);
+        for step in [
+            Step::Rle1,
+            Step::ZeroRun,
+            Step::Lz78(Mode::Raw),
+            Step::Huffman,
+            Step::AdaptiveHuffman,
+            Step::RangeCoder,
+        ] {
+            let mut compressed = Vec::new();
+            let mut encoder = step.encoder();
+            encoder.push(&data, &mut compressed).unwrap();
+            encoder.finish(&mut compressed).unwrap();
+
+            // Every step on its own, fed a whole chunk at a time
+            let mut stage = step.decoder(options);
+            let mut output = Vec::new();
+            let e = compressed
+                .chunks(CHUNK)
+                .try_for_each(|c| stage.push(c, &mut output))
+                .unwrap_err();
+            assert!(
+                matches!(e.kind(), Some(ErrorKind::LimitExceeded(_))),
+                "{:?}",
+                step
+            );
+            assert!(output.len() as u64 <= limit, "{:?}: {}", step, output.len());
+
+            let stream = compress(&Pipeline::new().then(step), &data);
+            let mut uncompressed = Vec::new();
+            let mut d = PipelineDecompressor::with_options(&mut uncompressed, options);
+            assert!(d.read(&stream).and_then(|_| d.finalize()).is_err());
+            assert!(uncompressed.len() as u64 <= limit);
+        }
+    }
+}
@@ -0,0 +1,329 @@
+//! Stacks of transforms and codecs run as one compressor.
+//!
+//! A [`Pipeline`] lists [`Step`]s, run in order when compressing and in
+//! reverse when decompressing. [`PipelineCompressor`] writes the list at the
+//! start of the stream, so [`PipelineDecompressor`] needs nothing but the
+//! stream to undo it:
+//!
+//! ```
+//! use kompression::{
+//!     pipeline::{Huffman, Pipeline, PipelineCompressor, PipelineDecompressor},
+//!     transform::{Bwt, Mtf},
+//! };
+//!
+//! let pipeline = Pipeline::new().then(Bwt).then(Mtf).then(Huffman);
+//! let mut compressed = Vec::new();
+//! let mut c = PipelineCompressor::new(&mut compressed, &pipeline);
+//! c.write(b"banana bandana").unwrap();
+//! c.finalize().unwrap();
+//!
+//! let mut uncompressed = Vec::new();
+//! let mut d = PipelineDecompressor::new(&mut uncompressed);
+//! d.read(&compressed).unwrap();
+//! assert_eq!(d.pipeline(), Some(&pipeline));
+//! d.finalize().unwrap();
+//! assert_eq!(uncompressed, b"banana bandana");
+//! ```
+
+mod compress;
+mod decompress;
+mod stage;
+
+use alloc::vec::Vec;
+
+use crate::{
+    error::ErrorKind,
+    lz78::Mode,
+    transform::{BitShuffle, Bwt, Delta, Mtf, Rle1, Shuffle, Xor, ZeroRun},
+};
+
+pub use self::{compress::PipelineCompressor, decompress::PipelineDecompressor};
+
+/// Name reported in errors from the pipeline container.
+const CODEC: &str = "pipeline";
+
+/// Start of every pipeline stream.
+const MAGIC: [u8; 3] = *b"KPL";
+const VERSION: u8 = 1;
+/// Most steps a pipeline can have.
+pub const MAX_STEPS: usize = 16;
+
+/// One step of a [`Pipeline`]. Each transform and codec in the crate has
+/// one, and the codecs have marker types to pass to [`Pipeline::then`]. The
+/// filters for numeric data keep their width in the header, in a byte after
+/// their id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Step {
+    Rle1,
+    Mtf,
+    ZeroRun,
+    Bwt,
+    Lz78(Mode),
+    Huffman,
+    AdaptiveHuffman,
+    RangeCoder,
+    Bzip2,
+    Delta(Delta),
+    Xor(Xor),
+    Shuffle(Shuffle),
+    BitShuffle(BitShuffle),
+}
+
+impl Step {
+    /// The step's byte in the stream header.
+    fn id(&self) -> u8 {
+        match self {
+            Step::Rle1 => 1,
+            Step::Mtf => 2,
+            Step::ZeroRun => 3,
+            Step::Bwt => 4,
+            Step::Lz78(Mode::Raw) => 5,
+            Step::Lz78(Mode::Entropy) => 6,
+            Step::Huffman => 7,
+            Step::AdaptiveHuffman => 8,
+            Step::RangeCoder => 9,
+            Step::Bzip2 => 10,
+            Step::Delta(_) => 11,
+            Step::Xor(_) => 12,
+            Step::Shuffle(_) => 13,
+            Step::BitShuffle(_) => 14,
+        }
+    }
+
+    /// The byte after the id, for the steps that have one: their width less
+    /// one.
+    fn param(&self) -> Option<u8> {
+        let width = match self {
+            Step::Delta(delta) => delta.stride(),
+            Step::Xor(xor) => xor.width(),
+            Step::Shuffle(shuffle) => shuffle.width(),
+            Step::BitShuffle(shuffle) => shuffle.width(),
+            _ => return None,
+        };
+        Some((width - 1) as u8)
+    }
+
+    fn has_param(id: u8) -> bool {
+        (11..=14).contains(&id)
+    }
+
+    fn from_id(id: u8, param: u8) -> Option<Self> {
+        let width = param as usize + 1;
+        Some(match id {
+            1 => Step::Rle1,
+            2 => Step::Mtf,
+            3 => Step::ZeroRun,
+            4 => Step::Bwt,
+            5 => Step::Lz78(Mode::Raw),
+            6 => Step::Lz78(Mode::Entropy),
+            7 => Step::Huffman,
+            8 => Step::AdaptiveHuffman,
+            9 => Step::RangeCoder,
+            10 => Step::Bzip2,
+            11 => Step::Delta(Delta::new(width)),
+            12 => Step::Xor(Xor::new(width)),
+            13 => Step::Shuffle(Shuffle::new(width)),
+            14 => Step::BitShuffle(BitShuffle::new(width)),
+            _ => return None,
+        })
+    }
+}
+
+/// [`LZ78Compressor`](crate::LZ78Compressor) in [`Mode::Raw`], use
+/// `Step::Lz78(Mode::Entropy)` for the entropy coded mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Lz78;
+
+/// [`HuffmanCompressor`](crate::huffman::HuffmanCompressor).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Huffman;
+
+/// [`AdaptiveHuffmanCompressor`](crate::huffman::AdaptiveHuffmanCompressor).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AdaptiveHuffman;
+
+/// [`RangeCompressor`](crate::range_coder::RangeCompressor) with the default
+/// model.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RangeCoder;
+
+/// [`Bzip2Compressor`](crate::bzip2::Bzip2Compressor) at its default level.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Bzip2;
+
+macro_rules! impl_step {
+    ($($t:ident => $step:expr),*) => {
+        $(
+            impl From<$t> for Step {
+                fn from(_: $t) -> Self {
+                    $step
+                }
+            }
+        )*
+    };
+}
+
+impl_step!(
+    Rle1 => Step::Rle1,
+    Mtf => Step::Mtf,
+    ZeroRun => Step::ZeroRun,
+    Bwt => Step::Bwt,
+    Lz78 => Step::Lz78(Mode::Raw),
+    Huffman => Step::Huffman,
+    AdaptiveHuffman => Step::AdaptiveHuffman,
+    RangeCoder => Step::RangeCoder,
+    Bzip2 => Step::Bzip2
+);
+
+macro_rules! impl_filter_step {
+    ($($t:ident),*) => {
+        $(
+            impl From<$t> for Step {
+                fn from(filter: $t) -> Self {
+                    Step::$t(filter)
+                }
+            }
+        )*
+    };
+}
+
+impl_filter_step!(Delta, Xor, Shuffle, BitShuffle);
+
+/// The steps to run, in the order they run when compressing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pipeline {
+    steps: Vec<Step>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a step to run on the output of the ones before it. Panics past
+    /// [`MAX_STEPS`].
+    pub fn then(mut self, step: impl Into<Step>) -> Self {
+        assert!(self.steps.len() < MAX_STEPS, "too many pipeline steps");
+        self.steps.push(step.into());
+        self
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// The stream header: magic, version, then the number of steps and
+    /// their ids, each followed by its parameter if it has one.
+    pub(crate) fn header(&self) -> Vec<u8> {
+        let mut header = MAGIC.to_vec();
+        header.push(VERSION);
+        header.push(self.steps.len() as u8);
+        for step in &self.steps {
+            header.push(step.id());
+            header.extend(step.param());
+        }
+        header
+    }
+
+    /// Read a header from the front of `data`, returning the pipeline and
+    /// the header's length.
+    pub(crate) fn read_header(data: &[u8]) -> Result<(Self, usize), ErrorKind> {
+        let truncated = || ErrorKind::Truncated(data.len() * 8);
+        let magic = data.get(..MAGIC.len()).ok_or_else(truncated)?;
+        if magic != MAGIC {
+            let magic = magic.iter().fold(0, |m, &b| m << 8 | b as u32);
+            return Err(ErrorKind::BadCode(magic));
+        }
+        let version = *data.get(3).ok_or_else(truncated)?;
+        if version != VERSION {
+            return Err(ErrorKind::UnsupportedVersion(version));
+        }
+        let count = *data.get(4).ok_or_else(truncated)? as usize;
+        if count > MAX_STEPS {
+            return Err(ErrorKind::BadTable);
+        }
+        let mut steps = Vec::with_capacity(count);
+        let mut position = 5;
+        for _ in 0..count {
+            let id = *data.get(position).ok_or_else(truncated)?;
+            let mut param = 0;
+            if Step::has_param(id) {
+                param = *data.get(position + 1).ok_or_else(truncated)?;
+                position += 1;
+            }
+            position += 1;
+            steps.push(Step::from_id(id, param).ok_or(ErrorKind::BadCode(id as u32))?);
+        }
+        Ok((Self { steps }, position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header() {
+        let pipeline = Pipeline::new()
+            .then(Rle1)
+            .then(Step::Lz78(Mode::Entropy))
+            .then(Huffman);
+        let header = pipeline.header();
+        assert_eq!(header, b"KPL\x01\x03\x01\x06\x07");
+        assert_eq!(Pipeline::read_header(&header), Ok((pipeline, 8)));
+    }
+
+    #[test]
+    fn every_step() {
+        for id in 1..=14 {
+            let step = Step::from_id(id, 7).unwrap();
+            assert_eq!(step.id(), id);
+            assert_eq!(step.param().is_some(), Step::has_param(id));
+        }
+        assert_eq!(Step::from_id(0, 0), None);
+        assert_eq!(Step::from_id(15, 0), None);
+    }
+
+    #[test]
+    fn filters() {
+        let pipeline = Pipeline::new()
+            .then(Delta::new(4))
+            .then(Xor::new(8))
+            .then(Shuffle::new(256))
+            .then(BitShuffle::new(1))
+            .then(Huffman);
+        let header = pipeline.header();
+        assert_eq!(header, b"KPL\x01\x05\x0b\x03\x0c\x07\x0d\xff\x0e\x00\x07");
+        assert_eq!(Pipeline::read_header(&header), Ok((pipeline, 14)));
+        assert_eq!(
+            Pipeline::read_header(b"KPL\x01\x01\x0b"),
+            Err(ErrorKind::Truncated(48))
+        );
+    }
+
+    #[test]
+    fn bad_header() {
+        assert_eq!(Pipeline::read_header(b"KP"), Err(ErrorKind::Truncated(16)));
+        assert_eq!(
+            Pipeline::read_header(b"KPL\x01\x02\x01"),
+            Err(ErrorKind::Truncated(48))
+        );
+        assert_eq!(
+            Pipeline::read_header(b"BZh9"),
+            Err(ErrorKind::BadCode(0x425a68))
+        );
+        assert_eq!(
+            Pipeline::read_header(b"KPL\x02\x00"),
+            Err(ErrorKind::UnsupportedVersion(2))
+        );
+        assert_eq!(
+            Pipeline::read_header(b"KPL\x01\x01\x2a"),
+            Err(ErrorKind::BadCode(0x2a))
+        );
+        assert_eq!(
+            Pipeline::read_header(b"KPL\x01\x11"),
+            Err(ErrorKind::BadTable)
+        );
+    }
+}
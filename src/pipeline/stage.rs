@@ -0,0 +1,121 @@
+//! The codecs as [`Stage`]s, so they chain with the transforms.
+
+use alloc::{boxed::Box, rc::Rc, vec::Vec};
+use core::cell::RefCell;
+
+use crate::{
+    bzip2::{Bzip2Compressor, Bzip2Decompressor},
+    error::Error,
+    huffman::{
+        AdaptiveHuffmanCompressor, AdaptiveHuffmanDecompressor, HuffmanCompressor,
+        HuffmanDecompressor,
+    },
+    io::{Codec, Sink},
+    options::DecompressOptions,
+    pipeline::Step,
+    range_coder::{RangeCompressor, RangeDecompressor},
+    transform::{Bwt, Mtf, Rle1, Rle1Decoder, Stage, Transform, ZeroRun, ZeroRunDecoder},
+    LZ78Compressor, LZ78Decompressor,
+};
+
+/// A sink the stage keeps a handle to, so it can take back what the codec
+/// wrote.
+#[derive(Debug, Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Sink for Shared {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.0.borrow_mut().extend_from_slice(data);
+        Ok(data.len())
+    }
+}
+
+/// Runs a codec writing to a [`Shared`] sink. Once finished it takes no
+/// more input.
+struct CodecStage<C> {
+    codec: Option<C>,
+    output: Shared,
+}
+
+impl<C: Codec> CodecStage<C> {
+    fn new(make: impl FnOnce(Shared) -> C) -> Self {
+        let output = Shared::default();
+        Self {
+            codec: Some(make(output.clone())),
+            output,
+        }
+    }
+
+    fn take_output(&self, output: &mut Vec<u8>) {
+        output.append(&mut self.output.0.borrow_mut());
+    }
+}
+
+impl<C: Codec> Stage for CodecStage<C> {
+    fn push(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Error> {
+        if let Some(codec) = &mut self.codec {
+            codec.push(input)?;
+        }
+        self.take_output(output);
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Error> {
+        if let Some(codec) = self.codec.take() {
+            codec.finish()?;
+        }
+        self.take_output(output);
+        Ok(())
+    }
+}
+
+impl Step {
+    /// The stage running this step forwards.
+    pub(crate) fn encoder(&self) -> Box<dyn Stage> {
+        match *self {
+            Step::Rle1 => Box::new(Rle1.encoder()),
+            Step::Mtf => Box::new(Mtf.encoder()),
+            Step::ZeroRun => Box::new(ZeroRun.encoder()),
+            Step::Bwt => Box::new(Bwt.encoder()),
+            Step::Lz78(mode) => Box::new(CodecStage::new(|s| LZ78Compressor::with_mode(s, mode))),
+            Step::Huffman => Box::new(CodecStage::new(HuffmanCompressor::new)),
+            Step::AdaptiveHuffman => Box::new(CodecStage::new(AdaptiveHuffmanCompressor::new)),
+            Step::RangeCoder => Box::new(CodecStage::new(RangeCompressor::new)),
+            Step::Bzip2 => Box::new(CodecStage::new(Bzip2Compressor::new)),
+            Step::Delta(delta) => Box::new(delta.encoder()),
+            Step::Xor(xor) => Box::new(xor.encoder()),
+            Step::Shuffle(shuffle) => Box::new(shuffle.encoder()),
+            Step::BitShuffle(shuffle) => Box::new(shuffle.encoder()),
+        }
+    }
+
+    /// The stage undoing this step, with `options` for the ones that can
+    /// expand their input.
+    pub(crate) fn decoder(&self, options: DecompressOptions) -> Box<dyn Stage> {
+        match *self {
+            Step::Rle1 => Box::new(Rle1Decoder::with_options(options)),
+            Step::Mtf => Box::new(Mtf.decoder()),
+            Step::ZeroRun => Box::new(ZeroRunDecoder::with_options(options)),
+            Step::Bwt => Box::new(Bwt.decoder()),
+            Step::Lz78(mode) => Box::new(CodecStage::new(|s| {
+                LZ78Decompressor::with_mode(s, mode, options)
+            })),
+            Step::Huffman => Box::new(CodecStage::new(|s| {
+                HuffmanDecompressor::with_options(s, options)
+            })),
+            Step::AdaptiveHuffman => Box::new(CodecStage::new(|s| {
+                AdaptiveHuffmanDecompressor::with_options(s, options)
+            })),
+            Step::RangeCoder => Box::new(CodecStage::new(|s| {
+                RangeDecompressor::with_options(s, options)
+            })),
+            Step::Bzip2 => Box::new(CodecStage::new(|s| {
+                Bzip2Decompressor::with_options(s, options)
+            })),
+            Step::Delta(delta) => Box::new(delta.decoder()),
+            Step::Xor(xor) => Box::new(xor.decoder()),
+            Step::Shuffle(shuffle) => Box::new(shuffle.decoder()),
+            Step::BitShuffle(shuffle) => Box::new(shuffle.decoder()),
+        }
+    }
+}
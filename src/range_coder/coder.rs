@@ -0,0 +1,351 @@
+use crate::{
+    error::{Error, ErrorKind},
+    io::Sink,
+};
+
+/// Normalize once the range drops below this.
+pub(crate) const TOP: u32 = 1 << 24;
+/// Most bytes a resumed encoder may hold back. Every one but the first is a
+/// `0xff`, so a run this long doesn't come up, only a bad state would have
+/// one.
+const MAX_HELD: u64 = 1 << 20;
+/// Binary probabilities are out of `1 << PROBABILITY_BITS`.
+pub const PROBABILITY_BITS: u32 = 12;
+/// Frequency totals have to stay at or below this for the range to keep
+/// enough precision.
+pub const MAX_TOTAL: u32 = 1 << 16;
+
+/// 32 bit range encoder with carry propagation, in the style of LZMA's. A
+/// byte that might still be bumped by a carry is held back in `cache`,
+/// along with any run of `0xff` bytes after it.
+#[derive(Debug)]
+pub struct RangeEncoder<W> {
+    writer: W,
+    low: u64,
+    range: u32,
+    cache: u8,
+    cache_size: u64,
+}
+
+impl<W: Sink> RangeEncoder<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            low: 0,
+            range: u32::MAX,
+            cache: 0,
+            cache_size: 1,
+        }
+    }
+
+    /// Encode a symbol that takes up `[cumulative, cumulative + frequency)`
+    /// out of `total`. Returns how many bytes went out to the sink.
+    pub fn encode(&mut self, cumulative: u32, frequency: u32, total: u32) -> Result<usize, Error> {
+        debug_assert!(frequency > 0 && cumulative + frequency <= total && total <= MAX_TOTAL);
+        let r = self.range / total;
+        self.low += r as u64 * cumulative as u64;
+        self.range = r * frequency;
+        self.normalize()
+    }
+
+    /// Encode one bit, where `probability` is the chance of a zero out of
+    /// `1 << PROBABILITY_BITS`.
+    pub fn encode_bit(&mut self, probability: u16, bit: bool) -> Result<usize, Error> {
+        let bound = (self.range >> PROBABILITY_BITS) * probability as u32;
+        if bit {
+            self.low += bound as u64;
+            self.range -= bound;
+        } else {
+            self.range = bound;
+        }
+        self.normalize()
+    }
+
+    fn normalize(&mut self) -> Result<usize, Error> {
+        let mut written = 0;
+        while self.range < TOP {
+            self.range <<= 8;
+            written += self.shift_low()?;
+        }
+        Ok(written)
+    }
+
+    fn shift_low(&mut self) -> Result<usize, Error> {
+        let mut written = 0;
+        if (self.low as u32) < 0xff00_0000 || self.low >> 32 != 0 {
+            let carry = (self.low >> 32) as u8;
+            let mut byte = self.cache;
+            loop {
+                self.writer.write_all(&[byte.wrapping_add(carry)])?;
+                written += 1;
+                byte = 0xff;
+                self.cache_size -= 1;
+                if self.cache_size == 0 {
+                    break;
+                }
+            }
+            self.cache = (self.low >> 24) as u8;
+        }
+        self.cache_size += 1;
+        self.low = (self.low & 0x00ff_ffff) << 8;
+        Ok(written)
+    }
+
+    /// Write out enough of `low` to pin down the final range.
+    pub fn flush(&mut self) -> Result<usize, Error> {
+        let mut written = 0;
+        for _ in 0..5 {
+            written += self.shift_low()?;
+        }
+        Ok(written)
+    }
+
+    /// The state to [`resume`] from.
+    ///
+    /// [`resume`]: RangeEncoder::resume
+    pub(crate) fn state(&self) -> (u64, u32, u8, u64) {
+        (self.low, self.range, self.cache, self.cache_size)
+    }
+
+    /// Pick up where an encoder left off, given its state. `None` if the
+    /// state isn't one an encoder could be in, or holds back over
+    /// [`MAX_HELD`] bytes.
+    pub(crate) fn resume(
+        writer: W,
+        low: u64,
+        range: u32,
+        cache: u8,
+        cache_size: u64,
+    ) -> Option<Self> {
+        if low >> 33 != 0 || range < TOP || !(1..=MAX_HELD).contains(&cache_size) {
+            return None;
+        }
+        Some(Self {
+            writer,
+            low,
+            range,
+            cache,
+            cache_size,
+        })
+    }
+
+    /// Bits settled on but held back in case of a carry.
+    pub fn pending_bits(&self) -> u64 {
+        self.cache_size * 8
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Carry on over `writer` instead, handing back the old one.
+    pub(crate) fn replace_writer<V>(self, writer: V) -> (RangeEncoder<V>, W) {
+        let encoder = RangeEncoder {
+            writer,
+            low: self.low,
+            range: self.range,
+            cache: self.cache,
+            cache_size: self.cache_size,
+        };
+        (encoder, self.writer)
+    }
+}
+
+/// Decoder for [`RangeEncoder`] over a byte slice. Reading past the end of
+/// the slice gives zeros, check [`overrun`] to tell if that happened.
+///
+/// [`overrun`]: RangeDecoder::overrun
+#[derive(Debug, Clone)]
+pub struct RangeDecoder<'a> {
+    data: &'a [u8],
+    position: usize,
+    code: u32,
+    range: u32,
+}
+
+impl<'a> RangeDecoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        let mut d = Self::resume(data, 0, 0, u32::MAX);
+        for _ in 0..5 {
+            d.code = d.code << 8 | d.next_byte() as u32;
+        }
+        d
+    }
+
+    /// Pick up where a decoder left off, given its state.
+    pub(crate) fn resume(data: &'a [u8], position: usize, code: u32, range: u32) -> Self {
+        Self {
+            data,
+            position,
+            code,
+            range,
+        }
+    }
+
+    /// The state to [`resume`] from.
+    ///
+    /// [`resume`]: RangeDecoder::resume
+    pub(crate) fn state(&self) -> (usize, u32, u32) {
+        (self.position, self.code, self.range)
+    }
+
+    /// Offset of the next unread byte.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Whether the decoder has needed bytes past the end of its data.
+    pub fn overrun(&self) -> bool {
+        self.position > self.data.len()
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        let b = self.data.get(self.position).copied().unwrap_or(0);
+        self.position += 1;
+        b
+    }
+
+    /// Decode a symbol out of `total`. `find` maps a value in `[0, total)` to
+    /// the symbol whose range holds it, along with that range as
+    /// `(symbol, cumulative, frequency)`.
+    pub fn decode(
+        &mut self,
+        total: u32,
+        find: impl FnOnce(u32) -> (usize, u32, u32),
+    ) -> Result<usize, ErrorKind> {
+        let r = self.range / total;
+        let value = self.code / r;
+        if value >= total {
+            return Err(ErrorKind::BadCode(value));
+        }
+        let (symbol, cumulative, frequency) = find(value);
+        self.code -= r * cumulative;
+        self.range = r * frequency;
+        self.normalize();
+        Ok(symbol)
+    }
+
+    /// Decode one bit, where `probability` is the chance of a zero out of
+    /// `1 << PROBABILITY_BITS`.
+    pub fn decode_bit(&mut self, probability: u16) -> bool {
+        let bound = (self.range >> PROBABILITY_BITS) * probability as u32;
+        let bit = if self.code < bound {
+            self.range = bound;
+            false
+        } else {
+            self.code -= bound;
+            self.range -= bound;
+            true
+        };
+        self.normalize();
+        bit
+    }
+
+    fn normalize(&mut self) {
+        while self.range < TOP {
+            self.range <<= 8;
+            self.code = self.code << 8 | self.next_byte() as u32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        let mut out = Vec::new();
+        let mut e = RangeEncoder::new(&mut out);
+        assert_eq!(e.flush().unwrap(), 5);
+        assert_eq!(out, [0; 5]);
+    }
+
+    #[test]
+    fn frequencies() {
+        // Symbols 0, 1 and 2 with frequencies 1, 2 and 5
+        let ranges = [(0, 1), (1, 2), (3, 5)];
+        let symbols = [2, 2, 0, 1, 2, 2, 2, 1, 0, 2];
+        let mut out = Vec::new();
+        let mut e = RangeEncoder::new(&mut out);
+        for &s in &symbols {
+            let (c, f) = ranges[s];
+            e.encode(c, f, 8).unwrap();
+        }
+        e.flush().unwrap();
+
+        let mut d = RangeDecoder::new(&out);
+        for &s in &symbols {
+            let decoded = d
+                .decode(8, |v| {
+                    let s = ranges.iter().position(|&(c, f)| v < c + f).unwrap();
+                    (s, ranges[s].0, ranges[s].1)
+                })
+                .unwrap();
+            assert_eq!(decoded, s);
+        }
+        assert_eq!(d.position(), out.len());
+        assert!(!d.overrun());
+    }
+
+    #[test]
+    fn bits() {
+        let bits = (0..1000).map(|i| i % 7 == 0).collect::<Vec<_>>();
+        let mut out = Vec::new();
+        let mut e = RangeEncoder::new(&mut out);
+        for &b in &bits {
+            e.encode_bit(3500, b).unwrap();
+        }
+        e.flush().unwrap();
+        // Well under a bit each
+        assert!(out.len() < 1000 / 8 * 2 / 3);
+
+        let mut d = RangeDecoder::new(&out);
+        for &b in &bits {
+            assert_eq!(d.decode_bit(3500), b);
+        }
+        assert!(!d.overrun());
+    }
+
+    #[test]
+    fn carry() {
+        // Pushing `low` right up against the top of the range forces carries
+        // through runs of 0xff
+        let mut out = Vec::new();
+        let mut e = RangeEncoder::new(&mut out);
+        for _ in 0..100 {
+            e.encode(MAX_TOTAL - 1, 1, MAX_TOTAL).unwrap();
+            e.encode(0, 1, MAX_TOTAL).unwrap();
+        }
+        e.flush().unwrap();
+
+        let mut d = RangeDecoder::new(&out);
+        for _ in 0..100 {
+            let s = d.decode(MAX_TOTAL, |v| {
+                if v == MAX_TOTAL - 1 {
+                    (1, v, 1)
+                } else {
+                    (0, 0, MAX_TOTAL - 1)
+                }
+            });
+            assert_eq!(s, Ok(1));
+            let s = d.decode(MAX_TOTAL, |v| {
+                if v == 0 {
+                    (0, 0, 1)
+                } else {
+                    (1, 1, MAX_TOTAL - 1)
+                }
+            });
+            assert_eq!(s, Ok(0));
+        }
+        assert!(!d.overrun());
+    }
+
+    #[test]
+    fn overrun() {
+        let mut d = RangeDecoder::new(&[0, 0]);
+        assert!(d.overrun());
+        d.decode_bit(2048);
+        assert_eq!(d.position(), 5);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::{
+    error::Error,
+    io::Sink,
+    range_coder::{
+        default_model, FrequencyModel, Model, RangeEncoder, END_OF_STREAM, MAX_TOTAL, SYMBOLS,
+    },
+    stats::Stats,
+};
+
+/// Compresses a byte stream with a range coder, one symbol per byte and an
+/// end of stream symbol at the end.
+pub struct RangeCompressor<W, M = FrequencyModel> {
+    encoder: RangeEncoder<W>,
+    model: M,
+    input: u64,
+    output: u64,
+}
+
+impl<W: Sink> RangeCompressor<W> {
+    /// Compress with an order-0 [`FrequencyModel`].
+    pub fn new(writer: W) -> Self {
+        Self::with_model(writer, default_model())
+    }
+}
+
+impl<W: Sink, M: Model> RangeCompressor<W, M> {
+    /// Compress with `model`, which has to code symbols up to 256. The
+    /// decompressor needs to start from the same model.
+    pub fn with_model(writer: W, model: M) -> Self {
+        Self {
+            encoder: RangeEncoder::new(writer),
+            model,
+            input: 0,
+            output: 0,
+        }
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        let mut written = 0;
+        for &b in data {
+            written += self.model.encode(&mut self.encoder, b as usize)?;
+        }
+        self.input += data.len() as u64;
+        self.output += written as u64;
+        Ok(written)
+    }
+
+    /// What's gone in and out so far.
+    pub fn stats(&self) -> Stats {
+        Stats::new(self.input, self.output * 8 + self.encoder.pending_bits())
+    }
+
+    /// Carry on over `writer` instead, handing back the old one.
+    pub(crate) fn replace_writer<V>(self, writer: V) -> (RangeCompressor<V, M>, W) {
+        let (encoder, old) = self.encoder.replace_writer(writer);
+        let codec = RangeCompressor {
+            encoder,
+            model: self.model,
+            input: self.input,
+            output: self.output,
+        };
+        (codec, old)
+    }
+
+    /// Encode the end of stream symbol and flush the coder.
+    pub fn finalize(mut self) -> Result<usize, Error> {
+        let written = self.model.encode(&mut self.encoder, END_OF_STREAM)?;
+        Ok(written + self.encoder.flush()?)
+    }
+}
+
+/// Worst case size of `len` bytes compressed by [`RangeCompressor::new`],
+/// with the [`default_model`]. Other models make no promises.
+///
+/// No count drops below one, so a symbol costs at most the log of the
+/// model's total, which starts at [`SYMBOLS`] and grows by
+/// [`FrequencyModel::INCREMENT`] a symbol up to [`MAX_TOTAL`]. Rounding
+/// the range down loses under 1/128 of a bit a symbol, and flushing the
+/// coder adds 5 bytes.
+///
+/// [`default_model`]: crate::range_coder::default_model
+pub const fn compress_bound(len: usize) -> usize {
+    let symbols = len as u64 + 1;
+    let mut bits = symbols.div_ceil(128);
+    let mut t = 0;
+    while t < symbols {
+        let total = SYMBOLS as u64 + FrequencyModel::INCREMENT as u64 * t;
+        if total >= MAX_TOTAL as u64 {
+            bits += (symbols - t) * MAX_TOTAL.trailing_zeros() as u64;
+            break;
+        }
+        bits += (u64::BITS - (total - 1).leading_zeros()) as u64;
+        t += 1;
+    }
+    bits.div_ceil(8) as usize + 5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        io::SliceSink,
+        range_coder::{BitTreeModel, ContextModel},
+        utils::noise,
+    };
+
+    #[test]
+    fn bound() {
+        assert!(compress_bound(0) >= 6);
+        // Every byte once and then the rarest byte again and again, which
+        // keeps the model as wrong as it can be
+        let spread = (0..=255).cycle().take(100_000).collect::<Vec<u8>>();
+        let mut rarest = (0..=255).collect::<Vec<u8>>();
+        let mut model = default_model();
+        let mut encoder = RangeEncoder::new(Vec::new());
+        for b in 0..256 {
+            model.encode(&mut encoder, b).unwrap();
+        }
+        while rarest.len() < 100_000 {
+            let f = model.frequencies();
+            let b = (0..256).min_by_key(|&b| f[b]).unwrap();
+            model.encode(&mut encoder, b).unwrap();
+            rarest.push(b as u8);
+        }
+        for data in [noise(100_000), noise(100), spread, rarest] {
+            let mut out = vec![0; compress_bound(data.len())];
+            let mut c = RangeCompressor::new(SliceSink::new(&mut out));
+            c.write(&data).unwrap();
+            c.finalize().unwrap();
+        }
+    }
+
+    #[test]
+    fn empty() {
+        let mut compressed = Vec::new();
+        let c = RangeCompressor::new(&mut compressed);
+        assert_eq!(c.finalize().unwrap(), 6);
+        // End of stream is the last of 257 equally likely symbols
+        assert_eq!(compressed, [0, 0xff, 0, 0xff, 0, 0]);
+    }
+
+    #[test]
+    fn three_bytes() {
+        let mut compressed = Vec::new();
+        let mut c = RangeCompressor::new(&mut compressed);
+        assert_eq!(c.write(b"aaa").unwrap(), 1);
+        assert_eq!(c.finalize().unwrap(), 6);
+        assert_eq!(compressed, [0, 0x61, 0x03, 0xa5, 0x36, 0x91, 0]);
+    }
+
+    #[test]
+    fn skewed() {
+        let data = b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaabaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".repeat(10);
+        let mut compressed = Vec::new();
+        let mut c = RangeCompressor::new(&mut compressed);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+        assert!(compressed.len() < data.len() / 8, "{}", compressed.len());
+    }
+
+    #[test]
+    fn other_models() {
+        let mut compressed = Vec::new();
+        let mut c = RangeCompressor::with_model(&mut compressed, BitTreeModel::new(9));
+        c.write(b"abc").unwrap();
+        c.finalize().unwrap();
+
+        let mut compressed = Vec::new();
+        let model = ContextModel::new(default_model(), 1, 9);
+        let mut c = RangeCompressor::with_model(&mut compressed, model);
+        c.write(b"abc").unwrap();
+        c.finalize().unwrap();
+    }
+}
@@ -0,0 +1,269 @@
+use alloc::vec::Vec;
+
+use crate::{
+    error::{Error, ErrorKind},
+    io::Sink,
+    options::DecompressOptions,
+    range_coder::{default_model, FrequencyModel, Model, RangeDecoder, CODEC, END_OF_STREAM},
+};
+
+/// Compressed bytes to hold back before decoding a symbol mid-stream, so a
+/// symbol never needs more input than has arrived.
+const LOOKAHEAD: usize = 32;
+
+/// Bytes the decoder reads before the first symbol.
+const HEADER: usize = 5;
+
+/// Decompresses a stream from [`RangeCompressor`]. Symbols are decoded once
+/// enough input has arrived after them, the last few when the stream is
+/// finalized.
+///
+/// [`RangeCompressor`]: crate::range_coder::RangeCompressor
+pub struct RangeDecompressor<W, M = FrequencyModel> {
+    writer: W,
+    options: DecompressOptions,
+    model: M,
+
+    /// Compressed bytes not read by the decoder yet.
+    buffer: Vec<u8>,
+    /// Bytes dropped from the front of `buffer` so far.
+    dropped: u64,
+    produced: u64,
+
+    /// Decoder state, `None` until the header arrives.
+    state: Option<(u32, u32)>,
+    done: bool,
+}
+
+impl<W: Sink> RangeDecompressor<W> {
+    pub fn new(writer: W) -> Self {
+        Self::with_options(writer, DecompressOptions::default())
+    }
+
+    pub fn with_options(writer: W, options: DecompressOptions) -> Self {
+        Self::with_model(writer, default_model(), options)
+    }
+}
+
+impl<W: Sink, M: Model> RangeDecompressor<W, M> {
+    /// Decompress a stream compressed with `model`.
+    pub fn with_model(writer: W, model: M, options: DecompressOptions) -> Self {
+        Self {
+            writer,
+            options,
+            model,
+
+            buffer: Vec::new(),
+            dropped: 0,
+            produced: 0,
+
+            state: None,
+            done: false,
+        }
+    }
+
+    fn error(&self, kind: ErrorKind, position: usize) -> Error {
+        Error::stream(
+            CODEC,
+            kind,
+            (self.dropped + position as u64) * 8,
+            self.produced,
+        )
+    }
+
+    pub fn read(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.buffer.extend_from_slice(data);
+        self.decode(LOOKAHEAD)
+    }
+
+    /// Decode symbols while at least `lookahead` bytes are left after the
+    /// decoder's position, or until the end of the stream.
+    fn decode(&mut self, lookahead: usize) -> Result<usize, Error> {
+        if self.done {
+            return Ok(0);
+        }
+        let consumed = self.dropped + self.buffer.len() as u64;
+        let mut decoder = match self.state {
+            Some((code, range)) => RangeDecoder::resume(&self.buffer, 0, code, range),
+            None if self.buffer.len() >= HEADER + lookahead => RangeDecoder::new(&self.buffer),
+            None => return Ok(0),
+        };
+        let mut output = Vec::new();
+
+        while !self.done && self.buffer.len() >= decoder.position() + lookahead {
+            let position = decoder.position();
+            let symbol = self
+                .model
+                .decode(&mut decoder)
+                .map_err(|k| self.error(k, position))?;
+            if decoder.overrun() {
+                let bits = (self.buffer.len() - position) * 8;
+                return Err(self.error(ErrorKind::Truncated(bits), position));
+            }
+            match symbol {
+                END_OF_STREAM => self.done = true,
+                s if s < END_OF_STREAM => {
+                    // Checked as it goes, a skewed model can decode a lot
+                    // from little input. What came before the limit is
+                    // written out.
+                    let n = output.len() as u64 + 1;
+                    if let Err(l) = self.options.check_output(consumed, self.produced, n) {
+                        self.writer.write_all(&output)?;
+                        self.produced += output.len() as u64;
+                        return Err(self.error(ErrorKind::LimitExceeded(l), position));
+                    }
+                    output.push(s as u8);
+                }
+                s => return Err(self.error(ErrorKind::BadCode(s as u32), position)),
+            }
+        }
+
+        let (position, code, range) = decoder.state();
+        self.writer.write_all(&output)?;
+        self.produced += output.len() as u64;
+
+        let keep = position.min(self.buffer.len());
+        self.buffer.drain(..keep);
+        self.dropped += keep as u64;
+        self.state = Some((code, range));
+        Ok(output.len())
+    }
+
+    /// Carry on over `writer` instead, handing back the old one.
+    pub(crate) fn replace_writer<V>(self, writer: V) -> (RangeDecompressor<V, M>, W) {
+        let codec = RangeDecompressor {
+            writer,
+            options: self.options,
+            model: self.model,
+            buffer: self.buffer,
+            dropped: self.dropped,
+            produced: self.produced,
+            state: self.state,
+            done: self.done,
+        };
+        (codec, self.writer)
+    }
+
+    /// Decode whatever symbols are left, failing if the end of stream symbol
+    /// never arrives.
+    pub fn finalize(mut self) -> Result<usize, Error> {
+        if self.state.is_none() && self.buffer.len() < HEADER {
+            let bits = self.buffer.len() * 8;
+            return Err(self.error(ErrorKind::Truncated(bits), 0));
+        }
+        let written = self.decode(0)?;
+        match self.done {
+            true => Ok(written),
+            false => Err(self.error(ErrorKind::Truncated(0), self.buffer.len())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::Limit;
+
+    const THREE_BYTES: [u8; 7] = [0, 0x61, 0x03, 0xa5, 0x36, 0x91, 0];
+
+    #[test]
+    fn empty() {
+        let mut uncompressed = Vec::new();
+        let mut d = RangeDecompressor::new(&mut uncompressed);
+        assert_eq!(d.read(&[0, 0xff, 0, 0xff, 0, 0]).unwrap(), 0);
+        assert_eq!(d.finalize().unwrap(), 0);
+        assert!(uncompressed.is_empty());
+    }
+
+    #[test]
+    fn three_bytes() {
+        let mut uncompressed = Vec::new();
+        let mut d = RangeDecompressor::new(&mut uncompressed);
+        // Too short to decode anything before the end
+        assert_eq!(d.read(&THREE_BYTES).unwrap(), 0);
+        assert_eq!(d.finalize().unwrap(), 3);
+        assert_eq!(uncompressed, b"aaa");
+    }
+
+    #[test]
+    fn serial() {
+        let data = (0..2000).map(|i| (i % 13) as u8).collect::<Vec<_>>();
+        let mut compressed = Vec::new();
+        let mut c = crate::range_coder::RangeCompressor::new(&mut compressed);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+
+        let mut uncompressed = Vec::new();
+        let mut d = RangeDecompressor::new(&mut uncompressed);
+        let written = compressed
+            .iter()
+            .map(|b| d.read(&[*b]).unwrap())
+            .sum::<usize>();
+        assert!(written > 0);
+        assert_eq!(written + d.finalize().unwrap(), data.len());
+        assert_eq!(uncompressed, data);
+    }
+
+    #[test]
+    fn truncated() {
+        let mut uncompressed = Vec::new();
+        let mut d = RangeDecompressor::new(&mut uncompressed);
+        d.read(&THREE_BYTES[..6]).unwrap();
+        let e = d.finalize().unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::Truncated(0)));
+        assert_eq!(e.codec(), Some("range"));
+        assert_eq!(e.bit_offset(), Some(48));
+
+        let mut d = RangeDecompressor::new(&mut uncompressed);
+        d.read(&THREE_BYTES[..3]).unwrap();
+        let e = d.finalize().unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::Truncated(24)));
+    }
+
+    #[test]
+    fn bad_code() {
+        let mut uncompressed = Vec::new();
+        let mut d = RangeDecompressor::new(&mut uncompressed);
+        // The code has to start below the range
+        d.read(&[0xff; 8]).unwrap();
+        let e = d.finalize().unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::BadCode(257)));
+        assert_eq!(e.bit_offset(), Some(40));
+    }
+
+    #[test]
+    fn max_output_bytes() {
+        let mut uncompressed = Vec::new();
+        let options = DecompressOptions::new().max_output_bytes(2);
+        let mut d = RangeDecompressor::with_options(&mut uncompressed, options);
+        d.read(&THREE_BYTES).unwrap();
+        let e = d.finalize().unwrap_err();
+        assert_eq!(
+            e.kind(),
+            Some(ErrorKind::LimitExceeded(Limit::OutputBytes(2)))
+        );
+        assert_eq!(e.byte_offset(), Some(2));
+        assert_eq!(uncompressed, b"aa");
+    }
+
+    #[test]
+    fn limit_within_a_read() {
+        let data = vec![0; 1 << 16];
+        let mut compressed = Vec::new();
+        let mut c = crate::range_coder::RangeCompressor::new(&mut compressed);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+
+        // The limit is hit partway through the one read, not after it
+        let mut uncompressed = Vec::new();
+        let options = DecompressOptions::new().max_output_bytes(1000);
+        let mut d = RangeDecompressor::with_options(&mut uncompressed, options);
+        let e = d.read(&compressed).unwrap_err();
+        assert_eq!(
+            e.kind(),
+            Some(ErrorKind::LimitExceeded(Limit::OutputBytes(1000)))
+        );
+        assert_eq!(e.byte_offset(), Some(1000));
+        assert_eq!(uncompressed.len(), 1000);
+    }
+}
@@ -0,0 +1,35 @@
+//! Range coding with pluggable probability models.
+//!
+//! [`RangeEncoder`] and [`RangeDecoder`] are a 32 bit range coder with carry
+//! propagation. What they code is up to a [`Model`]: [`FrequencyModel`] is
+//! an order-0 adaptive frequency table, [`BitModel`] and [`BitTreeModel`]
+//! code single bits and bit strings, and [`ContextModel`] conditions any of
+//! them on the previous symbols.
+//!
+//! [`RangeCompressor`] and [`RangeDecompressor`] code byte streams with any
+//! model over 257 symbols, the bytes plus an end of stream symbol.
+
+mod coder;
+mod compress;
+mod decompress;
+mod model;
+
+pub(crate) use self::coder::TOP;
+pub use self::{
+    coder::{RangeDecoder, RangeEncoder, MAX_TOTAL, PROBABILITY_BITS},
+    compress::{compress_bound, RangeCompressor},
+    decompress::RangeDecompressor,
+    model::{BitModel, BitTreeModel, ContextModel, FrequencyModel, Model},
+};
+
+/// Name reported in errors from the range coder.
+const CODEC: &str = "range";
+
+/// Byte streams use every byte value plus an end of stream symbol.
+pub const SYMBOLS: usize = 257;
+const END_OF_STREAM: usize = 256;
+
+/// The model [`RangeCompressor::new`] and [`RangeDecompressor::new`] use.
+pub fn default_model() -> FrequencyModel {
+    FrequencyModel::new(SYMBOLS)
+}
@@ -0,0 +1,473 @@
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+
+use crate::{
+    error::{Error, ErrorKind},
+    io::Sink,
+    range_coder::{RangeDecoder, RangeEncoder, MAX_TOTAL, PROBABILITY_BITS},
+};
+
+/// Probability model driving a range coder. Encoding and decoding the same
+/// symbols from the same starting model has to leave both sides in the same
+/// state, so updates happen inside `encode` and `decode`.
+///
+/// A single symbol shouldn't take more than about 32 bytes of coded output,
+/// the streaming decompressor holds back that much input before decoding.
+pub trait Model {
+    /// Encode `symbol`, returning how many bytes went out to the sink.
+    fn encode<W: Sink>(
+        &mut self,
+        encoder: &mut RangeEncoder<W>,
+        symbol: usize,
+    ) -> Result<usize, Error>;
+
+    fn decode(&mut self, decoder: &mut RangeDecoder) -> Result<usize, ErrorKind>;
+}
+
+/// Order-0 adaptive frequency table. Every symbol starts with a count of one
+/// and gains [`FrequencyModel::INCREMENT`] each time it's seen, with counts
+/// halved whenever the total would go past [`MAX_TOTAL`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrequencyModel {
+    frequencies: Vec<u32>,
+    total: u32,
+}
+
+impl FrequencyModel {
+    pub const INCREMENT: u32 = 32;
+
+    pub fn new(symbols: usize) -> Self {
+        assert!(symbols > 0 && symbols as u32 <= MAX_TOTAL / 2);
+        Self {
+            frequencies: vec![1; symbols],
+            total: symbols as u32,
+        }
+    }
+
+    pub fn symbols(&self) -> usize {
+        self.frequencies.len()
+    }
+
+    pub(crate) fn frequencies(&self) -> &[u32] {
+        &self.frequencies
+    }
+
+    /// A model with `frequencies` as its counts, as [`frequencies`] gave
+    /// them. `None` if no model could have them.
+    ///
+    /// [`frequencies`]: FrequencyModel::frequencies
+    pub(crate) fn from_frequencies(frequencies: Vec<u32>) -> Option<Self> {
+        let total = frequencies
+            .iter()
+            .try_fold(0_u32, |total, &f| total.checked_add(f).filter(|_| f > 0))?;
+        if frequencies.is_empty() || total > MAX_TOTAL {
+            return None;
+        }
+        Some(Self { frequencies, total })
+    }
+
+    /// `(cumulative, frequency)` for `symbol`.
+    fn range(&self, symbol: usize) -> (u32, u32) {
+        let cumulative = self.frequencies[..symbol].iter().sum();
+        (cumulative, self.frequencies[symbol])
+    }
+
+    /// The symbol whose range holds `value`, with that range.
+    fn find(&self, value: u32) -> (usize, u32, u32) {
+        let mut cumulative = 0;
+        for (s, &f) in self.frequencies.iter().enumerate() {
+            if value < cumulative + f {
+                return (s, cumulative, f);
+            }
+            cumulative += f;
+        }
+        unreachable!("value checked against the total");
+    }
+
+    fn update(&mut self, symbol: usize) {
+        self.frequencies[symbol] += Self::INCREMENT;
+        self.total += Self::INCREMENT;
+        if self.total > MAX_TOTAL {
+            self.total = 0;
+            for f in &mut self.frequencies {
+                *f = f.div_ceil(2);
+                self.total += *f;
+            }
+        }
+    }
+}
+
+impl Model for FrequencyModel {
+    fn encode<W: Sink>(
+        &mut self,
+        encoder: &mut RangeEncoder<W>,
+        symbol: usize,
+    ) -> Result<usize, Error> {
+        let (cumulative, frequency) = self.range(symbol);
+        let written = encoder.encode(cumulative, frequency, self.total)?;
+        self.update(symbol);
+        Ok(written)
+    }
+
+    fn decode(&mut self, decoder: &mut RangeDecoder) -> Result<usize, ErrorKind> {
+        let symbol = decoder.decode(self.total, |v| self.find(v))?;
+        self.update(symbol);
+        Ok(symbol)
+    }
+}
+
+/// Adaptive probability for a single bit, moving a fraction of the way
+/// towards each bit it sees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitModel {
+    /// Chance of a zero, out of `1 << PROBABILITY_BITS`.
+    probability: u16,
+}
+
+impl BitModel {
+    /// How quickly the probability moves, as a right shift.
+    const SHIFT: u32 = 5;
+
+    pub fn new() -> Self {
+        Self {
+            probability: 1 << (PROBABILITY_BITS - 1),
+        }
+    }
+
+    pub(crate) fn probability(&self) -> u16 {
+        self.probability
+    }
+
+    /// `None` for a probability that would leave no room for a zero or a
+    /// one.
+    pub(crate) fn with_probability(probability: u16) -> Option<Self> {
+        (1..1 << PROBABILITY_BITS)
+            .contains(&probability)
+            .then_some(Self { probability })
+    }
+
+    fn update(&mut self, bit: bool) {
+        if bit {
+            self.probability -= self.probability >> Self::SHIFT;
+        } else {
+            self.probability += ((1 << PROBABILITY_BITS) - self.probability) >> Self::SHIFT;
+        }
+    }
+}
+
+impl Default for BitModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Model for BitModel {
+    fn encode<W: Sink>(
+        &mut self,
+        encoder: &mut RangeEncoder<W>,
+        symbol: usize,
+    ) -> Result<usize, Error> {
+        let bit = symbol != 0;
+        let written = encoder.encode_bit(self.probability, bit)?;
+        self.update(bit);
+        Ok(written)
+    }
+
+    fn decode(&mut self, decoder: &mut RangeDecoder) -> Result<usize, ErrorKind> {
+        let bit = decoder.decode_bit(self.probability);
+        self.update(bit);
+        Ok(bit as usize)
+    }
+}
+
+/// Codes `bits` wide symbols a bit at a time, most significant first, with
+/// a [`BitModel`] for every prefix seen so far.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BitTreeModel {
+    bits: u32,
+    /// Indexed by the prefix with a leading one bit, so `models[1]` is the
+    /// top bit.
+    models: Vec<BitModel>,
+}
+
+impl BitTreeModel {
+    pub fn new(bits: u32) -> Self {
+        assert!(bits > 0 && bits <= 16);
+        Self {
+            bits,
+            models: vec![BitModel::new(); 1 << bits],
+        }
+    }
+
+    pub(crate) fn models(&self) -> &[BitModel] {
+        &self.models
+    }
+
+    /// A tree from the models [`models`] gave for one `bits` wide.
+    ///
+    /// [`models`]: BitTreeModel::models
+    pub(crate) fn from_models(bits: u32, models: Vec<BitModel>) -> Option<Self> {
+        (models.len() == 1 << bits).then_some(Self { bits, models })
+    }
+}
+
+impl Model for BitTreeModel {
+    fn encode<W: Sink>(
+        &mut self,
+        encoder: &mut RangeEncoder<W>,
+        symbol: usize,
+    ) -> Result<usize, Error> {
+        debug_assert!(symbol < self.models.len());
+        let mut written = 0;
+        let mut node = 1;
+        for i in (0..self.bits).rev() {
+            let bit = (symbol >> i) & 1;
+            written += self.models[node].encode(encoder, bit)?;
+            node = node << 1 | bit;
+        }
+        Ok(written)
+    }
+
+    fn decode(&mut self, decoder: &mut RangeDecoder) -> Result<usize, ErrorKind> {
+        let mut node = 1;
+        for _ in 0..self.bits {
+            node = node << 1 | self.models[node].decode(decoder)?;
+        }
+        Ok(node - self.models.len())
+    }
+}
+
+/// Order-N context model: keeps a separate copy of an inner model for each
+/// combination of the previous `order` symbols, starting each one from
+/// `initial` the first time its context comes up.
+///
+/// There are at most `1 << table_bits` copies. Contexts wider than that are
+/// hashed down to fit, so some of them share a model, which costs a little
+/// compression but keeps memory bounded whatever the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextModel<M> {
+    initial: M,
+    models: BTreeMap<u64, M>,
+    /// The previous symbols, `symbol_bits` each, newest in the low bits.
+    context: u64,
+    mask: u64,
+    symbol_bits: u32,
+    table_bits: u32,
+}
+
+impl<M: Model + Clone> ContextModel<M> {
+    /// Table size [`ContextModel::new`] uses, 4096 models.
+    pub const DEFAULT_TABLE_BITS: u32 = 12;
+    /// Largest table [`ContextModel::with_table_bits`] makes.
+    pub const MAX_TABLE_BITS: u32 = 24;
+
+    /// `symbol_bits` is how many bits every symbol fits in, the context has
+    /// to fit in 64 bits. Keeps up to [`DEFAULT_TABLE_BITS`] worth of models.
+    ///
+    /// [`DEFAULT_TABLE_BITS`]: ContextModel::DEFAULT_TABLE_BITS
+    pub fn new(initial: M, order: u32, symbol_bits: u32) -> Self {
+        Self::with_table_bits(initial, order, symbol_bits, Self::DEFAULT_TABLE_BITS)
+    }
+
+    /// Like [`new`], keeping up to `1 << table_bits` models. `table_bits`
+    /// is clamped to [`MAX_TABLE_BITS`]. Both sides have to use the same
+    /// table size.
+    ///
+    /// [`new`]: ContextModel::new
+    /// [`MAX_TABLE_BITS`]: ContextModel::MAX_TABLE_BITS
+    pub fn with_table_bits(initial: M, order: u32, symbol_bits: u32, table_bits: u32) -> Self {
+        let context_bits = order * symbol_bits;
+        assert!(context_bits <= 64);
+        Self {
+            initial,
+            models: BTreeMap::new(),
+            context: 0,
+            mask: u64::MAX.checked_shr(64 - context_bits).unwrap_or(0),
+            symbol_bits,
+            table_bits: table_bits.min(Self::MAX_TABLE_BITS),
+        }
+    }
+
+    /// How many models have been made so far, one per context until they
+    /// outnumber the table.
+    pub fn contexts(&self) -> usize {
+        self.models.len()
+    }
+
+    /// Where the current context's model goes in the table. Contexts that
+    /// fit are used as they are, wider ones are hashed.
+    fn slot(&self) -> u64 {
+        if self.mask >> self.table_bits == 0 {
+            self.context
+        } else {
+            self.context.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> (64 - self.table_bits)
+        }
+    }
+
+    fn model(&mut self) -> &mut M {
+        let slot = self.slot();
+        let initial = &self.initial;
+        self.models.entry(slot).or_insert_with(|| initial.clone())
+    }
+
+    fn update(&mut self, symbol: usize) {
+        self.context = self
+            .context
+            .checked_shl(self.symbol_bits)
+            .map_or(0, |c| (c | symbol as u64) & self.mask);
+    }
+}
+
+impl<M: Model + Clone> Model for ContextModel<M> {
+    fn encode<W: Sink>(
+        &mut self,
+        encoder: &mut RangeEncoder<W>,
+        symbol: usize,
+    ) -> Result<usize, Error> {
+        let written = self.model().encode(encoder, symbol)?;
+        self.update(symbol);
+        Ok(written)
+    }
+
+    fn decode(&mut self, decoder: &mut RangeDecoder) -> Result<usize, ErrorKind> {
+        let symbol = self.model().decode(decoder)?;
+        self.update(symbol);
+        Ok(symbol)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip<M: Model + Clone>(model: M, symbols: &[usize]) -> usize {
+        let mut out = Vec::new();
+        let mut e = RangeEncoder::new(&mut out);
+        let mut m = model.clone();
+        for &s in symbols {
+            m.encode(&mut e, s).unwrap();
+        }
+        e.flush().unwrap();
+
+        let mut d = RangeDecoder::new(&out);
+        let mut m = model;
+        for &s in symbols {
+            assert_eq!(m.decode(&mut d), Ok(s));
+        }
+        assert!(!d.overrun());
+        out.len()
+    }
+
+    #[test]
+    fn frequency_adapts() {
+        let symbols = [7; 1000];
+        let len = roundtrip(FrequencyModel::new(256), &symbols);
+        assert!(len < 40, "{}", len);
+    }
+
+    #[test]
+    fn frequency_rescales() {
+        let mut m = FrequencyModel::new(4);
+        for _ in 0..10_000 {
+            m.update(1);
+        }
+        assert!(m.total <= MAX_TOTAL);
+        assert_eq!(m.total, m.frequencies.iter().sum::<u32>());
+        assert!(m.frequencies.iter().all(|&f| f > 0));
+
+        let symbols = (0..5000).map(|i| i % 4).collect::<Vec<_>>();
+        roundtrip(FrequencyModel::new(4), &symbols);
+    }
+
+    #[test]
+    fn bit_model() {
+        let symbols = (0..1000)
+            .map(|i| (i % 10 == 0) as usize)
+            .collect::<Vec<_>>();
+        let len = roundtrip(BitModel::new(), &symbols);
+        assert!(len < 1000 / 8 * 2 / 3, "{}", len);
+    }
+
+    #[test]
+    fn bit_model_saturates() {
+        let mut m = BitModel::new();
+        for _ in 0..1000 {
+            m.update(false);
+        }
+        assert!(m.probability < 1 << PROBABILITY_BITS);
+        for _ in 0..2000 {
+            m.update(true);
+        }
+        assert!(m.probability > 0);
+    }
+
+    #[test]
+    fn bit_tree() {
+        let symbols = b"abracadabra"
+            .iter()
+            .cycle()
+            .take(500)
+            .map(|&b| b as usize)
+            .collect::<Vec<_>>();
+        roundtrip(BitTreeModel::new(8), &symbols);
+        roundtrip(BitTreeModel::new(1), &[0, 1, 1, 0]);
+    }
+
+    #[test]
+    fn context_beats_order_0() {
+        // Each byte predicts the next exactly, but they're all equally common
+        let symbols = (0..4000).map(|i| (i * 7) % 256).collect::<Vec<_>>();
+        let order_0 = roundtrip(FrequencyModel::new(256), &symbols);
+        let order_1 = roundtrip(ContextModel::new(FrequencyModel::new(256), 1, 8), &symbols);
+        assert!(order_1 < order_0 / 2, "{} vs {}", order_1, order_0);
+    }
+
+    #[test]
+    fn context_orders() {
+        let symbols = b"mississippi"
+            .iter()
+            .cycle()
+            .take(200)
+            .map(|&b| b as usize)
+            .collect::<Vec<_>>();
+        for order in 0..=8 {
+            roundtrip(ContextModel::new(BitTreeModel::new(8), order, 8), &symbols);
+        }
+
+        let mut m = ContextModel::new(FrequencyModel::new(256), 2, 8);
+        let mut out = Vec::new();
+        let mut e = RangeEncoder::new(&mut out);
+        for &s in &symbols {
+            m.encode(&mut e, s).unwrap();
+        }
+        // "mi", "is", "ss", "si", "ip", "pp", "pi", "im" plus the empty
+        // contexts at the start
+        assert_eq!(m.contexts(), 10);
+    }
+
+    #[test]
+    fn context_table_is_bounded() {
+        let symbols = crate::utils::noise(50_000)
+            .into_iter()
+            .map(|b| b as usize)
+            .collect::<Vec<_>>();
+        let model = ContextModel::new(FrequencyModel::new(256), 3, 8);
+        let mut m = model.clone();
+        let mut e = RangeEncoder::new(Vec::new());
+        for &s in &symbols {
+            m.encode(&mut e, s).unwrap();
+        }
+        assert_eq!(
+            m.contexts(),
+            1 << ContextModel::<FrequencyModel>::DEFAULT_TABLE_BITS
+        );
+        roundtrip(model, &symbols);
+
+        let model = ContextModel::with_table_bits(FrequencyModel::new(256), 2, 8, 4);
+        let mut m = model.clone();
+        for &s in &symbols {
+            m.encode(&mut e, s).unwrap();
+        }
+        assert_eq!(m.contexts(), 16);
+        roundtrip(model, &symbols[..5000]);
+    }
+}
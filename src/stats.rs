@@ -0,0 +1,93 @@
+//! Running numbers on what a compressor has done so far, from the
+//! compressors' `stats()`. They're cheap to keep and to read, so can be
+//! sampled while a stream is going to decide whether compressing it pays.
+
+/// Input taken and output made by a compressor so far.
+///
+/// Output a codec holds back isn't counted until it goes out: Huffman
+/// holds everything until it finishes, and a pipeline's steps hold back
+/// their own. Bits a bit packer or range coder has settled on but not
+/// written yet are counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Stats {
+    pub input_bytes: u64,
+    pub output_bits: u64,
+    /// Numbers on the phrase dictionary, for the codecs with one.
+    pub dictionary: Option<DictionaryStats>,
+}
+
+impl Stats {
+    pub(crate) fn new(input_bytes: u64, output_bits: u64) -> Self {
+        Self {
+            input_bytes,
+            output_bits,
+            dictionary: None,
+        }
+    }
+
+    /// Output bits per input byte, `None` before any input.
+    pub fn bits_per_byte(&self) -> Option<f64> {
+        match self.input_bytes {
+            0 => None,
+            n => Some(self.output_bits as f64 / n as f64),
+        }
+    }
+
+    /// Input bytes per phrase, `None` without a dictionary or before the
+    /// first phrase. The phrase under way counts toward it.
+    pub fn average_phrase_length(&self) -> Option<f64> {
+        match self.dictionary?.phrases {
+            0 => None,
+            n => Some(self.input_bytes as f64 / n as f64),
+        }
+    }
+}
+
+/// Numbers on a dictionary coder's phrase table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DictionaryStats {
+    /// Phrases written out.
+    pub phrases: u64,
+    /// Entries in the table, not counting the empty phrase.
+    pub entries: u64,
+    /// Entries the table has room for.
+    pub capacity: u64,
+    /// Times the table was emptied to start over. LZ78's table stops
+    /// growing once it's full instead, so it never resets.
+    pub resets: u64,
+    /// Phrases written at each code width, indexed by width in bits. Codes
+    /// only get wider as the table grows, so this also says when each
+    /// width was reached.
+    pub widths: [u64; 17],
+}
+
+impl DictionaryStats {
+    /// How full the table is, from 0 to 1.
+    pub fn fill(&self) -> f64 {
+        self.entries as f64 / self.capacity as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ratios() {
+        let mut stats = Stats::new(0, 16);
+        assert_eq!(stats.bits_per_byte(), None);
+        assert_eq!(stats.average_phrase_length(), None);
+        stats.input_bytes = 8;
+        assert_eq!(stats.bits_per_byte(), Some(2.0));
+        stats.dictionary = Some(DictionaryStats {
+            phrases: 4,
+            entries: 4,
+            capacity: 16,
+            ..DictionaryStats::default()
+        });
+        assert_eq!(stats.average_phrase_length(), Some(2.0));
+        assert_eq!(stats.dictionary.unwrap().fill(), 0.25);
+    }
+}
@@ -0,0 +1,389 @@
+//! A push/pull core for the codecs, with no I/O of its own.
+//!
+//! A [`Stream`] wraps any compressor or decompressor. Each call to
+//! [`Stream::process`] takes what it can from an input buffer and fills an
+//! output buffer, returning `(consumed, produced, status)` in the style of
+//! zlib's `z_stream`. [`Stream::finish`] then drains the end of the stream.
+//! The codec writes straight into the output buffer, and stops taking input
+//! once it's full. Nothing has to be written in one go, so it runs over
+//! fixed buffers, from an event loop or across FFI:
+//!
+//! ```
+//! use kompression::{stream::{Status, Stream}, LZ78Compressor};
+//!
+//! let mut s = Stream::new(LZ78Compressor::new);
+//! let mut input = &b"banana bandana"[..];
+//! let mut output = [0; 4];
+//! let mut compressed = Vec::new();
+//! while !input.is_empty() {
+//!     let (consumed, produced, _) = s.process(input, &mut output).unwrap();
+//!     input = &input[consumed..];
+//!     compressed.extend_from_slice(&output[..produced]);
+//! }
+//! loop {
+//!     let (produced, status) = s.finish(&mut output).unwrap();
+//!     compressed.extend_from_slice(&output[..produced]);
+//!     if status == Status::Done {
+//!         break;
+//!     }
+//! }
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::{
+    bzip2::{Bzip2Compressor, Bzip2Decompressor},
+    error::Error,
+    huffman::{
+        AdaptiveHuffmanCompressor, AdaptiveHuffmanDecompressor, HuffmanCompressor,
+        HuffmanDecompressor,
+    },
+    io::{Codec, Sink},
+    pipeline::{PipelineCompressor, PipelineDecompressor},
+    range_coder::{RangeCompressor, RangeDecompressor},
+    LZ78Compressor, LZ78Decompressor,
+};
+
+/// Most input pushed through the codec at a time. It's never more than the
+/// room left in the caller's buffer either, so a [`Stream`] holds back
+/// little more than one piece's worth of output.
+const CHUNK_SIZE: usize = 4096;
+
+/// Where a [`Stream`] is up to after a call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// All output so far has been handed back, more input can go in.
+    NeedInput,
+    /// Output is waiting, or input was left for lack of room. Call again
+    /// with more.
+    NeedOutput,
+    /// Finished, and all output has been handed back.
+    Done,
+}
+
+/// Output written but not handed back yet. A [`Stream`]'s codec is built
+/// over one, and the stream keeps another for whatever doesn't fit the
+/// caller's buffer.
+#[derive(Debug, Default)]
+pub struct Held {
+    bytes: Vec<u8>,
+    /// Bytes at the front of `bytes` already handed back.
+    position: usize,
+}
+
+impl Held {
+    fn pending(&self) -> &[u8] {
+        &self.bytes[self.position..]
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.position = usize::min(self.position + n, self.bytes.len());
+    }
+}
+
+impl Sink for Held {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        if self.position == self.bytes.len() {
+            self.bytes.clear();
+            self.position = 0;
+        }
+        self.bytes.extend_from_slice(data);
+        Ok(data.len())
+    }
+}
+
+/// What a [`Stream`]'s codec writes to during a call: straight into the
+/// caller's buffer, then into the stream's [`Held`] once that's full.
+#[derive(Debug)]
+pub struct Window<'a> {
+    output: &'a mut [u8],
+    /// Bytes of `output` filled.
+    produced: &'a mut usize,
+    held: &'a mut Held,
+}
+
+impl Sink for Window<'_> {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        let mut rest = data;
+        if self.held.pending().is_empty() {
+            let n = usize::min(rest.len(), self.output.len() - *self.produced);
+            self.output[*self.produced..][..n].copy_from_slice(&rest[..n]);
+            *self.produced += n;
+            rest = &rest[n..];
+        }
+        self.held.write_all(rest)?;
+        Ok(data.len())
+    }
+}
+
+/// A codec a [`Stream`] can run: one built over a [`Held`], which the
+/// stream points at the caller's buffer for each call.
+pub trait Detachable: Sized {
+    /// The same codec writing to a [`Window`].
+    type Attached<'a>: Codec;
+
+    /// Write to `window` from now on, handing back what was written before.
+    fn attach(self, window: Window<'_>) -> (Self::Attached<'_>, Held);
+
+    /// Let go of the window again.
+    fn detach(codec: Self::Attached<'_>) -> Self;
+}
+
+macro_rules! impl_detachable {
+    ($($t:ident),*) => {
+        $(
+            impl Detachable for $t<Held> {
+                type Attached<'a> = $t<Window<'a>>;
+
+                fn attach(self, window: Window<'_>) -> (Self::Attached<'_>, Held) {
+                    self.replace_writer(window)
+                }
+
+                fn detach(codec: Self::Attached<'_>) -> Self {
+                    codec.replace_writer(Held::default()).0
+                }
+            }
+        )*
+    };
+}
+
+impl_detachable!(
+    LZ78Compressor,
+    LZ78Decompressor,
+    HuffmanCompressor,
+    HuffmanDecompressor,
+    AdaptiveHuffmanCompressor,
+    AdaptiveHuffmanDecompressor,
+    RangeCompressor,
+    RangeDecompressor,
+    Bzip2Compressor,
+    Bzip2Decompressor,
+    PipelineCompressor,
+    PipelineDecompressor
+);
+
+/// A codec run by handing it buffers. See the [module docs](self).
+#[derive(Debug)]
+pub struct Stream<C> {
+    /// `None` once finalized.
+    codec: Option<C>,
+    held: Held,
+}
+
+impl<C: Detachable> Stream<C> {
+    /// Run the codec `make` builds over a [`Held`].
+    pub fn new(make: impl FnOnce(Held) -> C) -> Self {
+        // Take over anything written on the way in, a dictionary id say
+        let (mut produced, mut held) = (0, Held::default());
+        let window = Window {
+            output: &mut [],
+            produced: &mut produced,
+            held: &mut held,
+        };
+        let (codec, written) = make(Held::default()).attach(window);
+        Self {
+            codec: Some(C::detach(codec)),
+            held: written,
+        }
+    }
+
+    /// Push as much of `input` through the codec as there's room for in
+    /// `output`, which the codec writes to directly. Input is only taken
+    /// while there's room left and nothing held back, so an empty `output`
+    /// takes none. Returns the bytes of input consumed, the bytes of output
+    /// produced and the status.
+    ///
+    /// Fails once the stream is finished.
+    pub fn process(
+        &mut self,
+        mut input: &[u8],
+        output: &mut [u8],
+    ) -> Result<(usize, usize, Status), Error> {
+        if !input.is_empty() && self.codec.is_none() {
+            return Err(Error::Finished);
+        }
+        let mut produced = self.take(output);
+        let mut consumed = 0;
+        while !input.is_empty() && produced < output.len() && self.pending().is_empty() {
+            let n = usize::min(input.len(), CHUNK_SIZE).min(output.len() - produced);
+            let codec = self.codec.take().ok_or(Error::Finished)?;
+            let mut room = 0;
+            let window = Window {
+                output: &mut output[produced..],
+                produced: &mut room,
+                held: &mut self.held,
+            };
+            let (mut codec, _) = codec.attach(window);
+            let result = codec.push(&input[..n]);
+            self.codec = Some(C::detach(codec));
+            produced += room;
+            result?;
+            input = &input[n..];
+            consumed += n;
+        }
+        let status = if input.is_empty() {
+            self.status()
+        } else {
+            Status::NeedOutput
+        };
+        Ok((consumed, produced, status))
+    }
+
+    /// Finalize the codec, the first time, and fill `output` with what's
+    /// left. Call again until it returns [`Status::Done`].
+    pub fn finish(&mut self, output: &mut [u8]) -> Result<(usize, Status), Error> {
+        let mut produced = self.take(output);
+        if let Some(codec) = self.codec.take() {
+            let mut room = 0;
+            let window = Window {
+                output: &mut output[produced..],
+                produced: &mut room,
+                held: &mut self.held,
+            };
+            let (codec, _) = codec.attach(window);
+            let result = codec.finish();
+            produced += room;
+            result?;
+        }
+        Ok((produced, self.status()))
+    }
+
+    pub fn status(&self) -> Status {
+        if !self.pending().is_empty() {
+            Status::NeedOutput
+        } else if self.codec.is_none() {
+            Status::Done
+        } else {
+            Status::NeedInput
+        }
+    }
+
+    /// Output held back for lack of room, for callers that would rather
+    /// not copy it. Mark what they used with [`consume`].
+    ///
+    /// [`consume`]: Stream::consume
+    pub fn pending(&self) -> &[u8] {
+        self.held.pending()
+    }
+
+    pub fn consume(&mut self, n: usize) {
+        self.held.consume(n);
+    }
+
+    fn take(&mut self, output: &mut [u8]) -> usize {
+        let n = usize::min(output.len(), self.pending().len());
+        output[..n].copy_from_slice(&self.pending()[..n]);
+        self.consume(n);
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bzip2::Bzip2Decompressor,
+        error::ErrorKind,
+        huffman::HuffmanCompressor,
+        lz78::{Dictionary, Mode},
+        LZ78Compressor, LZ78Decompressor,
+    };
+
+    /// Run `stream` over `input` a few bytes at a time each way.
+    fn run<C: Detachable>(mut stream: Stream<C>, mut input: &[u8], room: usize) -> Vec<u8> {
+        let mut output = vec![0; room];
+        let mut result = Vec::new();
+        while !input.is_empty() {
+            let n = usize::min(input.len(), 3);
+            let (consumed, produced, _) = stream.process(&input[..n], &mut output).unwrap();
+            input = &input[consumed..];
+            result.extend_from_slice(&output[..produced]);
+        }
+        loop {
+            let (produced, status) = stream.finish(&mut output).unwrap();
+            result.extend_from_slice(&output[..produced]);
+            if status == Status::Done {
+                return result;
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = b"She sells sea shells by the sea shore. ".repeat(100);
+        let mut expected = Vec::new();
+        let mut c = LZ78Compressor::new(&mut expected);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+
+        for room in [1, 7, 4096] {
+            let compressed = run(Stream::new(LZ78Compressor::new), &data, room);
+            assert_eq!(compressed, expected);
+            let uncompressed = run(Stream::new(LZ78Decompressor::new), &compressed, room);
+            assert!(uncompressed == data);
+        }
+    }
+
+    #[test]
+    fn status() {
+        let mut s = Stream::new(HuffmanCompressor::new);
+        assert_eq!(s.status(), Status::NeedInput);
+        // No room, no input taken
+        assert_eq!(
+            s.process(b"abc", &mut []).unwrap(),
+            (0, 0, Status::NeedOutput)
+        );
+        // Huffman holds everything back until it finishes
+        assert_eq!(
+            s.process(b"abc", &mut [0; 1]).unwrap(),
+            (3, 0, Status::NeedInput)
+        );
+        assert_eq!(s.finish(&mut [0; 2]).unwrap(), (2, Status::NeedOutput));
+        assert!(!s.pending().is_empty());
+        s.consume(s.pending().len());
+        assert_eq!(s.finish(&mut [0; 2]).unwrap(), (0, Status::Done));
+        assert!(matches!(s.process(b"d", &mut []), Err(Error::Finished)));
+        assert_eq!(s.process(b"", &mut []).unwrap(), (0, 0, Status::Done));
+    }
+
+    #[test]
+    fn held_output() {
+        // Input only goes in while there's room for what comes out, so a
+        // small buffer over a lot of output holds little back
+        let data = vec![b'a'; 3 * CHUNK_SIZE];
+        let mut compressed = Vec::new();
+        let mut c = LZ78Compressor::new(&mut compressed);
+        c.write(&data).unwrap();
+        c.finalize().unwrap();
+
+        let mut s = Stream::new(LZ78Decompressor::new);
+        let mut output = [0; 16];
+        let (consumed, produced, status) = s.process(&compressed, &mut output).unwrap();
+        assert!(consumed <= output.len());
+        assert_eq!((produced, status), (output.len(), Status::NeedOutput));
+        assert!(s.pending().len() < CHUNK_SIZE);
+    }
+
+    #[test]
+    fn written_on_the_way_in() {
+        let dictionary = Dictionary::from_corpus(b"banana bandana");
+        let mut expected = Vec::new();
+        let mut c = LZ78Compressor::with_dictionary(&mut expected, Mode::Raw, &dictionary).unwrap();
+        c.write(b"bandana").unwrap();
+        c.finalize().unwrap();
+
+        // The dictionary id goes out before any input
+        let s =
+            Stream::new(|w| LZ78Compressor::with_dictionary(w, Mode::Raw, &dictionary).unwrap());
+        assert_eq!(s.status(), Status::NeedOutput);
+        assert_eq!(run(s, b"bandana", 5), expected);
+    }
+
+    #[test]
+    fn bad_stream() {
+        let mut s = Stream::new(Bzip2Decompressor::new);
+        s.process(b"BZh9\x00", &mut []).unwrap();
+        let e = s.finish(&mut []).unwrap_err();
+        assert!(matches!(e.kind(), Some(ErrorKind::Truncated(_))));
+    }
+}
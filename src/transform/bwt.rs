@@ -0,0 +1,184 @@
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+use crate::{
+    bwt::{forward, inverse, BLOCK_SIZE},
+    error::{Error, ErrorKind},
+    transform::{Stage, Transform},
+};
+
+/// Name reported in errors from the BWT stage.
+const CODEC: &str = "bwt";
+
+/// Each block starts with its length and its original row, 32 bits each.
+const HEADER: usize = 8;
+
+/// The Burrows-Wheeler transform as a stage. Input is cut into blocks of up
+/// to [`BLOCK_SIZE`], each written as its length, the row it sorted to and
+/// the last column of its rotations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Bwt;
+
+impl Transform for Bwt {
+    type Encoder = BwtEncoder;
+    type Decoder = BwtDecoder;
+
+    fn encoder(&self) -> BwtEncoder {
+        BwtEncoder { block: Vec::new() }
+    }
+
+    fn decoder(&self) -> BwtDecoder {
+        BwtDecoder {
+            buffer: Vec::new(),
+            consumed: 0,
+            produced: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BwtEncoder {
+    block: Vec<u8>,
+}
+
+impl BwtEncoder {
+    fn flush(&mut self, output: &mut Vec<u8>) {
+        if self.block.is_empty() {
+            return;
+        }
+        let (last, origin) = forward(&self.block);
+        output.extend_from_slice(&(last.len() as u32).to_be_bytes());
+        output.extend_from_slice(&(origin as u32).to_be_bytes());
+        output.extend_from_slice(&last);
+        self.block.clear();
+    }
+}
+
+impl Stage for BwtEncoder {
+    fn push(&mut self, mut input: &[u8], output: &mut Vec<u8>) -> Result<(), Error> {
+        while !input.is_empty() {
+            let n = usize::min(BLOCK_SIZE - self.block.len(), input.len());
+            self.block.extend_from_slice(&input[..n]);
+            input = &input[n..];
+            if self.block.len() == BLOCK_SIZE {
+                self.flush(output);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Error> {
+        self.flush(output);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BwtDecoder {
+    /// Input not making up a whole block yet.
+    buffer: Vec<u8>,
+    /// Input bytes before `buffer`.
+    consumed: u64,
+    produced: u64,
+}
+
+impl BwtDecoder {
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error::stream(CODEC, kind, self.consumed * 8, self.produced)
+    }
+}
+
+impl Stage for BwtDecoder {
+    fn push(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Error> {
+        self.buffer.extend_from_slice(input);
+        let mut start = 0;
+        while self.buffer.len() - start >= HEADER {
+            let word = |i: usize| {
+                let bytes = self.buffer[start + i..start + i + 4].try_into().unwrap();
+                u32::from_be_bytes(bytes) as usize
+            };
+            let (len, origin) = (word(0), word(4));
+            if len == 0 || len > BLOCK_SIZE {
+                self.buffer.drain(..start);
+                return Err(self.error(ErrorKind::BadCode(len as u32)));
+            }
+            let end = start + HEADER + len;
+            if self.buffer.len() < end {
+                break;
+            }
+            match inverse(&self.buffer[start + HEADER..end], origin) {
+                Ok(block) => output.extend_from_slice(&block),
+                Err(kind) => {
+                    self.buffer.drain(..start);
+                    return Err(self.error(kind));
+                }
+            }
+            self.consumed += (end - start) as u64;
+            self.produced += len as u64;
+            start = end;
+        }
+        self.buffer.drain(..start);
+        Ok(())
+    }
+
+    fn finish(&mut self, _output: &mut Vec<u8>) -> Result<(), Error> {
+        if !self.buffer.is_empty() {
+            return Err(self.error(ErrorKind::Truncated(self.buffer.len() * 8)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn banana() {
+        let forward = Bwt.forward(b"banana").unwrap();
+        assert_eq!(forward, b"\x00\x00\x00\x06\x00\x00\x00\x03nnbaaa");
+        assert_eq!(Bwt.inverse(&forward).unwrap(), b"banana");
+    }
+
+    #[test]
+    fn empty() {
+        assert!(Bwt.forward(b"").unwrap().is_empty());
+        assert!(Bwt.inverse(b"").unwrap().is_empty());
+    }
+
+    #[test]
+    fn blocks() {
+        let data = (0..BLOCK_SIZE + 1000)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let forward = Bwt.forward(&data).unwrap();
+        assert_eq!(forward.len(), data.len() + 2 * HEADER);
+
+        let mut d = Bwt.decoder();
+        let mut inverse = Vec::new();
+        for chunk in forward.chunks(100_000) {
+            d.push(chunk, &mut inverse).unwrap();
+        }
+        d.finish(&mut inverse).unwrap();
+        assert!(inverse == data);
+    }
+
+    #[test]
+    fn bad_blocks() {
+        let e = Bwt
+            .inverse(b"\x00\x00\x00\x06\x00\x00\x00\x06nnbaaa")
+            .unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::BadCode(6)));
+        assert_eq!(e.codec(), Some("bwt"));
+
+        let e = Bwt
+            .inverse(b"\x00\x0f\x00\x00\x00\x00\x00\x00")
+            .unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::BadCode(0xf0000)));
+
+        let e = Bwt
+            .inverse(b"\x00\x00\x00\x06\x00\x00\x00\x03nn")
+            .unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::Truncated(80)));
+    }
+}
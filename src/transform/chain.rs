@@ -0,0 +1,85 @@
+use alloc::vec::Vec;
+
+use crate::{
+    error::Error,
+    transform::{Stage, Transform},
+};
+
+/// Two transforms one after the other, made with [`Transform::then`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chain<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> Chain<A, B> {
+    pub fn new(first: A, second: B) -> Self {
+        Self { first, second }
+    }
+}
+
+impl<A: Transform, B: Transform> Transform for Chain<A, B> {
+    type Encoder = ChainStage<A::Encoder, B::Encoder>;
+    type Decoder = ChainStage<B::Decoder, A::Decoder>;
+
+    fn encoder(&self) -> Self::Encoder {
+        ChainStage::new(self.first.encoder(), self.second.encoder())
+    }
+
+    /// Undoes the second transform first.
+    fn decoder(&self) -> Self::Decoder {
+        ChainStage::new(self.second.decoder(), self.first.decoder())
+    }
+}
+
+/// Feeds the output of one stage into another.
+#[derive(Debug, Clone)]
+pub struct ChainStage<A, B> {
+    first: A,
+    second: B,
+    buffer: Vec<u8>,
+}
+
+impl<A, B> ChainStage<A, B> {
+    pub fn new(first: A, second: B) -> Self {
+        Self {
+            first,
+            second,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<A: Stage, B: Stage> Stage for ChainStage<A, B> {
+    fn push(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Error> {
+        self.first.push(input, &mut self.buffer)?;
+        self.second.push(&self.buffer, output)?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Error> {
+        self.first.finish(&mut self.buffer)?;
+        self.second.push(&self.buffer, output)?;
+        self.buffer.clear();
+        self.second.finish(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::{Mtf, Rle1, ZeroRun};
+
+    #[test]
+    fn order() {
+        let data = b"aaaaaaabbbbbbbbbbbbbbbbbbbbbbbbb";
+        let chain = Rle1.then(Mtf).then(ZeroRun);
+        let forward = chain.forward(data).unwrap();
+        let by_hand = ZeroRun
+            .forward(&Mtf.forward(&Rle1.forward(data).unwrap()).unwrap())
+            .unwrap();
+        assert_eq!(forward, by_hand);
+        assert_eq!(chain.inverse(&forward).unwrap(), data);
+    }
+}
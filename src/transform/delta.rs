@@ -0,0 +1,201 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    error::Error,
+    transform::{Stage, Transform},
+};
+
+/// Largest stride for [`Delta`], and width for [`Xor`] and the shuffles.
+pub const MAX_STRIDE: usize = 256;
+
+/// Delta coding: each byte becomes its difference from the byte `stride`
+/// before it. With the stride set to the size of a value, slowly changing
+/// arrays of numbers turn into runs of small bytes. The same as xz's delta
+/// filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Delta {
+    stride: usize,
+}
+
+impl Delta {
+    /// Delta code bytes `stride` apart, clamped to 1 and [`MAX_STRIDE`].
+    pub fn new(stride: usize) -> Self {
+        Self {
+            stride: stride.clamp(1, MAX_STRIDE),
+        }
+    }
+
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+}
+
+impl Transform for Delta {
+    type Encoder = DeltaEncoder;
+    type Decoder = DeltaDecoder;
+
+    fn encoder(&self) -> DeltaEncoder {
+        DeltaEncoder(History::new(self.stride))
+    }
+
+    fn decoder(&self) -> DeltaDecoder {
+        DeltaDecoder(History::new(self.stride))
+    }
+}
+
+/// XOR with the previous value, as Gorilla does for floats. Neighboring
+/// floats tend to share their sign, exponent and top of the mantissa, so
+/// those bytes come out zero. XOR has no carries, so XORing whole values
+/// of `width` bytes is the same as XORing each byte with the one `width`
+/// before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Xor {
+    width: usize,
+}
+
+impl Xor {
+    /// XOR values `width` bytes wide, clamped to 1 and [`MAX_STRIDE`].
+    pub fn new(width: usize) -> Self {
+        Self {
+            width: width.clamp(1, MAX_STRIDE),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+}
+
+impl Transform for Xor {
+    type Encoder = XorEncoder;
+    type Decoder = XorDecoder;
+
+    fn encoder(&self) -> XorEncoder {
+        XorEncoder(History::new(self.width))
+    }
+
+    fn decoder(&self) -> XorDecoder {
+        XorDecoder(History::new(self.width))
+    }
+}
+
+/// The last `stride` bytes, starting out as zeros.
+#[derive(Debug, Clone)]
+struct History {
+    bytes: Vec<u8>,
+    position: usize,
+}
+
+impl History {
+    fn new(stride: usize) -> Self {
+        Self {
+            bytes: vec![0; stride],
+            position: 0,
+        }
+    }
+
+    /// Run `f` on each byte and the byte `stride` before it in the original
+    /// data, which is the input when encoding and the output when decoding.
+    fn apply(
+        &mut self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        f: impl Fn(u8, u8) -> u8,
+        keep_output: bool,
+    ) {
+        output.extend(input.iter().map(|&b| {
+            let previous = &mut self.bytes[self.position];
+            let out = f(b, *previous);
+            *previous = if keep_output { out } else { b };
+            self.position = (self.position + 1) % self.bytes.len();
+            out
+        }));
+    }
+}
+
+macro_rules! impl_stage {
+    ($($t:ident: $f:expr, $keep_output:expr;)*) => {
+        $(
+            #[derive(Debug, Clone)]
+            pub struct $t(History);
+
+            impl Stage for $t {
+                fn push(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Error> {
+                    self.0.apply(input, output, $f, $keep_output);
+                    Ok(())
+                }
+
+                fn finish(&mut self, _output: &mut Vec<u8>) -> Result<(), Error> {
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_stage! {
+    DeltaEncoder: u8::wrapping_sub, false;
+    DeltaDecoder: u8::wrapping_add, true;
+    XorEncoder: |b, previous| b ^ previous, false;
+    XorDecoder: |b, previous| b ^ previous, true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counting() {
+        let data = (1000..1010_i32)
+            .flat_map(|i| i.to_le_bytes())
+            .collect::<Vec<_>>();
+        let delta = Delta::new(4);
+        let forward = delta.forward(&data).unwrap();
+        assert_eq!(&forward[..8], [0xe8, 0x03, 0, 0, 1, 0, 0, 0]);
+        assert!(forward[8..].chunks(4).all(|c| c == [1, 0, 0, 0]));
+        assert_eq!(delta.inverse(&forward).unwrap(), data);
+    }
+
+    #[test]
+    fn wrapping() {
+        let data = [0, 255, 1, 254];
+        let forward = Delta::new(1).forward(&data).unwrap();
+        assert_eq!(forward, [0, 255, 2, 253]);
+        assert_eq!(Delta::new(1).inverse(&forward).unwrap(), data);
+    }
+
+    #[test]
+    fn floats() {
+        let data = [1.5_f32, 1.5, 1.75, -1.75]
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect::<Vec<_>>();
+        let xor = Xor::new(4);
+        let forward = xor.forward(&data).unwrap();
+        assert_eq!(
+            forward,
+            [0, 0, 0xc0, 0x3f, 0, 0, 0, 0, 0, 0, 0x20, 0, 0, 0, 0, 0x80]
+        );
+        assert_eq!(xor.inverse(&forward).unwrap(), data);
+    }
+
+    #[test]
+    fn clamped() {
+        assert_eq!(Delta::new(0).stride(), 1);
+        assert_eq!(Xor::new(1000).width(), MAX_STRIDE);
+    }
+
+    #[test]
+    fn chunks() {
+        let data = (0..100_u8).map(|i| i.wrapping_mul(37)).collect::<Vec<_>>();
+        for stride in [1, 3, 8] {
+            let mut e = Delta::new(stride).encoder();
+            let mut forward = Vec::new();
+            for chunk in data.chunks(5) {
+                e.push(chunk, &mut forward).unwrap();
+            }
+            e.finish(&mut forward).unwrap();
+            assert_eq!(forward, Delta::new(stride).forward(&data).unwrap());
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Reversible byte transforms to run ahead of a compressor.
+//!
+//! A [`Transform`] hands out a [`Stage`] for each direction, fed a chunk at
+//! a time. [`Transform::then`] chains transforms, and a [`TransformSink`]
+//! puts a stage in front of any [`Sink`], including the compressors:
+//!
+//! ```
+//! use kompression::{
+//!     transform::{Mtf, Rle1, Transform, TransformSink},
+//!     LZ78Compressor,
+//! };
+//!
+//! let mut compressed = Vec::new();
+//! let c = LZ78Compressor::new(&mut compressed);
+//! let mut sink = TransformSink::new(Rle1.then(Mtf).encoder(), c);
+//! kompression::io::Sink::write_all(&mut sink, b"aaaaaaaabcbcbc").unwrap();
+//! sink.finish().unwrap().finalize().unwrap();
+//! ```
+
+mod bwt;
+mod chain;
+mod delta;
+mod mtf;
+mod rle1;
+mod shuffle;
+mod sink;
+mod zero_run;
+
+use alloc::vec::Vec;
+
+use crate::{
+    error::Error,
+    huffman::{AdaptiveHuffmanCompressor, HuffmanCompressor},
+    io::Sink,
+    range_coder::RangeCompressor,
+    LZ78Compressor,
+};
+
+pub use self::{
+    bwt::{Bwt, BwtDecoder, BwtEncoder},
+    chain::{Chain, ChainStage},
+    delta::{Delta, DeltaDecoder, DeltaEncoder, Xor, XorDecoder, XorEncoder, MAX_STRIDE},
+    mtf::{Mtf, MtfDecoder, MtfEncoder},
+    rle1::{Rle1, Rle1Decoder, Rle1Encoder},
+    shuffle::{BitShuffle, Shuffle, ShuffleDecoder, ShuffleEncoder},
+    sink::TransformSink,
+    zero_run::{ZeroRun, ZeroRunDecoder, ZeroRunEncoder},
+};
+
+pub(crate) use self::mtf::MtfList;
+
+/// One direction of a transform. Splitting the input into chunks doesn't
+/// change the output.
+pub trait Stage {
+    /// Transform `input`, appending to `output`. Some input may be held
+    /// back until more arrives.
+    fn push(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Error>;
+
+    /// Write out anything held back, failing if the input stopped somewhere
+    /// it couldn't have.
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Error>;
+}
+
+/// A reversible transform, as a pair of stages.
+pub trait Transform {
+    type Encoder: Stage;
+    type Decoder: Stage;
+
+    fn encoder(&self) -> Self::Encoder;
+
+    fn decoder(&self) -> Self::Decoder;
+
+    /// Transform a whole buffer.
+    fn forward(&self, input: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut output = Vec::new();
+        let mut e = self.encoder();
+        e.push(input, &mut output)?;
+        e.finish(&mut output)?;
+        Ok(output)
+    }
+
+    /// Undo [`forward`] on a whole buffer.
+    ///
+    /// [`forward`]: Transform::forward
+    fn inverse(&self, input: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut output = Vec::new();
+        let mut d = self.decoder();
+        d.push(input, &mut output)?;
+        d.finish(&mut output)?;
+        Ok(output)
+    }
+
+    /// Run `next` on the output of this transform.
+    fn then<T: Transform>(self, next: T) -> Chain<Self, T>
+    where
+        Self: Sized,
+    {
+        Chain::new(self, next)
+    }
+}
+
+/// Lets a compressor sit behind a [`TransformSink`]. Every byte is taken.
+macro_rules! impl_sink {
+    ($($t:ident),*) => {
+        $(
+            impl<W: Sink> Sink for $t<W> {
+                fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+                    $t::write(self, data)?;
+                    Ok(data.len())
+                }
+            }
+        )*
+    };
+}
+
+impl_sink!(
+    LZ78Compressor,
+    HuffmanCompressor,
+    AdaptiveHuffmanCompressor,
+    RangeCompressor
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LZ78Decompressor;
+
+    #[test]
+    fn compressor_sink() {
+        let data = b"aaaaaaaaaaaaaaaaaaaabbbbbbbbbbbbbbbbbbbbbbbbcacacacacacaca";
+        let transform = Rle1.then(Mtf);
+
+        let mut compressed = Vec::new();
+        let c = LZ78Compressor::new(&mut compressed);
+        let mut sink = TransformSink::new(transform.encoder(), c);
+        for chunk in data.chunks(5) {
+            sink.write_all(chunk).unwrap();
+        }
+        sink.finish().unwrap().finalize().unwrap();
+
+        let mut uncompressed = Vec::new();
+        let mut sink = TransformSink::new(transform.decoder(), &mut uncompressed);
+        let mut d = LZ78Decompressor::new(&mut sink);
+        d.read(&compressed).unwrap();
+        d.finalize().unwrap();
+        sink.finish().unwrap();
+        assert_eq!(uncompressed, data);
+    }
+}
@@ -0,0 +1,105 @@
+use alloc::vec::Vec;
+
+use crate::{
+    error::Error,
+    transform::{Stage, Transform},
+};
+
+/// Move-to-front: each byte becomes its position in a list of recently seen
+/// bytes, and moves to the front. Runs of a byte turn into runs of zeros.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Mtf;
+
+impl Transform for Mtf {
+    type Encoder = MtfEncoder;
+    type Decoder = MtfDecoder;
+
+    fn encoder(&self) -> MtfEncoder {
+        MtfEncoder(MtfList::new())
+    }
+
+    fn decoder(&self) -> MtfDecoder {
+        MtfDecoder(MtfList::new())
+    }
+}
+
+/// Bytes in order of how recently they were seen.
+#[derive(Debug, Clone)]
+pub(crate) struct MtfList([u8; 256]);
+
+impl MtfList {
+    pub fn new() -> Self {
+        let mut list = [0; 256];
+        for (i, b) in list.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        Self(list)
+    }
+
+    pub fn encode(&mut self, b: u8) -> u8 {
+        let i = self.0.iter().position(|&x| x == b).unwrap();
+        self.0.copy_within(..i, 1);
+        self.0[0] = b;
+        i as u8
+    }
+
+    pub fn decode(&mut self, i: u8) -> u8 {
+        let i = i as usize;
+        let b = self.0[i];
+        self.0.copy_within(..i, 1);
+        self.0[0] = b;
+        b
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MtfEncoder(MtfList);
+
+impl Stage for MtfEncoder {
+    fn push(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Error> {
+        output.extend(input.iter().map(|&b| self.0.encode(b)));
+        Ok(())
+    }
+
+    fn finish(&mut self, _output: &mut Vec<u8>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MtfDecoder(MtfList);
+
+impl Stage for MtfDecoder {
+    fn push(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Error> {
+        output.extend(input.iter().map(|&i| self.0.decode(i)));
+        Ok(())
+    }
+
+    fn finish(&mut self, _output: &mut Vec<u8>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn banana() {
+        let forward = Mtf.forward(b"bananaaa").unwrap();
+        assert_eq!(forward, [b'b', b'b', b'n', 1, 1, 1, 0, 0]);
+        assert_eq!(Mtf.inverse(&forward).unwrap(), b"bananaaa");
+    }
+
+    #[test]
+    fn chunks() {
+        let data = b"abracadabra abracadabra";
+        let mut e = Mtf.encoder();
+        let mut forward = Vec::new();
+        for chunk in data.chunks(3) {
+            e.push(chunk, &mut forward).unwrap();
+        }
+        e.finish(&mut forward).unwrap();
+        assert_eq!(forward, Mtf.forward(data).unwrap());
+    }
+}
@@ -0,0 +1,204 @@
+use alloc::vec::Vec;
+
+use crate::{
+    error::{Error, ErrorKind},
+    options::DecompressOptions,
+    transform::{Stage, Transform},
+};
+
+/// Name reported in errors from RLE1.
+const CODEC: &str = "rle1";
+
+/// Longest run one group can hold, four bytes plus a count of up to 251.
+const MAX_RUN: usize = 255;
+
+/// bzip2's first run-length stage: a run of 4 to 255 equal bytes becomes
+/// the first four followed by a count of the rest. Shorter runs are left
+/// alone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rle1;
+
+impl Transform for Rle1 {
+    type Encoder = Rle1Encoder;
+    type Decoder = Rle1Decoder;
+
+    fn encoder(&self) -> Rle1Encoder {
+        Rle1Encoder { byte: 0, run: 0 }
+    }
+
+    fn decoder(&self) -> Rle1Decoder {
+        Rle1Decoder::with_options(DecompressOptions::new())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Rle1Encoder {
+    byte: u8,
+    /// Length of the current run of `byte`.
+    run: usize,
+}
+
+impl Rle1Encoder {
+    fn flush(&mut self, output: &mut Vec<u8>) {
+        let n = self.run.min(4);
+        output.extend((0..n).map(|_| self.byte));
+        if self.run >= 4 {
+            output.push((self.run - 4) as u8);
+        }
+        self.run = 0;
+    }
+}
+
+impl Stage for Rle1Encoder {
+    fn push(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Error> {
+        for &b in input {
+            if self.run > 0 && (b != self.byte || self.run == MAX_RUN) {
+                self.flush(output);
+            }
+            self.byte = b;
+            self.run += 1;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Error> {
+        self.flush(output);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Rle1Decoder {
+    byte: u8,
+    /// How many times in a row `byte` has been seen, the next byte is a
+    /// count once it hits four.
+    run: usize,
+    options: DecompressOptions,
+    consumed: u64,
+    produced: u64,
+}
+
+impl Rle1Decoder {
+    /// A decoder that checks its output against `options` before writing
+    /// each run, so a few counts can't expand far past the limits.
+    pub fn with_options(options: DecompressOptions) -> Self {
+        Self {
+            byte: 0,
+            run: 0,
+            options,
+            consumed: 0,
+            produced: 0,
+        }
+    }
+
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error::stream(CODEC, kind, self.consumed * 8, self.produced)
+    }
+}
+
+impl Stage for Rle1Decoder {
+    fn push(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Error> {
+        for &b in input {
+            let len = if self.run == 4 { b as u64 } else { 1 };
+            if let Err(l) = self
+                .options
+                .check_output(self.consumed + 1, self.produced, len)
+            {
+                return Err(self.error(ErrorKind::LimitExceeded(l)));
+            }
+            if self.run == 4 {
+                output.extend((0..b).map(|_| self.byte));
+                self.run = 0;
+            } else {
+                if self.run > 0 && b != self.byte {
+                    self.run = 0;
+                }
+                self.byte = b;
+                self.run += 1;
+                output.push(b);
+            }
+            self.consumed += 1;
+            self.produced += len;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, _output: &mut Vec<u8>) -> Result<(), Error> {
+        if self.run == 4 {
+            // Four in a row always come with a count
+            return Err(self.error(ErrorKind::Truncated(0)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::Limit;
+
+    #[test]
+    fn runs() {
+        let data = b"abbbccccddddd";
+        let forward = Rle1.forward(data).unwrap();
+        assert_eq!(forward, b"abbbcccc\x00dddd\x01");
+        assert_eq!(Rle1.inverse(&forward).unwrap(), data);
+    }
+
+    #[test]
+    fn long_run() {
+        let data = [7; 600];
+        let forward = Rle1.forward(&data).unwrap();
+        // 255 + 255 + 90
+        assert_eq!(forward, [7, 7, 7, 7, 251, 7, 7, 7, 7, 251, 7, 7, 7, 7, 86]);
+        assert_eq!(Rle1.inverse(&forward).unwrap(), data);
+    }
+
+    #[test]
+    fn chunks() {
+        let data = b"aaaaaaaabaaaabbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbc";
+        let expected = Rle1.forward(data).unwrap();
+        for size in 1..10 {
+            let mut e = Rle1.encoder();
+            let mut forward = Vec::new();
+            for chunk in data.chunks(size) {
+                e.push(chunk, &mut forward).unwrap();
+            }
+            e.finish(&mut forward).unwrap();
+            assert_eq!(forward, expected);
+
+            let mut d = Rle1.decoder();
+            let mut inverse = Vec::new();
+            for chunk in forward.chunks(size) {
+                d.push(chunk, &mut inverse).unwrap();
+            }
+            d.finish(&mut inverse).unwrap();
+            assert_eq!(inverse, data);
+        }
+    }
+
+    #[test]
+    fn limits() {
+        let forward = Rle1.forward(&[7; 2000]).unwrap();
+        let options = DecompressOptions::new().max_output_bytes(1000);
+        let mut d = Rle1Decoder::with_options(options);
+        let mut inverse = Vec::new();
+        let e = d.push(&forward, &mut inverse).unwrap_err();
+        assert_eq!(
+            e.kind(),
+            Some(ErrorKind::LimitExceeded(Limit::OutputBytes(1000)))
+        );
+        // Stopped at the fourth count, which would have crossed it
+        assert_eq!(inverse.len(), 3 * 255 + 4);
+        assert_eq!(e.byte_offset(), Some(3 * 255 + 4));
+        assert_eq!(e.bit_offset(), Some(19 * 8));
+    }
+
+    #[test]
+    fn missing_count() {
+        let e = Rle1.inverse(b"abbbb").unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::Truncated(0)));
+        assert_eq!(e.codec(), Some("rle1"));
+        assert_eq!(e.bit_offset(), Some(40));
+    }
+}
@@ -0,0 +1,249 @@
+use alloc::vec::Vec;
+
+use crate::{
+    error::Error,
+    transform::{Stage, Transform, MAX_STRIDE},
+};
+
+/// Bytes shuffled at a time, rounded down to a whole number of groups of
+/// eight values.
+const BLOCK_SIZE: usize = 1 << 16;
+
+/// Byte shuffling, as in blosc: within each block of values `width` bytes
+/// wide, the first bytes of every value come first, then the second bytes
+/// and so on. The high bytes of similar numbers end up next to each other.
+///
+/// Input is shuffled in blocks of up to 64k. Whatever doesn't fill a whole
+/// value at the end is left alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shuffle {
+    width: usize,
+}
+
+/// Bit shuffling, as in blosc and the bitshuffle library: like [`Shuffle`]
+/// but with bits, so the first bits of every value come first. Blocks are
+/// shuffled eight values at a time, the values left over at the end of a
+/// block are left alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitShuffle {
+    width: usize,
+}
+
+macro_rules! impl_shuffle {
+    ($($t:ident: $bits:expr),*) => {
+        $(
+            impl $t {
+                /// Shuffle values `width` bytes wide, clamped to 1 and
+                /// [`MAX_STRIDE`].
+                pub fn new(width: usize) -> Self {
+                    Self {
+                        width: width.clamp(1, MAX_STRIDE),
+                    }
+                }
+
+                pub fn width(&self) -> usize {
+                    self.width
+                }
+            }
+
+            impl Transform for $t {
+                type Encoder = ShuffleEncoder;
+                type Decoder = ShuffleDecoder;
+
+                fn encoder(&self) -> ShuffleEncoder {
+                    ShuffleEncoder(Blocks::new(self.width, $bits))
+                }
+
+                fn decoder(&self) -> ShuffleDecoder {
+                    ShuffleDecoder(Blocks::new(self.width, $bits))
+                }
+            }
+        )*
+    };
+}
+
+impl_shuffle!(Shuffle: false, BitShuffle: true);
+
+/// Input gathered into blocks, the same size either way.
+#[derive(Debug, Clone)]
+struct Blocks {
+    width: usize,
+    bits: bool,
+    block_size: usize,
+    buffer: Vec<u8>,
+}
+
+impl Blocks {
+    fn new(width: usize, bits: bool) -> Self {
+        Self {
+            width,
+            bits,
+            block_size: BLOCK_SIZE - BLOCK_SIZE % (8 * width),
+            buffer: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, mut input: &[u8], output: &mut Vec<u8>, forward: bool) {
+        while !input.is_empty() {
+            let n = usize::min(self.block_size - self.buffer.len(), input.len());
+            self.buffer.extend_from_slice(&input[..n]);
+            input = &input[n..];
+            if self.buffer.len() == self.block_size {
+                self.flush(output, forward);
+            }
+        }
+    }
+
+    fn flush(&mut self, output: &mut Vec<u8>, forward: bool) {
+        let block = &self.buffer[..];
+        let start = output.len();
+        match (self.bits, forward) {
+            (false, true) => shuffle(block, self.width, output),
+            (false, false) => unshuffle(block, self.width, output),
+            (true, true) => bit_shuffle(block, self.width, output),
+            (true, false) => bit_unshuffle(block, self.width, output),
+        }
+        output.extend_from_slice(&block[output.len() - start..]);
+        self.buffer.clear();
+    }
+}
+
+/// Shuffle the whole values at the front of `block`.
+fn shuffle(block: &[u8], width: usize, output: &mut Vec<u8>) {
+    let values = block.len() / width;
+    for byte in 0..width {
+        output.extend((0..values).map(|v| block[v * width + byte]));
+    }
+}
+
+fn unshuffle(block: &[u8], width: usize, output: &mut Vec<u8>) {
+    let values = block.len() / width;
+    for v in 0..values {
+        output.extend((0..width).map(|byte| block[byte * values + v]));
+    }
+}
+
+/// Bit shuffle the whole groups of eight values at the front of `block`.
+/// Each bit of the values makes a row, from the lowest bit of the first
+/// byte on, with the bit from the first value in the lowest bit of the
+/// row's first byte.
+fn bit_shuffle(block: &[u8], width: usize, output: &mut Vec<u8>) {
+    let groups = block.len() / (8 * width);
+    for byte in 0..width {
+        for bit in 0..8 {
+            output.extend((0..groups).map(|g| {
+                (0..8).fold(0, |row, v| {
+                    let b = block[(8 * g + v) * width + byte];
+                    row | ((b >> bit) & 1) << v
+                })
+            }));
+        }
+    }
+}
+
+fn bit_unshuffle(block: &[u8], width: usize, output: &mut Vec<u8>) {
+    let groups = block.len() / (8 * width);
+    let start = output.len();
+    output.resize(start + groups * 8 * width, 0);
+    let values = &mut output[start..];
+    for byte in 0..width {
+        for bit in 0..8 {
+            let row = &block[(8 * byte + bit) * groups..][..groups];
+            for (g, &r) in row.iter().enumerate() {
+                for v in 0..8 {
+                    values[(8 * g + v) * width + byte] |= ((r >> v) & 1) << bit;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ShuffleEncoder(Blocks);
+
+impl Stage for ShuffleEncoder {
+    fn push(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Error> {
+        self.0.push(input, output, true);
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Error> {
+        self.0.flush(output, true);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ShuffleDecoder(Blocks);
+
+impl Stage for ShuffleDecoder {
+    fn push(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Error> {
+        self.0.push(input, output, false);
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Error> {
+        self.0.flush(output, false);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes() {
+        let data = b"ABCDabcd0123xy";
+        let forward = Shuffle::new(4).forward(data).unwrap();
+        assert_eq!(forward, b"Aa0Bb1Cc2Dd3xy");
+        assert_eq!(Shuffle::new(4).inverse(&forward).unwrap(), data);
+    }
+
+    #[test]
+    fn bits() {
+        // Eight 16 bit values, each with one bit set in its low byte, and
+        // one left over
+        let mut data = (0..8).flat_map(|v| [1 << v, 0x80]).collect::<Vec<_>>();
+        data.extend_from_slice(&[5, 6, 7]);
+        let forward = BitShuffle::new(2).forward(&data).unwrap();
+        let mut expected = (0..8).map(|bit| 1 << bit).collect::<Vec<_>>();
+        expected.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0xff, 5, 6, 7]);
+        assert_eq!(forward, expected);
+        assert_eq!(BitShuffle::new(2).inverse(&forward).unwrap(), data);
+    }
+
+    #[test]
+    fn blocks() {
+        let data = (0..200_000_u32)
+            .map(|i| (i * 7 % 251) as u8)
+            .collect::<Vec<_>>();
+        for width in [1, 3, 4, 8] {
+            for bits in [false, true] {
+                let mut blocks = Blocks::new(width, bits);
+                let mut forward = Vec::new();
+                for chunk in data.chunks(10_000) {
+                    blocks.push(chunk, &mut forward, true);
+                }
+                blocks.flush(&mut forward, true);
+                assert_eq!(forward.len(), data.len());
+
+                let mut blocks = Blocks::new(width, bits);
+                let mut inverse = Vec::new();
+                blocks.push(&forward, &mut inverse, false);
+                blocks.flush(&mut inverse, false);
+                assert!(inverse == data, "width {} bits {}", width, bits);
+            }
+        }
+    }
+
+    #[test]
+    fn numbers() {
+        // Shuffled small numbers give long runs of zeros
+        let data = (0..1000_u32)
+            .flat_map(|i| i.to_le_bytes())
+            .collect::<Vec<_>>();
+        let forward = Shuffle::new(4).forward(&data).unwrap();
+        assert!(forward[2000..].iter().all(|&b| b == 0));
+    }
+}
@@ -0,0 +1,47 @@
+use alloc::vec::Vec;
+
+use crate::{error::Error, io::Sink, transform::Stage};
+
+/// A [`Sink`] that runs everything written to it through a [`Stage`] on the
+/// way to another sink.
+#[derive(Debug)]
+pub struct TransformSink<S, W> {
+    stage: S,
+    sink: W,
+    buffer: Vec<u8>,
+}
+
+impl<S: Stage, W: Sink> TransformSink<S, W> {
+    pub fn new(stage: S, sink: W) -> Self {
+        Self {
+            stage,
+            sink,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Flush the stage and hand back the inner sink, to finalize it if
+    /// it's a compressor.
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.stage.finish(&mut self.buffer)?;
+        self.sink.write_all(&self.buffer)?;
+        Ok(self.sink)
+    }
+}
+
+impl<S: Stage, W: Sink> Sink for TransformSink<S, W> {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.stage.push(data, &mut self.buffer)?;
+        self.sink.write_all(&self.buffer)?;
+        self.buffer.clear();
+        Ok(data.len())
+    }
+}
+
+/// Without `std` every `&mut` sink is a sink already.
+#[cfg(feature = "std")]
+impl<S: Stage, W: Sink> Sink for &mut TransformSink<S, W> {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        (**self).write(data)
+    }
+}
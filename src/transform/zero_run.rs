@@ -0,0 +1,352 @@
+use alloc::vec::Vec;
+
+use crate::{
+    error::{Error, ErrorKind},
+    options::DecompressOptions,
+    transform::{Stage, Transform},
+};
+
+/// Name reported in errors from the zero-run stage.
+const CODEC: &str = "zero-run";
+
+pub(crate) const RUNA: u16 = 0;
+pub(crate) const RUNB: u16 = 1;
+
+/// Byte that starts a two byte symbol in [`ZeroRun`]'s byte form.
+const ESCAPE: u8 = 255;
+
+/// Longest run of zeros in one go, longer runs are split. Decoding ends a
+/// run as soon as it reaches this, which keeps a handful of digits from
+/// standing for an enormous run.
+pub(crate) const MAX_RUN: u64 = 1 << 20;
+
+/// bzip2's second run-length stage (RLE2), meant for the output of
+/// [`Mtf`](crate::transform::Mtf). A run of zeros is written as its length
+/// in bijective base 2, least significant digit first, with RUNA for a 1
+/// and RUNB for a 2. Every other byte `b` becomes the symbol `b + 1`. Runs
+/// are split every 2^20 zeros.
+///
+/// As a byte transform, symbols up to 254 are single bytes, 255 is
+/// `[255, 0]` and 256 is `[255, 1]`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ZeroRun;
+
+impl Transform for ZeroRun {
+    type Encoder = ZeroRunEncoder;
+    type Decoder = ZeroRunDecoder;
+
+    fn encoder(&self) -> ZeroRunEncoder {
+        ZeroRunEncoder::new()
+    }
+
+    fn decoder(&self) -> ZeroRunDecoder {
+        ZeroRunDecoder::new()
+    }
+}
+
+/// Turns bytes into RUNA/RUNB symbols.
+#[derive(Debug, Clone)]
+pub struct ZeroRunEncoder {
+    zeros: u64,
+    symbols: Vec<u16>,
+}
+
+impl ZeroRunEncoder {
+    pub(crate) fn new() -> Self {
+        Self {
+            zeros: 0,
+            symbols: Vec::new(),
+        }
+    }
+
+    /// Append the symbols for `input` to `output`.
+    pub(crate) fn push_symbols(&mut self, input: &[u8], output: &mut Vec<u16>) {
+        for &b in input {
+            if b == 0 {
+                self.zeros += 1;
+                if self.zeros == MAX_RUN {
+                    self.flush_symbols(output);
+                }
+            } else {
+                self.flush_symbols(output);
+                output.push(b as u16 + 1);
+            }
+        }
+    }
+
+    /// Write out any pending run of zeros.
+    pub(crate) fn flush_symbols(&mut self, output: &mut Vec<u16>) {
+        let mut n = self.zeros;
+        while n > 0 {
+            if n & 1 == 1 {
+                output.push(RUNA);
+                n = (n - 1) / 2;
+            } else {
+                output.push(RUNB);
+                n = (n - 2) / 2;
+            }
+        }
+        self.zeros = 0;
+    }
+
+    fn write_bytes(&mut self, output: &mut Vec<u8>) {
+        for &s in &self.symbols {
+            match s {
+                0..=254 => output.push(s as u8),
+                _ => output.extend([ESCAPE, (s - 255) as u8]),
+            }
+        }
+        self.symbols.clear();
+    }
+}
+
+impl Stage for ZeroRunEncoder {
+    fn push(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Error> {
+        let mut symbols = core::mem::take(&mut self.symbols);
+        self.push_symbols(input, &mut symbols);
+        self.symbols = symbols;
+        self.write_bytes(output);
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Error> {
+        let mut symbols = core::mem::take(&mut self.symbols);
+        self.flush_symbols(&mut symbols);
+        self.symbols = symbols;
+        self.write_bytes(output);
+        Ok(())
+    }
+}
+
+/// Turns RUNA/RUNB symbols back into bytes.
+#[derive(Debug, Clone)]
+pub struct ZeroRunDecoder {
+    /// Length of the run of zeros so far.
+    zeros: u64,
+    /// Weight of the next RUNA or RUNB digit.
+    weight: u64,
+    /// Whether the last byte was an escape.
+    escaped: bool,
+    options: DecompressOptions,
+    consumed: u64,
+    produced: u64,
+}
+
+impl ZeroRunDecoder {
+    pub(crate) fn new() -> Self {
+        Self::with_options(DecompressOptions::new())
+    }
+
+    /// A decoder that checks its output against `options` before writing
+    /// each run, so a few digits can't expand far past the limits.
+    pub fn with_options(options: DecompressOptions) -> Self {
+        Self {
+            zeros: 0,
+            weight: 1,
+            escaped: false,
+            options,
+            consumed: 0,
+            produced: 0,
+        }
+    }
+
+    /// How many bytes [`push_symbol`] would write for `symbol`.
+    ///
+    /// [`push_symbol`]: ZeroRunDecoder::push_symbol
+    fn pending(&self, symbol: u16) -> u64 {
+        match symbol {
+            RUNA | RUNB if self.zeros + (symbol as u64 + 1) * self.weight == MAX_RUN => MAX_RUN,
+            2..=256 => self.zeros + 1,
+            _ => 0,
+        }
+    }
+
+    fn check(&self, len: u64) -> Result<(), Error> {
+        self.options
+            .check_output(self.consumed + 1, self.produced, len)
+            .map_err(|l| self.error(ErrorKind::LimitExceeded(l)))
+    }
+
+    /// Decode one symbol, `None` if it's past the alphabet or makes a run
+    /// longer than [`MAX_RUN`].
+    pub(crate) fn push_symbol(&mut self, symbol: u16, output: &mut Vec<u8>) -> Option<()> {
+        match symbol {
+            RUNA | RUNB => {
+                self.zeros += (symbol as u64 + 1) * self.weight;
+                self.weight *= 2;
+                match self.zeros {
+                    MAX_RUN => self.flush_zeros(output),
+                    z if z > MAX_RUN => return None,
+                    _ => {}
+                }
+            }
+            2..=256 => {
+                self.flush_zeros(output);
+                output.push((symbol - 1) as u8);
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+
+    /// Write out any pending run of zeros.
+    pub(crate) fn flush_zeros(&mut self, output: &mut Vec<u8>) {
+        output.extend((0..self.zeros).map(|_| 0));
+        self.zeros = 0;
+        self.weight = 1;
+    }
+
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error::stream(CODEC, kind, self.consumed * 8, self.produced)
+    }
+}
+
+impl Stage for ZeroRunDecoder {
+    fn push(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Error> {
+        for &b in input {
+            let start = output.len();
+            let symbol = match (self.escaped, b) {
+                (false, ESCAPE) => {
+                    self.escaped = true;
+                    self.consumed += 1;
+                    continue;
+                }
+                (false, b) => b as u16,
+                (true, 0 | 1) => 255 + b as u16,
+                (true, b) => return Err(self.error(ErrorKind::BadCode(b as u32))),
+            };
+            self.escaped = false;
+            self.check(self.pending(symbol))?;
+            self.push_symbol(symbol, output)
+                .ok_or_else(|| self.error(ErrorKind::BadCode(symbol as u32)))?;
+            self.consumed += 1;
+            self.produced += (output.len() - start) as u64;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Error> {
+        if self.escaped {
+            return Err(self.error(ErrorKind::Truncated(8)));
+        }
+        self.check(self.zeros)?;
+        self.produced += self.zeros;
+        self.flush_zeros(output);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::Limit;
+
+    fn symbols(data: &[u8]) -> Vec<u16> {
+        let mut e = ZeroRunEncoder::new();
+        let mut symbols = Vec::new();
+        e.push_symbols(data, &mut symbols);
+        e.flush_symbols(&mut symbols);
+        symbols
+    }
+
+    #[test]
+    fn runs() {
+        assert_eq!(symbols(&[0]), [RUNA]);
+        assert_eq!(symbols(&[0, 0]), [RUNB]);
+        assert_eq!(symbols(&[0, 0, 0]), [RUNA, RUNA]);
+        assert_eq!(symbols(&[0, 0, 0, 0]), [RUNB, RUNA]);
+        assert_eq!(symbols(&[0; 5]), [RUNA, RUNB]);
+        assert_eq!(symbols(&[0; 6]), [RUNB, RUNB]);
+        assert_eq!(symbols(&[0; 7]), [RUNA, RUNA, RUNA]);
+        assert_eq!(symbols(&[3, 0, 0, 255]), [4, RUNB, 256]);
+    }
+
+    #[test]
+    fn bytes() {
+        let data = [0, 0, 0, 1, 253, 254, 255, 0];
+        let forward = ZeroRun.forward(&data).unwrap();
+        assert_eq!(forward, [0, 0, 2, 254, 255, 0, 255, 1, 0]);
+        assert_eq!(ZeroRun.inverse(&forward).unwrap(), data);
+    }
+
+    #[test]
+    fn long_runs() {
+        for n in [100, 1000, 65535, 100_000] {
+            let data = vec![0; n];
+            let forward = ZeroRun.forward(&data).unwrap();
+            assert!(forward.len() <= 17);
+            assert_eq!(ZeroRun.inverse(&forward).unwrap(), data);
+        }
+
+        // Split in two, and the second run starts from scratch
+        let n = MAX_RUN as usize + 3;
+        let forward = ZeroRun.forward(&vec![0; n]).unwrap();
+        assert_eq!(&forward[forward.len() - 2..], [0, 0]);
+        assert_eq!(ZeroRun.inverse(&forward).unwrap().len(), n);
+
+        // A run past the limit
+        let e = ZeroRun.inverse(&[1; 21]).unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::BadCode(1)));
+    }
+
+    #[test]
+    fn chunks() {
+        let data = [0, 0, 0, 255, 0, 255, 255, 1, 0, 0, 0, 0, 0, 0, 0, 5];
+        let forward = ZeroRun.forward(&data).unwrap();
+        for size in 1..5 {
+            let mut d = ZeroRun.decoder();
+            let mut inverse = Vec::new();
+            for chunk in forward.chunks(size) {
+                d.push(chunk, &mut inverse).unwrap();
+            }
+            d.finish(&mut inverse).unwrap();
+            assert_eq!(inverse, data);
+        }
+    }
+
+    #[test]
+    fn limits() {
+        // Runs of 2^20 zeros, 20 digits each, then one that ends early
+        let run = ZeroRun.forward(&vec![0; MAX_RUN as usize]).unwrap();
+        let mut data = run.repeat(4);
+        data.extend_from_slice(&run[..10]);
+        let limit = 3 * MAX_RUN + 1000;
+        let options = DecompressOptions::new().max_output_bytes(limit);
+        let mut d = ZeroRunDecoder::with_options(options);
+        let mut inverse = Vec::new();
+        let e = d.push(&data, &mut inverse).unwrap_err();
+        assert_eq!(
+            e.kind(),
+            Some(ErrorKind::LimitExceeded(Limit::OutputBytes(limit)))
+        );
+        assert_eq!(inverse.len() as u64, 3 * MAX_RUN);
+        assert_eq!(e.byte_offset(), Some(3 * MAX_RUN));
+
+        // The run left over at the end counts too
+        let options = DecompressOptions::new().max_output_bytes(100);
+        let mut d = ZeroRunDecoder::with_options(options);
+        d.push(&run[..10], &mut inverse).unwrap();
+        let e = d.finish(&mut inverse).unwrap_err();
+        assert_eq!(
+            e.kind(),
+            Some(ErrorKind::LimitExceeded(Limit::OutputBytes(100)))
+        );
+    }
+
+    #[test]
+    fn bad_escape() {
+        let e = ZeroRun.inverse(&[2, 255, 2]).unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::BadCode(2)));
+        assert_eq!(e.codec(), Some("zero-run"));
+        assert_eq!(e.bit_offset(), Some(16));
+
+        let e = ZeroRun.inverse(&[2, 255]).unwrap_err();
+        assert_eq!(e.kind(), Some(ErrorKind::Truncated(8)));
+    }
+
+    #[test]
+    fn bad_symbol() {
+        let mut d = ZeroRunDecoder::new();
+        assert_eq!(d.push_symbol(257, &mut Vec::new()), None);
+    }
+}
@@ -1,7 +1,38 @@
-pub(crate) fn format_bits(data: u64) -> String {
+use alloc::string::String;
+
+/// The low `bits` bits of `data`, most significant first, with a space
+/// between bytes counted from the right.
+pub(crate) fn format_bits(data: u64, bits: usize) -> String {
     let mut s = String::new();
-    data.to_be_bytes().iter().for_each(|b| {
-        s.push_str(&format!("{:08b} ", b));
-    });
+    for i in (0..bits).rev() {
+        s.push(if data >> i & 1 == 1 { '1' } else { '0' });
+        if i % 8 == 0 && i > 0 {
+            s.push(' ');
+        }
+    }
     s
 }
+
+/// `len` bytes of the same pseudo-random data every time.
+#[cfg(test)]
+pub(crate) fn noise(len: usize) -> alloc::vec::Vec<u8> {
+    let mut state = 1_u32;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bits() {
+        assert_eq!(format_bits(0b101, 3), "101");
+        assert_eq!(format_bits(0x1ff, 10), "01 11111111");
+        assert_eq!(format_bits(u64::MAX, 0), "");
+    }
+}